@@ -1,21 +1,28 @@
 use {
     crate::build_info::ENV_PREFIX,
-    crate::pubip::{self, HttpProvider, Set, Token, TrustFactorAuthority},
-    anyhow::{Error, Result, anyhow, bail, ensure},
+    crate::pubip::{
+        self, Catalog, Cidr, Family, HttpProvider, KeySource, Pin, Provider, Rejected, Resolver,
+        Set, Token, TrustFactorAuthority, Uplink,
+    },
+    anyhow::{Context as _, Error, Result, anyhow, bail, ensure},
     clap::{
         Args as ClapArgs,
         builder::{PossibleValuesParser, TypedValueParser},
     },
     const_format::concatcp,
     humantime::parse_duration,
-    std::{str::FromStr, time::Duration as StdDuration},
-    strum::{VariantArray, VariantNames},
-    tracing::warn,
+    reqwest::{NoProxy, Proxy, Url},
+    std::{
+        collections::HashMap, fs, net::IpAddr, path::PathBuf, str::FromStr,
+        time::Duration as StdDuration,
+    },
+    strum::VariantNames,
+    tracing::{info, warn},
 };
 
 /// Keeps a provider named twice from paying its trust factor twice into the
 /// same address' bucket.
-fn push_once(providers: &mut Vec<HttpProvider>, provider: HttpProvider) {
+fn push_once(providers: &mut Vec<Provider>, provider: Provider) {
     if !providers.contains(&provider) {
         providers.push(provider);
     }
@@ -64,10 +71,525 @@ pub struct Global {
     pub json: bool,
 }
 
+/// How the providers are reached, as opposed to which of them are asked.
+#[derive(Clone, Default, ClapArgs)]
+pub struct OfNetwork {
+    /// Address families to resolve, each confirmed on its own (ipv4, ipv6)
+    #[arg(
+        long,
+        value_name("FAMILY"),
+        value_delimiter = ',',
+        help_heading = "Network",
+        default_values_t = Family::ALL,
+        env(concatcp!(ENV_PREFIX, "FAMILIES")),
+        hide_env=true,
+    )]
+    pub families: Vec<Family>,
+
+    /// Ways out of the node, each resolved on its own: default, auto (every
+    /// interface a default route leaves by), or interface names and source
+    /// addresses
+    #[arg(
+        long,
+        value_name("UPLINK"),
+        value_delimiter = ',',
+        help_heading = "Network",
+        value_parser = Self::parse_uplink_token,
+        env(concatcp!(ENV_PREFIX, "UPLINKS")),
+        hide_env=true,
+    )]
+    pub uplinks: Vec<UplinkToken>,
+
+    /// HTTP proxy provider requests go through; HTTPS_PROXY, HTTP_PROXY and
+    /// NO_PROXY are honoured when it is left out
+    #[allow(clippy::doc_markdown, reason = "this doc comment is CLI help text")]
+    #[arg(
+        long,
+        value_name("URL"),
+        help_heading = "Network",
+        env(concatcp!(ENV_PREFIX, "PROXY")),
+        hide_env=true,
+    )]
+    pub proxy: Option<Url>,
+
+    /// File holding the proxy's USER:PASSWORD, such as a mounted Secret
+    #[arg(
+        long,
+        value_name("FILE"),
+        requires = "proxy",
+        help_heading = "Network",
+        env(concatcp!(ENV_PREFIX, "PROXY_AUTH_FILE")),
+        hide_env=true,
+    )]
+    pub proxy_auth_file: Option<PathBuf>,
+
+    /// Providers asked straight out by the uplink, past any proxy
+    #[arg(
+        long,
+        value_name("NAME"),
+        value_delimiter = ',',
+        help_heading = "Network",
+        env(concatcp!(ENV_PREFIX, "PROXY_EXEMPT")),
+        hide_env=true,
+    )]
+    pub proxy_exempt: Vec<String>,
+
+    /// Addresses the proxy reaches the Internet from; none of them is ever
+    /// confirmed, and a round in which every provider reports one is degraded
+    #[arg(
+        long,
+        value_name("CIDR"),
+        value_delimiter = ',',
+        help_heading = "Network",
+        value_parser = Self::parse_cidr,
+        env(concatcp!(ENV_PREFIX, "PROXY_EGRESS")),
+        hide_env=true,
+    )]
+    pub proxy_egress: Vec<Cidr>,
+
+    /// Addresses a provider's host is reached at, whatever DNS says; more
+    /// addresses of the same host follow it as bare IPs
+    #[arg(
+        long,
+        value_name("HOST=IP"),
+        value_delimiter = ',',
+        help_heading = "Network",
+        value_parser = Self::parse_resolve_part,
+        env(concatcp!(ENV_PREFIX, "RESOLVE")),
+        hide_env=true,
+    )]
+    pub resolve: Vec<(Option<String>, IpAddr)>,
+
+    /// DNS-over-HTTPS resolver the hosts `--resolve` leaves out are looked
+    /// up with, rather than the system's
+    #[arg(
+        long,
+        value_name("URL"),
+        help_heading = "Network",
+        env(concatcp!(ENV_PREFIX, "DOH")),
+        hide_env=true,
+    )]
+    pub doh: Option<Url>,
+
+    /// The uplinks this run will actually resolve.
+    /// Computed lately by [`Self::setup`] from `uplinks`.
+    #[arg(skip)]
+    pub resolved_uplinks: Vec<Uplink>,
+}
+
+/// One name given to `--uplinks`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum UplinkToken {
+    /// Wherever the default route goes, and the only uplink there used to be.
+    Default,
+    /// Every interface a default route leaves by, found at startup.
+    Auto,
+    Named(Uplink),
+}
+
+impl OfNetwork {
+    /// Works out which uplinks this run will resolve.
+    ///
+    /// ```text
+    ///   nothing given         ->  the default route, unbound
+    ///   --uplinks auto        ->  every interface a default route leaves by
+    ///   --uplinks eth0,eth1   ->  exactly those, each bound to its interface
+    ///   --uplinks 203.0.113.7 ->  that source address, over its family only
+    /// ```
+    pub fn setup(&mut self) -> Result<()> {
+        let standalone = self
+            .uplinks
+            .iter()
+            .any(|token| !matches!(token, UplinkToken::Named(_)));
+
+        ensure!(
+            !standalone || self.uplinks.len() == 1,
+            "`default` and `auto` stand alone and cannot be combined with other uplinks",
+        );
+
+        let uplinks = match self.uplinks.first() {
+            None | Some(UplinkToken::Default) => vec![Uplink::Default],
+            Some(UplinkToken::Auto) => {
+                ensure!(
+                    Uplink::BINDS_INTERFACES,
+                    "`auto` reads the Linux routing table, which this platform does not have",
+                );
+
+                let found = pubip::discover_uplinks();
+                ensure!(!found.is_empty(), "no interface carries a default route");
+                found
+            }
+            Some(UplinkToken::Named(_)) => {
+                let mut named = Vec::with_capacity(self.uplinks.len());
+                for token in &self.uplinks {
+                    if let UplinkToken::Named(uplink) = token
+                        && !named.contains(uplink)
+                    {
+                        named.push(uplink.clone());
+                    }
+                }
+                named
+            }
+        };
+
+        for uplink in &uplinks {
+            if let Uplink::Interface(name) = uplink {
+                ensure!(
+                    Uplink::BINDS_INTERFACES,
+                    "uplink {name} is an interface, which cannot be bound on this platform",
+                );
+                ensure!(
+                    pubip::interface_exists(name),
+                    "no interface is named {name}"
+                );
+            }
+
+            ensure!(
+                self.families.iter().any(|family| uplink.carries(*family)),
+                "uplink {uplink} carries none of the families asked for",
+            );
+        }
+
+        self.resolved_uplinks = uplinks;
+
+        if let Some(proxy) = self.proxy()? {
+            pubip::set_proxy(proxy);
+        }
+
+        let overrides = self.overrides()?;
+        if !overrides.is_empty() {
+            info!(
+                hosts = overrides.len(),
+                "provider hosts are resolved as given"
+            );
+            pubip::set_host_overrides(overrides);
+        }
+
+        if let Some(url) = &self.doh {
+            ensure!(url.scheme() == "https", "the DoH resolver must be https");
+            info!(resolver = %url, "provider hosts are looked up over DoH");
+            pubip::set_doh(url.clone());
+        }
+
+        Ok(())
+    }
+
+    /// The addresses `--resolve` gives each host, a bare address belonging
+    /// to the host named last before it.
+    fn overrides(&self) -> Result<HashMap<String, Vec<IpAddr>>> {
+        let mut overrides: HashMap<String, Vec<IpAddr>> = HashMap::new();
+        let mut last = None;
+
+        for (host, ip_addr) in &self.resolve {
+            if let Some(host) = host {
+                last = Some(host.to_ascii_lowercase());
+            }
+            let Some(host) = &last else {
+                bail!("--resolve {ip_addr} names no host");
+            };
+
+            let addrs = overrides.entry(host.clone()).or_default();
+            if !addrs.contains(ip_addr) {
+                addrs.push(*ip_addr);
+            }
+        }
+
+        Ok(overrides)
+    }
+
+    /// The proxy `--proxy` names, with `NO_PROXY` still honoured and the
+    /// credentials in `--proxy-auth-file` if given.
+    fn proxy(&self) -> Result<Option<Proxy>> {
+        let Some(url) = &self.proxy else {
+            return Ok(None);
+        };
+
+        ensure!(
+            matches!(url.scheme(), "http" | "https"),
+            "the proxy must be http or https",
+        );
+
+        let mut proxy = Proxy::all(url.clone())?.no_proxy(NoProxy::from_env());
+
+        if let Some(path) = &self.proxy_auth_file {
+            let auth = fs::read_to_string(path)
+                .with_context(|| format!("cannot read proxy auth file {}", path.display()))?;
+            let Some((user, password)) = auth.trim().split_once(':') else {
+                bail!(
+                    "the proxy auth file {} is not USER:PASSWORD",
+                    path.display()
+                );
+            };
+
+            proxy = proxy.basic_auth(user, password);
+        }
+
+        // The host alone: the URL may carry credentials of its own.
+        info!(
+            proxy = url.host_str(),
+            "provider requests go through the proxy",
+        );
+
+        Ok(Some(proxy))
+    }
+
+    /// Builds one [`Resolver`] per uplink, every one of them asking the same
+    /// providers under the same rules, each with its own pacing: two uplinks
+    /// are two machines as far as a provider's rate limit can tell.
+    pub fn resolvers(
+        &self,
+        providers: &OfProviders,
+        tfa: &TrustFactorAuthority,
+    ) -> Result<Vec<Resolver>> {
+        let direct = self
+            .proxy_exempt
+            .iter()
+            .map(|name| {
+                providers
+                    .catalog
+                    .find(name)
+                    .with_context(|| format!("provider {name} not found"))
+            })
+            .collect::<Result<Vec<Provider>>>()?;
+
+        self.resolved_uplinks
+            .iter()
+            .map(|uplink| {
+                let mut resolver = Resolver::new(providers.enabled.clone(), tfa.clone())?;
+                resolver
+                    .set_rate_limits(providers.gaps.iter().copied())
+                    .set_ignore_rate_limits(providers.ignore_rate_limits)
+                    .set_carry_forward(providers.carry_forward.unwrap_or(pubip::CARRY_FORWARD))
+                    .set_retries(providers.retry_counts.iter().copied())
+                    .set_retry_backoff(providers.retry_backoff.unwrap_or(pubip::RETRY_BACKOFF))
+                    .set_retry_on(providers.retry_kinds())
+                    .set_break_after(providers.break_trips())
+                    .set_break_cool_down(
+                        providers.break_cool_down.unwrap_or(pubip::BREAK_COOL_DOWN),
+                    )
+                    .set_endpoints(providers.catalog.endpoints())
+                    .set_credentials(providers.catalog.credentials())
+                    .set_identities(providers.catalog.identities())
+                    .set_pins(providers.catalog.pins())
+                    .set_direct(direct.iter().copied())
+                    .set_proxy_egress(self.proxy_egress.iter().copied())
+                    .set_families(self.families.iter().copied())
+                    .set_uplink(uplink.clone());
+
+                Ok(resolver)
+            })
+            .collect()
+    }
+
+    pub fn parse_cidr(s: &str) -> Result<Cidr> {
+        Cidr::from_str(s.trim()).map_err(Error::msg)
+    }
+
+    pub fn parse_resolve_part(s: &str) -> Result<(Option<String>, IpAddr)> {
+        let (host, value) = match s.split_once('=') {
+            Some((host, value)) => {
+                let host = host.trim().trim_end_matches('.');
+                ensure!(
+                    !host.is_empty()
+                        && host
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_')),
+                    "`{host}` is not a host name",
+                );
+                (Some(host.to_owned()), value)
+            }
+            None => (None, s),
+        };
+
+        let ip_addr = IpAddr::from_str(value.trim())
+            .with_context(|| format!("`{}` is not an IP address", value.trim()))?;
+
+        Ok((host, ip_addr))
+    }
+
+    pub fn parse_uplink_token(s: &str) -> Result<UplinkToken> {
+        let token = match s.trim().to_ascii_lowercase().as_str() {
+            "default" => UplinkToken::Default,
+            "auto" => UplinkToken::Auto,
+            _ => UplinkToken::Named(Uplink::from_str(s).map_err(Error::msg)?),
+        };
+
+        Ok(token)
+    }
+}
+
+/// Where the providers beyond the compiled-in ones come from.
+#[derive(Clone, Default, ClapArgs)]
+pub struct OfCatalog {
+    /// YAML or JSON file declaring providers beside the built-in ones, read
+    /// once at startup
+    #[arg(
+        long,
+        value_name("FILE"),
+        help_heading = "Providers",
+        env(concatcp!(ENV_PREFIX, "PROVIDERS_FILE")),
+        hide_env=true,
+    )]
+    pub providers_file: Option<PathBuf>,
+
+    /// Ask a provider at another URL, keeping its decoder, trust factor and
+    /// rate limit, such as a self-hosted httpbin
+    #[arg(
+        long,
+        value_name("NAME=URL"),
+        value_delimiter = ',',
+        help_heading = "Providers",
+        value_parser = Self::parse_provider_url_pair,
+        env(concatcp!(ENV_PREFIX, "PROVIDER_URL")),
+        hide_env=true,
+    )]
+    pub provider_url: Vec<(String, Url)>,
+
+    /// Key of a provider that needs one, read from a file such as a mounted
+    /// Secret, or from an environment variable given as env:NAME
+    #[arg(
+        long,
+        value_name("NAME=FILE"),
+        value_delimiter = ',',
+        help_heading = "Providers",
+        value_parser = Self::parse_provider_key_pair,
+        env(concatcp!(ENV_PREFIX, "PROVIDER_KEY")),
+        hide_env=true,
+    )]
+    pub provider_key: Vec<(String, KeySource)>,
+
+    /// PEM bundle of the CA an operator's own provider is signed by, trusted
+    /// beside the built-in roots
+    #[arg(
+        long,
+        value_name("FILE"),
+        help_heading = "Providers",
+        env(concatcp!(ENV_PREFIX, "PROVIDER_CA")),
+        hide_env=true,
+    )]
+    pub provider_ca: Option<PathBuf>,
+
+    /// PEM client certificate a provider is asked with, for one that wants
+    /// to know who is asking; needs its key in `--provider-cert-key`
+    #[arg(
+        long,
+        value_name("NAME=FILE"),
+        value_delimiter = ',',
+        help_heading = "Providers",
+        value_parser = Self::parse_provider_file_pair,
+        env(concatcp!(ENV_PREFIX, "PROVIDER_CERT")),
+        hide_env=true,
+    )]
+    pub provider_cert: Vec<(String, PathBuf)>,
+
+    /// PEM private key of the client certificate of `--provider-cert`
+    #[arg(
+        long,
+        value_name("NAME=FILE"),
+        value_delimiter = ',',
+        help_heading = "Providers",
+        value_parser = Self::parse_provider_file_pair,
+        env(concatcp!(ENV_PREFIX, "PROVIDER_CERT_KEY")),
+        hide_env=true,
+    )]
+    pub provider_cert_key: Vec<(String, PathBuf)>,
+
+    /// Key one of a provider's certificates must be issued under, as
+    /// sha256/BASE64 of its SPKI, replacing any pins of its own; none lifts
+    /// them
+    #[arg(
+        long,
+        value_name("NAME=PIN"),
+        value_delimiter = ',',
+        help_heading = "Providers",
+        value_parser = Self::parse_provider_pin_pair,
+        env(concatcp!(ENV_PREFIX, "PROVIDER_PIN")),
+        hide_env=true,
+    )]
+    pub provider_pin: Vec<(String, Option<Pin>)>,
+}
+
+impl OfCatalog {
+    /// Every provider this run knows of, the file's included when given, and
+    /// each pointed wherever `--provider-url` says.
+    pub fn load(&self) -> Result<Catalog> {
+        // Before anything is asked, so that no client is built without them.
+        if let Some(path) = &self.provider_ca {
+            let roots = pubip::load_provider_ca(path)?;
+            info!(path = %path.display(), roots, "provider CA is trusted");
+        }
+
+        let catalog = self
+            .providers_file
+            .as_deref()
+            .map_or_else(|| Ok(Catalog::default()), Catalog::load)?
+            .with_endpoints(&self.provider_url)?
+            .with_keys(&self.provider_key)?
+            .with_identities(&self.provider_cert, &self.provider_cert_key)?
+            .with_pins(&self.provider_pin)?;
+
+        for (provider, url) in catalog.endpoints() {
+            if url.starts_with("http:") {
+                warn!(
+                    %provider,
+                    url,
+                    "provider is asked over plain HTTP, anything on the path can answer for it",
+                );
+            }
+        }
+
+        Ok(catalog)
+    }
+
+    pub fn parse_provider_url_pair(s: &str) -> Result<(String, Url)> {
+        let (provider, value) = OfProviders::split_pair(s)?;
+        let url = Url::parse(value.trim())?;
+
+        Ok((provider, url))
+    }
+
+    pub fn parse_provider_key_pair(s: &str) -> Result<(String, KeySource)> {
+        let (provider, value) = OfProviders::split_pair(s)?;
+        let source = KeySource::from_str(value.trim()).map_err(Error::msg)?;
+
+        Ok((provider, source))
+    }
+
+    pub fn parse_provider_pin_pair(s: &str) -> Result<(String, Option<Pin>)> {
+        let (provider, value) = OfProviders::split_pair(s)?;
+        let pin = match value.trim() {
+            none if none.eq_ignore_ascii_case("none") => None,
+            pin => Some(Pin::from_str(pin).map_err(Error::msg)?),
+        };
+
+        Ok((provider, pin))
+    }
+
+    pub fn parse_provider_file_pair(s: &str) -> Result<(String, PathBuf)> {
+        let (provider, value) = OfProviders::split_pair(s)?;
+        let value = value.trim();
+        ensure!(
+            !value.is_empty(),
+            "no file is named for provider {provider}"
+        );
+
+        Ok((provider, PathBuf::from(value)))
+    }
+}
+
 #[derive(Clone, Default, ClapArgs)]
 pub struct OfProviders {
-    /// Providers to ask: their names, a version to pin (v1.5), or a set
-    /// (all, default, trust1, trust2, trust3, also low, med, hig)
+    #[command(flatten)]
+    pub declared: OfCatalog,
+
+    /// Every provider the names below are looked up in.
+    /// Computed lately by [`Self::setup`] from `declared`.
+    #[arg(skip)]
+    pub catalog: Catalog,
+
+    /// Providers to ask: their names, a version to pin (v1.5), or a set (all,
+    /// default, trust1, trust2, trust3, also low, med, hig); all is every
+    /// provider on the Internet, and local, metadata and node name the rest
     #[arg(
         long,
         value_name("NAME"),
@@ -108,7 +630,7 @@ pub struct OfProviders {
     /// The providers this run will actually ask.
     /// Computed lately by [`Self::setup`] from `enable` and `disable`.
     #[arg(skip)]
-    pub enabled: Vec<HttpProvider>,
+    pub enabled: Vec<Provider>,
 
     /// Ask every provider every round, whatever rate limit it publishes
     #[arg(
@@ -136,7 +658,92 @@ pub struct OfProviders {
         env(concatcp!(ENV_PREFIX, "RATE_LIMIT")),
         hide_env=true,
     )]
-    pub rate_limit: Vec<(HttpProvider, StdDuration)>,
+    pub rate_limit: Vec<(String, StdDuration)>,
+
+    /// The gaps of `rate_limit`, with the providers they name looked up.
+    /// Computed lately by [`Self::setup`].
+    #[arg(skip)]
+    pub gaps: Vec<(Provider, StdDuration)>,
+
+    /// How old the last answer of a provider serving out its gap may be and
+    /// still be weighed in the rounds that skip it, zero never to [default: 5m]
+    #[arg(
+        long,
+        value_name("DURATION"),
+        help_heading="Providers",
+        value_parser=parse_duration,
+        env(concatcp!(ENV_PREFIX, "CARRY_FORWARD")),
+        hide_env=true,
+    )]
+    pub carry_forward: Option<StdDuration>,
+
+    /// Times a provider that gave no answer is asked again within a round,
+    /// zero to ask it once; one that asks for a gap is never asked again
+    #[arg(
+        long,
+        value_name("KEY=COUNT"),
+        value_delimiter=',',
+        help_heading="Providers",
+        value_parser=Self::parse_retries_pair,
+        env(concatcp!(ENV_PREFIX, "RETRIES")),
+        hide_env=true,
+    )]
+    pub retries: Vec<(String, u32)>,
+
+    /// How long the first retry waits, each one after it twice as long as
+    /// the one before [default: 250ms]
+    #[arg(
+        long,
+        value_name("DURATION"),
+        help_heading="Providers",
+        value_parser=Self::parse_retry_backoff,
+        env(concatcp!(ENV_PREFIX, "RETRY_BACKOFF")),
+        hide_env=true,
+    )]
+    pub retry_backoff: Option<StdDuration>,
+
+    /// Error types a retry is made after, or none [default: timeout,unreachable]
+    #[arg(
+        long,
+        value_name("TYPE"),
+        value_delimiter=',',
+        help_heading="Providers",
+        value_parser=Self::parse_retry_kind,
+        env(concatcp!(ENV_PREFIX, "RETRY_ON")),
+        hide_env=true,
+    )]
+    pub retry_on: Vec<Option<&'static str>>,
+
+    /// Rounds in a row a provider may fail with an error type before it is
+    /// no longer asked, or none [default: timeout=10,unreachable=10,not_public=3]
+    #[allow(clippy::doc_markdown, reason = "this doc comment is CLI help text")]
+    #[arg(
+        long,
+        value_name("TYPE=COUNT"),
+        value_delimiter=',',
+        help_heading="Providers",
+        value_parser=Self::parse_break_trip,
+        env(concatcp!(ENV_PREFIX, "BREAK_AFTER")),
+        hide_env=true,
+    )]
+    pub break_after: Vec<Option<(&'static str, u32)>>,
+
+    /// How long a provider that is no longer asked is left alone before it
+    /// is asked once more [default: 15m]
+    #[arg(
+        long,
+        value_name("DURATION"),
+        help_heading="Providers",
+        value_parser=Self::parse_break_cool_down,
+        env(concatcp!(ENV_PREFIX, "BREAK_COOL_DOWN")),
+        hide_env=true,
+    )]
+    pub break_cool_down: Option<StdDuration>,
+
+    /// The counts of `retries`, with the providers they name looked up.
+    /// Computed lately by [`Self::setup`].
+    #[arg(skip)]
+    pub retry_counts: Vec<(Provider, u32)>,
 
     /// Custom trust factors of providers (1 - low, 2 - medium, 3 - high)
     #[arg(
@@ -149,7 +756,12 @@ pub struct OfProviders {
         env(concatcp!(ENV_PREFIX, "TRUST_FACTOR")),
         hide_env=true,
     )]
-    pub trust_factor: Vec<(HttpProvider, usize)>,
+    pub trust_factor: Vec<(String, usize)>,
+
+    /// The trust factors this run works with, `trust_factor` applied.
+    /// Computed lately by [`Self::setup`].
+    #[arg(skip)]
+    tfa: TrustFactorAuthority,
 }
 
 impl OfProviders {
@@ -166,7 +778,36 @@ impl OfProviders {
     ///   --enable A B       ->  exactly A and B, deprecated
     ///   --disable A        ->  the default set without A, deprecated
     /// ```
+    ///
+    /// Names are looked up only here, once the providers file has been read,
+    /// so a declared provider is named exactly like a compiled-in one.
     pub fn setup(&mut self) -> Result<()> {
+        self.catalog = self.declared.load()?;
+
+        for (name, trust_factor) in &self.trust_factor {
+            self.tfa.set_trust_factor(self.find(name)?, *trust_factor);
+        }
+
+        self.gaps = (self.rate_limit.iter())
+            .map(|(name, gap)| Ok((self.find(name)?, *gap)))
+            .collect::<Result<_>>()?;
+
+        self.retry_counts = (self.retries.iter())
+            .map(|(name, count)| Ok((self.find(name)?, *count)))
+            .collect::<Result<_>>()?;
+
+        for (provider, _) in &self.retry_counts {
+            let gap = self.gaps.iter().find(|(paced, _)| paced == provider);
+            let gap = gap.map_or_else(|| provider.rate_limit(), |(_, gap)| Some(*gap));
+
+            if !self.ignore_rate_limits && gap.is_some_and(|gap| !gap.is_zero()) {
+                warn!(
+                    %provider,
+                    "provider asks for a gap between requests, it is never asked again within a round",
+                );
+            }
+        }
+
         let deprecated = !self.enable.is_empty() || !self.disable.is_empty();
 
         ensure!(
@@ -185,6 +826,14 @@ impl OfProviders {
         };
 
         ensure!(!enabled.is_empty(), "at least one provider must be enabled");
+
+        // Only a provider named outright gets this far without its key: no
+        // set, nor leaving the flags alone, takes one in before it has it.
+        if let Some(keyless) = enabled.iter().find(|p| !self.catalog.can_ask(**p)) {
+            let name = keyless.name();
+            bail!("provider {name} needs a key, give it with --provider-key {name}=FILE");
+        }
+
         self.enabled = enabled;
 
         Ok(())
@@ -202,21 +851,43 @@ impl OfProviders {
         })
     }
 
+    /// The error types a retry is made after: those `--retry-on` names, none
+    /// when it says so, and the usual ones when it is left alone.
+    pub fn retry_kinds(&self) -> Vec<&'static str> {
+        if self.retry_on.is_empty() {
+            return pubip::RETRY_ON.to_vec();
+        }
+
+        self.retry_on.iter().flatten().copied().collect()
+    }
+
+    /// When a provider's breaker opens: after the failures `--break-after`
+    /// names, never when it says none, and after the usual ones when it is
+    /// left alone.
+    pub fn break_trips(&self) -> Vec<(&'static str, u32)> {
+        if self.break_after.is_empty() {
+            return pubip::BREAK_TRIPS.to_vec();
+        }
+
+        self.break_after.iter().flatten().copied().collect()
+    }
+
     /// The trust factors this run works with, the operator's overrides applied.
     pub fn trust_authority(&self) -> TrustFactorAuthority {
-        let mut tfa = TrustFactorAuthority::default();
-        for (provider, trust_factor) in &self.trust_factor {
-            tfa.set_trust_factor(*provider, *trust_factor);
-        }
+        self.tfa.clone()
+    }
 
-        tfa
+    fn find(&self, name: &str) -> Result<Provider> {
+        self.catalog
+            .find(name)
+            .ok_or_else(|| anyhow!("provider {name} not found"))
     }
 
     /// Unions what every name stands for, in the order they were given.
     ///
     /// A version is exclusive: pinning means reproducing exactly what that
     /// release asked, and a pin that can be amended is not one.
-    fn resolve_tokens(&self) -> Result<Vec<HttpProvider>> {
+    fn resolve_tokens(&self) -> Result<Vec<Provider>> {
         let tfa = self.trust_authority();
         let pinned = self.providers.iter().find(|token| match token {
             Token::Set(set) => set.pins(),
@@ -239,13 +910,18 @@ impl OfProviders {
         let mut enabled = Vec::new();
         for token in &self.providers {
             match token {
-                Token::Provider(provider) => push_once(&mut enabled, *provider),
+                Token::Provider(name) => {
+                    let provider = (self.catalog.find(name))
+                        .ok_or_else(|| anyhow!("{}", Rejected::Unknown(name.clone())))?;
+
+                    push_once(&mut enabled, provider);
+                }
                 Token::Set(set) => {
-                    for provider in set.members(&tfa) {
+                    for provider in set.members(self.catalog, &tfa) {
                         push_once(&mut enabled, provider);
                     }
 
-                    for skipped in set.skipped(&tfa) {
+                    for skipped in set.skipped(self.catalog, &tfa) {
                         warn!(
                             provider = %skipped,
                             "provider is off by default and no set enables it, name it outright",
@@ -258,21 +934,27 @@ impl OfProviders {
         Ok(enabled)
     }
 
-    fn resolve_deprecated(&self) -> Vec<HttpProvider> {
+    // The deprecated flags only ever knew the compiled-in providers. Left
+    // alone, they still ask the declared ones that are on by default, which
+    // is what leaving them alone has always meant.
+    fn resolve_deprecated(&self) -> Vec<Provider> {
         let by_default = self.enable.is_empty();
 
-        let base: &[HttpProvider] = if by_default {
-            <HttpProvider as VariantArray>::VARIANTS
+        let base: Vec<Provider> = if by_default {
+            self.catalog.all().collect()
         } else {
-            &self.enable
+            self.enable.iter().copied().map(Provider::Http).collect()
         };
 
+        let disabled: Vec<Provider> = self.disable.iter().copied().map(Provider::Http).collect();
+
         let mut enabled = Vec::with_capacity(base.len());
         for provider in base {
-            let wanted = !by_default || provider.enabled_by_default();
+            let wanted =
+                !by_default || (provider.enabled_by_default() && self.catalog.can_ask(provider));
 
-            if wanted && !self.disable.contains(provider) {
-                push_once(&mut enabled, *provider);
+            if wanted && !disabled.contains(&provider) {
+                push_once(&mut enabled, provider);
             }
         }
 
@@ -285,7 +967,7 @@ impl OfProviders {
 
     // https://docs.rs/clap/latest/clap/_derive/_cookbook/typed_derive/index.html
     // https://github.com/clap-rs/clap/blob/f45a32ec/examples/typed-derive.rs#L26
-    pub fn parse_trust_factor_pair(s: &str) -> Result<(HttpProvider, usize)> {
+    pub fn parse_trust_factor_pair(s: &str) -> Result<(String, usize)> {
         const MIN: usize = TrustFactorAuthority::LOW;
         const MAX: usize = TrustFactorAuthority::HIG;
 
@@ -302,24 +984,72 @@ impl OfProviders {
     /// A gap of zero is meaningful and kept: it lifts a published rate limit
     /// rather than restoring it, which is the escape hatch for a provider whose
     /// stated limit has moved on without this table.
-    pub fn parse_rate_limit_pair(s: &str) -> Result<(HttpProvider, StdDuration)> {
+    pub fn parse_rate_limit_pair(s: &str) -> Result<(String, StdDuration)> {
         let (provider, value) = Self::split_pair(s)?;
         let gap = parse_duration(value).map_err(Error::msg)?;
 
         Ok((provider, gap))
     }
 
-    fn split_pair(s: &str) -> Result<(HttpProvider, &str)> {
+    pub fn parse_retries_pair(s: &str) -> Result<(String, u32)> {
+        let (provider, value) = Self::split_pair(s)?;
+        let count = u32::from_str(value.trim())
+            .with_context(|| format!("`{}` is not a number of retries", value.trim()))?;
+
+        Ok((provider, count))
+    }
+
+    pub fn parse_retry_backoff(s: &str) -> Result<StdDuration> {
+        let backoff = parse_duration(s).map_err(Error::msg)?;
+        ensure!(
+            !backoff.is_zero(),
+            "a retry waits some time before it is made"
+        );
+
+        Ok(backoff)
+    }
+
+    pub fn parse_retry_kind(s: &str) -> Result<Option<&'static str>> {
+        if s.trim().eq_ignore_ascii_case("none") {
+            return Ok(None);
+        }
+
+        pubip::parse_retry_kind(s).map(Some).map_err(Error::msg)
+    }
+
+    pub fn parse_break_trip(s: &str) -> Result<Option<(&'static str, u32)>> {
+        if s.trim().eq_ignore_ascii_case("none") {
+            return Ok(None);
+        }
+
+        pubip::parse_break_trip(s).map(Some).map_err(Error::msg)
+    }
+
+    pub fn parse_break_cool_down(s: &str) -> Result<StdDuration> {
+        let cool_down = parse_duration(s).map_err(Error::msg)?;
+        ensure!(
+            !cool_down.is_zero(),
+            "a provider that keeps failing is left alone for some time"
+        );
+
+        Ok(cool_down)
+    }
+
+    // The name is only looked up by [`Self::setup`], once the providers file
+    // has been read; here it only has to be there.
+    fn split_pair(s: &str) -> Result<(String, &str)> {
         let pos = s
             .find('=')
             .or_else(|| s.find(':'))
             .ok_or_else(|| anyhow!("invalid KEY=VALUE: no `=` found in `{s}`"))?;
 
-        let name = &s[..pos];
-        let provider =
-            HttpProvider::from_str(name).map_err(|_| anyhow!("provider {name} not found"))?;
+        let name = s[..pos].trim();
+        ensure!(
+            !name.is_empty(),
+            "invalid KEY=VALUE: no provider named in `{s}`"
+        );
 
-        Ok((provider, &s[pos + 1..]))
+        Ok((name.to_owned(), &s[pos + 1..]))
     }
 }
 
@@ -327,13 +1057,17 @@ impl OfProviders {
 mod tests {
     use super::*;
 
-    fn resolve(of: OfProviders) -> Result<Vec<HttpProvider>> {
+    fn http(providers: &[HttpProvider]) -> Vec<Provider> {
+        providers.iter().copied().map(Provider::Http).collect()
+    }
+
+    fn resolve(of: OfProviders) -> Result<Vec<Provider>> {
         let mut of = of;
         of.setup()?;
         Ok(of.enabled)
     }
 
-    fn asked(enable: &[HttpProvider], disable: &[HttpProvider]) -> Result<Vec<HttpProvider>> {
+    fn asked(enable: &[HttpProvider], disable: &[HttpProvider]) -> Result<Vec<Provider>> {
         resolve(OfProviders {
             enable: enable.to_vec(),
             disable: disable.to_vec(),
@@ -341,7 +1075,7 @@ mod tests {
         })
     }
 
-    fn must_ask(enable: &[HttpProvider], disable: &[HttpProvider]) -> Vec<HttpProvider> {
+    fn must_ask(enable: &[HttpProvider], disable: &[HttpProvider]) -> Vec<Provider> {
         asked(enable, disable).expect("this combination must leave a provider standing")
     }
 
@@ -355,14 +1089,14 @@ mod tests {
             .collect()
     }
 
-    fn named(names: &[&str]) -> Result<Vec<HttpProvider>> {
+    fn named(names: &[&str]) -> Result<Vec<Provider>> {
         resolve(OfProviders {
             providers: tokens(names),
             ..OfProviders::default()
         })
     }
 
-    fn must_name(names: &[&str]) -> Vec<HttpProvider> {
+    fn must_name(names: &[&str]) -> Vec<Provider> {
         named(names).unwrap_or_else(|err| panic!("{names:?} must resolve: {err}"))
     }
 
@@ -370,13 +1104,13 @@ mod tests {
     fn nothing_given_asks_the_providers_enabled_by_default() {
         let enabled = must_ask(&[], &[]);
 
-        assert!(!enabled.contains(&HttpProvider::HttpBin));
-        assert!(enabled.contains(&HttpProvider::Ipify));
+        assert!(!enabled.contains(&HttpProvider::HttpBin.into()));
+        assert!(enabled.contains(&HttpProvider::Ipify.into()));
 
         assert!(enabled.iter().all(|p| p.enabled_by_default()));
         assert_eq!(
             enabled.len(),
-            <HttpProvider as VariantArray>::VARIANTS
+            <HttpProvider as strum::VariantArray>::VARIANTS
                 .iter()
                 .filter(|p| p.enabled_by_default())
                 .count(),
@@ -386,15 +1120,15 @@ mod tests {
     #[test]
     fn enable_replaces_the_default_set_rather_than_adding_to_it() {
         let enabled = must_ask(&[HttpProvider::HttpBin], &[]);
-        assert_eq!(enabled, vec![HttpProvider::HttpBin]);
+        assert_eq!(enabled, http(&[HttpProvider::HttpBin]));
     }
 
     #[test]
     fn disable_subtracts_from_the_default_set() {
         let enabled = must_ask(&[], &[HttpProvider::Ipify]);
 
-        assert!(!enabled.contains(&HttpProvider::Ipify));
-        assert!(enabled.contains(&HttpProvider::SeeIp));
+        assert!(!enabled.contains(&HttpProvider::Ipify.into()));
+        assert!(enabled.contains(&HttpProvider::SeeIp.into()));
     }
 
     #[test]
@@ -404,38 +1138,38 @@ mod tests {
             &[HttpProvider::HttpBin],
         );
 
-        assert_eq!(enabled, vec![HttpProvider::MyIpWtf]);
+        assert_eq!(enabled, http(&[HttpProvider::MyIpWtf]));
     }
 
     #[test]
     fn a_provider_named_twice_is_asked_once() {
         let enabled = must_ask(&[HttpProvider::MyIpWtf, HttpProvider::MyIpWtf], &[]);
-        assert_eq!(enabled, vec![HttpProvider::MyIpWtf]);
+        assert_eq!(enabled, http(&[HttpProvider::MyIpWtf]));
     }
 
     #[test]
     fn providers_replaces_the_default_set() {
         assert_eq!(
             must_name(&["HttpBin", "SeeIp"]),
-            vec![HttpProvider::HttpBin, HttpProvider::SeeIp],
+            http(&[HttpProvider::HttpBin, HttpProvider::SeeIp]),
         );
     }
 
     #[test]
     fn providers_named_twice_is_asked_once() {
-        assert_eq!(must_name(&["SeeIp", "seeip"]), vec![HttpProvider::SeeIp]);
+        assert_eq!(must_name(&["SeeIp", "seeip"]), http(&[HttpProvider::SeeIp]));
     }
 
     #[test]
     fn a_set_and_a_provider_beside_it_are_unioned() {
         let enabled = must_name(&["hig", "HttpBin"]);
-        assert_eq!(enabled, vec![HttpProvider::Ipify, HttpProvider::HttpBin]);
+        assert_eq!(enabled, http(&[HttpProvider::Ipify, HttpProvider::HttpBin]));
     }
 
     #[test]
     fn a_set_never_reaches_a_provider_that_is_off_by_default() {
-        assert!(!must_name(&["trust1"]).contains(&HttpProvider::HttpBin));
-        assert!(must_name(&["all"]).contains(&HttpProvider::HttpBin));
+        assert!(!must_name(&["trust1"]).contains(&HttpProvider::HttpBin.into()));
+        assert!(must_name(&["all"]).contains(&HttpProvider::HttpBin.into()));
     }
 
     #[test]
@@ -472,7 +1206,7 @@ mod tests {
 
     #[test]
     fn leaving_nothing_enabled_is_an_error() {
-        let disable: Vec<_> = <HttpProvider as VariantArray>::VARIANTS.to_vec();
+        let disable: Vec<_> = <HttpProvider as strum::VariantArray>::VARIANTS.to_vec();
         assert!(asked(&[], &disable).is_err());
     }
 
@@ -485,9 +1219,12 @@ mod tests {
         }
 
         let parse = |args: &[&str]| {
-            <Wrapper as clap::Parser>::try_parse_from(args)
+            let mut providers = <Wrapper as clap::Parser>::try_parse_from(args)
                 .expect("these arguments must parse")
-                .providers
+                .providers;
+
+            providers.setup().expect("these providers must resolve");
+            providers
         };
 
         let commas = parse(&["fckloud", "--enable", "HttpBin,MyIpWtf"]);
@@ -498,28 +1235,365 @@ mod tests {
         assert_eq!(commas.enable, repeats.enable);
 
         let factors = parse(&["fckloud", "--trust-factor", "HttpBin=3,MyIpWtf=1"]);
-        assert_eq!(
-            factors.trust_factor,
-            vec![(HttpProvider::HttpBin, 3), (HttpProvider::MyIpWtf, 1)],
-        );
+        let tfa = factors.trust_authority();
+        assert_eq!(tfa.trust_factor(HttpProvider::HttpBin), 3);
+        assert_eq!(tfa.trust_factor(HttpProvider::MyIpWtf), 1);
 
         let gaps = parse(&["fckloud", "--rate-limit", "MyIpWtf=90s,Ipify=0s"]);
         assert_eq!(
-            gaps.rate_limit,
+            gaps.gaps,
             vec![
-                (HttpProvider::MyIpWtf, StdDuration::from_secs(90)),
-                (HttpProvider::Ipify, StdDuration::ZERO),
+                (HttpProvider::MyIpWtf.into(), StdDuration::from_secs(90)),
+                (HttpProvider::Ipify.into(), StdDuration::ZERO),
             ],
         );
     }
 
+    #[test]
+    fn both_families_are_resolved_unless_one_is_named() {
+        #[derive(clap::Parser)]
+        struct Wrapper {
+            #[command(flatten)]
+            network: OfNetwork,
+        }
+
+        let parse = |args: &[&str]| {
+            <Wrapper as clap::Parser>::try_parse_from(args)
+                .expect("these arguments must parse")
+                .network
+                .families
+        };
+
+        assert_eq!(parse(&["fckloud"]), Family::ALL);
+        assert_eq!(parse(&["fckloud", "--families", "ipv6"]), [Family::V6]);
+        assert_eq!(parse(&["fckloud", "--families", "v4,v6"]), Family::ALL);
+    }
+
+    fn uplinks(tokens: &[&str], families: &[Family]) -> Result<Vec<Uplink>> {
+        let mut network = OfNetwork {
+            families: families.to_vec(),
+            uplinks: tokens
+                .iter()
+                .map(|token| {
+                    OfNetwork::parse_uplink_token(token)
+                        .unwrap_or_else(|err| panic!("`{token}` must parse: {err}"))
+                })
+                .collect(),
+            ..OfNetwork::default()
+        };
+
+        network.setup()?;
+        Ok(network.resolved_uplinks)
+    }
+
+    #[test]
+    fn nothing_given_resolves_over_the_default_route() {
+        let resolved = uplinks(&[], &Family::ALL).expect("no uplinks must resolve");
+        assert_eq!(resolved, [Uplink::Default]);
+    }
+
+    #[test]
+    fn source_addresses_are_resolved_each_on_its_own() {
+        let resolved = uplinks(
+            &["203.0.113.7", "198.51.100.9", "203.0.113.7"],
+            &Family::ALL,
+        )
+        .expect("two source addresses must resolve");
+
+        assert_eq!(resolved.len(), 2);
+    }
+
+    #[test]
+    fn default_and_auto_stand_alone() {
+        assert!(uplinks(&["default", "203.0.113.7"], &Family::ALL).is_err());
+        assert!(uplinks(&["auto", "default"], &Family::ALL).is_err());
+    }
+
+    #[test]
+    fn an_uplink_outside_every_family_asked_for_is_refused() {
+        assert!(uplinks(&["2001:db8::7"], &[Family::V4]).is_err());
+        assert!(uplinks(&["2001:db8::7"], &[Family::V6]).is_ok());
+    }
+
+    #[test]
+    fn a_proxy_is_http_its_auth_a_pair_and_its_exemptions_known_providers() {
+        let path = std::env::temp_dir().join(format!("fckloud-proxy-auth-{}", std::process::id()));
+        let proxied = |proxy: &str, auth: Option<&str>| {
+            if let Some(auth) = auth {
+                fs::write(&path, auth).expect("temp dir must be writable");
+            }
+
+            OfNetwork {
+                proxy: Some(Url::parse(proxy).expect("test URL must parse")),
+                proxy_auth_file: auth.map(|_| path.clone()),
+                ..OfNetwork::default()
+            }
+            .proxy()
+        };
+
+        assert!(
+            proxied("http://proxy.corp:3128", Some("fckloud:s3cr3t\n"))
+                .expect("the proxy and its auth are fine")
+                .is_some()
+        );
+        assert!(proxied("http://proxy.corp:3128", Some("s3cr3t")).is_err());
+        assert!(proxied("socks5://proxy.corp:1080", None).is_err());
+        let _ = fs::remove_file(&path);
+
+        let mut providers = OfProviders {
+            providers: tokens(&["default"]),
+            ..OfProviders::default()
+        };
+        providers.setup().expect("the default set resolves");
+        let tfa = providers.trust_authority();
+
+        let exempting = |names: &[&str]| OfNetwork {
+            families: Family::ALL.to_vec(),
+            proxy_exempt: names.iter().map(|name| (*name).to_owned()).collect(),
+            resolved_uplinks: vec![Uplink::Default],
+            ..OfNetwork::default()
+        };
+        assert!(
+            exempting(&["Ipify", "hetzner"])
+                .resolvers(&providers, &tfa)
+                .is_ok()
+        );
+        assert!(exempting(&["Nope"]).resolvers(&providers, &tfa).is_err());
+    }
+
+    #[test]
+    fn a_resolved_host_takes_the_bare_addresses_that_follow_it() {
+        let resolving = |parts: &[&str]| {
+            OfNetwork {
+                resolve: parts
+                    .iter()
+                    .map(|part| OfNetwork::parse_resolve_part(part).expect("test part must parse"))
+                    .collect(),
+                ..OfNetwork::default()
+            }
+            .overrides()
+        };
+
+        let overrides = resolving(&[
+            "API.ipify.org=104.26.12.205",
+            "104.26.13.205",
+            "2606:4700:20::681a:ccd",
+            "ipinfo.io.=34.117.59.81",
+        ])
+        .expect("every address follows a host");
+        assert_eq!(overrides["api.ipify.org"].len(), 3);
+        assert_eq!(overrides["ipinfo.io"].len(), 1);
+
+        assert!(resolving(&["104.26.13.205", "api.ipify.org=104.26.12.205"]).is_err());
+
+        for wrong in [
+            "api.ipify.org=",
+            "api.ipify.org=nope",
+            "=1.1.1.1",
+            "a/b=1.1.1.1",
+        ] {
+            assert!(
+                OfNetwork::parse_resolve_part(wrong).is_err(),
+                "`{wrong}` is taken"
+            );
+        }
+    }
+
+    #[test]
+    fn a_retry_is_counted_per_provider_and_made_after_the_error_types_named() {
+        assert!(OfProviders::parse_retries_pair("HttpBin=2").is_ok());
+        assert!(OfProviders::parse_retries_pair("HttpBin=-1").is_err());
+        assert!(OfProviders::parse_retries_pair("HttpBin=often").is_err());
+        assert!(OfProviders::parse_retry_backoff("0s").is_err());
+        assert!(OfProviders::parse_retry_kind("flaky").is_err());
+
+        let kinds = |names: &[&str]| {
+            OfProviders {
+                retry_on: names
+                    .iter()
+                    .map(|name| OfProviders::parse_retry_kind(name).expect("test type must parse"))
+                    .collect(),
+                ..OfProviders::default()
+            }
+            .retry_kinds()
+        };
+
+        assert_eq!(kinds(&[]), pubip::RETRY_ON);
+        assert_eq!(
+            kinds(&["Timeout", "http_status"]),
+            ["timeout", "http_status"]
+        );
+        assert!(kinds(&["none"]).is_empty());
+    }
+
+    #[test]
+    fn a_breaker_opens_after_the_failures_named_or_never() {
+        assert!(OfProviders::parse_break_trip("unreachable").is_err());
+        assert!(OfProviders::parse_break_cool_down("0s").is_err());
+
+        let trips = |names: &[&str]| {
+            OfProviders {
+                break_after: names
+                    .iter()
+                    .map(|name| OfProviders::parse_break_trip(name).expect("test trip must parse"))
+                    .collect(),
+                ..OfProviders::default()
+            }
+            .break_trips()
+        };
+
+        assert_eq!(trips(&[]), pubip::BREAK_TRIPS);
+        assert_eq!(trips(&["Timeout=4"]), [("timeout", 4)]);
+        assert!(trips(&["none"]).is_empty());
+    }
+
     #[test]
     fn a_rate_limit_needs_a_known_provider_and_a_duration() {
         assert!(OfProviders::parse_rate_limit_pair("MyIpWtf=1m").is_ok());
         assert!(OfProviders::parse_rate_limit_pair("MyIpWtf:1m").is_ok());
 
         assert!(OfProviders::parse_rate_limit_pair("MyIpWtf").is_err());
-        assert!(OfProviders::parse_rate_limit_pair("Nope=1m").is_err());
+        assert!(OfProviders::parse_rate_limit_pair("=1m").is_err());
         assert!(OfProviders::parse_rate_limit_pair("MyIpWtf=soon").is_err());
+
+        // Only known once the providers file has been read.
+        let unknown = OfProviders {
+            rate_limit: vec![OfProviders::parse_rate_limit_pair("Nope=1m").expect("it parses")],
+            ..OfProviders::default()
+        };
+        assert!(resolve(unknown).is_err());
+    }
+
+    // A file per test and per run, so tests running side by side never read
+    // each other's.
+    fn declaring(test: &str, yaml: &str) -> OfCatalog {
+        let path = std::env::temp_dir().join(format!("fckloud-{}-{test}.yaml", std::process::id()));
+        std::fs::write(&path, yaml).expect("the providers file must be writable");
+
+        OfCatalog {
+            providers_file: Some(path),
+            ..OfCatalog::default()
+        }
+    }
+
+    const DECLARED: &str = "
+providers:
+  - { name: Intranet, uri: 'https://echo.example.net/', response: text, trust_factor: 3 }
+  - { name: Spare, uri: 'https://spare.example.net/', response: text, enabled_by_default: false }
+";
+
+    #[test]
+    fn a_declared_provider_is_named_like_a_built_in_one() {
+        let mut of = OfProviders {
+            declared: declaring("named", DECLARED),
+            providers: tokens(&["intranet", "spare", "Ipify"]),
+            trust_factor: vec![("Spare".to_owned(), 2)],
+            rate_limit: vec![("INTRANET".to_owned(), StdDuration::from_secs(30))],
+            ..OfProviders::default()
+        };
+
+        of.setup().expect("declared names must resolve");
+
+        let names: Vec<&str> = of.enabled.iter().map(|p| p.name()).collect();
+        assert_eq!(names, ["Intranet", "Spare", "Ipify"]);
+
+        assert_eq!(of.trust_authority().trust_factor(of.enabled[0]), 3);
+        assert_eq!(of.trust_authority().trust_factor(of.enabled[1]), 2);
+        assert_eq!(of.gaps, [(of.enabled[0], StdDuration::from_secs(30))]);
+    }
+
+    #[test]
+    fn a_declared_provider_joins_the_sets_but_never_a_pinned_one() {
+        let resolve_with = |test, names: &[&str]| -> Vec<&'static str> {
+            let mut of = OfProviders {
+                declared: declaring(test, DECLARED),
+                providers: tokens(names),
+                ..OfProviders::default()
+            };
+
+            of.setup().expect("these names must resolve");
+            of.enabled.iter().map(|p| p.name()).collect()
+        };
+
+        assert!(resolve_with("default", &["default"]).contains(&"Intranet"));
+        assert!(!resolve_with("default", &["default"]).contains(&"Spare"));
+        assert!(resolve_with("hig", &["hig"]).contains(&"Intranet"));
+        assert!(resolve_with("all", &["all"]).contains(&"Spare"));
+        assert!(!resolve_with("pinned", &["v1.10"]).contains(&"Intranet"));
+    }
+
+    #[test]
+    fn a_provider_url_replaces_the_endpoint_and_nothing_else() {
+        let pair = |s: &str| {
+            OfCatalog::parse_provider_url_pair(s).unwrap_or_else(|err| panic!("`{s}`: {err}"))
+        };
+
+        assert!(OfCatalog::parse_provider_url_pair("HttpBin").is_err());
+        assert!(OfCatalog::parse_provider_url_pair("HttpBin=not a url").is_err());
+
+        let mut of = OfProviders {
+            declared: OfCatalog {
+                provider_url: vec![pair("httpbin=http://10.0.0.7:8080/ip")],
+                ..OfCatalog::default()
+            },
+            providers: tokens(&["HttpBin"]),
+            ..OfProviders::default()
+        };
+
+        of.setup()
+            .expect("a built-in provider may be pointed elsewhere");
+
+        let httpbin = Provider::Http(HttpProvider::HttpBin);
+        assert_eq!(of.enabled, [httpbin]);
+        assert_eq!(of.catalog.request_uri(httpbin), "http://10.0.0.7:8080/ip");
+        assert_eq!(of.trust_authority().trust_factor(httpbin), 1);
+
+        let unknown = OfProviders {
+            declared: OfCatalog {
+                provider_url: vec![pair("Nope=https://x.example.net/")],
+                ..OfCatalog::default()
+            },
+            ..OfProviders::default()
+        };
+        assert!(resolve(unknown).is_err());
+    }
+
+    #[test]
+    fn a_keyed_provider_is_asked_only_once_it_has_its_key() {
+        let ipinfo = Provider::Http(HttpProvider::IpInfo);
+
+        let keyless = OfProviders {
+            providers: tokens(&["IpInfo"]),
+            ..OfProviders::default()
+        };
+        let err = resolve(keyless).expect_err("IpInfo cannot be asked without a key");
+        assert!(err.to_string().contains("--provider-key IpInfo=FILE"));
+
+        assert!(!must_name(&["all"]).contains(&ipinfo));
+
+        // PATH is set wherever tests run, and is as good a key as any here.
+        let mut keyed = OfProviders {
+            declared: OfCatalog {
+                provider_key: vec![("IpInfo".into(), KeySource::Env("PATH".into()))],
+                ..OfCatalog::default()
+            },
+            providers: tokens(&["IpInfo", "all"]),
+            ..OfProviders::default()
+        };
+        keyed.setup().expect("IpInfo has its key");
+        assert!(keyed.enabled.contains(&ipinfo));
+        assert!(keyed.catalog.credential(ipinfo).is_some());
+
+        let duplicate = OfProviders {
+            declared: OfCatalog {
+                provider_key: vec![
+                    ("IpInfo".into(), KeySource::Env("PATH".into())),
+                    ("ipinfo".into(), KeySource::Env("PATH".into())),
+                ],
+                ..OfCatalog::default()
+            },
+            ..OfProviders::default()
+        };
+        assert!(resolve(duplicate).is_err());
     }
 }
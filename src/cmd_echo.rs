@@ -0,0 +1,132 @@
+use {
+    crate::{
+        Executable,
+        build_info::ENV_PREFIX,
+        pubip::{self, Secret},
+    },
+    anyhow::{Context as _, Result},
+    clap::Args as ClapArgs,
+    const_format::concatcp,
+    std::{
+        fs,
+        net::SocketAddr,
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+    tokio::net::TcpListener,
+    tokio_rustls::{
+        TlsAcceptor,
+        rustls::{
+            ServerConfig,
+            crypto::ring::default_provider,
+            pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject as _},
+        },
+    },
+    tracing::info,
+};
+
+/// The list of options for the "echo" command.
+#[derive(ClapArgs)]
+pub struct Args {
+    /// Address and port to answer on
+    #[arg(
+        long,
+        value_name("ADDR"),
+        default_value = "[::]:8080",
+        help_heading = "Echo",
+        env(concatcp!(ENV_PREFIX, "LISTEN")),
+        hide_env=true,
+    )]
+    listen: SocketAddr,
+
+    /// PEM certificate chain to answer over HTTPS with, beside --tls-key
+    #[arg(
+        long,
+        value_name("FILE"),
+        requires("tls_key"),
+        help_heading = "Echo",
+        env(concatcp!(ENV_PREFIX, "TLS_CERT")),
+        hide_env=true,
+    )]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key of the certificate given by --tls-cert
+    #[arg(
+        long,
+        value_name("FILE"),
+        requires("tls_cert"),
+        help_heading = "Echo",
+        env(concatcp!(ENV_PREFIX, "TLS_KEY")),
+        hide_env=true,
+    )]
+    tls_key: Option<PathBuf>,
+
+    /// File holding the key shared with the providers that ask, to sign
+    /// every answer with
+    #[arg(
+        long,
+        value_name("FILE"),
+        help_heading = "Echo",
+        env(concatcp!(ENV_PREFIX, "SECRET_FILE")),
+        hide_env=true,
+    )]
+    secret_file: Option<PathBuf>,
+
+    /// Computed lately by [`Self::setup`] from the two TLS files.
+    #[arg(skip)]
+    tls: Option<TlsAcceptor>,
+
+    /// Read lately by [`Self::setup`] from the secret file.
+    #[arg(skip)]
+    secret: Option<Secret>,
+}
+
+impl Executable for Args {
+    fn setup(mut self) -> Result<Self> {
+        if let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) {
+            self.tls = Some(acceptor(cert, key)?);
+        }
+
+        self.secret = self.secret_file.as_deref().map(Secret::read).transpose()?;
+        Ok(self)
+    }
+
+    async fn run(self) -> Result<()> {
+        let listener = TcpListener::bind(self.listen)
+            .await
+            .with_context(|| format!("cannot listen on {}", self.listen))?;
+
+        info!(
+            listen = %self.listen,
+            tls = self.tls.is_some(),
+            signed = self.secret.is_some(),
+            "echo is answering",
+        );
+
+        pubip::serve_echo(listener, self.tls, self.secret)
+            .await
+            .context("cannot accept a connection")
+    }
+}
+
+/// A TLS acceptor over the certificate chain and key, with the same ring
+/// provider every TLS client in the binary uses.
+fn acceptor(cert: &Path, key: &Path) -> Result<TlsAcceptor> {
+    let read =
+        |path: &Path| fs::read(path).with_context(|| format!("cannot read {}", path.display()));
+
+    let chain = CertificateDer::pem_slice_iter(&read(cert)?)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("cannot parse certificate file {}", cert.display()))?;
+    let key = PrivateKeyDer::from_pem_slice(&read(key)?)
+        .with_context(|| format!("cannot parse key file {}", key.display()))?;
+
+    let config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .expect("ring must support the default TLS versions")
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .context("the certificate and the key do not make a pair")?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
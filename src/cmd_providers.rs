@@ -1,12 +1,11 @@
 use {
     crate::{
-        Executable,
-        pubip::{HttpProvider, TrustFactorAuthority},
+        Executable, args,
+        pubip::{Catalog, Provider, TrustFactorAuthority},
     },
     anyhow::Result,
     clap::Args as ClapArgs,
     humantime::Duration as DisplayedDuration,
-    strum::VariantArray,
 };
 
 /// The list of options for the "providers" command.
@@ -15,39 +14,50 @@ pub struct Args {
     /// Print the providers as JSON instead of prose
     #[arg(long, default_value_t = false)]
     json: bool,
+
+    #[command(flatten)]
+    declared: args::OfCatalog,
+
+    /// Every provider to list. Computed lately by [`Self::setup`].
+    #[arg(skip)]
+    catalog: Catalog,
 }
 
 impl Executable for Args {
-    fn setup(self) -> Result<Self> {
+    fn setup(mut self) -> Result<Self> {
+        self.catalog = self.declared.load()?;
         Ok(self)
     }
 
-    // Everything here is compiled in, so nothing is fetched and nothing can
-    // fail. PROVIDERS.md holds the same information at length.
+    // Everything here is compiled in or read from the providers file, so
+    // nothing is fetched. PROVIDERS.md holds the same information at length.
     async fn run(self) -> Result<()> {
         let tfa = TrustFactorAuthority::default();
 
         if self.json {
-            print!("{}", as_json(&tfa));
+            print!("{}", as_json(self.catalog, &tfa));
             return Ok(());
         }
 
-        for provider in <HttpProvider as VariantArray>::VARIANTS {
-            describe(*provider, &tfa);
+        for provider in self.catalog.all() {
+            describe(provider, self.catalog, &tfa);
         }
 
-        summarise(&tfa);
+        summarise(self.catalog, &tfa);
         Ok(())
     }
 }
 
-fn describe(provider: HttpProvider, tfa: &TrustFactorAuthority) {
+fn describe(provider: Provider, catalog: Catalog, tfa: &TrustFactorAuthority) {
     let rate_limit = provider.rate_limit().map_or_else(
         || "none published".to_owned(),
         |gap| DisplayedDuration::from(gap).to_string(),
     );
 
     println!("{provider}");
+    if provider.is_declared() {
+        println!("  declared as   {}", provider.name());
+    }
     println!("  trust factor  {}", tfa.trust_factor(provider));
     println!(
         "  by default    {}",
@@ -66,8 +76,28 @@ fn describe(provider: HttpProvider, tfa: &TrustFactorAuthority) {
         },
     );
     println!("  rate limit    {rate_limit}");
-    println!("  endpoint      {}", provider.request_uri());
-    println!("  terms         {}", provider.terms());
+    println!("  endpoint      {}", catalog.request_uri(provider));
+    for mirror in catalog.mirrors(provider) {
+        println!("  mirror        {mirror}");
+    }
+    if let Some(placement) = provider.key_placement() {
+        println!(
+            "  key           {placement}, {}",
+            key_state(provider, catalog)
+        );
+    }
+    if let Some(identity) = catalog.identity(provider) {
+        println!("  client cert   {}", identity.cert().display());
+    }
+    match catalog.pinned(provider) {
+        [] => {}
+        [pin] => println!("  pinned        {pin}"),
+        pins => println!("  pinned        {} keys, --json lists them", pins.len()),
+    }
+    println!(
+        "  terms         {}",
+        provider.terms().unwrap_or("none given")
+    );
 
     for line in wrapped(provider.summary(), 68) {
         println!("  {line}");
@@ -76,10 +106,9 @@ fn describe(provider: HttpProvider, tfa: &TrustFactorAuthority) {
     println!();
 }
 
-fn summarise(tfa: &TrustFactorAuthority) {
-    let enabled: Vec<HttpProvider> = <HttpProvider as VariantArray>::VARIANTS
-        .iter()
-        .copied()
+fn summarise(catalog: Catalog, tfa: &TrustFactorAuthority) {
+    let enabled: Vec<Provider> = catalog
+        .all()
         .filter(|provider| provider.enabled_by_default())
         .collect();
 
@@ -88,7 +117,7 @@ fn summarise(tfa: &TrustFactorAuthority) {
     println!(
         "{} of {} providers are asked by default, carrying {total} trust between them.",
         enabled.len(),
-        <HttpProvider as VariantArray>::VARIANTS.len(),
+        catalog.all().count(),
     );
     println!(
         "An address confirms at {}, and at less when fewer of them answer.",
@@ -96,37 +125,68 @@ fn summarise(tfa: &TrustFactorAuthority) {
     );
 }
 
-fn as_json(tfa: &TrustFactorAuthority) -> String {
-    let entries: Vec<String> = <HttpProvider as VariantArray>::VARIANTS
-        .iter()
+fn as_json(catalog: Catalog, tfa: &TrustFactorAuthority) -> String {
+    let entries: Vec<String> = catalog
+        .all()
         .map(|provider| {
             let rate_limit = provider
                 .rate_limit()
                 .map_or_else(|| "null".to_owned(), |gap| gap.as_secs().to_string());
+            let terms = provider.terms().map_or_else(|| "null".to_owned(), quoted);
+            let mirrors: Vec<String> = catalog.mirrors(provider).into_iter().map(quoted).collect();
+            let pins: Vec<String> = catalog
+                .pinned(provider)
+                .iter()
+                .map(|pin| quoted(&pin.to_string()))
+                .collect();
+            let client_cert = catalog.identity(provider).map_or_else(
+                || "null".to_owned(),
+                |identity| quoted(&identity.cert().display().to_string()),
+            );
+            let key = provider.key_placement().map_or_else(
+                || "null".to_owned(),
+                |placement| {
+                    format!(
+                        "{{ \"placement\": {}, \"given\": {} }}",
+                        quoted(&placement.to_string()),
+                        catalog.credential(provider).is_some(),
+                    )
+                },
+            );
 
             format!(
                 concat!(
                     "  {{\n",
-                    "    \"name\": {:?},\n",
-                    "    \"host\": {:?},\n",
-                    "    \"endpoint\": {:?},\n",
-                    "    \"terms\": {:?},\n",
+                    "    \"name\": {},\n",
+                    "    \"host\": {},\n",
+                    "    \"endpoint\": {},\n",
+                    "    \"mirrors\": [{}],\n",
+                    "    \"key\": {},\n",
+                    "    \"client_cert\": {},\n",
+                    "    \"pins\": [{}],\n",
+                    "    \"terms\": {},\n",
+                    "    \"declared\": {},\n",
                     "    \"trust_factor\": {},\n",
                     "    \"enabled_by_default\": {},\n",
                     "    \"ipv6\": {},\n",
                     "    \"rate_limit_seconds\": {},\n",
-                    "    \"summary\": {:?}\n",
+                    "    \"summary\": {}\n",
                     "  }}",
                 ),
-                format!("{provider:?}"),
-                provider.host(),
-                provider.request_uri(),
-                provider.terms(),
-                tfa.trust_factor(*provider),
+                quoted(provider.name()),
+                quoted(provider.host()),
+                quoted(catalog.request_uri(provider)),
+                mirrors.join(", "),
+                key,
+                client_cert,
+                pins.join(", "),
+                terms,
+                provider.is_declared(),
+                tfa.trust_factor(provider),
                 provider.enabled_by_default(),
                 provider.has_ipv6(),
                 rate_limit,
-                provider.summary(),
+                quoted(provider.summary()),
             )
         })
         .collect();
@@ -134,6 +194,21 @@ fn as_json(tfa: &TrustFactorAuthority) -> String {
     format!("[\n{}\n]\n", entries.join(",\n"))
 }
 
+/// Whether the provider has its key, which is all that is ever said of it.
+fn key_state(provider: Provider, catalog: Catalog) -> &'static str {
+    if catalog.credential(provider).is_some() {
+        "given"
+    } else {
+        "not given, so it cannot be asked"
+    }
+}
+
+/// A JSON string. Not `{:?}`: a declared provider's text is the operator's,
+/// and Rust's escapes for what it might hold are not JSON's.
+fn quoted(text: &str) -> String {
+    serde_json::to_string(text).expect("a string always serialises")
+}
+
 /// Breaks a summary onto lines no wider than `width`, so the output stays
 /// readable in a terminal without pulling in a wrapping crate.
 fn wrapped(text: &str, width: usize) -> Vec<String> {
@@ -181,9 +256,10 @@ mod tests {
 
     #[test]
     fn every_provider_appears_in_the_json() {
-        let json = as_json(&TrustFactorAuthority::default());
+        let catalog = Catalog::default();
+        let json = as_json(catalog, &TrustFactorAuthority::default());
 
-        for provider in <HttpProvider as VariantArray>::VARIANTS {
+        for provider in catalog.all() {
             assert!(json.contains(provider.host()), "{provider} is missing");
         }
     }
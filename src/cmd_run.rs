@@ -2,8 +2,8 @@ use {
     crate::{
         Executable, args,
         build_info::ENV_PREFIX,
-        node::{AddrStatus, Manager as NodeManager, Removal},
-        pubip::{Resolver, TrustShare},
+        node::{Addition, AddrStatus, Manager as NodeManager, Removal},
+        pubip::{self, Resolver, TrustShare},
         telemetry::meter,
     },
     anyhow::{Context as _, Error, Result, bail, ensure},
@@ -11,7 +11,11 @@ use {
     const_format::concatcp,
     humantime::{Duration as DisplayedDuration, parse_duration},
     opentelemetry::{KeyValue, metrics::Histogram},
-    std::{sync::LazyLock, time::Duration as StdDuration},
+    std::{
+        collections::BTreeSet,
+        sync::{Arc, LazyLock},
+        time::Duration as StdDuration,
+    },
     tokio::time::{Instant, sleep},
     tracing::{debug, error, info, instrument, warn},
 };
@@ -42,7 +46,8 @@ pub struct Args {
     node: String,
 
     /// How long an unconfirmed ExternalIP is left alone before it is removed,
-    /// or "never" to leave it there
+    /// "never" to leave it there, or "replace[+DURATION]" to also wait until a
+    /// new one in its family is attached
     #[allow(clippy::doc_markdown, reason = "this doc comment is CLI help text")]
     #[arg(
         long,
@@ -54,6 +59,32 @@ pub struct Args {
     )]
     removal_grace: Option<Removal>,
 
+    /// How long a newly confirmed ExternalIP must stay confirmed before it is
+    /// added
+    #[allow(clippy::doc_markdown, reason = "this doc comment is CLI help text")]
+    #[arg(
+        long,
+        value_name("DURATION"),
+        help_heading = "Node",
+        value_parser = parse_duration,
+        env(concatcp!(ENV_PREFIX, "ADDITION_GRACE")),
+        hide_env=true,
+    )]
+    addition_grace: Option<StdDuration>,
+
+    /// Rounds in a row a newly confirmed ExternalIP must be confirmed in
+    /// before it is added
+    #[allow(clippy::doc_markdown, reason = "this doc comment is CLI help text")]
+    #[arg(
+        long,
+        value_name("COUNT"),
+        help_heading = "Node",
+        value_parser = Self::parse_flag_addition_rounds,
+        env(concatcp!(ENV_PREFIX, "ADDITION_ROUNDS")),
+        hide_env=true,
+    )]
+    addition_rounds: Option<usize>,
+
     /// Perform dry run (real node addresses will not be changed)
     #[arg(long, help_heading = "Node")]
     dry_run: bool,
@@ -76,6 +107,9 @@ pub struct Args {
     #[command(flatten)]
     providers: args::OfProviders,
 
+    #[command(flatten)]
+    network: args::OfNetwork,
+
     /// Share of the enabled trust an address must gather: 2/3, 75% or 0.75
     #[arg(
         long,
@@ -126,15 +160,37 @@ impl Args {
 
     // Parser for "--removal-grace" flag. A grace of zero still waits for the
     // rounds that make absence evidence; only the deprecated "--strict" skips
-    // them, which is the one thing it is kept around to keep doing.
+    // them, which is the one thing it is kept around to keep doing. "replace"
+    // waits for a replacement on top, and "replace+5m" for the grace as well.
     fn parse_flag_removal_grace(s: &str) -> Result<Removal> {
         if s.eq_ignore_ascii_case("never") {
             return Ok(Removal::Never);
         }
 
+        let (keyword, grace) = s.split_once('+').unwrap_or((s, ""));
+        if keyword.trim().eq_ignore_ascii_case("replace") {
+            let grace = match grace.trim() {
+                "" => StdDuration::ZERO,
+                grace => parse_duration(grace).map_err(Error::msg)?,
+            };
+            return Ok(Removal::Replace(grace));
+        }
+
         Ok(Removal::After(parse_duration(s).map_err(Error::msg)?))
     }
 
+    // Parser for "--addition-rounds" flag. The round that first confirms an
+    // address is one of them, so one is as soon as it can ever be added.
+    fn parse_flag_addition_rounds(s: &str) -> Result<usize> {
+        let rounds: usize = s.trim().parse()?;
+        ensure!(
+            rounds > 0,
+            "an address is added in the round confirming it at the soonest"
+        );
+
+        Ok(rounds)
+    }
+
     // Parser for "--interval" flag.
     fn parse_flag_interval(s: &str) -> Result<DisplayedDuration> {
         match parse_duration(s).map_err(Error::msg)? {
@@ -149,12 +205,21 @@ impl Args {
 
     // One tick: ask the world where we are, then tell Kubernetes.
     // Its own root span: nothing upstream hands this loop a trace context.
+    //
+    // Every uplink's addresses belong on the node, and silence is evidence
+    // only in a family every uplink answered well: an address behind a quiet
+    // uplink is no less there for it.
     #[instrument(name = "fckloud.tick", skip_all)]
-    async fn job(&self, node: &mut NodeManager, resolver: &Resolver) -> Result<()> {
-        let report = resolver.run().await;
-        let confirmed = report.confirmed.into_iter().collect();
+    async fn job(&self, node: &mut NodeManager, resolvers: &[Arc<Resolver>]) -> Result<()> {
+        let published = node
+            .read_published()
+            .await
+            .context("cannot read the Node")?;
+
+        let reports = pubip::run_all(resolvers, &published).await;
+        let confirmed: BTreeSet<_> = reports.iter().flat_map(pubip::Report::confirmed).collect();
 
-        node.apply(&confirmed, report.well_answered)
+        node.apply(&confirmed, &pubip::well_answered_across(&reports))
             .await
             .context("cannot apply the patch")?
             .into_iter()
@@ -162,6 +227,12 @@ impl Args {
                 AddrStatus::New => info!(?ip_addr, "new ExternalIP has been added"),
                 AddrStatus::Skipped => debug!(?ip_addr, "old ExternalIP is left intact"),
                 AddrStatus::Removed => warn!(?ip_addr, "old ExternalIP has been removed"),
+                AddrStatus::Candidate => {
+                    info!(
+                        ?ip_addr,
+                        "new ExternalIP is confirmed, waiting before it is added"
+                    );
+                }
             });
 
         Ok(())
@@ -172,6 +243,7 @@ impl Executable for Args {
     // The preparation for [run], that adjusts some parameters if they had to.
     fn setup(mut self) -> Result<Self> {
         self.providers.setup()?;
+        self.network.setup()?;
 
         assert!(*self.interval >= Self::MIN_INTERVAL);
         assert!(!self.node.is_empty());
@@ -220,10 +292,7 @@ impl Executable for Args {
         }
 
         let mut node = NodeManager::new(&self.node).await?;
-        let mut resolver = Resolver::new(self.providers.enabled.clone(), tfa)?;
-        resolver
-            .set_rate_limits(self.providers.rate_limit.iter().copied())
-            .set_ignore_rate_limits(self.providers.ignore_rate_limits);
+        let mut resolvers = self.network.resolvers(&self.providers, &tfa)?;
 
         node.current_external_ips()
             .await
@@ -237,14 +306,27 @@ impl Executable for Args {
             self.removal_grace.unwrap_or(Self::DEF_REMOVAL_GRACE)
         };
 
-        node.set_dry_run(self.dry_run).set_removal(removal);
+        let addition = Addition {
+            grace: self.addition_grace.unwrap_or(Addition::AT_ONCE.grace),
+            rounds: self.addition_rounds.unwrap_or(Addition::AT_ONCE.rounds),
+        };
+
+        node.set_dry_run(self.dry_run)
+            .set_removal(removal)
+            .set_addition(addition);
         info!(?removal, "unconfirmed addresses are removed");
+        info!(?addition, "confirmed addresses are added");
 
         if let Some(confirmations) = self.confirmations {
-            resolver.set_confirmations(confirmations);
+            for resolver in &mut resolvers {
+                resolver.set_confirmations(confirmations);
+            }
         }
 
-        resolver.announce(self.providers.pins());
+        let resolvers: Vec<Arc<Resolver>> = resolvers.into_iter().map(Arc::new).collect();
+        for resolver in &resolvers {
+            resolver.announce(self.providers.pins());
+        }
 
         loop {
             let now = Instant::now();
@@ -252,7 +334,7 @@ impl Executable for Args {
 
             // An operator that dies on a hiccup stops operating. The next tick
             // is a better answer to a flaky network than a container restart.
-            let outcome = self.job(&mut node, &resolver).await;
+            let outcome = self.job(&mut node, &resolvers).await;
             let elapsed = now.elapsed();
 
             // The semantic conventions' catch-all: which way a tick failed is
@@ -1,7 +1,8 @@
 use {
-    crate::{Executable, args, pubip::Resolver},
+    crate::{Executable, args, pubip},
     anyhow::Result,
     clap::Args as ClapArgs,
+    std::sync::Arc,
     tracing::info,
 };
 
@@ -10,12 +11,16 @@ use {
 pub struct Args {
     #[command(flatten)]
     providers: args::OfProviders,
+
+    #[command(flatten)]
+    network: args::OfNetwork,
 }
 
 impl Executable for Args {
     // The preparation for [test], that adjusts some parameters if they had to.
     fn setup(mut self) -> Result<Self> {
         self.providers.setup()?;
+        self.network.setup()?;
         Ok(self)
     }
 
@@ -25,19 +30,21 @@ impl Executable for Args {
         let pinned = self.providers.pins();
         let tfa = self.providers.trust_authority();
 
-        let mut resolver = Resolver::new(self.providers.enabled.clone(), tfa)?;
-        resolver
-            .set_rate_limits(self.providers.rate_limit.iter().copied())
-            .set_ignore_rate_limits(self.providers.ignore_rate_limits);
+        let resolvers: Vec<_> = (self.network.resolvers(&self.providers, &tfa)?)
+            .into_iter()
+            .map(Arc::new)
+            .collect();
 
-        resolver.announce(pinned);
+        for resolver in &resolvers {
+            resolver.announce(pinned);
+        }
 
-        resolver
-            .run()
-            .await
-            .confirmed
-            .iter()
-            .for_each(|ip_addr| info!(?ip_addr, "address has been confirmed"));
+        // No Node is read here, so a provider reading one has nothing to say.
+        for report in pubip::run_all(&resolvers, &Arc::default()).await {
+            report.confirmed().for_each(|ip_addr| {
+                info!(?ip_addr, uplink = %report.uplink, "address has been confirmed");
+            });
+        }
 
         Ok(())
     }
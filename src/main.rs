@@ -15,6 +15,7 @@ use tokio::signal::unix::SignalKind;
 
 mod args;
 mod build_info;
+mod cmd_echo;
 mod cmd_providers;
 mod cmd_run;
 mod cmd_test;
@@ -56,6 +57,9 @@ pub enum Command {
     Test(cmd_test::Args),
     /// List the known providers and what is known about them
     Providers(cmd_providers::Args),
+    /// Answer every caller with its own address, as a provider to deploy
+    /// outside the NAT
+    Echo(cmd_echo::Args),
 }
 
 // The interface must be implemented for a type to act as a CLI command.
@@ -127,6 +131,7 @@ impl App {
             Command::Run(run_args) => run_args.setup()?.run().await,
             Command::Test(test_args) => test_args.setup()?.run().await,
             Command::Providers(list_args) => list_args.setup()?.run().await,
+            Command::Echo(echo_args) => echo_args.setup()?.run().await,
         }
     }
 }
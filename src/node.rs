@@ -1,10 +1,11 @@
 mod metrics;
 mod reconcile;
 
-pub use self::reconcile::{AddrStatus, Removal};
+pub use self::reconcile::{Addition, AddrStatus, Removal};
 
 use {
-    self::reconcile::{Pending, new_external_ip, parse_external_ip, reconcile},
+    self::reconcile::{Notice, new_external_ip, parse_external_ip, reconcile},
+    crate::pubip::{Family, Published},
     anyhow::{Context, Error, Result, bail},
     k8s_openapi::api::core::v1::{Node, NodeAddress},
     kube::{
@@ -15,6 +16,7 @@ use {
     std::{
         collections::{BTreeMap, BTreeSet},
         net::IpAddr,
+        sync::Arc,
         time::{Duration, Instant},
     },
     tracing::{Span, debug, field::Empty, instrument, warn},
@@ -28,9 +30,13 @@ pub struct Manager {
 
     dry_run: bool,
     removal: Removal,
+    addition: Addition,
 
     previous: BTreeSet<IpAddr>,
-    pending: BTreeMap<IpAddr, Pending>,
+    notice: Notice,
+
+    /// Kept from every read of the Node, for the providers reading it.
+    published: Arc<Published>,
 }
 
 impl Manager {
@@ -39,11 +45,15 @@ impl Manager {
     /// Connects to the cluster, verifies the connection and that the given node
     /// exists and its status is ours to write. Panics if `node_name` is empty.
     pub async fn new(node_name: &str) -> Result<Self> {
-        assert!(!node_name.is_empty());
-
         let mut config = Config::infer().await?;
         config.connect_timeout = Self::CONNECTION_TIMEOUT.into();
 
+        Self::connect(config, node_name).await
+    }
+
+    async fn connect(config: Config, node_name: &str) -> Result<Self> {
+        assert!(!node_name.is_empty());
+
         let cluster_host = config.cluster_url.host().map(ToString::to_string);
         let client = Client::try_from(config)?;
 
@@ -54,13 +64,15 @@ impl Manager {
             "connected to the Kubernetes cluster"
         );
 
-        let manager = Self {
+        let mut manager = Self {
             api_nodes: Api::all(client),
             node_name: node_name.to_owned(),
             dry_run: false,
             removal: Removal::Never,
+            addition: Addition::AT_ONCE,
             previous: BTreeSet::new(),
-            pending: BTreeMap::new(),
+            notice: Notice::default(),
+            published: Arc::default(),
         };
 
         // Doubles as the "node exists and Nodes API is readable" check.
@@ -79,14 +91,32 @@ impl Manager {
         self
     }
 
-    /// Whether an address is waiting out its grace, and so whether the loop has
-    /// a reason to come back sooner than the interval would.
+    pub fn set_addition(&mut self, addition: Addition) -> &mut Self {
+        self.addition = addition;
+        self
+    }
+
+    /// Reads the Node afresh and hands over the annotations and labels it
+    /// carries, for the providers reading them.
+    ///
+    /// Done before every round rather than left to [`Self::apply`], which a
+    /// round that confirmed nothing never gets to: a Node provider alone would
+    /// otherwise never see an annotation written after startup.
+    pub async fn read_published(&mut self) -> Result<Arc<Published>> {
+        let _ = self.node_addresses().await?;
+        Ok(Arc::clone(&self.published))
+    }
+
+    /// Whether an address is waiting out its grace, on its way off the node
+    /// or onto it, and so whether the loop has a reason to come back sooner
+    /// than the interval would.
     pub fn has_pending(&self) -> bool {
-        !self.pending.is_empty()
+        !self.notice.is_empty()
     }
 
     /// Brings the node's `ExternalIP`s in line with the given addresses and
-    /// reports what changed.
+    /// reports what changed. Only an address in one of the `well_answered`
+    /// families can be on its way out.
     ///
     /// An error is returned if nothing is staged. Every provider being
     /// unreachable says nothing about where the node lives, and stripping its
@@ -95,11 +125,12 @@ impl Manager {
         fckloud.node.added = Empty,
         fckloud.node.kept = Empty,
         fckloud.node.removed = Empty,
+        fckloud.node.candidates = Empty,
     ))]
     pub async fn apply(
         &mut self,
         staged: &BTreeSet<IpAddr>,
-        well_answered: bool,
+        well_answered: &BTreeSet<Family>,
     ) -> Result<BTreeMap<IpAddr, AddrStatus>> {
         if staged.is_empty() {
             bail!("no addresses are staged, the node is left as it is")
@@ -119,12 +150,13 @@ impl Manager {
             current,
             staged,
             self.removal,
+            self.addition,
             well_answered,
-            &self.pending,
+            &self.notice,
             Instant::now(),
         );
 
-        self.pending = outcome.pending;
+        self.notice = outcome.notice;
 
         if outcome.has_changes {
             self.send_patch(outcome.addresses)
@@ -145,7 +177,7 @@ impl Manager {
 
         self.previous = report
             .iter()
-            .filter(|(_, status)| !status.is_removed())
+            .filter(|(_, status)| !status.is_removed() && !status.is_candidate())
             .map(|(external_ip, _)| *external_ip)
             .collect();
 
@@ -153,7 +185,8 @@ impl Manager {
         Span::current()
             .record("fckloud.node.added", tally(AddrStatus::is_new))
             .record("fckloud.node.kept", tally(AddrStatus::is_skipped))
-            .record("fckloud.node.removed", tally(AddrStatus::is_removed));
+            .record("fckloud.node.removed", tally(AddrStatus::is_removed))
+            .record("fckloud.node.candidates", tally(AddrStatus::is_candidate));
 
         metrics::record_report(&report);
 
@@ -161,7 +194,7 @@ impl Manager {
     }
 
     /// The `ExternalIP`s currently attached to the node.
-    pub async fn current_external_ips(&self) -> Result<Vec<IpAddr>> {
+    pub async fn current_external_ips(&mut self) -> Result<Vec<IpAddr>> {
         let it = self
             .node_addresses()
             .await?
@@ -201,9 +234,10 @@ impl Manager {
         patched
     }
 
-    /// Every address on the node, `InternalIP` and `Hostname` included.
+    /// Every address on the node, `InternalIP` and `Hostname` included. What
+    /// the Node publishes besides is kept on the way.
     #[instrument(name = "k8s.node.get", skip_all, fields(otel.kind = "client"))]
-    async fn node_addresses(&self) -> Result<Vec<NodeAddress>> {
+    async fn node_addresses(&mut self) -> Result<Vec<NodeAddress>> {
         let started = Instant::now();
         let node = self
             .api_nodes
@@ -213,7 +247,13 @@ impl Manager {
 
         metrics::record_request("get", started.elapsed(), node.as_ref().err());
 
-        let addrs = node?
+        let node = node?;
+        self.published = Arc::new(Published::new(
+            node.metadata.annotations.unwrap_or_default(),
+            node.metadata.labels.unwrap_or_default(),
+        ));
+
+        let addrs = node
             .status
             .and_then(|status| status.addresses)
             .unwrap_or_default();
@@ -221,3 +261,99 @@ impl Manager {
         Ok(addrs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::pubip::{
+            NodeProvider, Provider, Resolver, TrustFactorAuthority, well_answered_across,
+        },
+        std::sync::atomic::{AtomicUsize, Ordering},
+        tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+        },
+    };
+
+    /// An API server on loopback serving one Node, which carries k3s's
+    /// annotation from the given read of it on.
+    async fn api_stand_in(annotated_from: usize) -> Config {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("loopback must be bindable");
+        let addr = listener.local_addr().expect("it has an address");
+        let reads = AtomicUsize::new(0);
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 4096];
+                let read = stream.read(&mut request).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&request[..read]);
+
+                let body = if request.starts_with("GET /version") {
+                    json!({
+                        "major": "1", "minor": "32", "gitVersion": "v1.32.0",
+                        "gitCommit": "", "gitTreeState": "", "buildDate": "",
+                        "goVersion": "", "compiler": "", "platform": "",
+                    })
+                } else {
+                    let annotated = reads.fetch_add(1, Ordering::Relaxed) + 1 >= annotated_from;
+                    let annotations = if annotated {
+                        json!({ "k3s.io/external-ip": "1.2.3.4" })
+                    } else {
+                        json!({})
+                    };
+                    json!({
+                        "apiVersion": "v1",
+                        "kind": "Node",
+                        "metadata": { "name": "worker", "annotations": annotations },
+                    })
+                }
+                .to_string();
+
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                     content-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len(),
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        Config::new(
+            format!("http://{addr}")
+                .parse()
+                .expect("the stand-in's URL must parse"),
+        )
+    }
+
+    #[tokio::test]
+    async fn an_annotation_written_after_startup_is_read_by_the_next_round() {
+        // Read at startup, then before each of the two rounds below.
+        let mut node = Manager::connect(api_stand_in(3).await, "worker")
+            .await
+            .expect("the stand-in must serve the Node");
+        let resolver = Resolver::new(
+            vec![Provider::Node(NodeProvider::K3s)],
+            TrustFactorAuthority::default(),
+        )
+        .expect("the default share over one provider is valid");
+
+        let published = node.read_published().await.expect("the Node must be read");
+        let report = resolver.run(&published).await;
+        assert_eq!(report.confirmed().count(), 0);
+
+        let well_answered = well_answered_across(std::slice::from_ref(&report));
+        node.apply(&BTreeSet::new(), &well_answered)
+            .await
+            .expect_err("nothing was confirmed to apply");
+
+        let published = node.read_published().await.expect("the Node must be read");
+        let report = resolver.run(&published).await;
+        assert_eq!(
+            report.confirmed().collect::<Vec<_>>(),
+            [IpAddr::from([1, 2, 3, 4])],
+        );
+    }
+}
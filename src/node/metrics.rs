@@ -34,6 +34,14 @@ static EXTERNAL_IPS: LazyLock<Gauge<u64>> = LazyLock::new(|| {
         .build()
 });
 
+static CANDIDATES: LazyLock<Gauge<u64>> = LazyLock::new(|| {
+    meter()
+        .u64_gauge("fckloud.node.candidates")
+        .with_unit("{address}")
+        .with_description("Confirmed addresses still waiting out the addition grace")
+        .build()
+});
+
 /// Records what one call to the API server cost and whether it worked.
 pub fn record_request(operation: &'static str, elapsed: Duration, failure: Option<&Error>) {
     let mut attributes = vec![KeyValue::new("fckloud.k8s.operation", operation)];
@@ -54,19 +62,24 @@ pub fn record_request(operation: &'static str, elapsed: Duration, failure: Optio
 
 /// Records what the reconciliation decided. A rising `removed` is the one
 /// worth waking somebody for: it means addresses are being torn off a live
-/// node, which is what `--strict` does when consensus stops agreeing.
+/// node, which is what `--strict` does when consensus stops agreeing. A
+/// `candidate` that keeps rising without ever being `added` is an address
+/// consensus keeps confirming and then dropping again.
 pub fn record_report(report: &BTreeMap<IpAddr, AddrStatus>) {
     const STATUS: &str = "fckloud.address.status";
 
-    let mut attached = 0;
+    let (mut attached, mut candidates) = (0, 0);
     for status in report.values() {
         let name = match status {
             AddrStatus::New => "added",
             AddrStatus::Skipped => "kept",
             AddrStatus::Removed => "removed",
+            AddrStatus::Candidate => "candidate",
         };
 
-        if !status.is_removed() {
+        if status.is_candidate() {
+            candidates += 1;
+        } else if !status.is_removed() {
             attached += 1;
         }
 
@@ -74,6 +87,7 @@ pub fn record_report(report: &BTreeMap<IpAddr, AddrStatus>) {
     }
 
     EXTERNAL_IPS.record(attached, &[]);
+    CANDIDATES.record(candidates, &[]);
 }
 
 // Coarse on purpose. Which of the API server's many ways to say no this was
@@ -1,4 +1,5 @@
 use {
+    crate::pubip::Family,
     k8s_openapi::api::core::v1::NodeAddress,
     std::{
         collections::{BTreeMap, BTreeSet},
@@ -17,6 +18,8 @@ pub enum AddrStatus {
     New,
     Skipped,
     Removed,
+    /// Confirmed, but not for long enough yet to be put on the node.
+    Candidate,
 }
 
 /// What the node's addresses should become, and what changed getting there.
@@ -24,7 +27,21 @@ pub struct Outcome {
     pub addresses: Vec<NodeAddress>,
     pub report: BTreeMap<IpAddr, AddrStatus>,
     pub has_changes: bool,
+    pub notice: Notice,
+}
+
+/// The addresses on notice from one round to the next: those on their way
+/// off the node, and those on their way onto it.
+#[derive(Clone, Default, Debug)]
+pub struct Notice {
     pub pending: BTreeMap<IpAddr, Pending>,
+    pub candidates: BTreeMap<IpAddr, Candidate>,
+}
+
+impl Notice {
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty() && self.candidates.is_empty()
+    }
 }
 
 /// How long an address consensus stopped vouching for has been on notice.
@@ -34,6 +51,32 @@ pub struct Pending {
     pub misses: usize,
 }
 
+/// How long an address consensus vouches for has been waiting to be put on
+/// the node.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Candidate {
+    pub since: Instant,
+    pub rounds: usize,
+}
+
+/// When an address consensus vouches for may be put on the node: once it has
+/// been confirmed for as long as the grace and over as many rounds, and not
+/// missing from a well answered one in between.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Addition {
+    pub grace: Duration,
+    pub rounds: usize,
+}
+
+impl Addition {
+    /// The round it is first confirmed. What the operator gets without asking
+    /// for anything, and all there was before additions waited at all.
+    pub const AT_ONCE: Self = Self {
+        grace: Duration::ZERO,
+        rounds: 1,
+    };
+}
+
 /// When an address consensus no longer vouches for may be torn off the node.
 #[derive(Clone, Copy, Debug)]
 pub enum Removal {
@@ -44,6 +87,9 @@ pub enum Removal {
     After(Duration),
     /// The same round, as the deprecated `--strict` has always done.
     AtOnce,
+    /// As [`Removal::After`], and not before a replacement in its family is
+    /// confirmed and attached, so that the node is never left without one.
+    Replace(Duration),
 }
 
 /// Well answered rounds an address must be missing from before it goes, so
@@ -53,21 +99,52 @@ const MISSES: usize = 2;
 /// Decides the node's new address list from its current one and the addresses
 /// consensus vouched for.
 ///
+/// Whether a round was well answered is a question asked per family: a round
+/// every IPv6 provider missed says nothing about an IPv6 address, however many
+/// IPv4 answers it gathered.
+///
 /// Pure on purpose. Every branch that decides whether an address is added,
 /// kept or torn off a live node is decided here, where a test can reach it.
 pub fn reconcile(
     current: Vec<NodeAddress>,
     staged: &BTreeSet<IpAddr>,
     removal: Removal,
-    well_answered: bool,
-    was_pending: &BTreeMap<IpAddr, Pending>,
+    addition: Addition,
+    well_answered: &BTreeSet<Family>,
+    was: &Notice,
     now: Instant,
 ) -> Outcome {
     let mut report = BTreeMap::new();
     let mut addresses = Vec::with_capacity(current.len() + staged.len());
-    let mut pending = BTreeMap::new();
+    let mut notice = Notice::default();
     let mut has_changes = false;
 
+    let on_node: BTreeSet<IpAddr> = current
+        .iter()
+        .filter(|address| address.type_ == TYPE_EXTERNAL_IP)
+        .filter_map(|address| IpAddr::from_str(&address.address).ok())
+        .collect();
+
+    let admissions: BTreeMap<IpAddr, Admission> = staged
+        .difference(&on_node)
+        .map(|external_ip| {
+            let candidate = was.candidates.get(external_ip).copied();
+            (*external_ip, nominate(addition, candidate, now))
+        })
+        .collect();
+
+    // A confirmed address on the node, or going on it in this very patch,
+    // stands in for whatever its family is losing. A candidate does not: it
+    // may yet never be attached.
+    let replaced: BTreeSet<Family> = staged
+        .iter()
+        .filter(|external_ip| {
+            on_node.contains(external_ip)
+                || matches!(admissions.get(external_ip), Some(Admission::Attach))
+        })
+        .map(Family::of)
+        .collect();
+
     // The node keeps its Hostname and InternalIP untouched; only the
     // ExternalIPs are ours to decide upon. An unparsable one is somebody
     // else's business, so it is preserved and complained about.
@@ -84,10 +161,12 @@ pub fn reconcile(
             continue;
         }
 
+        let family = Family::of(&external_ip);
         let verdict = condemn(
             removal,
-            well_answered,
-            was_pending.get(&external_ip).copied(),
+            well_answered.contains(&family),
+            replaced.contains(&family),
+            was.pending.get(&external_ip).copied(),
             now,
         );
 
@@ -101,27 +180,39 @@ pub fn reconcile(
                 report.insert(external_ip, AddrStatus::Skipped);
 
                 if let Some(still) = still {
-                    pending.insert(external_ip, still);
+                    notice.pending.insert(external_ip, still);
                 }
             }
         }
     }
 
-    for external_ip in staged {
-        if report.contains_key(external_ip) {
-            continue;
+    for (external_ip, admission) in admissions {
+        match admission {
+            Admission::Attach => {
+                addresses.push(new_external_ip(&external_ip));
+                report.insert(external_ip, AddrStatus::New);
+                has_changes = true;
+            }
+            Admission::Wait(candidate) => {
+                report.insert(external_ip, AddrStatus::Candidate);
+                notice.candidates.insert(external_ip, candidate);
+            }
         }
+    }
 
-        addresses.push(new_external_ip(external_ip));
-        report.insert(*external_ip, AddrStatus::New);
-        has_changes = true;
+    // A candidate a degraded round did not mention is no less a candidate;
+    // one a well answered round left out starts over if it ever comes back.
+    for (external_ip, candidate) in &was.candidates {
+        if !staged.contains(external_ip) && !well_answered.contains(&Family::of(external_ip)) {
+            notice.candidates.insert(*external_ip, *candidate);
+        }
     }
 
     Outcome {
         addresses,
         report,
         has_changes,
-        pending,
+        notice,
     }
 }
 
@@ -130,21 +221,50 @@ enum Verdict {
     Keep(Option<Pending>),
 }
 
+enum Admission {
+    Attach,
+    Wait(Candidate),
+}
+
+/// Whether an address consensus vouched for this round has waited long
+/// enough to be put on the node, and what it still owes if it has not.
+fn nominate(addition: Addition, candidate: Option<Candidate>, now: Instant) -> Admission {
+    let noted = candidate.unwrap_or(Candidate {
+        since: now,
+        rounds: 0,
+    });
+
+    let counted = Candidate {
+        since: noted.since,
+        rounds: noted.rounds + 1,
+    };
+
+    let waited = now.saturating_duration_since(counted.since) >= addition.grace;
+    if waited && counted.rounds >= addition.rounds {
+        Admission::Attach
+    } else {
+        Admission::Wait(counted)
+    }
+}
+
 /// Whether an address consensus did not vouch for this round has run out of
 /// rope, and what it still owes if it has not.
 ///
 /// A degraded round is not evidence of anything: it neither condemns an address
-/// nor lets one off, so the clock simply does not move.
+/// nor lets one off, so the clock simply does not move. One waiting on a
+/// replacement keeps counting, and goes the round the replacement is attached.
 fn condemn(
     removal: Removal,
     well_answered: bool,
+    replaced: bool,
     pending: Option<Pending>,
     now: Instant,
 ) -> Verdict {
-    let grace = match removal {
+    let (grace, replaced) = match removal {
         Removal::Never => return Verdict::Keep(None),
         Removal::AtOnce => return Verdict::Remove,
-        Removal::After(grace) => grace,
+        Removal::After(grace) => (grace, true),
+        Removal::Replace(grace) => (grace, replaced),
     };
 
     if !well_answered {
@@ -162,7 +282,7 @@ fn condemn(
     };
 
     let waited = now.saturating_duration_since(counted.since) >= grace;
-    if waited && counted.misses >= MISSES {
+    if waited && counted.misses >= MISSES && replaced {
         Verdict::Remove
     } else {
         Verdict::Keep(Some(counted))
@@ -216,6 +336,14 @@ mod tests {
 
     const GRACE: Duration = Duration::from_mins(5);
 
+    fn families(well_answered: bool) -> BTreeSet<Family> {
+        if well_answered {
+            BTreeSet::from(Family::ALL)
+        } else {
+            BTreeSet::new()
+        }
+    }
+
     fn reconcile(
         current: Vec<NodeAddress>,
         staged: &BTreeSet<IpAddr>,
@@ -231,19 +359,15 @@ mod tests {
             current,
             staged,
             removal,
-            true,
-            &BTreeMap::new(),
+            Addition::AT_ONCE,
+            &families(true),
+            &Notice::default(),
             Instant::now(),
         )
     }
 
     /// Runs one round in grace mode and hands back what it decided.
-    fn round(
-        stale: &str,
-        well_answered: bool,
-        pending: &BTreeMap<IpAddr, Pending>,
-        now: Instant,
-    ) -> Outcome {
+    fn round(stale: &str, well_answered: bool, was: &Notice, now: Instant) -> Outcome {
         let mut current = node();
         current.push(addr("ExternalIP", stale));
 
@@ -251,8 +375,9 @@ mod tests {
             current,
             &staged(&["1.1.1.1"]),
             Removal::After(GRACE),
-            well_answered,
-            pending,
+            Addition::AT_ONCE,
+            &families(well_answered),
+            was,
             now,
         )
     }
@@ -263,9 +388,9 @@ mod tests {
         let late = start + GRACE + Duration::from_secs(1);
 
         // Time is up on the first sighting, but one round is not evidence.
-        let first = round("9.9.9.9", true, &BTreeMap::new(), late);
+        let first = round("9.9.9.9", true, &Notice::default(), late);
         assert_eq!(first.report[&ip("9.9.9.9")], AddrStatus::Skipped);
-        assert_eq!(first.pending[&ip("9.9.9.9")].misses, 1);
+        assert_eq!(first.notice.pending[&ip("9.9.9.9")].misses, 1);
     }
 
     #[test]
@@ -273,8 +398,8 @@ mod tests {
         let start = Instant::now();
         let late = start + GRACE + Duration::from_secs(1);
 
-        let first = round("9.9.9.9", true, &BTreeMap::new(), start);
-        let second = round("9.9.9.9", true, &first.pending, late);
+        let first = round("9.9.9.9", true, &Notice::default(), start);
+        let second = round("9.9.9.9", true, &first.notice, late);
 
         assert!(second.has_changes);
         assert_eq!(second.report[&ip("9.9.9.9")], AddrStatus::Removed);
@@ -285,12 +410,12 @@ mod tests {
     fn rounds_alone_do_not_reap_before_the_grace_is_spent() {
         let now = Instant::now();
 
-        let first = round("9.9.9.9", true, &BTreeMap::new(), now);
-        let second = round("9.9.9.9", true, &first.pending, now);
-        let third = round("9.9.9.9", true, &second.pending, now);
+        let first = round("9.9.9.9", true, &Notice::default(), now);
+        let second = round("9.9.9.9", true, &first.notice, now);
+        let third = round("9.9.9.9", true, &second.notice, now);
 
         assert_eq!(third.report[&ip("9.9.9.9")], AddrStatus::Skipped);
-        assert_eq!(third.pending[&ip("9.9.9.9")].misses, 3);
+        assert_eq!(third.notice.pending[&ip("9.9.9.9")].misses, 3);
     }
 
     #[test]
@@ -298,17 +423,20 @@ mod tests {
         let start = Instant::now();
         let late = start + GRACE + Duration::from_secs(1);
 
-        let first = round("9.9.9.9", true, &BTreeMap::new(), start);
-        let quiet = round("9.9.9.9", false, &first.pending, late);
+        let first = round("9.9.9.9", true, &Notice::default(), start);
+        let quiet = round("9.9.9.9", false, &first.notice, late);
 
         assert_eq!(quiet.report[&ip("9.9.9.9")], AddrStatus::Skipped);
-        assert_eq!(quiet.pending[&ip("9.9.9.9")], first.pending[&ip("9.9.9.9")]);
+        assert_eq!(
+            quiet.notice.pending[&ip("9.9.9.9")],
+            first.notice.pending[&ip("9.9.9.9")]
+        );
     }
 
     #[test]
     fn an_address_confirmed_again_is_off_the_hook() {
         let start = Instant::now();
-        let first = round("9.9.9.9", true, &BTreeMap::new(), start);
+        let first = round("9.9.9.9", true, &Notice::default(), start);
 
         let mut current = node();
         current.push(addr("ExternalIP", "9.9.9.9"));
@@ -317,15 +445,48 @@ mod tests {
             current,
             &staged(&["9.9.9.9"]),
             Removal::After(GRACE),
-            true,
-            &first.pending,
+            Addition::AT_ONCE,
+            &families(true),
+            &first.notice,
             start + GRACE,
         );
 
-        assert!(back.pending.is_empty());
+        assert!(back.notice.pending.is_empty());
         assert_eq!(back.report[&ip("9.9.9.9")], AddrStatus::Skipped);
     }
 
+    #[test]
+    fn a_round_well_answered_in_one_family_is_no_evidence_in_the_other() {
+        let start = Instant::now();
+        let late = start + GRACE + Duration::from_secs(1);
+
+        let mut current = node();
+        current.push(addr("ExternalIP", "2606:4700::1111"));
+
+        let only_v4 = BTreeSet::from([Family::V4]);
+        let first = super::reconcile(
+            current.clone(),
+            &staged(&["1.1.1.1"]),
+            Removal::After(GRACE),
+            Addition::AT_ONCE,
+            &only_v4,
+            &Notice::default(),
+            start,
+        );
+        let second = super::reconcile(
+            current,
+            &staged(&["1.1.1.1"]),
+            Removal::After(GRACE),
+            Addition::AT_ONCE,
+            &only_v4,
+            &first.notice,
+            late,
+        );
+
+        assert_eq!(second.report[&ip("2606:4700::1111")], AddrStatus::Skipped);
+        assert!(second.notice.pending.is_empty());
+    }
+
     #[test]
     fn never_removing_is_what_asking_for_nothing_gets() {
         let start = Instant::now();
@@ -336,13 +497,181 @@ mod tests {
             current,
             &staged(&["1.1.1.1"]),
             Removal::Never,
-            true,
-            &BTreeMap::new(),
+            Addition::AT_ONCE,
+            &families(true),
+            &Notice::default(),
             start + GRACE * 100,
         );
 
         assert!(out.addresses.contains(&addr("ExternalIP", "9.9.9.9")));
-        assert!(out.pending.is_empty());
+        assert!(out.notice.pending.is_empty());
+    }
+
+    /// Runs one round that removes only once a replacement is attached, with
+    /// additions waiting out twice the grace, and hands back what it decided.
+    fn replacing(on_node: &[&str], confirmed: &[&str], was: &Notice, now: Instant) -> Outcome {
+        let mut current = node();
+        current.extend(on_node.iter().map(|a| addr("ExternalIP", a)));
+
+        super::reconcile(
+            current,
+            &staged(confirmed),
+            Removal::Replace(GRACE),
+            Addition {
+                grace: GRACE * 2,
+                rounds: 1,
+            },
+            &families(true),
+            was,
+            now,
+        )
+    }
+
+    #[test]
+    fn an_address_outstays_its_grace_until_a_replacement_is_attached() {
+        let start = Instant::now();
+        let late = start + GRACE + Duration::from_secs(1);
+
+        let first = replacing(&["9.9.9.9"], &["1.1.1.1"], &Notice::default(), start);
+        assert_eq!(first.report[&ip("1.1.1.1")], AddrStatus::Candidate);
+
+        // Grace and rounds are spent, but the replacement is a candidate yet.
+        let second = replacing(&["9.9.9.9"], &["1.1.1.1"], &first.notice, late);
+        assert_eq!(second.report[&ip("9.9.9.9")], AddrStatus::Skipped);
+        assert_eq!(second.report[&ip("1.1.1.1")], AddrStatus::Candidate);
+        assert_eq!(second.notice.pending[&ip("9.9.9.9")].misses, 2);
+        assert!(!second.has_changes);
+
+        // The patch that attaches the replacement is the one that removes it.
+        let third = replacing(
+            &["9.9.9.9"],
+            &["1.1.1.1"],
+            &second.notice,
+            start + GRACE * 2,
+        );
+        assert_eq!(third.report[&ip("1.1.1.1")], AddrStatus::New);
+        assert_eq!(third.report[&ip("9.9.9.9")], AddrStatus::Removed);
+        assert!(third.addresses.contains(&addr("ExternalIP", "1.1.1.1")));
+        assert!(!third.addresses.contains(&addr("ExternalIP", "9.9.9.9")));
+    }
+
+    #[test]
+    fn a_replacement_already_on_the_node_lets_an_address_go_as_the_grace_would() {
+        let start = Instant::now();
+        let late = start + GRACE + Duration::from_secs(1);
+        let on_node = ["9.9.9.9", "1.1.1.1"];
+
+        let first = replacing(&on_node, &["1.1.1.1"], &Notice::default(), start);
+        assert_eq!(first.report[&ip("9.9.9.9")], AddrStatus::Skipped);
+
+        let second = replacing(&on_node, &["1.1.1.1"], &first.notice, late);
+        assert_eq!(second.report[&ip("9.9.9.9")], AddrStatus::Removed);
+        assert!(second.has_changes);
+    }
+
+    #[test]
+    fn a_replacement_in_the_other_family_does_not_count() {
+        let start = Instant::now();
+        let on_node = ["9.9.9.9", "2606:4700::1111"];
+        let confirmed = ["2606:4700::1111"];
+
+        let first = replacing(&on_node, &confirmed, &Notice::default(), start);
+        let second = replacing(&on_node, &confirmed, &first.notice, start + GRACE * 100);
+
+        assert_eq!(second.report[&ip("9.9.9.9")], AddrStatus::Skipped);
+        assert!(second.addresses.contains(&addr("ExternalIP", "9.9.9.9")));
+        assert!(!second.has_changes);
+    }
+
+    /// Runs one round with additions waiting out the grace and two rounds,
+    /// and hands back what it decided.
+    fn nominated(confirmed: &[&str], well_answered: bool, was: &Notice, now: Instant) -> Outcome {
+        super::reconcile(
+            node(),
+            &staged(confirmed),
+            Removal::Never,
+            Addition {
+                grace: GRACE,
+                rounds: 2,
+            },
+            &families(well_answered),
+            was,
+            now,
+        )
+    }
+
+    #[test]
+    fn an_address_goes_on_once_the_grace_and_the_rounds_are_both_spent() {
+        let start = Instant::now();
+        let late = start + GRACE + Duration::from_secs(1);
+
+        let first = nominated(&["1.1.1.1"], true, &Notice::default(), start);
+        assert!(!first.has_changes);
+        assert_eq!(first.report[&ip("1.1.1.1")], AddrStatus::Candidate);
+        assert!(!first.addresses.contains(&addr("ExternalIP", "1.1.1.1")));
+
+        // The rounds are in, but not the grace.
+        let second = nominated(&["1.1.1.1"], true, &first.notice, start);
+        assert_eq!(second.report[&ip("1.1.1.1")], AddrStatus::Candidate);
+        assert_eq!(second.notice.candidates[&ip("1.1.1.1")].rounds, 2);
+
+        let third = nominated(&["1.1.1.1"], true, &second.notice, late);
+        assert!(third.has_changes);
+        assert_eq!(third.report[&ip("1.1.1.1")], AddrStatus::New);
+        assert!(third.addresses.contains(&addr("ExternalIP", "1.1.1.1")));
+        assert!(third.notice.is_empty());
+    }
+
+    #[test]
+    fn grace_alone_does_not_add_before_the_rounds_are_in() {
+        let late = Instant::now() + GRACE * 2;
+
+        let first = nominated(&["1.1.1.1"], true, &Notice::default(), late);
+        assert_eq!(first.report[&ip("1.1.1.1")], AddrStatus::Candidate);
+    }
+
+    #[test]
+    fn a_candidate_a_well_answered_round_left_out_starts_over() {
+        let start = Instant::now();
+        let late = start + GRACE + Duration::from_secs(1);
+
+        let first = nominated(&["1.1.1.1"], true, &Notice::default(), start);
+
+        // Nobody could corroborate it: it is still a candidate, no further on.
+        let quiet = nominated(&["2606:4700::1111"], false, &first.notice, late);
+        assert_eq!(
+            quiet.notice.candidates[&ip("1.1.1.1")],
+            first.notice.candidates[&ip("1.1.1.1")]
+        );
+
+        let gone = nominated(&["2606:4700::1111"], true, &quiet.notice, late);
+        assert!(!gone.notice.candidates.contains_key(&ip("1.1.1.1")));
+
+        let back = nominated(&["1.1.1.1"], true, &gone.notice, late);
+        assert_eq!(back.report[&ip("1.1.1.1")], AddrStatus::Candidate);
+        assert_eq!(back.notice.candidates[&ip("1.1.1.1")].rounds, 1);
+    }
+
+    #[test]
+    fn an_address_already_on_the_node_is_never_a_candidate() {
+        let mut current = node();
+        current.push(addr("ExternalIP", "1.1.1.1"));
+
+        let out = super::reconcile(
+            current,
+            &staged(&["1.1.1.1"]),
+            Removal::Never,
+            Addition {
+                grace: GRACE,
+                rounds: 2,
+            },
+            &families(true),
+            &Notice::default(),
+            Instant::now(),
+        );
+
+        assert_eq!(out.report[&ip("1.1.1.1")], AddrStatus::Skipped);
+        assert!(out.notice.is_empty());
     }
 
     #[test]
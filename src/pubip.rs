@@ -1,69 +1,119 @@
 mod address;
+mod breaker;
+mod catalog;
+mod client;
 mod consensus;
+mod credential;
+mod dns;
+mod echo;
 mod error;
+mod family;
+mod gateway;
+mod hosts;
+mod interface;
 mod metrics;
 mod provider;
+mod published;
 mod ratelimit;
+mod retry;
 mod set;
 mod share;
+mod socket;
+mod stun;
+mod tls;
 mod trust;
+mod uplink;
 
 pub use self::{
-    consensus::Report,
-    provider::HttpProvider,
-    set::{Set, Token, parse_token as parse_provider_token, released},
+    address::Cidr,
+    breaker::{COOL_DOWN as BREAK_COOL_DOWN, TRIPS as BREAK_TRIPS, parse_trip as parse_break_trip},
+    catalog::Catalog,
+    client::set_proxy,
+    consensus::{Report, Verdict, well_answered_across},
+    credential::KeySource,
+    echo::{Secret, serve as serve_echo},
+    family::Family,
+    hosts::{set_doh, set_overrides as set_host_overrides},
+    provider::{
+        DnsProvider, HttpProvider, LocalProvider, MetadataProvider, NodeProvider, Provider,
+        StunProvider,
+    },
+    published::Published,
+    ratelimit::CARRY_FORWARD,
+    retry::{BACKOFF as RETRY_BACKOFF, RETRY_ON, parse_kind as parse_retry_kind},
+    set::{Rejected, Set, Token, parse_token as parse_provider_token, released},
     share::TrustShare,
+    tls::{Pin, load_roots as load_provider_ca},
     trust::TrustFactorAuthority,
+    uplink::{Uplink, discover as discover_uplinks, interface_exists},
 };
 
 use {
-    self::error::FetchError,
+    self::{
+        breaker::Breaker,
+        client::Route,
+        credential::Credential,
+        echo::Echo,
+        error::FetchError,
+        provider::Request,
+        ratelimit::{Answered, Asked},
+        retry::Policy,
+        tls::{ClientIdentity, Handshake},
+    },
     anyhow::{Result, ensure},
     humantime::Duration as DisplayedDuration,
-    reqwest::Client,
+    reqwest::{IntoUrl, Method, Url, header::RETRY_AFTER},
     std::{
-        collections::HashMap,
+        collections::{BTreeMap, HashMap, HashSet},
+        iter,
         net::IpAddr,
-        sync::{LazyLock, Mutex, MutexGuard, PoisonError},
-        time::{Duration, Instant},
+        panic,
+        sync::{Arc, Mutex, MutexGuard, PoisonError},
+        time::{Duration, Instant, SystemTime},
+    },
+    tokio::{
+        task::JoinSet,
+        time::{sleep, timeout},
+    },
+    tracing::{
+        Instrument as _, Span, debug, error, field::Empty, info, info_span, instrument, warn,
     },
-    tokio::task::JoinSet,
-    tracing::{Span, debug, error, field::Empty, info, instrument, warn},
 };
 
-const USER_AGENT: &str = concat!("fckloud/", env!("CARGO_PKG_VERSION"));
-
-const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
-const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
-
-// One client, one connection pool, one place where a stalled provider dies.
-static CLIENT: LazyLock<Client> = LazyLock::new(|| {
-    Client::builder()
-        .user_agent(USER_AGENT)
-        .connect_timeout(CONNECT_TIMEOUT)
-        .timeout(REQUEST_TIMEOUT)
-        .build()
-        .expect("HTTP client with static settings must be constructible")
-});
-
 /// Asks every enabled provider where this machine lives and weighs the answers.
 pub struct Resolver {
-    providers: Vec<HttpProvider>,
+    providers: Vec<Provider>,
     tfa: TrustFactorAuthority,
 
     /// Set only by the deprecated `--confirmations`. Left alone, every round
     /// works out its own threshold from the providers that answered it.
     confirmations: Option<usize>,
 
-    gaps: HashMap<HttpProvider, Duration>,
+    families: Vec<Family>,
+    uplink: Uplink,
+
+    gaps: HashMap<Provider, Duration>,
     honour: ratelimit::Honour,
-    asked: Mutex<HashMap<HttpProvider, Instant>>,
+    endpoints: HashMap<Provider, &'static str>,
+    credentials: HashMap<Provider, &'static Credential>,
+    identities: HashMap<Provider, &'static ClientIdentity>,
+    pins: HashMap<Provider, &'static [Pin]>,
+    direct: HashSet<Provider>,
+    proxy_egress: Vec<Cidr>,
+    retries: HashMap<Provider, u32>,
+    retry_backoff: Duration,
+    retry_on: Vec<&'static str>,
+    breaker: breaker::Policy,
+    carry_forward: Duration,
+    asked: Mutex<HashMap<Provider, Asked>>,
+    answered: Mutex<HashMap<(Provider, Family), Answered>>,
+    breakers: Mutex<HashMap<Provider, Breaker>>,
 }
 
 impl Resolver {
     /// Fails when the share and the enabled providers between them ask for a
     /// threshold nobody could clear, or for one an empty round would clear.
-    pub fn new(providers: Vec<HttpProvider>, tfa: TrustFactorAuthority) -> Result<Self> {
+    pub fn new(providers: Vec<Provider>, tfa: TrustFactorAuthority) -> Result<Self> {
         assert!(!providers.is_empty());
         metrics::register(&providers);
 
@@ -88,9 +138,24 @@ impl Resolver {
             providers,
             tfa,
             confirmations: None,
+            families: Family::ALL.to_vec(),
+            uplink: Uplink::Default,
             gaps: HashMap::new(),
             honour: ratelimit::Honour::Limits,
+            endpoints: HashMap::new(),
+            credentials: HashMap::new(),
+            identities: HashMap::new(),
+            pins: HashMap::new(),
+            direct: HashSet::new(),
+            proxy_egress: Vec::new(),
+            retries: HashMap::new(),
+            retry_backoff: retry::BACKOFF,
+            retry_on: retry::RETRY_ON.to_vec(),
+            breaker: breaker::Policy::default(),
+            carry_forward: ratelimit::CARRY_FORWARD,
             asked: Mutex::new(HashMap::new()),
+            answered: Mutex::new(HashMap::new()),
+            breakers: Mutex::new(HashMap::new()),
         })
     }
 
@@ -105,20 +170,56 @@ impl Resolver {
         self
     }
 
+    /// Narrows the families every round asks over. Both unless told otherwise,
+    /// and a family the node has no route for is skipped whatever this says.
+    pub fn set_families(&mut self, families: impl IntoIterator<Item = Family>) -> &mut Self {
+        let mut families: Vec<Family> = families.into_iter().collect();
+        families.sort_unstable();
+        families.dedup();
+
+        assert!(!families.is_empty());
+        self.families = families;
+        self
+    }
+
+    /// Binds every request this resolver makes to one way out of the node.
+    /// The default route unless told otherwise.
+    pub fn set_uplink(&mut self, uplink: Uplink) -> &mut Self {
+        self.uplink = uplink;
+        self
+    }
+
+    /// Whether any round of this resolver asks over the family at all.
+    pub fn resolves(&self, family: Family) -> bool {
+        self.families.contains(&family)
+            && self.uplink.carries(family)
+            && self.total_trust(family) > 0
+    }
+
     // A poisoned lock here costs one provider one round of pacing, which is
     // cheaper than refusing to resolve at all.
-    fn asked(&self) -> MutexGuard<'_, HashMap<HttpProvider, Instant>> {
+    fn asked(&self) -> MutexGuard<'_, HashMap<Provider, Asked>> {
         self.asked.lock().unwrap_or_else(PoisonError::into_inner)
     }
 
+    // And for an answer, which at worst is not carried forward once.
+    fn answered(&self) -> MutexGuard<'_, HashMap<(Provider, Family), Answered>> {
+        self.answered.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // The same goes for a breaker, which at worst stays closed a round longer.
+    fn breakers(&self) -> MutexGuard<'_, HashMap<Provider, Breaker>> {
+        self.breakers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
     /// States the pool this run will actually ask and what it takes to confirm
     /// an address with it.
     ///
-    /// One line at `info` for the pool as a whole, because how much trust an
-    /// address needs is the first thing anyone asks when a round did not
-    /// confirm what they expected. The providers themselves go one per line,
-    /// which is a screenful before anything has happened yet, so they wait for
-    /// `debug` and for somebody actually looking for them.
+    /// One line at `info` for the pool as a whole in each family, because how
+    /// much trust an address needs is the first thing anyone asks when a round
+    /// did not confirm what they expected. The providers themselves go one per
+    /// line, which is a screenful before anything has happened yet, so they
+    /// wait for `debug` and for somebody actually looking for them.
     pub fn announce(&self, pinned: bool) {
         for provider in &self.providers {
             let gap = ratelimit::gap_of(*provider, &self.gaps);
@@ -127,24 +228,45 @@ impl Resolver {
                 %provider,
                 trust_factor = self.tfa.trust_factor(*provider),
                 rate_limit = gap.map(|gap| DisplayedDuration::from(gap).to_string()),
+                retries = self.retry_policy(*provider).retries,
                 "provider takes part in consensus",
             );
         }
 
-        let enrolled = self.total_trust();
+        for family in &self.families {
+            let serving: Vec<Provider> = self
+                .providers
+                .iter()
+                .copied()
+                .filter(|provider| provider.serves(*family))
+                .collect();
 
-        info!(
-            providers = self.providers.len(),
-            trust_total = enrolled,
-            trust_share = %self.tfa.trust_share(),
-            confirmations = self
-                .confirmations
-                .unwrap_or_else(|| self.tfa.calc_confirmation_number(&self.providers)),
-            confirmations_pinned = self.confirmations.is_some(),
-            trust_floor = consensus::floor(enrolled),
-            pinned,
-            "consensus is set",
-        );
+            if serving.is_empty() {
+                warn!(
+                    %family,
+                    uplink = %self.uplink,
+                    "no enabled provider answers over this family, it is never resolved",
+                );
+                continue;
+            }
+
+            let enrolled = self.total_trust(*family);
+
+            info!(
+                %family,
+                uplink = %self.uplink,
+                providers = serving.len(),
+                trust_total = enrolled,
+                trust_share = %self.tfa.trust_share(),
+                confirmations = self
+                    .confirmations
+                    .unwrap_or_else(|| self.tfa.calc_confirmation_number(&serving)),
+                confirmations_pinned = self.confirmations.is_some(),
+                trust_floor = consensus::floor(enrolled),
+                pinned,
+                "consensus is set",
+            );
+        }
 
         if !pinned {
             debug!(concat!(
@@ -154,9 +276,20 @@ impl Resolver {
         }
     }
 
-    fn total_trust(&self) -> usize {
+    /// The trust enrolled in one family: that of every provider able to answer
+    /// over it. A provider without an AAAA record is not missing from an IPv6
+    /// round, it was never part of one.
+    fn total_trust(&self, family: Family) -> usize {
+        self.enrolled_trust(family, &HashSet::new())
+    }
+
+    /// The trust a round over the family counts on, that of every provider
+    /// whose breaker is open left out: like one that did not answer, a
+    /// provider that is not asked must not raise the bar it cannot clear.
+    fn enrolled_trust(&self, family: Family, open: &HashSet<Provider>) -> usize {
         self.providers
             .iter()
+            .filter(|provider| provider.serves(family) && !open.contains(*provider))
             .map(|provider| self.tfa.trust_factor(*provider))
             .sum()
     }
@@ -165,12 +298,128 @@ impl Resolver {
     /// published limit rather than restoring it.
     pub fn set_rate_limits(
         &mut self,
-        gaps: impl IntoIterator<Item = (HttpProvider, Duration)>,
+        gaps: impl IntoIterator<Item = (Provider, Duration)>,
     ) -> &mut Self {
         self.gaps = gaps.into_iter().collect();
         self
     }
 
+    /// Asks providers at another URL than their own, see
+    /// [`Catalog::with_endpoints`].
+    pub fn set_endpoints(
+        &mut self,
+        endpoints: impl IntoIterator<Item = (Provider, &'static str)>,
+    ) -> &mut Self {
+        self.endpoints = endpoints.into_iter().collect();
+        self
+    }
+
+    /// Asks keyed providers with their keys, see [`Catalog::with_keys`].
+    pub fn set_credentials(
+        &mut self,
+        credentials: impl IntoIterator<Item = (Provider, &'static Credential)>,
+    ) -> &mut Self {
+        self.credentials = credentials.into_iter().collect();
+        self
+    }
+
+    /// Asks providers presenting a client certificate, see
+    /// [`Catalog::with_identities`].
+    pub fn set_identities(
+        &mut self,
+        identities: impl IntoIterator<Item = (Provider, &'static ClientIdentity)>,
+    ) -> &mut Self {
+        self.identities = identities.into_iter().collect();
+        self
+    }
+
+    /// Insists on one of the pinned keys in the certificate chains of these
+    /// providers, see [`Catalog::pinned`].
+    pub fn set_pins(
+        &mut self,
+        pins: impl IntoIterator<Item = (Provider, &'static [Pin])>,
+    ) -> &mut Self {
+        self.pins = pins.into_iter().collect();
+        self
+    }
+
+    /// Asks these providers straight out by the uplink, past any proxy.
+    pub fn set_direct(&mut self, providers: impl IntoIterator<Item = Provider>) -> &mut Self {
+        self.direct = providers.into_iter().collect();
+        self
+    }
+
+    /// Where the proxy meets the Internet. An address in one of them is never
+    /// confirmed, and a round every answer of which falls there saw the proxy
+    /// rather than the node, and is degraded.
+    pub fn set_proxy_egress(&mut self, cidrs: impl IntoIterator<Item = Cidr>) -> &mut Self {
+        self.proxy_egress = cidrs.into_iter().collect();
+        self
+    }
+
+    /// Overwrites how many times a provider that gave no answer is asked
+    /// again within the round, see [`Provider::retries`].
+    pub fn set_retries(&mut self, retries: impl IntoIterator<Item = (Provider, u32)>) -> &mut Self {
+        self.retries = retries.into_iter().collect();
+        self
+    }
+
+    /// Waits this long before the first retry, and twice as long before
+    /// each one after it.
+    pub fn set_retry_backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// Retries only after failures of these error types.
+    pub fn set_retry_on(&mut self, kinds: impl IntoIterator<Item = &'static str>) -> &mut Self {
+        self.retry_on = kinds.into_iter().collect();
+        self
+    }
+
+    /// Opens a provider's breaker after it failed with one of these error
+    /// types this many rounds in a row. None given, no breaker ever opens.
+    pub fn set_break_after(
+        &mut self,
+        trips: impl IntoIterator<Item = (&'static str, u32)>,
+    ) -> &mut Self {
+        self.breaker.trips = trips.into_iter().collect();
+        self
+    }
+
+    /// Asks a provider whose breaker opened again after this long.
+    pub fn set_break_cool_down(&mut self, cool_down: Duration) -> &mut Self {
+        self.breaker.cool_down = cool_down;
+        self
+    }
+
+    /// Weighs what a provider still serving out its gap last answered, when
+    /// no older than this, in the rounds that do not ask it. Zero never does.
+    pub fn set_carry_forward(&mut self, max_age: Duration) -> &mut Self {
+        self.carry_forward = max_age;
+        self
+    }
+
+    /// How the provider is asked again within a round. Never while the gap
+    /// it asks for is honoured: a second request right after the first is
+    /// exactly what the gap is there to forbid.
+    fn retry_policy(&self, provider: Provider) -> Policy {
+        let paced = self.honour == ratelimit::Honour::Limits
+            && ratelimit::gap_of(provider, &self.gaps).is_some();
+
+        let retries = if paced {
+            0
+        } else {
+            (self.retries.get(&provider).copied()).unwrap_or_else(|| provider.retries())
+        };
+
+        Policy {
+            retries,
+            backoff: self.retry_backoff,
+            on: self.retry_on.clone(),
+        }
+    }
+
     /// Pins the threshold to one number for every round, whoever answered.
     /// Only the deprecated `--confirmations` does this.
     pub fn set_confirmations(&mut self, confirmations: usize) -> &mut Self {
@@ -178,22 +427,27 @@ impl Resolver {
         self
     }
 
-    /// Polls every provider in parallel, then hands what came back to
-    /// [`consensus::decide`].
-    #[instrument(name = "pubip.resolve", skip_all, fields(
-        fckloud.consensus.threshold = Empty,
-        fckloud.consensus.confirmed = Empty,
-        fckloud.consensus.unconfirmed = Empty,
-        fckloud.consensus.well_answered = Empty,
-    ))]
-    pub async fn run(&self) -> Report {
+    /// Polls every provider over every family in parallel, then hands what
+    /// came back to [`consensus::decide`], one family at a time. A provider
+    /// reading the Node reads it from what was published there.
+    ///
+    /// The round is over as soon as no answer still out could change any
+    /// family's verdict: one stalled provider would otherwise hold every tick
+    /// for as long as its timeout, to no effect.
+    #[instrument(name = "pubip.resolve", skip_all)]
+    pub async fn run(&self, published: &Arc<Published>) -> Report {
         let now = Instant::now();
+        let (admitted, open) = self.admit(now);
         let split = {
             let mut asked = self.asked();
-            let split = ratelimit::split(&self.providers, &self.gaps, &asked, now, self.honour);
+            let split = ratelimit::split(&admitted, &self.gaps, &asked, now, self.honour);
 
+            // Asking again is no answer to a pushback, so the stretch stays.
             for provider in &split.allowed {
-                asked.insert(*provider, now);
+                asked
+                    .entry(*provider)
+                    .and_modify(|asked| asked.at = now)
+                    .or_insert(Asked::at(now));
             }
 
             split
@@ -208,69 +462,528 @@ impl Resolver {
             );
         }
 
-        let answers = split
-            .allowed
+        // A family nobody can answer over gets no verdict, rather than an empty
+        // one that every one of its zero providers answered well. One the
+        // uplink has no route for gets an empty verdict instead: it was meant
+        // to be asked, and its silence must not read as anything.
+        let (families, unroutable): (Vec<Family>, Vec<Family>) = Family::ALL
+            .into_iter()
+            .filter(|family| self.resolves(*family))
+            .partition(|family| self.uplink.routable(*family));
+
+        let enrolled: BTreeMap<Family, usize> = families
             .iter()
-            .copied()
-            .map(|provider| async move { (provider, get_public_ip(provider).await) })
-            .collect::<JoinSet<_>>()
-            .join_all()
-            .await;
+            .map(|family| (*family, self.enrolled_trust(*family, &open)))
+            .collect();
+
+        let carried: BTreeMap<Family, Vec<(Provider, IpAddr)>> = {
+            let answered = self.answered();
+            families
+                .iter()
+                .map(|family| {
+                    let carried = ratelimit::carried(
+                        &split.holding,
+                        &answered,
+                        *family,
+                        now,
+                        self.carry_forward,
+                    );
+                    (*family, carried)
+                })
+                .collect()
+        };
+
+        for family in &unroutable {
+            debug!(%family, uplink = %self.uplink, "no route over this family, nobody is asked");
+        }
+
+        // One request per provider and family, and all of them at once: a
+        // provider asked over IPv6 owes nothing to the same provider's IPv4.
+        let mut pending: HashSet<(Provider, Family)> = families
+            .iter()
+            .flat_map(|family| {
+                split
+                    .allowed
+                    .iter()
+                    .filter(|provider| provider.serves(*family))
+                    .map(|provider| (*provider, *family))
+            })
+            .collect();
+
+        let mut asking = pending
+            .iter()
+            .map(|(provider, family)| {
+                let (provider, family) = (*provider, *family);
+                let uplink = self.uplink.clone();
+                let published = Arc::clone(published);
+                // The operator's URL stands alone, see [`Catalog::with_endpoints`].
+                let endpoints = match self.endpoints.get(&provider) {
+                    Some(uri) => vec![*uri],
+                    None => iter::once(provider.request_uri())
+                        .chain(provider.mirrors(family))
+                        .collect(),
+                };
+                let access = Access {
+                    credential: self.credentials.get(&provider).copied(),
+                    handshake: Handshake {
+                        identity: self.identities.get(&provider).copied(),
+                        pins: self.pins.get(&provider).copied().unwrap_or_default(),
+                    },
+                    route: if self.direct.contains(&provider) {
+                        Route::Direct
+                    } else {
+                        Route::Proxied
+                    },
+                };
+                let policy = self.retry_policy(provider);
+
+                async move {
+                    let answer = get_public_ip(
+                        provider, &endpoints, access, family, &uplink, &published, &policy,
+                    )
+                    .await;
+                    (provider, family, answer)
+                }
+            })
+            .collect::<JoinSet<_>>();
+
+        let mut answers = Vec::with_capacity(pending.len());
+        while let Some(joined) = asking.join_next().await {
+            let (provider, family, answer) =
+                joined.unwrap_or_else(|err| panic::resume_unwind(err.into_panic()));
+            pending.remove(&(provider, family));
+            answers.push((provider, family, answer));
+
+            if self.settled(&answers, &carried, &pending, &enrolled) {
+                break;
+            }
+        }
+
+        // Whatever the providers still out would say, the verdict stays the
+        // same: their requests are cancelled rather than waited out.
+        asking.abort_all();
+        self.adapt_gaps(&answers);
+        self.trip_breakers(&answers);
+        self.remember(&answers);
+
+        let mut by_family: BTreeMap<Family, Answers> = carried
+            .into_iter()
+            .map(|(family, carried)| {
+                let answers = Answers {
+                    carried,
+                    ..Answers::default()
+                };
+                (family, answers)
+            })
+            .collect();
+
+        for (provider, family, answer) in answers {
+            let answers = by_family.entry(family).or_default();
+
+            match answer {
+                Ok(ip_addr) => answers.reported.push((provider, ip_addr)),
+                Err(err) => answers.failed.push((provider, err)),
+            }
+        }
+
+        for (provider, family) in pending {
+            by_family.entry(family).or_default().skipped.push(provider);
+        }
+
+        let mut families: BTreeMap<Family, Verdict> = by_family
+            .into_iter()
+            .map(|(family, answers)| {
+                let enrolled = enrolled.get(&family).copied().unwrap_or_default();
+                (
+                    family,
+                    self.weigh(family, enrolled, &answers, &split.holding),
+                )
+            })
+            .collect();
+
+        for family in unroutable {
+            families.insert(family, Verdict::default());
+        }
 
-        let mut reported = Vec::with_capacity(answers.len());
-        let mut failed = Vec::new();
+        Report {
+            uplink: self.uplink.clone(),
+            families,
+        }
+    }
+
+    /// Whether the round may stop waiting on the providers still pending,
+    /// every family's verdict being [`consensus::settled`] without them.
+    fn settled(
+        &self,
+        answers: &[(Provider, Family, Result<IpAddr, FetchError>)],
+        carried: &BTreeMap<Family, Vec<(Provider, IpAddr)>>,
+        pending: &HashSet<(Provider, Family)>,
+        enrolled: &BTreeMap<Family, usize>,
+    ) -> bool {
+        Family::ALL.into_iter().all(|family| {
+            let outstanding: Vec<Provider> = pending
+                .iter()
+                .filter(|(_, over)| *over == family)
+                .map(|(provider, _)| *provider)
+                .collect();
+            let reported: Vec<(Provider, IpAddr)> = answers
+                .iter()
+                .filter(|(_, answered, _)| *answered == family)
+                .filter_map(|(provider, _, answer)| Some((*provider, *answer.as_ref().ok()?)))
+                .collect();
+
+            consensus::settled(
+                &reported,
+                carried.get(&family).map_or(&[], Vec::as_slice),
+                &outstanding,
+                &self.tfa,
+                self.confirmations,
+                enrolled.get(&family).copied().unwrap_or_default(),
+            )
+        })
+    }
 
-        for (provider, answer) in answers {
+    /// Keeps what every provider answered this round, to be carried forward
+    /// into the rounds its gap keeps it out of.
+    fn remember(&self, answers: &[(Provider, Family, Result<IpAddr, FetchError>)]) {
+        let now = Instant::now();
+        let mut answered = self.answered();
+
+        for (provider, family, answer) in answers {
+            if let Ok(ip_addr) = answer {
+                let ip_addr = *ip_addr;
+                answered.insert((*provider, *family), Answered { ip_addr, at: now });
+            }
+        }
+    }
+
+    /// The providers this round may ask as far as their breakers go, and
+    /// those it may not. A breaker that cooled down lets its provider
+    /// through, to see whether it recovered.
+    fn admit(&self, now: Instant) -> (Vec<Provider>, HashSet<Provider>) {
+        let mut breakers = self.breakers();
+        let (mut admitted, mut open) = (Vec::new(), HashSet::new());
+
+        for provider in &self.providers {
+            let breaker = breakers.entry(*provider).or_default();
+            let (asked, turned) = breaker.admit(&self.breaker, now);
+
+            if let Some(state) = turned {
+                self.breaker_turned(*provider, state);
+            }
+
+            if asked {
+                admitted.push(*provider);
+            } else {
+                open.insert(*provider);
+            }
+        }
+
+        (admitted, open)
+    }
+
+    /// Closes the breaker of every provider that answered over some family
+    /// this round, and counts the failures of those that answered over none.
+    /// Pushing back is not failing, and a provider the round stopped waiting
+    /// for did neither: a half-open one of either is opened again.
+    fn trip_breakers(&self, answers: &[(Provider, Family, Result<IpAddr, FetchError>)]) {
+        let mut failed: HashMap<Provider, Vec<&'static str>> = HashMap::new();
+        let mut answered: HashSet<Provider> = HashSet::new();
+
+        for (provider, _, answer) in answers {
             match answer {
-                Ok(ip_addr) => reported.push((provider, ip_addr)),
-                Err(err) => failed.push((provider, err)),
+                Ok(_) => {
+                    answered.insert(*provider);
+                }
+                Err(err) if err.throttled() => {}
+                Err(err) => failed
+                    .entry(*provider)
+                    .or_default()
+                    .push(err.as_error_type()),
+            }
+        }
+
+        let now = Instant::now();
+        let mut breakers = self.breakers();
+
+        for (provider, mut kinds) in failed {
+            if answered.contains(&provider) {
+                continue;
+            }
+
+            // Failing the same way over both families is one round failed.
+            kinds.sort_unstable();
+            kinds.dedup();
+
+            let breaker = breakers.entry(provider).or_default();
+            if let Some(state) = breaker.failed(kinds, &self.breaker, now) {
+                self.breaker_turned(provider, state);
             }
         }
 
-        let enrolled = self.total_trust();
-        let answered: Vec<HttpProvider> = reported.iter().map(|(provider, _)| *provider).collect();
-        let answered_trust: usize = answered
+        for provider in answered {
+            let breaker = breakers.entry(provider).or_default();
+            if let Some(state) = breaker.answered() {
+                self.breaker_turned(provider, state);
+            }
+        }
+
+        // Whatever is still half-open was neither heard from nor seen failing.
+        for (provider, breaker) in breakers.iter_mut() {
+            if let Some(state) = breaker.unprobed(now) {
+                metrics::record_breaker(*provider, &self.uplink, state);
+                info!(
+                    %provider,
+                    uplink = %self.uplink,
+                    "provider was not asked after it cooled down, leaving it out for another cool-down",
+                );
+            }
+        }
+    }
+
+    /// Says once that a provider's breaker moved, and exports where it stands.
+    fn breaker_turned(&self, provider: Provider, state: breaker::State) {
+        metrics::record_breaker(provider, &self.uplink, state);
+
+        match state {
+            breaker::State::Open => warn!(
+                %provider,
+                uplink = %self.uplink,
+                cool_down = DisplayedDuration::from(self.breaker.cool_down).to_string(),
+                "provider keeps failing, it is neither asked nor trusted until it cools down",
+            ),
+            breaker::State::HalfOpen => info!(
+                %provider,
+                uplink = %self.uplink,
+                "provider has cooled down, asking it once to see whether it recovered",
+            ),
+            breaker::State::Closed => info!(
+                %provider,
+                uplink = %self.uplink,
+                "provider answered again, it is asked and trusted every round",
+            ),
+        }
+    }
+
+    /// Stretches the gap of every provider that pushed back this round, and
+    /// eases it off again for every one that answered. A provider asked
+    /// over both families is held by either pushing back.
+    fn adapt_gaps(&self, answers: &[(Provider, Family, Result<IpAddr, FetchError>)]) {
+        if self.honour == ratelimit::Honour::Nothing {
+            return;
+        }
+
+        let mut pushed_back: HashMap<Provider, Option<Duration>> = HashMap::new();
+        let mut answered: HashSet<Provider> = HashSet::new();
+
+        for (provider, _, answer) in answers {
+            match answer {
+                Err(err) if err.throttled() => {
+                    let named = pushed_back.entry(*provider).or_default();
+                    *named = (*named).max(err.retry_after());
+                }
+                Ok(_) => {
+                    answered.insert(*provider);
+                }
+                Err(_) => {}
+            }
+        }
+
+        // Pushed back over one family and answered over the other, it is
+        // still held: easing off straight away would undo the Retry-After.
+        answered.retain(|provider| !pushed_back.contains_key(provider));
+
+        let mut asked = self.asked();
+
+        for (provider, retry_after) in pushed_back {
+            let Some(last) = asked.get_mut(&provider) else {
+                continue;
+            };
+            let gap = ratelimit::gap_of(provider, &self.gaps);
+            let stretched = ratelimit::stretch(last.stretched.or(gap), gap, retry_after);
+            last.stretched = Some(stretched);
+
+            metrics::record_gap(provider, stretched);
+            warn!(
+                %provider,
+                gap = DisplayedDuration::from(stretched).to_string(),
+                "provider pushed back, asking it less often",
+            );
+        }
+
+        for provider in answered {
+            let Some(last) = asked.get_mut(&provider) else {
+                continue;
+            };
+            let Some(stretched) = last.stretched else {
+                continue;
+            };
+            let gap = ratelimit::gap_of(provider, &self.gaps);
+            last.stretched = ratelimit::decay(stretched, gap);
+
+            let eased = last.stretched.or(gap).unwrap_or_default();
+            metrics::record_gap(provider, eased);
+            debug!(
+                %provider,
+                gap = DisplayedDuration::from(eased).to_string(),
+                "provider answered again, easing off its stretched gap",
+            );
+        }
+    }
+
+    /// Decides one family's verdict from what its providers answered, and
+    /// what those serving out their gap answered last.
+    fn weigh(
+        &self,
+        family: Family,
+        enrolled: usize,
+        answers: &Answers,
+        holding: &[(Provider, Duration)],
+    ) -> Verdict {
+        let span = info_span!(
+            "pubip.consensus",
+            network.type = family.as_network_type(),
+            fckloud.uplink = %self.uplink,
+            fckloud.consensus.threshold = Empty,
+            fckloud.consensus.confirmed = Empty,
+            fckloud.consensus.unconfirmed = Empty,
+            fckloud.consensus.well_answered = Empty,
+            fckloud.consensus.carried = Empty,
+        );
+        let _entered = span.enter();
+
+        // A carried answer weighs on the threshold like it weighs on the
+        // address, or it would be trust counted for one and not the other.
+        let evidence = [answers.reported.as_slice(), &answers.carried].concat();
+        let answered: Vec<Provider> = evidence.iter().map(|(provider, _)| *provider).collect();
+        let answered_trust: usize = answers
+            .reported
             .iter()
-            .map(|provider| self.tfa.trust_factor(*provider))
+            .map(|(provider, _)| self.tfa.trust_factor(*provider))
             .sum();
 
         let confirmations = self
             .confirmations
             .unwrap_or_else(|| consensus::confirmations_for(&answered, &self.tfa, enrolled));
 
-        let mut report = consensus::decide(&reported, &self.tfa, confirmations);
-        report.well_answered = consensus::well_answered(answered_trust, enrolled, &self.tfa);
+        let mut verdict = consensus::decide(&evidence, &self.tfa, confirmations);
+        verdict.well_answered = consensus::well_answered(answered_trust, enrolled, &self.tfa);
+        verdict.carried.clone_from(&answers.carried);
+
+        // The proxy's address is never the node's, however much trust agrees
+        // on it. It falls short instead, with the trust it gathered.
+        let proxied: Vec<IpAddr> = verdict
+            .confirmed
+            .iter()
+            .filter(|ip_addr| in_egress(ip_addr, &self.proxy_egress))
+            .copied()
+            .collect();
+
+        for ip_addr in &proxied {
+            let trust = evidence
+                .iter()
+                .filter(|(_, reported)| reported == ip_addr)
+                .map(|(provider, _)| self.tfa.trust_factor(*provider))
+                .sum();
+
+            verdict.confirmed.remove(ip_addr);
+            verdict.unconfirmed.insert(*ip_addr, trust);
+        }
+
+        if !proxied.is_empty() {
+            metrics::record_proxied(&self.uplink, family, proxied.len());
+        }
+
+        // A round that saw nothing but the proxy saw nothing of the node
+        // either, and its silence is no evidence an address is gone.
+        let through_proxy = seen_through_proxy(&answers.reported, &self.proxy_egress);
+        if through_proxy {
+            verdict.well_answered = false;
+        }
+
+        for (provider, ip_addr) in &verdict.carried {
+            debug!(
+                %provider,
+                %family,
+                ?ip_addr,
+                "provider is serving out its gap, weighing what it answered last",
+            );
+        }
 
-        if !report.well_answered {
+        if !verdict.well_answered {
             debug!(
+                %family,
                 answered_trust,
                 enrolled, "round is degraded, its silence says nothing about the node",
             );
         }
 
-        self.complain_about(&failed, &split.holding, &report);
+        if through_proxy {
+            let mut seen: Vec<IpAddr> = answers.reported.iter().map(|(_, ip)| *ip).collect();
+            seen.sort_unstable();
+            seen.dedup();
+
+            warn!(
+                %family,
+                uplink = %self.uplink,
+                addresses = ?seen,
+                "every provider reported the proxy's egress, not the node's address, it is not confirmed",
+            );
+        }
+
+        // One whose last answer was carried forward is not missing at all.
+        let holding: Vec<_> = holding
+            .iter()
+            .filter(|(provider, _)| provider.serves(family))
+            .filter(|(provider, _)| {
+                !answers
+                    .carried
+                    .iter()
+                    .any(|(carried, _)| carried == provider)
+            })
+            .copied()
+            .collect();
+
+        self.complain_about(family, &answers.failed, &holding, &verdict);
+
+        for provider in &answers.skipped {
+            metrics::record_skipped(*provider, family, &self.uplink);
+            debug!(
+                %provider,
+                %family,
+                "provider was not waited for, the round was settled without it",
+            );
+        }
 
         // Counts, not addresses: what an address is belongs in the log line
         // below, where it is read once, not in a label kept forever.
-        Span::current()
-            .record("fckloud.consensus.threshold", report.confirmations)
-            .record("fckloud.consensus.confirmed", report.confirmed.len())
-            .record("fckloud.consensus.unconfirmed", report.unconfirmed.len())
-            .record("fckloud.consensus.well_answered", report.well_answered);
+        span.record("fckloud.consensus.threshold", verdict.confirmations)
+            .record("fckloud.consensus.confirmed", verdict.confirmed.len())
+            .record("fckloud.consensus.unconfirmed", verdict.unconfirmed.len())
+            .record("fckloud.consensus.well_answered", verdict.well_answered)
+            .record("fckloud.consensus.carried", verdict.carried.len());
 
-        metrics::record_consensus(report.confirmed.len(), report.unconfirmed.len());
+        metrics::record_consensus(
+            &self.uplink,
+            family,
+            verdict.confirmed.len(),
+            verdict.unconfirmed.len(),
+        );
 
-        for ip_addr in &report.confirmed {
-            debug!(?ip_addr, report.confirmations, "address has been confirmed");
+        for ip_addr in &verdict.confirmed {
+            debug!(
+                ?ip_addr,
+                verdict.confirmations, "address has been confirmed"
+            );
         }
-        for (ip_addr, bucket) in &report.unconfirmed {
+        for (ip_addr, bucket) in &verdict.unconfirmed {
             debug!(
                 ?ip_addr,
-                bucket, report.confirmations, "address falls short"
+                bucket, verdict.confirmations, "address falls short"
             );
         }
 
-        report
+        verdict
     }
 
     /// Reports the providers that could not answer, at the severity their
@@ -279,17 +992,22 @@ impl Resolver {
     /// A provider being unreachable is routine and says nothing on its own:
     /// what matters is whether consensus needed it. If the round confirmed
     /// everything it was going to confirm anyway, the failure cost the node
-    /// nothing and an error only teaches the reader to ignore errors.
+    /// nothing and an error only teaches the reader to ignore errors. A pin
+    /// that did not hold is the exception, and always an error: it is not a
+    /// provider that went quiet but one somebody else may be answering for.
     /// A provider held back by its own rate limit is not a failure and is never
     /// complained about, but its trust is as absent as a failed one's, so it
-    /// counts towards whether the silence cost the round anything.
+    /// counts towards whether the silence cost the round anything. One the
+    /// round stopped waiting for is neither: by the time it was left behind,
+    /// its trust could no longer change the verdict.
     fn complain_about(
         &self,
-        failed: &[(HttpProvider, FetchError)],
-        holding: &[(HttpProvider, Duration)],
-        report: &Report,
+        family: Family,
+        failed: &[(Provider, FetchError)],
+        holding: &[(Provider, Duration)],
+        verdict: &Verdict,
     ) {
-        let trust_of = |provider: &HttpProvider| self.tfa.trust_factor(*provider);
+        let trust_of = |provider: &Provider| self.tfa.trust_factor(*provider);
 
         let missing: usize = failed
             .iter()
@@ -297,14 +1015,31 @@ impl Resolver {
             .chain(holding.iter().map(|(provider, _)| trust_of(provider)))
             .sum();
 
-        let mattered = consensus::missing_trust_mattered(report, missing);
+        let mattered = consensus::missing_trust_mattered(verdict, missing);
 
         for (provider, err) in failed {
-            if mattered {
-                error!(%provider, error.type = err.as_error_type(), %err, "provider cannot be used");
+            // Whatever consensus made of the round, a provider that could be
+            // impersonated may not be the only one.
+            if let FetchError::PinMismatch(_) = err {
+                error!(
+                    %provider,
+                    %family,
+                    error.type = err.as_error_type(),
+                    %err,
+                    "provider's certificate is not one pinned for it, something on the path may be impersonating it",
+                );
+            } else if mattered {
+                error!(
+                    %provider,
+                    %family,
+                    error.type = err.as_error_type(),
+                    %err,
+                    "provider cannot be used",
+                );
             } else {
                 warn!(
                     %provider,
+                    %family,
                     error.type = err.as_error_type(),
                     %err,
                     "provider cannot be used, consensus did not need it",
@@ -314,50 +1049,406 @@ impl Resolver {
     }
 }
 
-/// Asks the given [`HttpProvider`] which public IP address it sees us as.
+/// Runs a round on every uplink at once, one [`Report`] each, over the one
+/// reading of the Node.
 ///
-/// The span is named for the HTTP method, as the semantic conventions have it
-/// for client spans; `server.address` is what tells the providers apart.
-#[instrument(name = "http.request", skip_all, fields(
-    otel.kind = "client",
-    otel.name = %provider.request_method(),
-    otel.status_code = Empty,
-    http.request.method = %provider.request_method(),
-    http.response.status_code = Empty,
-    server.address = %provider,
-    url.full = provider.request_uri(),
-    error.type = Empty,
-))]
-async fn get_public_ip(provider: HttpProvider) -> Result<IpAddr, FetchError> {
-    let started = Instant::now();
-    let result = fetch(provider).await;
-    let elapsed = started.elapsed();
-
-    if let Err(err) = &result {
-        Span::current()
-            .record("otel.status_code", "ERROR")
-            .record("error.type", err.as_error_type());
-    }
-
-    metrics::record_request(provider, elapsed, result.as_ref().err());
-    result
+/// The same address confirmed behind two uplinks means they do not lead out
+/// by different ways at all, or that the binding is not taking effect: the
+/// reports are still kept, but somebody ought to hear about it.
+///
+/// The reports come back in the order of the resolvers, whichever finished
+/// first, so that one tick's logs read in the same order as the last one's.
+pub async fn run_all(resolvers: &[Arc<Resolver>], published: &Arc<Published>) -> Vec<Report> {
+    let mut reports = resolvers
+        .iter()
+        .enumerate()
+        .map(|(at, resolver)| {
+            let resolver = Arc::clone(resolver);
+            let published = Arc::clone(published);
+            async move { (at, resolver.run(&published).await) }
+        })
+        .collect::<JoinSet<_>>()
+        .join_all()
+        .await;
+
+    reports.sort_unstable_by_key(|(at, _)| *at);
+    let reports: Vec<Report> = reports.into_iter().map(|(_, report)| report).collect();
+
+    let mut seen: HashMap<IpAddr, &Uplink> = HashMap::new();
+    let mut shared = HashSet::new();
+
+    for report in &reports {
+        for ip_addr in report.confirmed() {
+            match seen.get(&ip_addr) {
+                Some(first) if shared.insert(ip_addr) => warn!(
+                    ?ip_addr,
+                    first = %first,
+                    second = %report.uplink,
+                    "address is confirmed behind more than one uplink, they may not be separate",
+                ),
+                Some(_) => {}
+                None => {
+                    seen.insert(ip_addr, &report.uplink);
+                }
+            }
+        }
+    }
+
+    reports
 }
 
-async fn fetch(provider: HttpProvider) -> Result<IpAddr, FetchError> {
-    let response = CLIENT
-        .request(provider.request_method(), provider.request_uri())
-        .send()
-        .await?;
+/// Whether every answer of the round is an address the proxy reaches the
+/// Internet from. Nothing tells the proxy's answer from the node's but where
+/// it falls.
+fn seen_through_proxy(reported: &[(Provider, IpAddr)], egress: &[Cidr]) -> bool {
+    !reported.is_empty()
+        && reported
+            .iter()
+            .all(|(_, ip_addr)| in_egress(ip_addr, egress))
+}
 
-    let status = response.status();
-    Span::current().record("http.response.status_code", status.as_u16());
+fn in_egress(ip_addr: &IpAddr, egress: &[Cidr]) -> bool {
+    egress.iter().any(|cidr| cidr.contains(ip_addr))
+}
 
-    if !status.is_success() {
-        return Err(FetchError::HttpStatus(status));
+/// What a provider is asked with beyond its request: its key, what its
+/// handshake presents and insists on, and whether a proxy may stand in
+/// between.
+#[derive(Clone, Copy, Default)]
+struct Access {
+    credential: Option<&'static Credential>,
+    handshake: Handshake,
+    route: Route,
+}
+
+/// What one family's providers came back with.
+#[derive(Default)]
+struct Answers {
+    reported: Vec<(Provider, IpAddr)>,
+    failed: Vec<(Provider, FetchError)>,
+    /// Still out when the verdict was settled, and no longer waited for.
+    skipped: Vec<Provider>,
+    /// Not asked for their gap, and weighed by what they last answered.
+    carried: Vec<(Provider, IpAddr)>,
+}
+
+/// Asks the given [`Provider`] which public IP address it sees us as over
+/// the given family, leaving by the given uplink.
+///
+/// The endpoints are asked in turn, the next one only when the one before
+/// [`FetchError::fails_over`], and all within the one span and the one
+/// recorded duration: to consensus it is a single provider answering or not.
+/// A mirror that was asked is named by `fckloud.provider.mirror` on both,
+/// and `url.full` is the endpoint asked last.
+///
+/// A provider that still failed is asked again as the policy has it, after
+/// a backoff and within [`retry::BUDGET`] of the first request. Every retry
+/// is a `pubip.retry` span of its own under the provider's, naming what it
+/// asked, and the provider's span counts them in `fckloud.retry.count`.
+///
+/// Every kind of provider has a span of its own, named for what it asks: an
+/// `http.request` for the method, as the semantic conventions have it for
+/// client spans, an echo's and a metadata service's among them; a
+/// `stun.request` for the Binding request it makes; a `dns.request` for the
+/// query; a `gateway.request` for the external address it asks the router
+/// for. Those answered without sending anything are `internal` rather than
+/// `client` spans: an `interface.request` for the `getifaddrs` it reads, and
+/// a `node.request` for the field of the published Node it looks up.
+/// Whatever the kind, `server.address` is what tells the providers apart,
+/// and `network.type` the two requests made to each of them.
+async fn get_public_ip(
+    provider: Provider,
+    endpoints: &[&str],
+    access: Access,
+    family: Family,
+    uplink: &Uplink,
+    published: &Published,
+    policy: &Policy,
+) -> Result<IpAddr, FetchError> {
+    let request = provider.request();
+
+    let span = match &request {
+        Request::Http(_) | Request::Echo(_) | Request::Metadata => {
+            let method = match &request {
+                Request::Http(method) => method.clone(),
+                _ => Method::GET,
+            };
+
+            info_span!(
+                "http.request",
+                otel.kind = "client",
+                otel.name = %method,
+                otel.status_code = Empty,
+                http.request.method = %method,
+                http.response.status_code = Empty,
+                network.type = family.as_network_type(),
+                fckloud.uplink = %uplink,
+                server.address = %provider,
+                url.full = Empty,
+                fckloud.provider.mirror = Empty,
+                fckloud.retry.count = Empty,
+                error.type = Empty,
+            )
+        }
+        Request::Stun(server) => info_span!(
+            "stun.request",
+            otel.kind = "client",
+            otel.name = "Binding",
+            otel.status_code = Empty,
+            network.transport = server.transport(),
+            network.type = family.as_network_type(),
+            fckloud.uplink = %uplink,
+            server.address = %provider,
+            server.port = server.port,
+            url.full = Empty,
+            fckloud.retry.count = Empty,
+            error.type = Empty,
+        ),
+        Request::Dns(lookup) => info_span!(
+            "dns.request",
+            otel.kind = "client",
+            otel.name = "QUERY",
+            otel.status_code = Empty,
+            dns.question.name = lookup.name,
+            network.transport = lookup.transport(),
+            network.type = family.as_network_type(),
+            fckloud.uplink = %uplink,
+            server.address = %provider,
+            server.port = lookup.port,
+            url.full = Empty,
+            fckloud.retry.count = Empty,
+            error.type = Empty,
+        ),
+        Request::Gateway(_) => info_span!(
+            "gateway.request",
+            otel.kind = "client",
+            otel.name = "GetExternalAddress",
+            otel.status_code = Empty,
+            network.protocol.name = Empty,
+            network.transport = "udp",
+            network.type = family.as_network_type(),
+            fckloud.uplink = %uplink,
+            server.address = %provider,
+            url.full = Empty,
+            fckloud.retry.count = Empty,
+            error.type = Empty,
+        ),
+        Request::Interface => info_span!(
+            "interface.request",
+            otel.kind = "internal",
+            otel.name = "getifaddrs",
+            otel.status_code = Empty,
+            network.type = family.as_network_type(),
+            fckloud.uplink = %uplink,
+            server.address = %provider,
+            url.full = Empty,
+            fckloud.retry.count = Empty,
+            error.type = Empty,
+        ),
+        Request::Node(key) => info_span!(
+            "node.request",
+            otel.kind = "internal",
+            otel.name = key.field.as_str(),
+            otel.status_code = Empty,
+            network.type = family.as_network_type(),
+            fckloud.uplink = %uplink,
+            server.address = %provider,
+            url.full = Empty,
+            fckloud.retry.count = Empty,
+            error.type = Empty,
+        ),
+    };
+
+    async move {
+        let started = Instant::now();
+        let deadline = started + retry::BUDGET;
+        let mut retried = 0;
+
+        let (result, mirror) = loop {
+            let asking = ask(provider, endpoints, access, family, uplink, published);
+
+            let (result, mirror) = if retried == 0 {
+                asking.await
+            } else {
+                let attempt = info_span!(
+                    "pubip.retry",
+                    otel.kind = "internal",
+                    otel.status_code = Empty,
+                    fckloud.retry.count = retried,
+                    http.response.status_code = Empty,
+                    url.full = Empty,
+                    fckloud.provider.mirror = Empty,
+                    error.type = Empty,
+                );
+
+                // A retry is given what is left of the budget, and no more.
+                let left = deadline.saturating_duration_since(Instant::now());
+                let (result, mirror) = timeout(left, asking.instrument(attempt.clone()))
+                    .await
+                    .unwrap_or((Err(FetchError::Timeout), None));
+
+                if let Err(err) = &result {
+                    attempt
+                        .record("otel.status_code", "ERROR")
+                        .record("error.type", err.as_error_type());
+                }
+
+                (result, mirror)
+            };
+
+            let Err(err) = &result else {
+                break (result, mirror);
+            };
+
+            let backoff = policy.backoff(retried + 1);
+            if !policy.retries_after(err, retried) || Instant::now() + backoff >= deadline {
+                break (result, mirror);
+            }
+
+            debug!(
+                %provider,
+                %family,
+                error.type = err.as_error_type(),
+                %err,
+                backoff = DisplayedDuration::from(backoff).to_string(),
+                "provider cannot be used, asking it again",
+            );
+            metrics::record_retry(provider, family, uplink, err);
+
+            sleep(backoff).await;
+            retried += 1;
+        };
+
+        let elapsed = started.elapsed();
+
+        if retried > 0 {
+            Span::current().record("fckloud.retry.count", retried);
+        }
+
+        if let Err(err) = &result {
+            Span::current()
+                .record("otel.status_code", "ERROR")
+                .record("error.type", err.as_error_type());
+        }
+
+        metrics::record_request(
+            provider,
+            mirror.as_deref(),
+            family,
+            uplink,
+            elapsed,
+            result.as_ref().err(),
+        );
+        result
+    }
+    .instrument(span)
+    .await
+}
+
+/// One attempt at the provider: its endpoints in turn, the next one only
+/// when the one before [`FetchError::fails_over`]. The mirror asked last is
+/// named alongside the answer, when one was asked at all.
+async fn ask(
+    provider: Provider,
+    endpoints: &[&str],
+    access: Access,
+    family: Family,
+    uplink: &Uplink,
+    published: &Published,
+) -> (Result<IpAddr, FetchError>, Option<String>) {
+    let mut mirror = None;
+    let mut result = None;
+
+    for (asked, uri) in endpoints.iter().enumerate() {
+        let url_full = access
+            .credential
+            .map_or_else(|| (*uri).to_owned(), |key| key.redact(uri));
+        Span::current().record("url.full", url_full);
+
+        if asked > 0 {
+            mirror = Url::parse(uri)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_owned));
+            Span::current().record("fckloud.provider.mirror", mirror.as_deref());
+        }
+
+        let answer = fetch(
+            provider,
+            provider.request(),
+            uri,
+            access,
+            family,
+            uplink,
+            published,
+        )
+        .await;
+
+        let next = match &answer {
+            Err(err) if err.fails_over() && asked + 1 < endpoints.len() => {
+                debug!(
+                    %provider,
+                    %family,
+                    error.type = err.as_error_type(),
+                    %err,
+                    "endpoint cannot be used, asking the provider's next mirror",
+                );
+                true
+            }
+            _ => false,
+        };
+
+        result = Some(answer);
+        if !next {
+            break;
+        }
     }
 
-    let body = response.bytes().await?;
-    let ip_addr = provider.response_decode(&body)?;
+    let result = result.expect("a provider is asked at its own endpoint at least");
+    (result, mirror)
+}
+
+async fn fetch(
+    provider: Provider,
+    request: Request,
+    uri: &str,
+    access: Access,
+    family: Family,
+    uplink: &Uplink,
+    published: &Published,
+) -> Result<IpAddr, FetchError> {
+    let ip_addr = match request {
+        Request::Http(method) => {
+            let body = fetch_body(method, uri, access, family, uplink).await?;
+            provider.response_decode(&body, family)?
+        }
+        // Link-local, and so out of reach of any proxy whatever the
+        // environment says.
+        Request::Metadata => {
+            let direct = Access {
+                route: Route::Direct,
+                ..Access::default()
+            };
+            let body = fetch_body(Method::GET, uri, direct, Family::V4, uplink).await?;
+            provider.response_decode(&body, family)?
+        }
+        Request::Echo(echo) => {
+            let nonce = echo.nonce();
+            let url = Echo::url(uri, nonce.as_deref())?;
+            // Signed, not keyed, though the operator's echo may still want
+            // to see a certificate.
+            let access = Access {
+                credential: None,
+                ..access
+            };
+            let body = fetch_body(Method::GET, url, access, family, uplink).await?;
+            echo.decode(&body, nonce.as_deref())?
+        }
+        Request::Stun(server) => stun::query(server, family, uplink).await?,
+        Request::Dns(lookup) => dns::query(lookup, family, uplink).await?,
+        Request::Gateway(gateway) => gateway::query(gateway, uplink).await?,
+        Request::Interface => interface::query(family, uplink).await?,
+        Request::Node(key) => published::query(key, published, family)?,
+    };
 
     // A node's ExternalIP that is not routable on the Internet is a lie,
     // no matter how confidently a provider states it.
@@ -365,5 +1456,619 @@ async fn fetch(provider: HttpProvider) -> Result<IpAddr, FetchError> {
         return Err(FetchError::NotPublic(ip_addr));
     }
 
+    // Whatever sat between the socket and the provider, an answer in the
+    // other family would be counted towards the wrong verdict.
+    if Family::of(&ip_addr) != family {
+        return Err(FetchError::WrongFamily {
+            asked: family,
+            answered: ip_addr,
+        });
+    }
+
     Ok(ip_addr)
 }
+
+/// The body of a successful response. A request carrying a key fails
+/// without its URL, which would carry the key too when it is a query
+/// parameter, and every error is logged.
+async fn fetch_body(
+    method: Method,
+    uri: impl IntoUrl,
+    access: Access,
+    family: Family,
+    uplink: &Uplink,
+) -> Result<Vec<u8>, FetchError> {
+    let request = client::get(uplink, family, access.route, access.handshake).request(method, uri);
+    let scrub = |err: reqwest::Error| match access.credential {
+        Some(_) => err.without_url(),
+        None => err,
+    };
+
+    let response = match access.credential {
+        Some(credential) => credential.apply(request),
+        None => request,
+    }
+    .send()
+    .await
+    .map_err(scrub)?;
+
+    let status = response.status();
+    Span::current().record("http.response.status_code", status.as_u16());
+
+    if !status.is_success() {
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| ratelimit::retry_after(value, SystemTime::now()));
+
+        return Err(FetchError::HttpStatus {
+            status,
+            retry_after,
+        });
+    }
+
+    Ok(response.bytes().await.map_err(scrub)?.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::str::FromStr,
+        tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+        },
+    };
+
+    /// An HTTP server on loopback answering every request with the one body,
+    /// and the address it listens on.
+    async fn stand_in(body: &'static str) -> String {
+        flaky_stand_in(0, body).await
+    }
+
+    /// A stand-in that hangs up on the first few requests without a word,
+    /// the way httpbin.org drops a fifth of them.
+    async fn flaky_stand_in(dropped: usize, body: &'static str) -> String {
+        serve(dropped, "200 OK\r\n", body).await
+    }
+
+    /// As [`flaky_stand_in`], with the status line and headers given.
+    async fn serve(dropped: usize, head: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("loopback must be bindable");
+        let addr = listener.local_addr().expect("it has an address");
+
+        tokio::spawn(async move {
+            let mut accepted = 0;
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+
+                accepted += 1;
+                if accepted <= dropped {
+                    continue;
+                }
+
+                let response = format!(
+                    "HTTP/1.1 {head}content-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len(),
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        format!("http://{addr}/ip")
+    }
+
+    #[tokio::test]
+    async fn a_provider_pointed_at_a_stand_in_is_asked_there_with_its_own_decoder() {
+        let uri = stand_in(r#"{"origin":"1.2.3.4"}"#).await;
+
+        let answered = get_public_ip(
+            Provider::Http(HttpProvider::HttpBin),
+            &[&uri],
+            Access::default(),
+            Family::V4,
+            &Uplink::Default,
+            &Published::default(),
+            &Policy::default(),
+        )
+        .await
+        .expect("the stand-in must be asked");
+
+        assert_eq!(answered, IpAddr::from([1, 2, 3, 4]));
+    }
+
+    #[tokio::test]
+    async fn a_key_in_the_query_is_left_out_of_what_a_failure_says() {
+        let path = std::env::temp_dir().join(format!("fckloud-pubip-key-{}", std::process::id()));
+        std::fs::write(&path, "s3cr3t").expect("temp dir must be writable");
+        let ipgeolocation = Provider::Http(HttpProvider::IpGeolocation);
+        let catalog = Catalog::default()
+            .with_keys(&[("IpGeolocation".to_owned(), KeySource::File(path.clone()))])
+            .expect("IpGeolocation takes a key");
+        let _ = std::fs::remove_file(&path);
+
+        // Nothing listens on a port just let go of.
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("loopback must be bindable")
+            .local_addr()
+            .expect("it has an address");
+
+        let err = get_public_ip(
+            ipgeolocation,
+            &[&format!("http://{closed}/ipgeo")],
+            Access {
+                credential: catalog.credential(ipgeolocation),
+                ..Access::default()
+            },
+            Family::V4,
+            &Uplink::Default,
+            &Published::default(),
+            &Policy::default(),
+        )
+        .await
+        .expect_err("nothing answers there");
+
+        assert!(!format!("{err} {err:?}").contains("s3cr3t"), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn a_handshake_that_fails_is_not_a_provider_out_of_reach() {
+        // A server that answers in plain HTTP is no TLS peer at all.
+        let plain = stand_in(r#"{"origin":"1.2.3.4"}"#).await;
+        let uri = plain.replacen("http:", "https:", 1);
+
+        let err = get_public_ip(
+            Provider::Http(HttpProvider::HttpBin),
+            &[&uri],
+            Access::default(),
+            Family::V4,
+            &Uplink::Default,
+            &Published::default(),
+            &Policy::default(),
+        )
+        .await
+        .expect_err("no handshake can succeed there");
+
+        assert_eq!(err.as_error_type(), "tls", "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn a_provider_that_hung_up_is_asked_again_within_the_round() {
+        let httpbin = Provider::Http(HttpProvider::HttpBin);
+        let once = Policy {
+            retries: 1,
+            backoff: Duration::from_millis(1),
+            ..Policy::default()
+        };
+        let ask = |uri: String, policy: Policy| async move {
+            get_public_ip(
+                httpbin,
+                &[&uri],
+                Access::default(),
+                Family::V4,
+                &Uplink::Default,
+                &Published::default(),
+                &policy,
+            )
+            .await
+        };
+
+        let answered = ask(
+            flaky_stand_in(1, r#"{"origin":"1.2.3.4"}"#).await,
+            once.clone(),
+        )
+        .await
+        .expect("the retry must be answered");
+        assert_eq!(answered, IpAddr::from([1, 2, 3, 4]));
+
+        let err = ask(flaky_stand_in(2, r#"{"origin":"1.2.3.4"}"#).await, once)
+            .await
+            .expect_err("one retry is not two");
+        assert_eq!(err.as_error_type(), "unreachable");
+
+        let err = ask(
+            flaky_stand_in(1, r#"{"origin":"1.2.3.4"}"#).await,
+            Policy::default(),
+        )
+        .await
+        .expect_err("a provider asked once is not asked again");
+        assert_eq!(err.as_error_type(), "unreachable");
+    }
+
+    #[test]
+    fn a_provider_serving_out_a_gap_is_never_retried() {
+        let wtf = Provider::Http(HttpProvider::MyIpWtf);
+        let ipify = Provider::Http(HttpProvider::Ipify);
+        let interface = Provider::Local(LocalProvider::Interface);
+
+        let mut resolver =
+            Resolver::new(vec![wtf, ipify, interface], TrustFactorAuthority::default())
+                .expect("the default share over three providers is valid");
+        resolver.set_retries([(wtf, 3)]);
+
+        assert_eq!(resolver.retry_policy(wtf).retries, 0);
+        assert_eq!(resolver.retry_policy(ipify).retries, 1);
+        assert_eq!(resolver.retry_policy(interface).retries, 0);
+
+        resolver.set_ignore_rate_limits(true);
+        assert_eq!(resolver.retry_policy(wtf).retries, 3);
+    }
+
+    #[tokio::test]
+    async fn a_provider_pushing_back_names_its_wait() {
+        let uri = serve(
+            0,
+            "429 Too Many Requests\r\nretry-after: 120\r\n",
+            "slow down",
+        )
+        .await;
+
+        let err = get_public_ip(
+            Provider::Http(HttpProvider::HttpBin),
+            &[&uri],
+            Access::default(),
+            Family::V4,
+            &Uplink::Default,
+            &Published::default(),
+            &Policy::default(),
+        )
+        .await
+        .expect_err("a 429 is no answer");
+
+        assert!(err.throttled(), "{err:?}");
+        assert_eq!(err.retry_after(), Some(Duration::from_mins(2)));
+    }
+
+    #[test]
+    fn a_gap_stretches_while_a_provider_pushes_back_and_eases_once_it_answers() {
+        let ipify = Provider::Http(HttpProvider::Ipify);
+        let resolver = Resolver::new(vec![ipify], TrustFactorAuthority::default())
+            .expect("the default share over one provider is valid");
+        resolver.asked().insert(ipify, Asked::at(Instant::now()));
+
+        let throttled = |retry_after| FetchError::HttpStatus {
+            status: reqwest::StatusCode::TOO_MANY_REQUESTS,
+            retry_after,
+        };
+        let stretched = || resolver.asked()[&ipify].stretched;
+
+        resolver.adapt_gaps(&[(ipify, Family::V4, Err(throttled(None)))]);
+        assert_eq!(stretched(), Some(ratelimit::MIN_STRETCH));
+
+        // Either family pushing back is enough, and the longer wait holds.
+        resolver.adapt_gaps(&[
+            (
+                ipify,
+                Family::V4,
+                Err(throttled(Some(Duration::from_mins(5)))),
+            ),
+            (
+                ipify,
+                Family::V6,
+                Err(throttled(Some(Duration::from_mins(8)))),
+            ),
+        ]);
+        assert_eq!(stretched(), Some(Duration::from_mins(8)));
+
+        let answered = |family| (ipify, family, Ok(IpAddr::from([1, 2, 3, 4])));
+        resolver.adapt_gaps(&[answered(Family::V4)]);
+        assert_eq!(stretched(), Some(Duration::from_mins(4)));
+
+        // Once a round, however many families it answered over.
+        resolver.adapt_gaps(&[answered(Family::V4), answered(Family::V6)]);
+        assert_eq!(stretched(), Some(Duration::from_mins(2)));
+        resolver.adapt_gaps(&[answered(Family::V4)]);
+        assert_eq!(stretched(), Some(Duration::from_mins(1)));
+        resolver.adapt_gaps(&[answered(Family::V4)]);
+        assert_eq!(stretched(), None);
+    }
+
+    #[test]
+    fn a_provider_pushing_back_over_one_family_is_held_whatever_the_other_answered() {
+        let ipify = Provider::Http(HttpProvider::Ipify);
+        let resolver = Resolver::new(vec![ipify], TrustFactorAuthority::default())
+            .expect("the default share over one provider is valid");
+        resolver.asked().insert(ipify, Asked::at(Instant::now()));
+
+        let throttled = FetchError::HttpStatus {
+            status: reqwest::StatusCode::SERVICE_UNAVAILABLE,
+            retry_after: Some(Duration::from_mins(5)),
+        };
+        resolver.adapt_gaps(&[
+            (ipify, Family::V4, Err(throttled)),
+            (
+                ipify,
+                Family::V6,
+                Ok(IpAddr::from([0x2001, 0xdb8, 0, 0, 0, 0, 0, 1])),
+            ),
+        ]);
+
+        assert_eq!(
+            resolver.asked()[&ipify].stretched,
+            Some(Duration::from_mins(5))
+        );
+    }
+
+    #[test]
+    fn a_provider_whose_breaker_opened_is_neither_asked_nor_enrolled() {
+        let (wtf, ipify) = (
+            Provider::Http(HttpProvider::MyIpWtf),
+            Provider::Http(HttpProvider::Ipify),
+        );
+        let mut resolver = Resolver::new(vec![wtf, ipify], TrustFactorAuthority::default())
+            .expect("the default share over two providers is valid");
+        resolver.set_break_after([("unreachable", 1)]);
+
+        let unreachable = || Err(FetchError::Unreachable("connection refused".into()));
+        resolver.trip_breakers(&[
+            (wtf, Family::V4, unreachable()),
+            (wtf, Family::V6, unreachable()),
+            (ipify, Family::V4, unreachable()),
+            (
+                ipify,
+                Family::V6,
+                Ok(IpAddr::from([0x2001, 0xdb8, 0, 0, 0, 0, 0, 1])),
+            ),
+        ]);
+
+        let (admitted, open) = resolver.admit(Instant::now());
+        assert_eq!(admitted, [ipify]);
+        assert_eq!(open, HashSet::from([wtf]));
+        assert_eq!(
+            resolver.enrolled_trust(Family::V4, &open),
+            resolver.tfa.trust_factor(ipify)
+        );
+    }
+
+    #[test]
+    fn a_provider_cooled_down_but_never_asked_is_left_out_again() {
+        let wtf = Provider::Http(HttpProvider::MyIpWtf);
+        let mut resolver = Resolver::new(vec![wtf], TrustFactorAuthority::default())
+            .expect("the default share over one provider is valid");
+        resolver
+            .set_break_after([("unreachable", 1)])
+            .set_break_cool_down(Duration::from_mins(10));
+
+        resolver.trip_breakers(&[(
+            wtf,
+            Family::V4,
+            Err(FetchError::Unreachable("connection refused".into())),
+        )]);
+        let now = Instant::now();
+
+        let (admitted, _) = resolver.admit(now + Duration::from_mins(10));
+        assert_eq!(admitted, [wtf]);
+
+        // Held by its gap, or cancelled with the round: no answer either way.
+        resolver.trip_breakers(&[]);
+        let (admitted, open) = resolver.admit(now + Duration::from_mins(5));
+        assert!(admitted.is_empty());
+        assert_eq!(open, HashSet::from([wtf]));
+    }
+
+    #[test]
+    fn a_carried_answer_helps_confirm_but_never_answers_the_round_well() {
+        let (httpbin, wtf) = (
+            Provider::Http(HttpProvider::HttpBin),
+            Provider::Http(HttpProvider::MyIpWtf),
+        );
+        let resolver = Resolver::new(vec![httpbin, wtf], TrustFactorAuthority::default())
+            .expect("the default share over two providers is valid");
+        let ip_addr = IpAddr::from([192, 0, 2, 1]);
+        let enrolled = resolver.total_trust(Family::V4);
+
+        let answers = Answers {
+            reported: vec![(httpbin, ip_addr)],
+            ..Answers::default()
+        };
+        let alone = resolver.weigh(Family::V4, enrolled, &answers, &[]);
+        assert!(alone.confirmed.is_empty());
+
+        let answers = Answers {
+            carried: vec![(wtf, ip_addr)],
+            ..answers
+        };
+        let verdict = resolver.weigh(
+            Family::V4,
+            enrolled,
+            &answers,
+            &[(wtf, Duration::from_secs(30))],
+        );
+        assert!(verdict.confirmed.contains(&ip_addr));
+        assert!(!verdict.well_answered);
+        assert_eq!(verdict.carried, [(wtf, ip_addr)]);
+    }
+
+    #[test]
+    fn a_round_is_seen_through_the_proxy_only_when_every_answer_is_its_egress() {
+        let egress = [
+            Cidr::from_str("198.51.100.0/28").expect("test block must parse"),
+            Cidr::from_str("2001:db8:cafe::/48").expect("test block must parse"),
+        ];
+        let ipify = Provider::Http(HttpProvider::Ipify);
+        let seeip = Provider::Http(HttpProvider::SeeIp);
+        let proxy = IpAddr::from([198, 51, 100, 7]);
+        let node = IpAddr::from([203, 0, 113, 9]);
+
+        assert!(seen_through_proxy(
+            &[(ipify, proxy), (seeip, proxy)],
+            &egress
+        ));
+        assert!(!seen_through_proxy(
+            &[(ipify, proxy), (seeip, node)],
+            &egress
+        ));
+        assert!(!seen_through_proxy(&[], &egress));
+        assert!(!seen_through_proxy(&[(ipify, proxy)], &[]));
+    }
+
+    #[test]
+    fn the_proxys_address_is_never_confirmed_however_many_report_it() {
+        let (httpbin, wtf) = (
+            Provider::Http(HttpProvider::HttpBin),
+            Provider::Http(HttpProvider::MyIpWtf),
+        );
+        let mut resolver = Resolver::new(vec![httpbin, wtf], TrustFactorAuthority::default())
+            .expect("the default share over two providers is valid");
+        resolver
+            .set_proxy_egress([Cidr::from_str("198.51.100.0/28").expect("test block must parse")]);
+        let enrolled = resolver.total_trust(Family::V4);
+
+        let proxy = IpAddr::from([198, 51, 100, 7]);
+        let answers = Answers {
+            reported: vec![(httpbin, proxy), (wtf, proxy)],
+            ..Answers::default()
+        };
+        let verdict = resolver.weigh(Family::V4, enrolled, &answers, &[]);
+
+        assert!(verdict.confirmed.is_empty());
+        assert_eq!(verdict.unconfirmed[&proxy], enrolled);
+        assert!(!verdict.well_answered);
+
+        // Outside the proxy's blocks, the same round confirms and counts.
+        let node = IpAddr::from([203, 0, 113, 9]);
+        let answers = Answers {
+            reported: vec![(httpbin, node), (wtf, node)],
+            ..Answers::default()
+        };
+        let verdict = resolver.weigh(Family::V4, enrolled, &answers, &[]);
+
+        assert!(verdict.confirmed.contains(&node));
+        assert!(verdict.well_answered);
+    }
+
+    #[tokio::test]
+    async fn an_endpoint_that_gives_no_answer_fails_over_to_the_next_mirror() {
+        // Nothing listens on a port just let go of.
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("loopback must be bindable")
+            .local_addr()
+            .expect("it has an address");
+        let closed = format!("http://{closed}/ip");
+        let mirror = stand_in(r#"{"origin":"1.2.3.4"}"#).await;
+        let httpbin = Provider::Http(HttpProvider::HttpBin);
+
+        let answered = get_public_ip(
+            httpbin,
+            &[&closed, &mirror],
+            Access::default(),
+            Family::V4,
+            &Uplink::Default,
+            &Published::default(),
+            &Policy::default(),
+        )
+        .await
+        .expect("the mirror must be asked once the endpoint is unreachable");
+        assert_eq!(answered, IpAddr::from([1, 2, 3, 4]));
+
+        // An answer that came back wrong is the provider's, not the endpoint's.
+        let wrong = stand_in("<html>busy</html>").await;
+        let err = get_public_ip(
+            httpbin,
+            &[&wrong, &mirror],
+            Access::default(),
+            Family::V4,
+            &Uplink::Default,
+            &Published::default(),
+            &Policy::default(),
+        )
+        .await
+        .expect_err("a body that is no answer must not fail over");
+        assert_eq!(err.as_error_type(), "decode");
+    }
+
+    #[tokio::test]
+    async fn a_stand_in_is_held_to_what_the_provider_is_held_to() {
+        let uri = stand_in(r#"{"origin":"10.0.0.1"}"#).await;
+
+        let err = get_public_ip(
+            Provider::Http(HttpProvider::HttpBin),
+            &[&uri],
+            Access::default(),
+            Family::V4,
+            &Uplink::Default,
+            &Published::default(),
+            &Policy::default(),
+        )
+        .await
+        .expect_err("a private address must not be believed from anywhere");
+
+        assert_eq!(err.as_error_type(), "not_public");
+    }
+
+    #[tokio::test]
+    async fn a_node_provider_answers_with_what_the_node_carries() {
+        let published = Published::new(
+            [(
+                "k3s.io/external-ip".to_owned(),
+                "1.2.3.4,2a01:4f8::1".to_owned(),
+            )]
+            .into(),
+            [].into(),
+        );
+        let k3s = Provider::Node(NodeProvider::K3s);
+
+        let answered = get_public_ip(
+            k3s,
+            &[k3s.request_uri()],
+            Access::default(),
+            Family::V6,
+            &Uplink::Default,
+            &published,
+            &Policy::default(),
+        )
+        .await
+        .expect("the annotation lists an IPv6 address");
+
+        assert_eq!(
+            answered,
+            "2a01:4f8::1".parse::<IpAddr>().expect("it parses")
+        );
+
+        let err = get_public_ip(
+            k3s,
+            &[k3s.request_uri()],
+            Access::default(),
+            Family::V4,
+            &Uplink::Default,
+            &Published::default(),
+            &Policy::default(),
+        )
+        .await
+        .expect_err("a Node without the annotation has nothing to say");
+        assert_eq!(err.as_error_type(), "not_published");
+    }
+
+    #[tokio::test]
+    async fn a_metadata_service_is_asked_over_ipv4_for_either_family() {
+        let uri = stand_in(
+            r#"{"interfaces":{"public":[{"ipv4":{"ip_address":"1.2.3.4"},
+                "ipv6":{"ip_address":"2a01:4f8::1"}}]}}"#,
+        )
+        .await;
+
+        let answered = get_public_ip(
+            Provider::Metadata(MetadataProvider::DigitalOcean),
+            &[&uri],
+            Access::default(),
+            Family::V6,
+            &Uplink::Default,
+            &Published::default(),
+            &Policy::default(),
+        )
+        .await
+        .expect("the stand-in must be asked over IPv4 loopback");
+
+        assert_eq!(
+            answered,
+            "2a01:4f8::1".parse::<IpAddr>().expect("it parses")
+        );
+    }
+}
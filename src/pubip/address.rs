@@ -1,4 +1,63 @@
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+/// A block of addresses, `203.0.113.0/24` or `2001:db8::/32`; a bare address
+/// is a block of one.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        match (self.network, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(*addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(*addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let network = IpAddr::from_str(addr).map_err(|err| format!("`{addr}`: {err}"))?;
+        let longest = if network.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix.map(u8::from_str) {
+            None => longest,
+            Some(Ok(prefix)) if prefix <= longest => prefix,
+            Some(_) => return Err(format!("`{s}` has no prefix length {addr} can have")),
+        };
+
+        Ok(Self { network, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
 
 /// Reports whether the rest of the Internet could route to this address.
 ///
@@ -52,7 +111,7 @@ fn is_public_v6(addr: &Ipv6Addr) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use {super::*, std::str::FromStr};
+    use super::*;
 
     fn public(s: &str) -> bool {
         is_public(&IpAddr::from_str(s).expect("test address must parse"))
@@ -112,4 +171,30 @@ mod tests {
         assert!(public("2001:0db9::1"));
         assert!(public("fbff::1"));
     }
+
+    #[test]
+    fn a_block_holds_the_addresses_its_prefix_covers_and_no_others() {
+        let cidr = |s: &str| Cidr::from_str(s).unwrap_or_else(|err| panic!("`{s}`: {err}"));
+        let ip = |s: &str| IpAddr::from_str(s).expect("test address must parse");
+
+        let block = cidr("203.0.113.0/24");
+        assert!(block.contains(&ip("203.0.113.200")));
+        assert!(!block.contains(&ip("203.0.114.1")));
+        assert!(!block.contains(&ip("::ffff:203.0.113.1")));
+
+        assert!(cidr("2001:db8::/32").contains(&ip("2001:db8:ff::1")));
+        assert!(cidr("198.51.100.7").contains(&ip("198.51.100.7")));
+        assert!(!cidr("198.51.100.7").contains(&ip("198.51.100.8")));
+        assert!(cidr("0.0.0.0/0").contains(&ip("8.8.8.8")));
+        assert_eq!(cidr("198.51.100.7").to_string(), "198.51.100.7/32");
+
+        for wrong in [
+            "203.0.113.0/33",
+            "2001:db8::/129",
+            "proxy/24",
+            "203.0.113.0/",
+        ] {
+            assert!(Cidr::from_str(wrong).is_err(), "`{wrong}` is taken");
+        }
+    }
 }
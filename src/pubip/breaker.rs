@@ -0,0 +1,288 @@
+use {
+    crate::pubip::error::FetchError,
+    std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    },
+};
+
+/// How long a provider whose breaker opened is left alone before it is
+/// asked again to see whether it recovered. Long enough that a provider gone
+/// for the afternoon costs a handful of requests an hour, short enough that
+/// one back within the hour is trusted again within the hour.
+pub const COOL_DOWN: Duration = Duration::from_mins(15);
+
+/// The rounds in a row a provider may fail so before its breaker opens. One
+/// out of reach is given a while, there being no telling a provider gone
+/// from a network that is. One answering an address nobody can reach it at
+/// is not given as long: an answer that wrong is not bad luck.
+pub const TRIPS: [(&str, u32); 3] = [("timeout", 10), ("unreachable", 10), ("not_public", 3)];
+
+/// Where a provider's breaker stands.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum State {
+    /// Asked every round, as if there were no breaker.
+    #[default]
+    Closed,
+    /// Not asked, and its trust not enrolled, until the cool-down is over.
+    Open,
+    /// Asked once more, and closed or opened again by how that went. A probe
+    /// the round never made, the provider being held by its gap, cancelled
+    /// with a round that settled without it, or only pushing back, says
+    /// nothing either way: the breaker opens again for another cool-down,
+    /// with no failure counted, rather than pass for closed.
+    HalfOpen,
+}
+
+impl State {
+    pub const ALL: [Self; 3] = [Self::Closed, Self::Open, Self::HalfOpen];
+
+    /// The `fckloud.breaker.state` attribute value.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// When a provider's breaker opens, and for how long.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Policy {
+    /// The [`FetchError::as_error_type`] a provider may fail with, and the
+    /// rounds in a row it may before the breaker opens. A type not listed
+    /// never opens it.
+    pub trips: Vec<(&'static str, u32)>,
+    pub cool_down: Duration,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            trips: TRIPS.to_vec(),
+            cool_down: COOL_DOWN,
+        }
+    }
+}
+
+impl Policy {
+    fn trips_after(&self, kind: &str) -> Option<u32> {
+        self.trips
+            .iter()
+            .find(|(tripping, _)| *tripping == kind)
+            .map(|(_, count)| *count)
+    }
+}
+
+/// The error type named, and the rounds in a row a provider may fail with it
+/// before its breaker opens.
+pub fn parse_trip(s: &str) -> Result<(&'static str, u32), String> {
+    let (kind, count) = s
+        .split_once('=')
+        .ok_or_else(|| format!("`{s}` is not TYPE=COUNT"))?;
+
+    let kind = FetchError::TYPES
+        .into_iter()
+        .find(|known| known.eq_ignore_ascii_case(kind.trim()))
+        .ok_or_else(|| {
+            format!(
+                "`{}` is not an error type: {}",
+                kind.trim(),
+                FetchError::TYPES.join(", ")
+            )
+        })?;
+
+    let count = count
+        .trim()
+        .parse::<u32>()
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or_else(|| format!("`{}` is not a number of rounds", count.trim()))?;
+
+    Ok((kind, count))
+}
+
+/// One provider's breaker. Every transition is returned by the call that
+/// made it, so that it is logged once, where it happened, and never again.
+///
+/// Pure on purpose, like the rate limits beside it: a cool-down that has or
+/// has not elapsed is arithmetic, and a test should not need a clock.
+#[derive(Clone, Debug, Default)]
+pub struct Breaker {
+    state: State,
+    /// Rounds in a row the provider failed with each error type. An answer
+    /// starts every count over, and a round failed otherwise the count of
+    /// each type it did not fail with.
+    failures: HashMap<&'static str, u32>,
+    opened: Option<Instant>,
+}
+
+impl Breaker {
+    /// Whether the provider may be asked this round. An open breaker whose
+    /// cool-down is over goes half-open and lets the provider through once.
+    pub fn admit(&mut self, policy: &Policy, now: Instant) -> (bool, Option<State>) {
+        let cooled = self
+            .opened
+            .is_some_and(|opened| now.saturating_duration_since(opened) >= policy.cool_down);
+
+        match self.state {
+            State::Open if cooled => (true, self.turn(State::HalfOpen)),
+            State::Open => (false, None),
+            State::Closed | State::HalfOpen => (true, None),
+        }
+    }
+
+    /// The provider answered: whatever it failed with before is forgotten.
+    pub fn answered(&mut self) -> Option<State> {
+        self.failures.clear();
+        self.opened = None;
+        self.turn(State::Closed)
+    }
+
+    /// The provider gave no answer in any family, failing with these types.
+    /// A probe that fails opens the breaker again whatever the failure was.
+    pub fn failed(
+        &mut self,
+        kinds: impl IntoIterator<Item = &'static str>,
+        policy: &Policy,
+        now: Instant,
+    ) -> Option<State> {
+        let kinds: Vec<&'static str> = kinds.into_iter().collect();
+        let mut tripped = self.state == State::HalfOpen;
+
+        self.failures.retain(|kind, _| kinds.contains(kind));
+        for kind in kinds {
+            let failures = self.failures.entry(kind).or_default();
+            *failures += 1;
+
+            tripped |= policy
+                .trips_after(kind)
+                .is_some_and(|count| *failures >= count);
+        }
+
+        if !tripped {
+            return None;
+        }
+
+        self.opened = Some(now);
+        self.turn(State::Open)
+    }
+
+    /// The round neither heard from the provider nor saw it fail. Only a
+    /// half-open breaker cares: its probe was never made, so it opens again
+    /// for another cool-down, see [`State::HalfOpen`].
+    pub fn unprobed(&mut self, now: Instant) -> Option<State> {
+        if self.state != State::HalfOpen {
+            return None;
+        }
+
+        self.opened = Some(now);
+        self.turn(State::Open)
+    }
+
+    fn turn(&mut self, state: State) -> Option<State> {
+        (self.state != state).then(|| {
+            self.state = state;
+            state
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> Policy {
+        Policy {
+            trips: vec![("unreachable", 3), ("not_public", 1)],
+            cool_down: Duration::from_mins(10),
+        }
+    }
+
+    #[test]
+    fn a_breaker_opens_after_the_rounds_in_a_row_its_error_type_allows() {
+        let (policy, now) = (policy(), Instant::now());
+        let mut breaker = Breaker::default();
+
+        assert_eq!(breaker.failed(["unreachable"], &policy, now), None);
+        assert_eq!(breaker.failed(["unreachable"], &policy, now), None);
+        assert_eq!(breaker.answered(), None);
+
+        // An answer in between starts the count over, and so does a round
+        // failed another way.
+        assert_eq!(breaker.failed(["unreachable"], &policy, now), None);
+        assert_eq!(breaker.failed(["unreachable"], &policy, now), None);
+        assert_eq!(breaker.failed(["timeout"], &policy, now), None);
+        assert_eq!(breaker.failed(["unreachable"], &policy, now), None);
+        assert_eq!(breaker.failed(["unreachable"], &policy, now), None);
+        assert_eq!(
+            breaker.failed(["unreachable", "timeout"], &policy, now),
+            Some(State::Open)
+        );
+        assert_eq!(breaker.admit(&policy, now), (false, None));
+
+        let mut liar = Breaker::default();
+        assert_eq!(liar.failed(["not_public"], &policy, now), Some(State::Open));
+    }
+
+    #[test]
+    fn an_open_breaker_lets_one_probe_through_once_it_has_cooled_down() {
+        let (policy, now) = (policy(), Instant::now());
+        let later = now + Duration::from_mins(10);
+        let mut breaker = Breaker::default();
+        breaker.failed(["not_public"], &policy, now);
+
+        assert_eq!(
+            breaker.admit(&policy, now + Duration::from_mins(9)),
+            (false, None)
+        );
+        assert_eq!(breaker.admit(&policy, later), (true, Some(State::HalfOpen)));
+
+        // A failed probe opens it for another cool-down, whatever failed.
+        assert_eq!(
+            breaker.failed(["http_status"], &policy, later),
+            Some(State::Open)
+        );
+        assert_eq!(breaker.admit(&policy, later), (false, None));
+
+        let again = later + Duration::from_mins(10);
+        assert_eq!(breaker.admit(&policy, again), (true, Some(State::HalfOpen)));
+        assert_eq!(breaker.answered(), Some(State::Closed));
+        assert_eq!(
+            breaker.failed(["not_public"], &policy, again),
+            Some(State::Open)
+        );
+    }
+
+    #[test]
+    fn a_probe_never_made_opens_the_breaker_for_another_cool_down() {
+        let (policy, now) = (policy(), Instant::now());
+        let later = now + Duration::from_mins(10);
+        let mut breaker = Breaker::default();
+        breaker.failed(["not_public"], &policy, now);
+
+        assert_eq!(Breaker::default().unprobed(now), None);
+        assert_eq!(breaker.unprobed(now), None);
+
+        assert_eq!(breaker.admit(&policy, later), (true, Some(State::HalfOpen)));
+        assert_eq!(breaker.unprobed(later), Some(State::Open));
+        assert_eq!(
+            breaker.admit(&policy, later + Duration::from_mins(9)),
+            (false, None)
+        );
+        assert_eq!(
+            breaker.admit(&policy, later + Duration::from_mins(10)),
+            (true, Some(State::HalfOpen))
+        );
+    }
+
+    #[test]
+    fn a_trip_names_a_known_error_type_and_a_number_of_rounds() {
+        assert_eq!(parse_trip("Unreachable=5"), Ok(("unreachable", 5)));
+        assert!(parse_trip("unreachable").is_err());
+        assert!(parse_trip("flaky=5").is_err());
+        assert!(parse_trip("timeout=0").is_err());
+        assert!(parse_trip("timeout=often").is_err());
+    }
+}
@@ -0,0 +1,1212 @@
+use {
+    crate::pubip::{
+        DnsProvider, Family, HttpProvider, LocalProvider, MetadataProvider, NodeProvider, Provider,
+        StunProvider, TrustFactorAuthority,
+        credential::{Credential, KeySource, Placement},
+        echo::{Echo, Secret},
+        error::FetchError,
+        provider::{Request, decode_text},
+        published::Key,
+        set::{self, Rejected},
+        stun::Server,
+        tls::{ClientIdentity, Pin},
+    },
+    anyhow::{Context as _, Result, bail, ensure},
+    humantime::parse_duration,
+    reqwest::{Method, Url},
+    serde::{Deserialize, de::Error as _},
+    std::{
+        fs, iter,
+        net::IpAddr,
+        path::{Path, PathBuf},
+        str::FromStr,
+        time::Duration,
+    },
+    strum::VariantArray,
+};
+
+/// Every provider a run knows of: the compiled-in ones, and whatever the
+/// operator declared beside them in a file. Also where the operator pointed
+/// any of them other than where they point themselves.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Catalog {
+    declared: &'static [Declared],
+    endpoints: &'static [(Provider, String)],
+    credentials: &'static [(Provider, Credential)],
+    identities: &'static [(Provider, ClientIdentity)],
+    pins: &'static [(Provider, Vec<Pin>)],
+}
+
+/// A provider read from the providers file rather than compiled in.
+///
+/// Nothing here is known beyond what the operator wrote down, so nothing is
+/// assumed either: an entry that does not say it answers over IPv6 is never
+/// asked over it, and one that does not state a trust factor carries the
+/// lowest there is. The one exception is an echo whose answers are signed:
+/// its operator is the operator of the cluster, and what it says cannot have
+/// been changed on the way.
+#[derive(PartialEq, Eq, Hash, Debug)]
+pub struct Declared {
+    pub name: String,
+    pub host: String,
+    pub uri: String,
+    pub mirrors: Vec<String>,
+    pub protocol: Protocol,
+    pub trust_factor: usize,
+    pub rate_limit: Option<Duration>,
+    pub ipv6: bool,
+    pub enabled_by_default: bool,
+    pub terms: Option<String>,
+    pub summary: String,
+}
+
+/// How a declared provider is asked, which its URI's scheme and its
+/// `response` decide.
+#[derive(PartialEq, Eq, Hash, Debug)]
+pub enum Protocol {
+    Http {
+        method: Method,
+        format: Format,
+        key_placement: Option<Placement>,
+    },
+    Stun(Server),
+    Echo(Echo),
+    Node(Key),
+}
+
+/// How a declared provider's body turns into an address.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Format {
+    /// A JSON document, the address being the string the pointer leads to.
+    Json(String),
+    /// The address and nothing else, whitespace around it aside.
+    Text,
+}
+
+/// The file as written: a list under `providers`, and room beside it for
+/// whatever a later version will want to put there.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    providers: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    name: String,
+    uri: String,
+    #[serde(default)]
+    mirrors: Vec<String>,
+    #[serde(default)]
+    method: Option<String>,
+    #[serde(default)]
+    response: Option<Response>,
+    #[serde(default)]
+    pointer: Option<String>,
+    #[serde(default)]
+    secret_file: Option<PathBuf>,
+    #[serde(default)]
+    key_placement: Option<String>,
+    #[serde(default)]
+    trust_factor: Option<usize>,
+    #[serde(default)]
+    rate_limit: Option<String>,
+    #[serde(default)]
+    ipv6: bool,
+    #[serde(default = "enabled")]
+    enabled_by_default: bool,
+    #[serde(default)]
+    terms: Option<String>,
+    #[serde(default)]
+    summary: Option<String>,
+}
+
+/// What `response` says, the pointer being a field of its own: a tagged
+/// enum is spelled `!json /ip` in YAML, which nobody writes by hand.
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Response {
+    Json,
+    Text,
+    /// An `fckloud echo`, whose shape is known and whose answers may be
+    /// signed.
+    Echo,
+}
+
+const fn enabled() -> bool {
+    true
+}
+
+impl Catalog {
+    /// Reads the providers file, YAML or JSON alike, and refuses the whole of
+    /// it over a single bad entry: a provider quietly missing from the pool is
+    /// trust nobody knows they lost.
+    ///
+    /// Read once, at startup; a changed file takes a restart to be noticed.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("cannot read providers file {}", path.display()))?;
+
+        Self::parse(&text).with_context(|| format!("cannot use providers file {}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let file: File = serde_yaml::from_str(text)?;
+
+        let mut declared: Vec<Declared> = Vec::with_capacity(file.providers.len());
+        for entry in file.providers {
+            let name = entry.name.clone();
+            let provider = Declared::try_from(entry)
+                .with_context(|| format!("provider {name} is declared wrongly"))?;
+
+            ensure!(
+                !declared
+                    .iter()
+                    .any(|other| other.name.eq_ignore_ascii_case(&provider.name)),
+                "provider {name} is declared twice",
+            );
+
+            declared.push(provider);
+        }
+
+        // Leaked on purpose: the table is read once and referred to by every
+        // round until the process exits, and a `'static` borrow is what lets a
+        // declared provider be as cheap to pass around as a compiled-in one.
+        Ok(Self {
+            declared: Box::leak(declared.into_boxed_slice()),
+            ..Self::default()
+        })
+    }
+
+    /// Points providers somewhere other than their own endpoint, each keeping
+    /// its decoder, trust factor and rate limit: a self-hosted httpbin is
+    /// still httpbin as far as consensus can tell.
+    ///
+    /// Only a provider asked over HTTP has an endpoint to replace, and the URL
+    /// replaces its mirrors with it: a provider pointed inside the operator's
+    /// network is not meant to fall back on the Internet. Plain `http` is
+    /// taken, unlike in the providers file, because an instance in the
+    /// operator's own network rarely has a certificate for its name - but not
+    /// for a provider asked with a key, which it would send in the clear. A
+    /// signed echo is asked with none, its secret never leaving the node.
+    pub fn with_endpoints(self, urls: &[(String, Url)]) -> Result<Self> {
+        let mut endpoints: Vec<(Provider, String)> = Vec::with_capacity(urls.len());
+
+        for (name, url) in urls {
+            let provider = self
+                .find(name)
+                .with_context(|| format!("provider {name} not found"))?;
+
+            ensure!(
+                matches!(
+                    provider.request(),
+                    Request::Http(_) | Request::Echo(_) | Request::Metadata
+                ),
+                "provider {name} is not asked over HTTP and has no URL to replace",
+            );
+            ensure!(
+                matches!(url.scheme(), "http" | "https"),
+                "the URL of provider {name} must be http or https",
+            );
+            ensure!(
+                url.scheme() == "https" || provider.key_placement().is_none(),
+                "the URL of provider {name} must be https, plain http would send its key in the clear",
+            );
+            ensure!(
+                !endpoints.iter().any(|(other, _)| *other == provider),
+                "provider {name} is given two URLs",
+            );
+
+            endpoints.push((provider, url.to_string()));
+        }
+
+        // Leaked for the reason the declared table is.
+        Ok(Self {
+            endpoints: Box::leak(endpoints.into_boxed_slice()),
+            ..self
+        })
+    }
+
+    /// Reads the key of every keyed provider the operator gave one for,
+    /// placed the way the provider's definition says.
+    ///
+    /// A key for a provider that takes none is refused rather than ignored:
+    /// it is a secret handed to somebody who was never meant to have it, or a
+    /// typo in the name of the one that was.
+    pub fn with_keys(self, keys: &[(String, KeySource)]) -> Result<Self> {
+        let mut credentials: Vec<(Provider, Credential)> = Vec::with_capacity(keys.len());
+
+        for (name, source) in keys {
+            let provider = self
+                .find(name)
+                .with_context(|| format!("provider {name} not found"))?;
+
+            let Some(placement) = provider.key_placement() else {
+                bail!("provider {name} takes no key");
+            };
+            ensure!(
+                !credentials.iter().any(|(other, _)| *other == provider),
+                "provider {name} is given two keys",
+            );
+
+            let credential = Credential::read(placement, source)
+                .with_context(|| format!("cannot read the key of provider {name}"))?;
+            credentials.push((provider, credential));
+        }
+
+        // Leaked for the reason the declared table is.
+        Ok(Self {
+            credentials: Box::leak(credentials.into_boxed_slice()),
+            ..self
+        })
+    }
+
+    /// Reads the client certificate and key of every provider the operator
+    /// gave a pair for, presented whenever it is asked from then on.
+    ///
+    /// Only a provider asked over HTTPS has a handshake to present one in,
+    /// and a certificate without its key, or a key without its certificate,
+    /// is refused rather than sent half.
+    pub fn with_identities(
+        self,
+        certs: &[(String, PathBuf)],
+        keys: &[(String, PathBuf)],
+    ) -> Result<Self> {
+        let mut identities: Vec<(Provider, ClientIdentity)> = Vec::with_capacity(certs.len());
+
+        for (name, _) in keys {
+            ensure!(
+                certs
+                    .iter()
+                    .any(|(other, _)| other.eq_ignore_ascii_case(name)),
+                "provider {name} is given a client key without its certificate",
+            );
+        }
+
+        for (name, cert) in certs {
+            let provider = self
+                .find(name)
+                .with_context(|| format!("provider {name} not found"))?;
+
+            ensure!(
+                matches!(provider.request(), Request::Http(_) | Request::Echo(_)),
+                "provider {name} is not asked over HTTP and has no handshake to present a certificate in",
+            );
+            ensure!(
+                !identities.iter().any(|(other, _)| *other == provider),
+                "provider {name} is given two client certificates",
+            );
+
+            let mut paired = keys
+                .iter()
+                .filter(|(other, _)| self.find(other) == Some(provider));
+            let Some((_, key)) = paired.next() else {
+                bail!("provider {name} is given a client certificate without its key");
+            };
+            ensure!(
+                paired.next().is_none(),
+                "provider {name} is given two client keys",
+            );
+
+            let identity = ClientIdentity::read(cert, key).with_context(|| {
+                format!("cannot read the client certificate of provider {name}")
+            })?;
+            identities.push((provider, identity));
+        }
+
+        // Leaked for the reason the declared table is.
+        Ok(Self {
+            identities: Box::leak(identities.into_boxed_slice()),
+            ..self
+        })
+    }
+
+    /// The client certificate the provider is asked with, if it was given one.
+    pub fn identity(self, provider: Provider) -> Option<&'static ClientIdentity> {
+        self.identities
+            .iter()
+            .find(|(presenting, _)| *presenting == provider)
+            .map(|(_, identity)| identity)
+    }
+
+    /// Every provider given a client certificate, and the certificate.
+    pub fn identities(self) -> impl Iterator<Item = (Provider, &'static ClientIdentity)> {
+        self.identities
+            .iter()
+            .map(|(provider, identity)| (*provider, identity))
+    }
+
+    /// Pins providers to the keys the operator gave for them, [`None`] for
+    /// none at all, in place of any the provider carries of its own.
+    ///
+    /// A provider asked over plain HTTP anywhere has no certificate to pin,
+    /// and is refused rather than asked there unpinned.
+    pub fn with_pins(self, given: &[(String, Option<Pin>)]) -> Result<Self> {
+        let mut pins: Vec<(Provider, Vec<Pin>)> = Vec::new();
+
+        for (name, pin) in given {
+            let provider = self
+                .find(name)
+                .with_context(|| format!("provider {name} not found"))?;
+
+            ensure!(
+                matches!(provider.request(), Request::Http(_) | Request::Echo(_)),
+                "provider {name} is not asked over HTTP and has no certificate to pin",
+            );
+            ensure!(
+                iter::once(self.request_uri(provider))
+                    .chain(self.mirrors(provider))
+                    .all(|uri| uri.starts_with("https:")),
+                "provider {name} is asked over plain HTTP, with no certificate to pin",
+            );
+
+            // `none` stands alone: it lifts the pins rather than adding one.
+            match pins.iter_mut().find(|(other, _)| *other == provider) {
+                Some((_, pinned)) if pin.is_some() && !pinned.is_empty() => {
+                    pinned.extend(*pin);
+                }
+                Some(_) => bail!("provider {name} is pinned to keys and to none at once"),
+                None => pins.push((provider, pin.iter().copied().collect())),
+            }
+        }
+
+        // Leaked for the reason the declared table is.
+        Ok(Self {
+            pins: Box::leak(pins.into_boxed_slice()),
+            ..self
+        })
+    }
+
+    /// The keys one of the provider's certificates must be issued under: the
+    /// operator's when given, its own unless it was pointed elsewhere, which
+    /// they would not hold for.
+    pub fn pinned(self, provider: Provider) -> &'static [Pin] {
+        if let Some((_, pins)) = self.pins.iter().find(|(pinned, _)| *pinned == provider) {
+            return pins;
+        }
+
+        if self
+            .endpoints
+            .iter()
+            .any(|(replaced, _)| *replaced == provider)
+        {
+            return &[];
+        }
+
+        provider.pins()
+    }
+
+    /// Every provider pinned to any key, and the keys.
+    pub fn pins(self) -> impl Iterator<Item = (Provider, &'static [Pin])> {
+        self.all()
+            .map(move |provider| (provider, self.pinned(provider)))
+            .filter(|(_, pins)| !pins.is_empty())
+    }
+
+    /// The key the provider is asked with, if it was given one.
+    pub fn credential(self, provider: Provider) -> Option<&'static Credential> {
+        self.credentials
+            .iter()
+            .find(|(keyed, _)| *keyed == provider)
+            .map(|(_, credential)| credential)
+    }
+
+    /// Every provider given a key, and the key.
+    pub fn credentials(self) -> impl Iterator<Item = (Provider, &'static Credential)> {
+        self.credentials
+            .iter()
+            .map(|(provider, credential)| (*provider, credential))
+    }
+
+    /// Whether the provider can be asked at all: one that takes a key can
+    /// only once it has been given one.
+    pub fn can_ask(self, provider: Provider) -> bool {
+        provider.key_placement().is_none() || self.credential(provider).is_some()
+    }
+
+    /// Where the provider is asked: the operator's URL when one was given,
+    /// its own otherwise.
+    pub fn request_uri(self, provider: Provider) -> &'static str {
+        self.endpoints
+            .iter()
+            .find(|(replaced, _)| *replaced == provider)
+            .map_or_else(|| provider.request_uri(), |(_, url)| url.as_str())
+    }
+
+    /// Where the provider is asked after its endpoint fails, over either
+    /// family: none once the operator pointed it elsewhere.
+    pub fn mirrors(self, provider: Provider) -> Vec<&'static str> {
+        if self
+            .endpoints
+            .iter()
+            .any(|(replaced, _)| *replaced == provider)
+        {
+            return Vec::new();
+        }
+
+        let mut mirrors = provider.mirrors(Family::V4);
+        for mirror in provider.mirrors(Family::V6) {
+            if !mirrors.contains(&mirror) {
+                mirrors.push(mirror);
+            }
+        }
+
+        mirrors
+    }
+
+    /// Every provider the operator pointed elsewhere, and where to.
+    pub fn endpoints(self) -> impl Iterator<Item = (Provider, &'static str)> {
+        self.endpoints
+            .iter()
+            .map(|(provider, url)| (*provider, url.as_str()))
+    }
+
+    /// The compiled-in providers first, HTTP, STUN, DNS, local, metadata then
+    /// Node and each in the order they were added, then the declared ones in
+    /// the order the file lists them.
+    pub fn all(self) -> impl Iterator<Item = Provider> {
+        <HttpProvider as VariantArray>::VARIANTS
+            .iter()
+            .copied()
+            .map(Provider::Http)
+            .chain(
+                <StunProvider as VariantArray>::VARIANTS
+                    .iter()
+                    .copied()
+                    .map(Provider::Stun),
+            )
+            .chain(
+                <DnsProvider as VariantArray>::VARIANTS
+                    .iter()
+                    .copied()
+                    .map(Provider::Dns),
+            )
+            .chain(
+                <LocalProvider as VariantArray>::VARIANTS
+                    .iter()
+                    .copied()
+                    .map(Provider::Local),
+            )
+            .chain(
+                <MetadataProvider as VariantArray>::VARIANTS
+                    .iter()
+                    .copied()
+                    .map(Provider::Metadata),
+            )
+            .chain(
+                <NodeProvider as VariantArray>::VARIANTS
+                    .iter()
+                    .copied()
+                    .map(Provider::Node),
+            )
+            .chain(self.declared.iter().map(Provider::Declared))
+    }
+
+    /// The provider going by that name, whatever its case.
+    pub fn find(self, name: &str) -> Option<Provider> {
+        HttpProvider::from_str(name)
+            .map(Provider::Http)
+            .or_else(|_| StunProvider::from_str(name).map(Provider::Stun))
+            .or_else(|_| DnsProvider::from_str(name).map(Provider::Dns))
+            .or_else(|_| LocalProvider::from_str(name).map(Provider::Local))
+            .or_else(|_| MetadataProvider::from_str(name).map(Provider::Metadata))
+            .or_else(|_| NodeProvider::from_str(name).map(Provider::Node))
+            .ok()
+            .or_else(|| {
+                self.declared
+                    .iter()
+                    .find(|declared| declared.name.eq_ignore_ascii_case(name))
+                    .map(Provider::Declared)
+            })
+    }
+}
+
+impl TryFrom<Entry> for Declared {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: Entry) -> Result<Self> {
+        let name = entry.name.trim().to_owned();
+
+        ensure!(
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+            "a name is letters, digits, `-` and `_`",
+        );
+
+        // A name that already means something to `--providers` would make
+        // that flag mean two things depending on whether the file is there.
+        ensure!(
+            HttpProvider::from_str(&name).is_err()
+                && StunProvider::from_str(&name).is_err()
+                && DnsProvider::from_str(&name).is_err()
+                && LocalProvider::from_str(&name).is_err()
+                && MetadataProvider::from_str(&name).is_err()
+                && NodeProvider::from_str(&name).is_err(),
+            "the name is taken by a built-in provider",
+        );
+        ensure!(
+            matches!(set::parse(&name), Err(Rejected::Unknown(_))),
+            "the name is taken by a provider set",
+        );
+
+        let scheme = entry
+            .uri
+            .split_once(':')
+            .map(|(scheme, _)| scheme.to_ascii_lowercase());
+
+        let (host, protocol) = if let Some("annotation" | "label") = scheme.as_deref() {
+            ensure!(
+                entry.method.is_none()
+                    && entry.response.is_none()
+                    && entry.pointer.is_none()
+                    && entry.secret_file.is_none()
+                    && entry.key_placement.is_none()
+                    && entry.mirrors.is_empty(),
+                "a Node annotation or label has no method, response, pointer, secret, key or mirror to state",
+            );
+
+            let key = Key::from_str(&entry.uri).map_err(anyhow::Error::msg)?;
+            (key.name.to_string(), Protocol::Node(key))
+        } else if let Some("stun" | "stuns") = scheme.as_deref() {
+            ensure!(
+                entry.method.is_none()
+                    && entry.response.is_none()
+                    && entry.pointer.is_none()
+                    && entry.secret_file.is_none()
+                    && entry.key_placement.is_none()
+                    && entry.mirrors.is_empty(),
+                "a STUN server has no method, response, pointer, secret, key or mirror to state",
+            );
+
+            let server = Server::from_str(&entry.uri).map_err(anyhow::Error::msg)?;
+            (server.host.to_string(), Protocol::Stun(server))
+        } else {
+            Self::http(&entry)?
+        };
+
+        let signed = matches!(protocol, Protocol::Echo(Echo { secret: Some(_) }));
+        let trust_factor = entry.trust_factor.unwrap_or(if signed {
+            TrustFactorAuthority::HIG
+        } else {
+            TrustFactorAuthority::LOW
+        });
+        ensure!(
+            TrustFactorAuthority::is_valid(trust_factor),
+            "incorrect trust factor {trust_factor}, must be in range [{}..{}]",
+            TrustFactorAuthority::LOW,
+            TrustFactorAuthority::HIG,
+        );
+
+        let rate_limit = entry
+            .rate_limit
+            .as_deref()
+            .map(parse_duration)
+            .transpose()
+            .context("the rate limit is not a duration")?
+            .filter(|gap| !gap.is_zero());
+
+        Ok(Self {
+            name,
+            host,
+            uri: entry.uri,
+            mirrors: entry.mirrors,
+            protocol,
+            trust_factor,
+            rate_limit,
+            ipv6: entry.ipv6,
+            enabled_by_default: entry.enabled_by_default,
+            terms: entry.terms,
+            summary: entry
+                .summary
+                .unwrap_or_else(|| "Declared in the providers file.".to_owned()),
+        })
+    }
+}
+
+impl Declared {
+    /// The host and the protocol of an entry asked over HTTP.
+    fn http(entry: &Entry) -> Result<(String, Protocol)> {
+        let signed = matches!(entry.response, Some(Response::Echo)) && entry.secret_file.is_some();
+
+        // Plain HTTP would let anything on the path answer for the provider,
+        // and its answer would be counted at whatever trust it was given.
+        // Unless the answer is signed, in which case anything on the path can
+        // only keep it from arriving.
+        let url = Url::parse(&entry.uri).context("the URI does not parse")?;
+        ensure!(
+            url.scheme() == "https" || (signed && url.scheme() == "http"),
+            "the URI must be https, stun, stuns, annotation or label, or http for a signed echo",
+        );
+        let Some(host) = url.host_str() else {
+            bail!("the URI names no host");
+        };
+
+        // A mirror answers for the provider, and is held to what it is.
+        for mirror in &entry.mirrors {
+            let url =
+                Url::parse(mirror).with_context(|| format!("mirror {mirror} does not parse"))?;
+            ensure!(
+                url.scheme() == "https" || (signed && url.scheme() == "http"),
+                "mirror {mirror} must be https, or http for a signed echo",
+            );
+            ensure!(url.host_str().is_some(), "mirror {mirror} names no host");
+        }
+
+        let method = match entry.method.as_deref().map(str::to_ascii_uppercase) {
+            None => Method::GET,
+            Some(method) if method == "GET" => Method::GET,
+            Some(method) if method == "POST" => Method::POST,
+            Some(method) => bail!("method {method} is not supported, use GET or POST"),
+        };
+
+        let format = match (&entry.response, &entry.pointer) {
+            (Some(Response::Json), Some(pointer)) => {
+                ensure!(
+                    pointer.is_empty() || pointer.starts_with('/'),
+                    "pointer {pointer} is not a JSON pointer, which starts with `/`",
+                );
+                Format::Json(pointer.clone())
+            }
+            (Some(Response::Json), None) => {
+                bail!("a JSON response needs a pointer to the address")
+            }
+            (Some(Response::Text), None) => Format::Text,
+            (Some(Response::Text), Some(_)) => bail!("a text response has nothing to point into"),
+            (Some(Response::Echo), pointer) => {
+                ensure!(
+                    pointer.is_none(),
+                    "an echo answers in a shape of its own, with nothing to point into",
+                );
+                ensure!(method == Method::GET, "an echo is asked with GET");
+                ensure!(
+                    entry.key_placement.is_none(),
+                    "an echo is ours, and asked without a key",
+                );
+
+                let secret = entry.secret_file.as_deref().map(Secret::read).transpose()?;
+                return Ok((host.to_owned(), Protocol::Echo(Echo { secret })));
+            }
+            (None, _) => bail!("an HTTP provider needs a response, json, text or echo"),
+        };
+
+        ensure!(
+            entry.secret_file.is_none(),
+            "only an echo signs its answers, a secret is of no use to any other",
+        );
+
+        let key_placement = entry
+            .key_placement
+            .as_deref()
+            .map(Placement::from_str)
+            .transpose()
+            .map_err(anyhow::Error::msg)?;
+
+        Ok((
+            host.to_owned(),
+            Protocol::Http {
+                method,
+                format,
+                key_placement,
+            },
+        ))
+    }
+
+    pub fn response_decode(&self, body: &[u8]) -> Result<IpAddr, FetchError> {
+        let Protocol::Http { format, .. } = &self.protocol else {
+            unreachable!("{} is not asked over plain HTTP", self.name);
+        };
+
+        let Format::Json(pointer) = format else {
+            return decode_text(body);
+        };
+
+        serde_json::from_slice::<serde_json::Value>(body)
+            .and_then(|document| match document.pointer(pointer) {
+                Some(value) => IpAddr::deserialize(value),
+                None => Err(serde_json::Error::custom(format!(
+                    "nothing is found at {pointer}"
+                ))),
+            })
+            .map_err(|source| FetchError::Decode {
+                body: String::from_utf8_lossy(body).into_owned(),
+                source: source.into(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "
+providers:
+  - name: Intranet
+    uri: https://echo.example.net/ip
+    response: json
+    pointer: /client/ip
+    trust_factor: 2
+    rate_limit: 30s
+    ipv6: true
+  - name: plain
+    uri: https://plain.example.net/
+    response: text
+    enabled_by_default: false
+  - name: Turn
+    uri: stuns:turn.example.net
+    ipv6: true
+";
+
+    fn catalog(text: &str) -> Catalog {
+        Catalog::parse(text).unwrap_or_else(|err| panic!("the file must parse: {err:#}"))
+    }
+
+    fn declared(catalog: Catalog, name: &str) -> &'static Declared {
+        match catalog.find(name) {
+            Some(Provider::Declared(declared)) => declared,
+            other => panic!("{name} must be declared, found {other:?}"),
+        }
+    }
+
+    #[test]
+    fn a_declared_provider_stands_beside_the_built_in_ones() {
+        let catalog = catalog(FILE);
+
+        assert_eq!(
+            catalog.all().count(),
+            <HttpProvider as VariantArray>::VARIANTS.len()
+                + <StunProvider as VariantArray>::VARIANTS.len()
+                + <DnsProvider as VariantArray>::VARIANTS.len()
+                + <LocalProvider as VariantArray>::VARIANTS.len()
+                + <MetadataProvider as VariantArray>::VARIANTS.len()
+                + <NodeProvider as VariantArray>::VARIANTS.len()
+                + 3
+        );
+        assert_eq!(
+            catalog.find("ipify"),
+            Some(Provider::Http(HttpProvider::Ipify))
+        );
+        assert_eq!(
+            catalog.find("googlestun"),
+            Some(Provider::Stun(StunProvider::GoogleStun))
+        );
+        assert_eq!(
+            catalog.find("cloudflaredns"),
+            Some(Provider::Dns(DnsProvider::CloudflareDns))
+        );
+        assert_eq!(
+            catalog.find("GATEWAY"),
+            Some(Provider::Local(LocalProvider::Gateway))
+        );
+
+        let intranet = declared(catalog, "INTRANET");
+        assert_eq!(intranet.host, "echo.example.net");
+        assert_eq!(intranet.trust_factor, TrustFactorAuthority::MED);
+        assert_eq!(intranet.rate_limit, Some(Duration::from_secs(30)));
+        assert!(intranet.ipv6 && intranet.enabled_by_default);
+    }
+
+    #[test]
+    fn what_an_entry_leaves_out_is_assumed_the_cautious_way() {
+        let plain = declared(catalog(FILE), "plain");
+
+        assert!(matches!(
+            plain.protocol,
+            Protocol::Http {
+                method: Method::GET,
+                ..
+            }
+        ));
+        assert_eq!(plain.trust_factor, TrustFactorAuthority::LOW);
+        assert_eq!(plain.rate_limit, None);
+        assert!(!plain.ipv6);
+        assert!(!plain.enabled_by_default);
+    }
+
+    #[test]
+    fn json_is_a_providers_file_too() {
+        let json = r#"{"providers": [
+            {"name": "Json", "uri": "https://json.example.net/", "response": "text"}
+        ]}"#;
+
+        assert!(catalog(json).find("json").is_some());
+    }
+
+    #[test]
+    fn a_declared_body_is_decoded_the_way_its_entry_says() {
+        let catalog = catalog(FILE);
+
+        let intranet = declared(catalog, "Intranet");
+        let decoded = intranet
+            .response_decode(br#"{"client":{"ip":"1.2.3.4"}}"#)
+            .expect("the pointed-to address must decode");
+        assert_eq!(decoded.to_string(), "1.2.3.4");
+
+        let err = intranet
+            .response_decode(br#"{"ip":"1.2.3.4"}"#)
+            .expect_err("a pointer leading nowhere must not decode");
+        assert_eq!(err.as_error_type(), "decode");
+
+        let plain = declared(catalog, "plain");
+        let decoded = plain
+            .response_decode(b"2606:4700::1111\n")
+            .expect("a bare address must decode");
+        assert_eq!(decoded.to_string(), "2606:4700::1111");
+    }
+
+    #[test]
+    fn a_stun_uri_declares_a_stun_server() {
+        let turn = declared(catalog(FILE), "turn");
+
+        assert_eq!(turn.host, "turn.example.net");
+        assert_eq!(
+            turn.protocol,
+            Protocol::Stun(Server::from_str("stuns:turn.example.net:5349").expect("it parses"))
+        );
+        assert_eq!(turn.trust_factor, TrustFactorAuthority::LOW);
+    }
+
+    #[test]
+    fn an_annotation_or_label_uri_declares_a_key_on_the_node() {
+        let catalog = catalog(concat!(
+            "providers:\n",
+            "  - { name: Flannel6, uri: 'annotation:flannel.alpha.coreos.com/public-ipv6', ",
+            "ipv6: true }\n",
+            "  - { name: Public, uri: 'label:example.com/public-ip' }\n",
+        ));
+
+        let flannel6 = declared(catalog, "flannel6");
+        assert_eq!(flannel6.host, "flannel.alpha.coreos.com/public-ipv6");
+        assert_eq!(
+            flannel6.protocol,
+            Protocol::Node(Key::annotation("flannel.alpha.coreos.com/public-ipv6"))
+        );
+        assert_eq!(flannel6.trust_factor, TrustFactorAuthority::LOW);
+        assert!(flannel6.ipv6);
+
+        let public = declared(catalog, "public");
+        assert!(matches!(
+            Provider::Declared(public).request(),
+            Request::Node(_)
+        ));
+        assert_eq!(catalog.find("k3s"), Some(Provider::Node(NodeProvider::K3s)));
+
+        for bad in [
+            "providers:\n  - { name: Odd, uri: 'annotation:k3s.io/external-ip', response: text }\n",
+            "providers:\n  - { name: Odd, uri: 'label:not a key' }\n",
+            "providers:\n  - { name: Flannel, uri: 'annotation:example.com/ip' }\n",
+        ] {
+            assert!(Catalog::parse(bad).is_err(), "must be refused:\n{bad}");
+        }
+    }
+
+    #[test]
+    fn a_signed_echo_is_trusted_as_our_own() {
+        let path = std::env::temp_dir().join(format!("fckloud-echo-{}", std::process::id()));
+        fs::write(&path, format!("{}\n", "s".repeat(32))).expect("temp dir must be writable");
+
+        let file = format!(
+            concat!(
+                "providers:\n",
+                "  - {{ name: Ours, uri: 'http://echo.example.net:8080/', response: echo, ",
+                "secret_file: '{}' }}\n",
+                "  - {{ name: Open, uri: 'https://echo.example.net/', response: echo }}\n",
+            ),
+            path.display(),
+        );
+        let catalog = catalog(&file);
+
+        let ours = declared(catalog, "ours");
+        assert!(matches!(
+            ours.protocol,
+            Protocol::Echo(Echo { secret: Some(_) })
+        ));
+        assert_eq!(ours.trust_factor, TrustFactorAuthority::HIG);
+
+        let open = declared(catalog, "open");
+        assert_eq!(open.protocol, Protocol::Echo(Echo { secret: None }));
+        assert_eq!(open.trust_factor, TrustFactorAuthority::LOW);
+
+        // Unlike a keyed provider, it has nothing to give away over http.
+        catalog
+            .with_endpoints(&[(
+                "ours".to_owned(),
+                Url::parse("http://10.0.0.8:8080/").expect("test URL must parse"),
+            )])
+            .expect("a signed echo sends no key");
+
+        let entry = |fields: &str| {
+            format!(
+                "providers:\n  - {{ name: Odd, response: echo, uri: {fields}, secret_file: '{}' }}\n",
+                path.display(),
+            )
+        };
+
+        for bad in [
+            entry("https://ok.example.net/, method: POST"),
+            entry("https://ok.example.net/, pointer: /ip"),
+            entry("https://ok.example.net/, key_placement: cookie"),
+            entry("https://ok.example.net/, method: GET").replace("echo", "text"),
+            "providers:\n  - { name: Odd, uri: 'http://ok.example.net/', response: echo }\n"
+                .to_owned(),
+            "providers:\n  - { name: Odd, uri: 'https://ok.example.net/', response: echo, \
+             secret_file: /nonexistent }\n"
+                .to_owned(),
+        ] {
+            assert!(Catalog::parse(&bad).is_err(), "must be refused:\n{bad}");
+        }
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_mirror_is_asked_after_the_endpoint_unless_the_operator_points_it_elsewhere() {
+        let file = "providers:\n  - { name: Twin, uri: https://a.example.net/ip, response: text, \
+                    mirrors: [https://b.example.net/ip] }\n";
+        let twin = Provider::Declared(declared(catalog(file), "twin"));
+        assert_eq!(twin.mirrors(Family::V6), ["https://b.example.net/ip"]);
+
+        let seeip = Provider::Http(HttpProvider::SeeIp);
+        assert_eq!(
+            catalog(file).mirrors(seeip),
+            [
+                "https://ipv4.seeip.org/jsonip",
+                "https://ipv6.seeip.org/jsonip"
+            ]
+        );
+
+        let pointed = catalog(file)
+            .with_endpoints(&[(
+                "seeip".to_owned(),
+                Url::parse("http://10.0.0.7/jsonip").expect("test URL must parse"),
+            )])
+            .expect("SeeIp is asked over HTTP");
+        assert!(pointed.mirrors(seeip).is_empty());
+
+        for bad in [
+            "providers:\n  - { name: Odd, uri: https://a.example.net/, response: text, \
+             mirrors: [http://b.example.net/] }\n",
+            "providers:\n  - { name: Odd, uri: 'stun:a.example.net', \
+             mirrors: ['stun:b.example.net'] }\n",
+        ] {
+            assert!(Catalog::parse(bad).is_err(), "must be refused:\n{bad}");
+        }
+    }
+
+    #[test]
+    fn a_provider_pointed_elsewhere_is_asked_there_and_nowhere_else() {
+        let url = |s: &str| Url::parse(s).expect("test URL must parse");
+
+        let catalog = catalog(FILE)
+            .with_endpoints(&[
+                ("httpbin".to_owned(), url("http://127.0.0.1:8080/ip")),
+                ("intranet".to_owned(), url("https://mirror.example.net/ip")),
+            ])
+            .expect("both are asked over HTTP");
+
+        assert_eq!(
+            catalog.request_uri(Provider::Http(HttpProvider::HttpBin)),
+            "http://127.0.0.1:8080/ip"
+        );
+        assert_eq!(
+            catalog.request_uri(Provider::Declared(declared(catalog, "intranet"))),
+            "https://mirror.example.net/ip"
+        );
+        assert_eq!(
+            catalog.request_uri(Provider::Http(HttpProvider::Ipify)),
+            HttpProvider::Ipify.request_uri()
+        );
+        assert_eq!(catalog.endpoints().count(), 2);
+
+        // A keyed provider keeps its key off the wire only over https.
+        let keyed = catalog
+            .with_endpoints(&[("ipinfo".to_owned(), url("https://10.0.0.7/json"))])
+            .expect("IpInfo may be pointed at an https URL");
+        assert_eq!(
+            keyed.request_uri(Provider::Http(HttpProvider::IpInfo)),
+            "https://10.0.0.7/json"
+        );
+
+        for bad in [
+            vec![("nobody".to_owned(), url("https://x.example.net/"))],
+            vec![("googlestun".to_owned(), url("https://x.example.net/"))],
+            vec![("turn".to_owned(), url("https://x.example.net/"))],
+            vec![("k3s".to_owned(), url("https://x.example.net/"))],
+            vec![("ipify".to_owned(), url("ftp://x.example.net/"))],
+            vec![("ipinfo".to_owned(), url("http://10.0.0.7/json"))],
+            vec![
+                ("ipify".to_owned(), url("https://x.example.net/")),
+                ("IPIFY".to_owned(), url("https://y.example.net/")),
+            ],
+        ] {
+            assert!(
+                catalog.with_endpoints(&bad).is_err(),
+                "must be refused: {bad:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn a_key_is_taken_by_a_provider_that_wants_one_and_no_other() {
+        let keyed = catalog(
+            "providers:\n  - { name: Paid, uri: https://paid.example.net/, response: text, \
+             key_placement: 'query:token' }\n",
+        );
+        let paid = keyed.find("paid").expect("Paid is declared");
+        assert_eq!(paid.key_placement(), Some(Placement::Query("token".into())));
+        assert!(!keyed.can_ask(paid));
+
+        // PATH is set wherever tests run, and is as good a key as any here.
+        let path = || KeySource::Env("PATH".to_owned());
+        let keyed = keyed
+            .with_keys(&[("Paid".to_owned(), path()), ("IpInfo".to_owned(), path())])
+            .expect("both take a key");
+        assert!(keyed.can_ask(paid));
+        assert!(keyed.can_ask(Provider::Http(HttpProvider::IpInfo)));
+        assert!(!keyed.can_ask(Provider::Http(HttpProvider::IpGeolocation)));
+        assert!(keyed.can_ask(Provider::Http(HttpProvider::Ipify)));
+
+        for bad in [
+            vec![("ipify".to_owned(), path())],
+            vec![("Nope".to_owned(), path())],
+            vec![("IpInfo".to_owned(), path()), ("ipinfo".to_owned(), path())],
+            vec![(
+                "IpInfo".to_owned(),
+                KeySource::Env("FCKLOUD_TEST_NO_SUCH_KEY".to_owned()),
+            )],
+        ] {
+            assert!(
+                Catalog::default().with_keys(&bad).is_err(),
+                "must be refused: {bad:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn a_client_certificate_comes_with_its_key_and_goes_to_an_https_provider() {
+        let pair = |name: &str, file: &str| vec![(name.to_owned(), PathBuf::from(file))];
+
+        for (certs, keys) in [
+            (pair("ipify", "ipify.crt"), vec![]),
+            (vec![], pair("ipify", "ipify.key")),
+            (
+                pair("ipify", "ipify.crt"),
+                pair("icanhazip", "icanhazip.key"),
+            ),
+            (
+                pair("GoogleStun", "stun.crt"),
+                pair("GoogleStun", "stun.key"),
+            ),
+            (pair("Nope", "nope.crt"), pair("Nope", "nope.key")),
+        ] {
+            let err = Catalog::default()
+                .with_identities(&certs, &keys)
+                .expect_err("the pair must be refused before either file is read");
+            assert!(!format!("{err:#}").contains("cannot read"), "{err:#}");
+        }
+
+        let err = Catalog::default()
+            .with_identities(
+                &pair("ipify", "/nonexistent.crt"),
+                &pair("Ipify", "/nonexistent.key"),
+            )
+            .expect_err("neither file is there");
+        assert!(format!("{err:#}").contains("/nonexistent.crt"), "{err:#}");
+    }
+
+    #[test]
+    fn a_provider_is_pinned_by_its_own_keys_unless_the_operator_gives_others() {
+        let ipify = Provider::Http(HttpProvider::Ipify);
+        let seeip = Provider::Http(HttpProvider::SeeIp);
+        let pin = |s: &str| Some(Pin::from_str(s).expect("test pin must parse"));
+        let isrg = pin("sha256/C5+lpZ7tcVwmwQIMcRtPbsQtWLABXhQzejna0wHFr8M=");
+        let ours = pin("sha256/47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=");
+
+        let built_in = Catalog::default();
+        assert!(built_in.pinned(ipify).len() > 1);
+        assert!(built_in.pinned(seeip).is_empty());
+
+        let given = Catalog::default()
+            .with_pins(&[
+                ("ipify".to_owned(), ours),
+                ("SeeIp".to_owned(), isrg),
+                ("SeeIp".to_owned(), ours),
+            ])
+            .expect("both are asked over HTTPS");
+        assert_eq!(given.pinned(ipify), [ours.expect("given")]);
+        assert_eq!(given.pinned(seeip).len(), 2);
+
+        let lifted = Catalog::default()
+            .with_pins(&[("Ipify".to_owned(), None)])
+            .expect("a built-in pin can be lifted");
+        assert!(lifted.pinned(ipify).is_empty());
+        assert!(lifted.pins().all(|(pinned, _)| pinned != ipify));
+
+        let elsewhere = Catalog::default()
+            .with_endpoints(&[(
+                "Ipify".to_owned(),
+                Url::parse("https://ipify.internal/").expect("test URL must parse"),
+            )])
+            .expect("Ipify is asked over HTTP");
+        assert!(elsewhere.pinned(ipify).is_empty());
+
+        let plain = elsewhere
+            .with_endpoints(&[(
+                "Ipify".to_owned(),
+                Url::parse("http://ipify.internal/").expect("test URL must parse"),
+            )])
+            .expect("plain HTTP is taken for an endpoint");
+        assert!(plain.with_pins(&[("Ipify".to_owned(), isrg)]).is_err());
+
+        for bad in [
+            vec![("GoogleStun".to_owned(), isrg)],
+            vec![("Nope".to_owned(), isrg)],
+            vec![("SeeIp".to_owned(), isrg), ("SeeIp".to_owned(), None)],
+            vec![("SeeIp".to_owned(), None), ("SeeIp".to_owned(), isrg)],
+        ] {
+            assert!(
+                Catalog::default().with_pins(&bad).is_err(),
+                "must be refused: {bad:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn a_file_with_one_bad_entry_is_refused_whole() {
+        let entry = |fields: &str| {
+            format!("providers:\n  - {{ name: Odd, response: text, uri: {fields} }}\n")
+        };
+
+        for bad in [
+            entry("http://plain.example.net/"),
+            entry("https://ok.example.net/, trust_factor: 4"),
+            entry("https://ok.example.net/, method: PUT"),
+            entry("https://ok.example.net/, rate_limit: soon"),
+            entry("https://ok.example.net/, colour: blue"),
+            entry("https://ok.example.net/, pointer: /ip"),
+            entry("https://ok.example.net/, key_placement: cookie"),
+            "providers:\n  - { name: Odd, uri: https://ok.example.net/, response: json }\n"
+                .to_owned(),
+            "providers:\n  - { name: Ipify, uri: https://ok.example.net/, response: text }\n"
+                .to_owned(),
+            "providers:\n  - { name: trust2, uri: https://ok.example.net/, response: text }\n"
+                .to_owned(),
+            "providers:\n  - { name: a b, uri: https://ok.example.net/, response: text }\n"
+                .to_owned(),
+            "providers:\n  - { name: Odd, uri: https://ok.example.net/ }\n".to_owned(),
+            "providers:\n  - { name: Odd, uri: 'stun:ok.example.net', response: text }\n"
+                .to_owned(),
+            "providers:\n  - { name: Odd, uri: 'stun:ok.example.net/path' }\n".to_owned(),
+            "providers:\n  - { name: CloudflareStun, uri: 'stun:ok.example.net' }\n".to_owned(),
+            "providers:\n  - { name: opendns, uri: 'stun:ok.example.net' }\n".to_owned(),
+            format!(
+                "{FILE}  - {{ name: PLAIN, uri: https://other.example.net/, response: text }}\n"
+            ),
+        ] {
+            assert!(Catalog::parse(&bad).is_err(), "must be refused:\n{bad}");
+        }
+    }
+}
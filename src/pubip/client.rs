@@ -0,0 +1,110 @@
+use {
+    crate::pubip::{
+        Family, Uplink,
+        hosts::{self, Hosts},
+        tls::{self, Handshake},
+    },
+    reqwest::{Client, ClientBuilder, Proxy},
+    std::{
+        collections::HashMap,
+        sync::{Arc, LazyLock, Mutex, OnceLock, PoisonError},
+        time::Duration,
+    },
+};
+
+const USER_AGENT: &str = concat!("fckloud/", env!("CARGO_PKG_VERSION"));
+
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+// One client per uplink, family, route and handshake, one connection pool
+// each, one place where a stalled provider dies. Binding the unspecified
+// address of a family is what keeps a connection to a dual-stacked host from
+// arriving over the other one.
+static CLIENTS: LazyLock<Mutex<HashMap<Way, Client>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+type Way = (Uplink, Family, Route, Handshake);
+
+// Set once at startup, before the first client is built, and never again.
+static PROXY: OnceLock<Proxy> = OnceLock::new();
+
+/// Whether a request may go through the proxy, the one `--proxy` names or,
+/// without it, whichever `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY` say.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug)]
+pub enum Route {
+    #[default]
+    Proxied,
+    /// Straight out by the uplink, whatever the environment says: a provider
+    /// exempted from the proxy, or a metadata service no proxy could reach.
+    Direct,
+}
+
+/// Sends every proxied request through the proxy rather than the one the
+/// environment names. Only the first call counts.
+pub fn set_proxy(proxy: Proxy) {
+    let _ = PROXY.set(proxy);
+}
+
+/// The client that reaches a provider over the family, out by the uplink and
+/// the route, with the handshake the provider asks for. Built on first use
+/// and shared after that; a clone is a handle, not a copy.
+pub fn get(uplink: &Uplink, family: Family, route: Route, handshake: Handshake) -> Client {
+    // A poisoned lock loses nothing here: the worst it can hold is a client
+    // that was about to be inserted, and building another is harmless.
+    CLIENTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry((uplink.clone(), family, route, handshake))
+        .or_insert_with(|| build(uplink, family, route, handshake))
+        .clone()
+}
+
+fn build(uplink: &Uplink, family: Family, route: Route, handshake: Handshake) -> Client {
+    let builder = Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT);
+
+    // Certificates are checked, and presented, by the one config every
+    // TLS connection to a provider is made with.
+    let builder = builder.use_preconfigured_tls(tls::config(handshake));
+
+    // Left alone, the builder reads the proxy from the environment itself.
+    let builder = match (route, PROXY.get()) {
+        (Route::Direct, _) => builder.no_proxy(),
+        (Route::Proxied, Some(proxy)) => builder.proxy(proxy.clone()),
+        (Route::Proxied, None) => builder,
+    };
+
+    // Left alone, the builder asks the system's resolver, which is all a run
+    // without `--resolve` or `--doh` wants.
+    let builder = if hosts::configured() {
+        builder.dns_resolver(Arc::new(Hosts {
+            family,
+            uplink: uplink.clone(),
+        }))
+    } else {
+        builder
+    };
+
+    let builder = match uplink {
+        Uplink::Interface(name) => bind_interface(builder, name),
+        Uplink::Default | Uplink::Source(_) => builder,
+    };
+
+    builder
+        .local_address(uplink.local_address(family))
+        .build()
+        .expect("HTTP client with static settings must be constructible")
+}
+
+#[cfg(target_os = "linux")]
+fn bind_interface(builder: ClientBuilder, name: &str) -> ClientBuilder {
+    builder.interface(name)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_interface(_: ClientBuilder, name: &str) -> ClientBuilder {
+    unreachable!("interface {name} must have been refused at startup on this platform")
+}
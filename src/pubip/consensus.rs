@@ -1,14 +1,54 @@
 use {
-    crate::pubip::{HttpProvider, TrustFactorAuthority},
+    crate::pubip::{Family, Provider, TrustFactorAuthority, Uplink},
     std::{
-        collections::{HashMap, HashSet},
+        cmp::Reverse,
+        collections::{BTreeMap, BTreeSet, HashMap, HashSet},
         net::IpAddr,
     },
 };
 
-/// What a round of asking the providers came to.
+/// What a round of asking the providers came to on one uplink, one verdict per
+/// family it asked over. A family the round was never meant to ask has no
+/// verdict at all; one it meant to ask and could not reach has an empty one,
+/// which confirmed nothing and was not well answered.
 #[derive(Default, Debug)]
 pub struct Report {
+    pub uplink: Uplink,
+    pub families: BTreeMap<Family, Verdict>,
+}
+
+impl Report {
+    /// Every address confirmed, whichever family it was confirmed in.
+    pub fn confirmed(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.families
+            .values()
+            .flat_map(|verdict| verdict.confirmed.iter().copied())
+    }
+}
+
+/// The families in which the silence of every uplink's round means something.
+///
+/// A family counts only when every uplink that asked over it was well answered
+/// there. An address only a quiet uplink could have confirmed would otherwise
+/// look gone because the other uplinks, which could never have seen it, did
+/// not mention it.
+pub fn well_answered_across(reports: &[Report]) -> BTreeSet<Family> {
+    Family::ALL
+        .into_iter()
+        .filter(|family| {
+            let mut verdicts = reports
+                .iter()
+                .filter_map(|report| report.families.get(family))
+                .peekable();
+
+            verdicts.peek().is_some() && verdicts.all(|verdict| verdict.well_answered)
+        })
+        .collect()
+}
+
+/// What the providers asked over one family came to.
+#[derive(Default, Debug)]
+pub struct Verdict {
     pub confirmations: usize,
     pub confirmed: HashSet<IpAddr>,
     pub unconfirmed: HashMap<IpAddr, usize>,
@@ -17,6 +57,11 @@ pub struct Report {
     /// to mean anything. A degraded round may add and keep addresses; only a
     /// well answered one may be read as evidence that an address is gone.
     pub well_answered: bool,
+
+    /// What providers still serving out their gap last answered, weighed
+    /// beside this round's answers but never counted towards answering it
+    /// well: their trust is evidence for an address, not of a fresh look.
+    pub carried: Vec<(Provider, IpAddr)>,
 }
 
 /// The least trust an address must gather however few providers answered.
@@ -36,7 +81,7 @@ pub fn floor(enrolled_trust: usize) -> usize {
 /// rather than only from the numerator, so enrolling a provider an egress
 /// policy blocks costs nothing instead of quietly raising the bar forever.
 pub fn confirmations_for(
-    answered: &[HttpProvider],
+    answered: &[Provider],
     tfa: &TrustFactorAuthority,
     enrolled_trust: usize,
 ) -> usize {
@@ -50,13 +95,14 @@ pub fn confirmations_for(
 }
 
 /// Whether enough of the enrolled trust turned up to read anything into what
-/// this round did not say.
+/// this round did not say. A round nobody answered never is, however little
+/// was left enrolled.
 pub fn well_answered(
     answered_trust: usize,
     enrolled_trust: usize,
     tfa: &TrustFactorAuthority,
 ) -> bool {
-    answered_trust >= tfa.trust_share().floor_of(enrolled_trust)
+    answered_trust > 0 && answered_trust >= tfa.trust_share().floor_of(enrolled_trust)
 }
 
 /// Weighs what the providers reported and decides which addresses carry enough
@@ -65,10 +111,10 @@ pub fn well_answered(
 /// Pure on purpose: every subtlety of the consensus lives here, and none of it
 /// needs a network to exercise.
 pub fn decide(
-    reported: &[(HttpProvider, IpAddr)],
+    reported: &[(Provider, IpAddr)],
     tfa: &TrustFactorAuthority,
     confirmations: usize,
-) -> Report {
+) -> Verdict {
     let mut buckets: HashMap<IpAddr, usize> = HashMap::new();
 
     for (provider, ip_addr) in reported {
@@ -79,11 +125,12 @@ pub fn decide(
         .into_iter()
         .partition::<HashMap<_, _>, _>(|(_, bucket)| *bucket >= confirmations);
 
-    Report {
+    Verdict {
         confirmations,
         confirmed: confirmed.into_keys().collect(),
         unconfirmed,
         well_answered: false,
+        carried: Vec::new(),
     }
 }
 
@@ -96,21 +143,99 @@ pub fn decide(
 ///
 /// Pure on purpose, like [`decide`]: this is the whole difference between a
 /// log line worth waking up for and one worth noting.
-pub fn missing_trust_mattered(report: &Report, missing: usize) -> bool {
+pub fn missing_trust_mattered(verdict: &Verdict, missing: usize) -> bool {
     if missing == 0 {
         return false;
     }
 
-    missing >= report.confirmations
-        || report
+    missing >= verdict.confirmations
+        || verdict
             .unconfirmed
             .values()
-            .any(|bucket| bucket + missing >= report.confirmations)
+            .any(|bucket| bucket + missing >= verdict.confirmations)
+}
+
+/// Whether the providers still outstanding could change this family's
+/// verdict however they answered, or whether the round may stop waiting for
+/// them.
+///
+/// The reasoning of [`missing_trust_mattered`], made before the round is
+/// over rather than after, with one more turn to it: the threshold follows
+/// the trust that answered, so an address confirmed now stays so only if it
+/// would still clear the highest bar the late answers could raise, and one
+/// short of it only if it could not reach the lowest they could lower it to.
+/// A round that is well answered stays so, and one that could never be stays
+/// degraded. Answers carried forward weigh like the rest, but only fresh ones
+/// answer the round well.
+///
+/// Pure on purpose, like [`decide`]: what it takes to stop early is
+/// arithmetic, and cheap enough to check after every answer.
+pub fn settled(
+    reported: &[(Provider, IpAddr)],
+    carried: &[(Provider, IpAddr)],
+    outstanding: &[Provider],
+    tfa: &TrustFactorAuthority,
+    pinned: Option<usize>,
+    enrolled_trust: usize,
+) -> bool {
+    if outstanding.is_empty() {
+        return true;
+    }
+
+    let trust_of = |providers: &[Provider]| -> usize {
+        providers
+            .iter()
+            .map(|provider| tfa.trust_factor(*provider))
+            .sum()
+    };
+    let fresh: Vec<Provider> = reported.iter().map(|(provider, _)| *provider).collect();
+    let fresh_trust = trust_of(&fresh);
+    let missing = trust_of(outstanding);
+
+    if well_answered(fresh_trust, enrolled_trust, tfa)
+        != well_answered(fresh_trust + missing, enrolled_trust, tfa)
+    {
+        return false;
+    }
+
+    let evidence = [reported, carried].concat();
+    let answered: Vec<Provider> = evidence.iter().map(|(provider, _)| *provider).collect();
+    let answered_trust = trust_of(&answered);
+
+    let (lowest, highest) = if let Some(confirmations) = pinned {
+        (confirmations, confirmations)
+    } else {
+        // However many answer, the share of what did is the least the bar
+        // is set at. The most is whichever answers raise it furthest:
+        // everyone, or with two answers or fewer counted whole or rounded
+        // up, the most trusted of the rest.
+        let mut late = outstanding.to_vec();
+        late.sort_unstable_by_key(|provider| Reverse(tfa.trust_factor(*provider)));
+
+        let highest = (0..=late.len().min(2))
+            .map(|most_trusted| &late[..most_trusted])
+            .chain([late.as_slice()])
+            .map(|late| [answered.as_slice(), late].concat())
+            .map(|answered| confirmations_for(&answered, tfa, enrolled_trust))
+            .max()
+            .unwrap_or_default();
+
+        let lowest = tfa
+            .trust_share()
+            .floor_of(answered_trust)
+            .max(floor(enrolled_trust));
+
+        (lowest, highest)
+    };
+
+    let at_lowest = decide(&evidence, tfa, lowest);
+    at_lowest.confirmed == decide(&evidence, tfa, highest).confirmed
+        && !missing_trust_mattered(&at_lowest, missing)
 }
 
 #[cfg(test)]
 mod tests {
-    use {super::*, std::str::FromStr};
+    use {super::*, crate::pubip::HttpProvider, std::str::FromStr};
 
     fn ip(s: &str) -> IpAddr {
         IpAddr::from_str(s).expect("test address must parse")
@@ -123,7 +248,11 @@ mod tests {
     #[test]
     fn a_lone_low_trust_provider_does_not_reach_the_default_threshold() {
         let tfa = TrustFactorAuthority::default();
-        let report = decide(&[(HttpProvider::HttpBin, ip("1.1.1.1"))], &tfa, 2);
+        let report = decide(
+            &[(Provider::Http(HttpProvider::HttpBin), ip("1.1.1.1"))],
+            &tfa,
+            2,
+        );
 
         assert!(report.confirmed.is_empty());
         assert_eq!(report.unconfirmed[&ip("1.1.1.1")], 1);
@@ -132,7 +261,11 @@ mod tests {
     #[test]
     fn a_lone_medium_trust_provider_does() {
         let tfa = TrustFactorAuthority::default();
-        let report = decide(&[(HttpProvider::MyIpWtf, ip("1.1.1.1"))], &tfa, 2);
+        let report = decide(
+            &[(Provider::Http(HttpProvider::MyIpWtf), ip("1.1.1.1"))],
+            &tfa,
+            2,
+        );
 
         assert!(report.confirmed.contains(&ip("1.1.1.1")));
         assert!(report.unconfirmed.is_empty());
@@ -143,8 +276,8 @@ mod tests {
         let tfa = TrustFactorAuthority::default();
         let report = decide(
             &[
-                (HttpProvider::HttpBin, ip("1.1.1.1")),
-                (HttpProvider::MyIpWtf, ip("1.1.1.1")),
+                (Provider::Http(HttpProvider::HttpBin), ip("1.1.1.1")),
+                (Provider::Http(HttpProvider::MyIpWtf), ip("1.1.1.1")),
             ],
             &tfa,
             3,
@@ -158,8 +291,8 @@ mod tests {
         let tfa = TrustFactorAuthority::default();
         let report = decide(
             &[
-                (HttpProvider::HttpBin, ip("1.1.1.1")),
-                (HttpProvider::MyIpWtf, ip("2.2.2.2")),
+                (Provider::Http(HttpProvider::HttpBin), ip("1.1.1.1")),
+                (Provider::Http(HttpProvider::MyIpWtf), ip("2.2.2.2")),
             ],
             &tfa,
             3,
@@ -181,13 +314,21 @@ mod tests {
 
     #[test]
     fn nothing_failed_never_matters() {
-        let report = decide(&[(HttpProvider::MyIpWtf, ip("1.1.1.1"))], &trust(), 2);
+        let report = decide(
+            &[(Provider::Http(HttpProvider::MyIpWtf), ip("1.1.1.1"))],
+            &trust(),
+            2,
+        );
         assert!(!missing_trust_mattered(&report, 0));
     }
 
     #[test]
     fn a_failure_beside_a_confirmed_address_did_not_cost_anything() {
-        let report = decide(&[(HttpProvider::MyIpWtf, ip("1.1.1.1"))], &trust(), 2);
+        let report = decide(
+            &[(Provider::Http(HttpProvider::MyIpWtf), ip("1.1.1.1"))],
+            &trust(),
+            2,
+        );
 
         assert!(report.confirmed.contains(&ip("1.1.1.1")));
         assert!(!missing_trust_mattered(&report, 1));
@@ -195,7 +336,11 @@ mod tests {
 
     #[test]
     fn a_failure_that_would_have_confirmed_an_address_did() {
-        let report = decide(&[(HttpProvider::HttpBin, ip("1.1.1.1"))], &trust(), 2);
+        let report = decide(
+            &[(Provider::Http(HttpProvider::HttpBin), ip("1.1.1.1"))],
+            &trust(),
+            2,
+        );
 
         assert_eq!(report.unconfirmed[&ip("1.1.1.1")], 1);
         assert!(missing_trust_mattered(&report, 1));
@@ -203,7 +348,11 @@ mod tests {
 
     #[test]
     fn a_failure_too_small_to_reach_the_threshold_did_not() {
-        let report = decide(&[(HttpProvider::HttpBin, ip("1.1.1.1"))], &trust(), 5);
+        let report = decide(
+            &[(Provider::Http(HttpProvider::HttpBin), ip("1.1.1.1"))],
+            &trust(),
+            5,
+        );
         assert!(!missing_trust_mattered(&report, 1));
     }
 
@@ -219,12 +368,15 @@ mod tests {
     #[test]
     fn a_second_address_left_short_matters_even_beside_a_confirmed_one() {
         let mut authority = TrustFactorAuthority::default();
-        authority.set_trust_factor(HttpProvider::HttpBin, TrustFactorAuthority::HIG);
+        authority.set_trust_factor(
+            Provider::Http(HttpProvider::HttpBin),
+            TrustFactorAuthority::HIG,
+        );
 
         let report = decide(
             &[
-                (HttpProvider::HttpBin, ip("1.1.1.1")),
-                (HttpProvider::MyIpWtf, ip("2606:4700::1111")),
+                (Provider::Http(HttpProvider::HttpBin), ip("1.1.1.1")),
+                (Provider::Http(HttpProvider::MyIpWtf), ip("2606:4700::1111")),
             ],
             &authority,
             3,
@@ -242,18 +394,18 @@ mod tests {
 
         // All six answer: two thirds of eleven, exactly as before.
         let all = [
-            HttpProvider::MyIpWtf,
-            HttpProvider::SeeIp,
-            HttpProvider::Ipify,
-            HttpProvider::MyIpCom,
-            HttpProvider::BigDataCloud,
-            HttpProvider::MyIpLa,
+            Provider::Http(HttpProvider::MyIpWtf),
+            Provider::Http(HttpProvider::SeeIp),
+            Provider::Http(HttpProvider::Ipify),
+            Provider::Http(HttpProvider::MyIpCom),
+            Provider::Http(HttpProvider::BigDataCloud),
+            Provider::Http(HttpProvider::MyIpLa),
         ];
         assert_eq!(confirmations_for(&all, &tfa, enrolled), 7);
 
         // One survivor of six decides for itself, and is believed if its own
         // trust is worth believing.
-        let alone = [HttpProvider::MyIpWtf];
+        let alone = [Provider::Http(HttpProvider::MyIpWtf)];
         assert_eq!(confirmations_for(&alone, &tfa, enrolled), 2);
     }
 
@@ -261,10 +413,14 @@ mod tests {
     fn the_floor_stops_the_weakest_survivor_from_deciding_alone() {
         let tfa = trust();
 
-        let weakest = [HttpProvider::MyIpLa];
+        let weakest = [Provider::Http(HttpProvider::MyIpLa)];
         assert_eq!(confirmations_for(&weakest, &tfa, 11), 2);
 
-        let report = decide(&[(HttpProvider::MyIpLa, ip("1.1.1.1"))], &tfa, 2);
+        let report = decide(
+            &[(Provider::Http(HttpProvider::MyIpLa), ip("1.1.1.1"))],
+            &tfa,
+            2,
+        );
         assert!(report.confirmed.is_empty());
     }
 
@@ -273,7 +429,7 @@ mod tests {
         let tfa = trust();
 
         // One low provider enabled on purpose: it must still be able to work.
-        let only = [HttpProvider::MyIpLa];
+        let only = [Provider::Http(HttpProvider::MyIpLa)];
         assert_eq!(confirmations_for(&only, &tfa, 1), 1);
     }
 
@@ -298,12 +454,159 @@ mod tests {
         assert!(well_answered(3, 3, &tfa));
     }
 
+    #[test]
+    fn each_family_is_graded_on_its_own() {
+        let tfa = trust();
+
+        let mut v4 = decide(
+            &[(Provider::Http(HttpProvider::Ipify), ip("1.1.1.1"))],
+            &tfa,
+            2,
+        );
+        v4.well_answered = true;
+        let v6 = decide(
+            &[(Provider::Http(HttpProvider::MyIpLa), ip("2606:4700::1111"))],
+            &tfa,
+            2,
+        );
+
+        let report = Report {
+            families: BTreeMap::from([(Family::V4, v4), (Family::V6, v6)]),
+            ..Report::default()
+        };
+
+        assert_eq!(report.confirmed().collect::<Vec<_>>(), [ip("1.1.1.1")]);
+        assert_eq!(
+            well_answered_across(std::slice::from_ref(&report)),
+            BTreeSet::from([Family::V4])
+        );
+    }
+
+    #[test]
+    fn one_quiet_uplink_keeps_its_family_from_being_well_answered() {
+        let answered = |well_answered| Verdict {
+            well_answered,
+            ..Verdict::default()
+        };
+
+        let eth0 = Report {
+            uplink: Uplink::Interface("eth0".into()),
+            families: BTreeMap::from([(Family::V4, answered(true)), (Family::V6, answered(true))]),
+        };
+        let eth1 = Report {
+            uplink: Uplink::Interface("eth1".into()),
+            families: BTreeMap::from([(Family::V4, answered(false))]),
+        };
+
+        assert_eq!(
+            well_answered_across(&[eth0, eth1]),
+            BTreeSet::from([Family::V6]),
+        );
+        assert!(well_answered_across(&[]).is_empty());
+    }
+
     #[test]
     fn a_custom_trust_factor_changes_the_verdict() {
         let mut tfa = TrustFactorAuthority::default();
-        tfa.set_trust_factor(HttpProvider::HttpBin, TrustFactorAuthority::HIG);
+        tfa.set_trust_factor(
+            Provider::Http(HttpProvider::HttpBin),
+            TrustFactorAuthority::HIG,
+        );
 
-        let report = decide(&[(HttpProvider::HttpBin, ip("1.1.1.1"))], &tfa, 2);
+        let report = decide(
+            &[(Provider::Http(HttpProvider::HttpBin), ip("1.1.1.1"))],
+            &tfa,
+            2,
+        );
         assert!(report.confirmed.contains(&ip("1.1.1.1")));
     }
+
+    #[test]
+    fn a_round_is_settled_once_the_late_trust_could_change_nothing() {
+        let tfa = trust();
+        let (wtf, seeip, ipify, httpbin) = (
+            Provider::Http(HttpProvider::MyIpWtf),
+            Provider::Http(HttpProvider::SeeIp),
+            Provider::Http(HttpProvider::Ipify),
+            Provider::Http(HttpProvider::HttpBin),
+        );
+        let enrolled: usize = [wtf, seeip, ipify, httpbin]
+            .iter()
+            .map(|provider| tfa.trust_factor(*provider))
+            .sum();
+        let agreeing = [
+            (wtf, ip("1.1.1.1")),
+            (seeip, ip("1.1.1.1")),
+            (ipify, ip("1.1.1.1")),
+        ];
+
+        // Whatever the last one says, the three agreeing clear every bar it
+        // could set, and the round is well answered already.
+        assert!(settled(&agreeing, &[], &[httpbin], &tfa, None, enrolled));
+
+        // Nobody late, nothing to wait for.
+        assert!(settled(&agreeing, &[], &[], &tfa, None, enrolled));
+
+        // One answer in, and the rest could still outvote it.
+        assert!(!settled(
+            &agreeing[..1],
+            &[],
+            &[seeip, ipify, httpbin],
+            &tfa,
+            None,
+            enrolled
+        ));
+    }
+
+    #[test]
+    fn a_round_is_not_settled_while_the_late_trust_could_confirm_or_unconfirm() {
+        let tfa = trust();
+        let (wtf, seeip, ipify, httpbin) = (
+            Provider::Http(HttpProvider::MyIpWtf),
+            Provider::Http(HttpProvider::SeeIp),
+            Provider::Http(HttpProvider::Ipify),
+            Provider::Http(HttpProvider::HttpBin),
+        );
+
+        // Confirmed at the bar the answers so far set, but not at the one
+        // the high-trust provider still out would raise it to.
+        let split = [
+            (wtf, ip("1.1.1.1")),
+            (seeip, ip("1.1.1.1")),
+            (httpbin, ip("2.2.2.2")),
+        ];
+        assert!(decide(&split, &tfa, 3).confirmed.contains(&ip("1.1.1.1")));
+        assert!(!settled(&split, &[], &[ipify], &tfa, None, 8));
+
+        // Short of a pinned bar, and the late trust could carry it over.
+        let short = [(wtf, ip("1.1.1.1")), (httpbin, ip("2.2.2.2"))];
+        assert!(!settled(&short, &[], &[seeip], &tfa, Some(4), 9));
+        assert!(settled(&short, &[], &[seeip], &tfa, Some(5), 9));
+    }
+
+    #[test]
+    fn a_carried_answer_settles_an_address_but_not_whether_the_round_was_answered_well() {
+        let tfa = trust();
+        let (wtf, seeip, ipify) = (
+            Provider::Http(HttpProvider::MyIpWtf),
+            Provider::Http(HttpProvider::SeeIp),
+            Provider::Http(HttpProvider::Ipify),
+        );
+        let fresh = [(ipify, ip("1.1.1.1"))];
+        let carried = [(wtf, ip("1.1.1.1"))];
+
+        // Fresh, the two would answer the round well whatever the last said.
+        assert!(settled(
+            &[fresh[0], carried[0]],
+            &[],
+            &[seeip],
+            &tfa,
+            Some(5),
+            7
+        ));
+
+        // Carried, the high-trust answer alone is short of that, and the
+        // late one could still tip it.
+        assert!(!settled(&fresh, &carried, &[seeip], &tfa, Some(5), 7));
+    }
 }
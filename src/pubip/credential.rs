@@ -0,0 +1,249 @@
+use {
+    anyhow::{Context as _, Result, bail, ensure},
+    reqwest::{
+        RequestBuilder,
+        header::{AUTHORIZATION, HeaderName, HeaderValue},
+    },
+    std::{borrow::Cow, env, fmt, fs, path::PathBuf, str::FromStr},
+};
+
+/// What stands in for a key wherever one would otherwise be shown.
+pub const REDACTED: &str = "REDACTED";
+
+/// Where a keyed provider wants its key: its definition says, the operator
+/// only gives the key.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Placement {
+    /// `Authorization: Bearer KEY`.
+    Bearer,
+    /// A header of the provider's own, holding the key and nothing else.
+    Header(Cow<'static, str>),
+    /// A query parameter added to the URL.
+    Query(Cow<'static, str>),
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bearer => f.write_str("bearer"),
+            Self::Header(name) => write!(f, "header:{name}"),
+            Self::Query(name) => write!(f, "query:{name}"),
+        }
+    }
+}
+
+/// `bearer`, `header:NAME` or `query:NAME`, the way the providers file
+/// writes it.
+impl FromStr for Placement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let placement = match s.split_once(':') {
+            None if s.eq_ignore_ascii_case("bearer") => Self::Bearer,
+            Some((kind, name)) if kind.eq_ignore_ascii_case("header") => {
+                HeaderName::from_str(name).map_err(|err| format!("`{name}`: {err}"))?;
+                Self::Header(Cow::Owned(name.to_owned()))
+            }
+            Some((kind, name)) if kind.eq_ignore_ascii_case("query") && !name.is_empty() => {
+                Self::Query(Cow::Owned(name.to_owned()))
+            }
+            _ => return Err(format!("`{s}` is not bearer, header:NAME or query:NAME")),
+        };
+
+        Ok(placement)
+    }
+}
+
+/// Where the operator keeps a key: a file, such as a mounted Secret, or an
+/// environment variable, written `env:NAME`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum KeySource {
+    File(PathBuf),
+    Env(String),
+}
+
+impl FromStr for KeySource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let source = match s.strip_prefix("env:") {
+            Some("") => return Err("`env:` names no variable".to_owned()),
+            Some(name) => Self::Env(name.to_owned()),
+            None if s.is_empty() => return Err("no file is named".to_owned()),
+            None => Self::File(PathBuf::from(s)),
+        };
+
+        Ok(source)
+    }
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Env(name) => write!(f, "env:{name}"),
+        }
+    }
+}
+
+/// A provider's key, read once at startup, and where it goes.
+///
+/// Never shown: [`fmt::Debug`] prints the placement alone, and the key is
+/// added to a request only as it is sent, so the URL every span and log line
+/// carries is the one without it.
+#[derive(PartialEq, Eq, Hash)]
+pub struct Credential {
+    placement: Placement,
+    key: String,
+}
+
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Credential({}, ..)", self.placement)
+    }
+}
+
+impl Credential {
+    /// Reads the key, surrounding whitespace aside: a Secret's file as often
+    /// ends with a newline as not.
+    pub fn read(placement: Placement, source: &KeySource) -> Result<Self> {
+        let key = match source {
+            KeySource::File(path) => fs::read_to_string(path)
+                .with_context(|| format!("cannot read key file {}", path.display()))?,
+            KeySource::Env(name) => match env::var(name) {
+                Ok(key) => key,
+                Err(err) => bail!("cannot read key variable {name}: {err}"),
+            },
+        };
+
+        let key = key.trim().to_owned();
+        ensure!(!key.is_empty(), "the key in {source} is empty");
+
+        // Checked here rather than at every request, where a key that cannot
+        // be a header would fail the round with nothing to say why.
+        if !matches!(placement, Placement::Query(_)) {
+            HeaderValue::from_str(&key)
+                .with_context(|| format!("the key in {source} cannot be sent in a header"))?;
+        }
+
+        Ok(Self { placement, key })
+    }
+
+    /// Adds the key to the request. A header holding it is marked sensitive,
+    /// which keeps it out of the request's own [`fmt::Debug`].
+    pub fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        let (name, value) = match &self.placement {
+            Placement::Bearer => (AUTHORIZATION, format!("Bearer {}", self.key)),
+            Placement::Header(name) => (
+                HeaderName::from_str(name).expect("the header name was checked when parsed"),
+                self.key.clone(),
+            ),
+            Placement::Query(name) => {
+                return request.query(&[(name.as_ref(), self.key.as_str())]);
+            }
+        };
+
+        let mut value = HeaderValue::from_str(&value).expect("the key was checked when read");
+        value.set_sensitive(true);
+        request.header(name, value)
+    }
+
+    /// The URL as asked, with the key a query parameter carries replaced by
+    /// [`REDACTED`], for `url.full`.
+    pub fn redact(&self, uri: &str) -> String {
+        match &self.placement {
+            Placement::Query(name) => {
+                let separator = if uri.contains('?') { '&' } else { '?' };
+                format!("{uri}{separator}{name}={REDACTED}")
+            }
+            Placement::Bearer | Placement::Header(_) => uri.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, reqwest::Client};
+
+    fn credential(placement: &str, key: &str) -> Credential {
+        Credential {
+            placement: Placement::from_str(placement).expect("test placement must parse"),
+            key: key.to_owned(),
+        }
+    }
+
+    #[test]
+    fn a_key_goes_where_the_provider_wants_it_and_shows_nowhere() {
+        let client = Client::new();
+        let ask = |credential: &Credential| {
+            credential
+                .apply(client.get("https://ipinfo.io/json?lang=en"))
+                .build()
+                .expect("the request must build")
+        };
+
+        let bearer = ask(&credential("bearer", "s3cr3t"));
+        assert_eq!(bearer.headers()[AUTHORIZATION], "Bearer s3cr3t");
+        assert!(!format!("{bearer:?}").contains("s3cr3t"));
+
+        let header = ask(&credential("header:X-Api-Key", "s3cr3t"));
+        assert_eq!(header.headers()["x-api-key"], "s3cr3t");
+        assert!(!format!("{header:?}").contains("s3cr3t"));
+
+        let query = credential("query:token", "s3cr3t");
+        assert_eq!(
+            ask(&query).url().as_str(),
+            "https://ipinfo.io/json?lang=en&token=s3cr3t"
+        );
+        assert_eq!(
+            query.redact("https://ipinfo.io/json?lang=en"),
+            "https://ipinfo.io/json?lang=en&token=REDACTED"
+        );
+        assert_eq!(
+            query.redact("https://ipinfo.io/"),
+            "https://ipinfo.io/?token=REDACTED"
+        );
+
+        assert!(!format!("{query:?}").contains("s3cr3t"));
+    }
+
+    #[test]
+    fn a_key_is_read_from_a_file_or_the_environment() {
+        let path = env::temp_dir().join(format!("fckloud-key-{}", std::process::id()));
+        fs::write(&path, "  s3cr3t\n").expect("temp dir must be writable");
+
+        let source = KeySource::from_str(path.to_str().expect("temp path is UTF-8"))
+            .expect("a path is a source");
+        let read = Credential::read(Placement::Bearer, &source).expect("the file holds a key");
+        assert_eq!(read.key, "s3cr3t");
+
+        fs::write(&path, "\n").expect("temp dir must be writable");
+        assert!(Credential::read(Placement::Bearer, &source).is_err());
+        let _ = fs::remove_file(&path);
+
+        // PATH is set wherever tests run, and is as good a key as any here.
+        let from_env = Credential::read(
+            Placement::Query("key".into()),
+            &KeySource::from_str("env:PATH").expect("a variable is a source"),
+        )
+        .expect("PATH is set");
+        assert!(!from_env.key.is_empty());
+
+        let unset = KeySource::from_str("env:FCKLOUD_TEST_NO_SUCH_KEY").expect("it parses");
+        assert!(Credential::read(Placement::Bearer, &unset).is_err());
+        assert!(KeySource::from_str("env:").is_err());
+    }
+
+    #[test]
+    fn a_placement_is_bearer_a_header_or_a_query_parameter() {
+        assert_eq!(Placement::from_str("Bearer"), Ok(Placement::Bearer));
+        assert_eq!(
+            Placement::from_str("query:apiKey"),
+            Ok(Placement::Query("apiKey".into()))
+        );
+
+        for wrong in ["cookie:session", "header:bad header", "query:", "basic"] {
+            assert!(Placement::from_str(wrong).is_err(), "`{wrong}` is taken");
+        }
+    }
+}
@@ -0,0 +1,635 @@
+use {
+    crate::pubip::{
+        Family, Uplink, client,
+        error::{Cause, FetchError, hex},
+        socket,
+    },
+    ring::rand::{SecureRandom as _, SystemRandom},
+    std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        time::timeout,
+    },
+};
+
+// RFC 1035, section 4.1.1, and the record types and classes of section 3.2.
+const HEADER_LEN: usize = 12;
+const QR: u16 = 0x8000;
+const OPCODE: u16 = 0x7800;
+const AA: u16 = 0x0400;
+const TC: u16 = 0x0200;
+const RD: u16 = 0x0100;
+const RCODE: u16 = 0x000f;
+const TYPE_A: u16 = 1;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+
+// RFC 1035, section 2.3.4, the name counted as it is written, dots and all.
+const MAX_LABEL: usize = 63;
+const MAX_NAME: usize = 253;
+
+/// Where a name is asked, what is asked of it, and which part of the answer
+/// is the address.
+///
+/// A whoami name is answered by the authority with the address the query
+/// arrived from. Asked of a recursive resolver instead, it is answered with
+/// the resolver's own address, routable and wrong; only the authority may
+/// set the AA bit, so an answer without it is refused.
+#[derive(PartialEq, Eq, Hash, Debug)]
+pub struct Lookup {
+    pub server: &'static str,
+    pub port: u16,
+    pub tls: bool,
+    pub name: &'static str,
+    pub class: Class,
+    pub record: Record,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Class {
+    In = 1,
+    /// CHAOS, where servers keep the names that are about themselves.
+    Ch = 3,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Record {
+    /// A over IPv4 and AAAA over IPv6, the name resolving to the asker.
+    Address,
+    /// A TXT record whose first string is the asker's address.
+    Txt,
+}
+
+impl Lookup {
+    pub const PORT: u16 = 53;
+    pub const TLS_PORT: u16 = 853;
+
+    /// The `network.transport` attribute value: DNS over TLS rides on TCP,
+    /// plain DNS on UDP.
+    pub const fn transport(&self) -> &'static str {
+        if self.tls { "tcp" } else { "udp" }
+    }
+
+    const fn kind(&self, family: Family) -> u16 {
+        match self.record {
+            Record::Address => address_kind(family),
+            Record::Txt => TYPE_TXT,
+        }
+    }
+}
+
+/// A over IPv4, AAAA over IPv6.
+const fn address_kind(family: Family) -> u16 {
+    match family {
+        Family::V4 => TYPE_A,
+        Family::V6 => TYPE_AAAA,
+    }
+}
+
+/// Asks the authority which address our query arrives from, over the family
+/// and out by the uplink.
+pub async fn query(lookup: &Lookup, family: Family, uplink: &Uplink) -> Result<IpAddr, FetchError> {
+    let id = transaction_id();
+    let request = encode(lookup, family, id)?;
+
+    let exchange = async {
+        let peer = socket::resolve(lookup.server, lookup.port, family, uplink).await?;
+
+        if lookup.tls {
+            over_tls(lookup, peer, uplink, &request).await
+        } else {
+            socket::exchange_udp(peer, uplink, &request, |message| belongs_to(message, id)).await
+        }
+    };
+
+    let message = timeout(client::REQUEST_TIMEOUT, exchange)
+        .await
+        .map_err(|_| FetchError::Timeout)??;
+
+    decode(&message, lookup, family, id)
+}
+
+/// One query and one answer over a TLS connection, each behind the two-byte
+/// length RFC 7858 frames them with.
+async fn over_tls(
+    lookup: &Lookup,
+    peer: SocketAddr,
+    uplink: &Uplink,
+    request: &[u8],
+) -> Result<Vec<u8>, FetchError> {
+    let mut stream = socket::connect_tls(lookup.server, peer, uplink).await?;
+
+    let length = u16::try_from(request.len()).expect("a query is far shorter than 64 KiB");
+    let mut framed = length.to_be_bytes().to_vec();
+    framed.extend_from_slice(request);
+    stream.write_all(&framed).await?;
+
+    let length = usize::from(stream.read_u16().await?);
+    let mut message = vec![0; length];
+    stream.read_exact(&mut message).await?;
+
+    Ok(message)
+}
+
+/// Sixteen bits nobody else on the path could have guessed ahead of time,
+/// drawn from the system's own randomness. The source port is the other half
+/// of what a spoofed answer has to guess, and sixteen bits are little enough.
+fn transaction_id() -> u16 {
+    let mut id = [0; 2];
+    SystemRandom::new()
+        .fill(&mut id)
+        .expect("the system must have randomness to give");
+
+    u16::from_be_bytes(id)
+}
+
+/// A query for the one question, recursion not desired: the server asked is
+/// the one meant to answer, and nobody is to go asking on our behalf.
+fn encode(lookup: &Lookup, family: Family, id: u16) -> Result<Vec<u8>, FetchError> {
+    question(lookup.name, lookup.kind(family), lookup.class as u16, id, 0)
+}
+
+/// A query for the addresses of a name in the family, recursion desired,
+/// the way a resolver over HTTPS is asked. Its ID is zero, as RFC 8484 wants
+/// of a query an HTTP cache may answer.
+pub fn encode_recursive(name: &str, family: Family) -> Result<Vec<u8>, FetchError> {
+    question(name, address_kind(family), Class::In as u16, 0, RD)
+}
+
+fn question(name: &str, kind: u16, class: u16, id: u16, flags: u16) -> Result<Vec<u8>, FetchError> {
+    let labels = labels(name)?;
+    let mut message = Vec::with_capacity(HEADER_LEN + name.len() + 6);
+
+    message.extend(id.to_be_bytes());
+    message.extend(flags.to_be_bytes());
+    message.extend(1u16.to_be_bytes());
+    message.extend([0; 6]);
+
+    // Every label is known to fit its length byte by now.
+    for label in labels {
+        message.extend(u8::try_from(label.len()));
+        message.extend(label.as_bytes());
+    }
+    message.push(0);
+
+    message.extend(kind.to_be_bytes());
+    message.extend(class.to_be_bytes());
+
+    Ok(message)
+}
+
+/// The labels of a name, once it is known to be one DNS can carry: none of
+/// them empty or longer than a length byte may say, and all of them together
+/// no longer than a name may be.
+fn labels(name: &str) -> Result<Vec<&str>, FetchError> {
+    let name = name.trim_end_matches('.');
+    let invalid =
+        |reason| FetchError::Unreachable(format!("`{name}` is not a DNS name: {reason}").into());
+
+    if name.len() > MAX_NAME {
+        return Err(invalid("it is longer than 253 bytes"));
+    }
+
+    let labels: Vec<&str> = name.split('.').collect();
+    if labels.iter().any(|label| label.is_empty()) {
+        return Err(invalid("a label is empty"));
+    }
+    if labels.iter().any(|label| label.len() > MAX_LABEL) {
+        return Err(invalid("a label is longer than 63 bytes"));
+    }
+
+    Ok(labels)
+}
+
+/// Whether the message is a response answering our query.
+fn belongs_to(message: &[u8], id: u16) -> bool {
+    message.len() >= HEADER_LEN
+        && message[0..2] == id.to_be_bytes()
+        && u16::from_be_bytes([message[2], message[3]]) & QR != 0
+}
+
+/// The address the authority says the query came from.
+fn decode(message: &[u8], lookup: &Lookup, family: Family, id: u16) -> Result<IpAddr, FetchError> {
+    let kind = lookup.kind(family);
+    let records = answers(message, id, lookup.name, kind, lookup.class as u16, true)?;
+    let asked = lookup.name.trim_end_matches('.');
+
+    let Some((_, data)) = records
+        .into_iter()
+        .find(|(owner, _)| owner.eq_ignore_ascii_case(asked))
+    else {
+        return Err(malformed(message, "no record answers the question"));
+    };
+
+    address(data, lookup.record)
+        .ok_or_else(|| malformed(message, "the record does not hold an address"))
+}
+
+/// Every address a resolver answered a recursive query with, whichever name
+/// in a chain of CNAMEs owns it.
+pub fn decode_recursive(
+    message: &[u8],
+    name: &str,
+    family: Family,
+) -> Result<Vec<IpAddr>, FetchError> {
+    answers(
+        message,
+        0,
+        name,
+        address_kind(family),
+        Class::In as u16,
+        false,
+    )?
+    .into_iter()
+    .map(|(_, data)| {
+        address(data, Record::Address)
+            .filter(|ip_addr| Family::of(ip_addr) == family)
+            .ok_or_else(|| malformed(message, "the record does not hold an address"))
+    })
+    .collect()
+}
+
+fn malformed(message: &[u8], reason: &'static str) -> FetchError {
+    FetchError::Decode {
+        body: hex(message),
+        source: Cause::from(reason),
+    }
+}
+
+/// The owner and data of every record in the answer of the kind and class
+/// asked, once the answer is known to be to the one question asked. An
+/// authoritative answer is one the server answered for itself.
+fn answers<'m>(
+    message: &'m [u8],
+    id: u16,
+    name: &str,
+    kind: u16,
+    class: u16,
+    authoritative: bool,
+) -> Result<Vec<(String, &'m [u8])>, FetchError> {
+    let malformed = |reason| malformed(message, reason);
+
+    if !belongs_to(message, id) {
+        return Err(malformed("not a DNS answer to this query"));
+    }
+
+    let flags = u16::from_be_bytes([message[2], message[3]]);
+    let count = |at: usize| u16::from_be_bytes([message[at], message[at + 1]]);
+
+    if flags & OPCODE != 0 {
+        return Err(malformed("not an answer to a standard query"));
+    }
+
+    // A truncated answer is one the server would have sent in full over TCP.
+    // The names asked here fit a datagram many times over, so one that did
+    // not is nothing worth a second connection.
+    if flags & TC != 0 {
+        return Err(malformed("the answer is truncated"));
+    }
+
+    let code = flags & RCODE;
+    if code != 0 {
+        return Err(FetchError::DnsStatus {
+            code,
+            name: rcode_name(code),
+        });
+    }
+
+    if authoritative && flags & AA == 0 {
+        return Err(FetchError::NotAuthoritative);
+    }
+
+    if count(4) != 1 {
+        return Err(malformed("the answer does not repeat the one question"));
+    }
+
+    let mut at = HEADER_LEN;
+    let question =
+        self::name(message, &mut at).ok_or_else(|| malformed("the question does not parse"))?;
+    let asked = message
+        .get(at..at + 4)
+        .ok_or_else(|| malformed("the question does not parse"))?;
+    at += 4;
+
+    if !question.eq_ignore_ascii_case(name.trim_end_matches('.'))
+        || asked[0..2] != kind.to_be_bytes()
+        || asked[2..4] != class.to_be_bytes()
+    {
+        return Err(malformed("the answer is to another question"));
+    }
+
+    let mut records = Vec::new();
+    for _ in 0..count(6) {
+        let owner =
+            self::name(message, &mut at).ok_or_else(|| malformed("a record does not parse"))?;
+        let fixed = message
+            .get(at..at + 10)
+            .ok_or_else(|| malformed("a record does not parse"))?;
+        let length = usize::from(u16::from_be_bytes([fixed[8], fixed[9]]));
+        let data = message
+            .get(at + 10..at + 10 + length)
+            .ok_or_else(|| malformed("a record runs past the message"))?;
+        at += 10 + length;
+
+        if fixed[0..2] == kind.to_be_bytes() && fixed[2..4] == class.to_be_bytes() {
+            records.push((owner, data));
+        }
+    }
+
+    Ok(records)
+}
+
+/// The address a record's data holds: the data itself for A and AAAA, the
+/// first string of it for TXT.
+fn address(data: &[u8], record: Record) -> Option<IpAddr> {
+    match record {
+        Record::Address => match data.len() {
+            4 => Some(IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(data).ok()?))),
+            16 => Some(IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(data).ok()?))),
+            _ => None,
+        },
+        Record::Txt => {
+            let length = usize::from(*data.first()?);
+            std::str::from_utf8(data.get(1..=length)?)
+                .ok()?
+                .trim()
+                .parse()
+                .ok()
+        }
+    }
+}
+
+/// The name starting at `at`, dotted, with `at` moved past it. Compression
+/// pointers are followed, but only backwards, so that a loop of them ends.
+fn name(message: &[u8], at: &mut usize) -> Option<String> {
+    let mut labels: Vec<&str> = Vec::new();
+    let mut cursor = *at;
+    let mut resumed = None;
+
+    loop {
+        let length = *message.get(cursor)?;
+
+        match length & 0xc0 {
+            0x00 if length == 0 => {
+                *at = resumed.unwrap_or(cursor + 1);
+                return Some(labels.join("."));
+            }
+            0x00 => {
+                let label = message.get(cursor + 1..=cursor + usize::from(length))?;
+                labels.push(std::str::from_utf8(label).ok()?);
+                cursor += 1 + usize::from(length);
+            }
+            0xc0 => {
+                let target = usize::from(u16::from_be_bytes([
+                    length & 0x3f,
+                    *message.get(cursor + 1)?,
+                ]));
+                if target >= cursor {
+                    return None;
+                }
+
+                resumed.get_or_insert(cursor + 2);
+                cursor = target;
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// The mnemonic RFC 1035 and RFC 2136 give a response code.
+const fn rcode_name(code: u16) -> &'static str {
+    match code {
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        6 => "YXDOMAIN",
+        7 => "YXRRSET",
+        8 => "NXRRSET",
+        9 => "NOTAUTH",
+        10 => "NOTZONE",
+        _ => "unassigned",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::str::FromStr, tokio::net::UdpSocket};
+
+    const ID: u16 = 0xbeef;
+
+    const OPENDNS: Lookup = Lookup {
+        server: "127.0.0.1",
+        port: Lookup::PORT,
+        tls: false,
+        name: "myip.opendns.com",
+        class: Class::In,
+        record: Record::Address,
+    };
+
+    const WHOAMI: Lookup = Lookup {
+        server: "127.0.0.1",
+        port: Lookup::PORT,
+        tls: false,
+        name: "whoami.cloudflare",
+        class: Class::Ch,
+        record: Record::Txt,
+    };
+
+    fn ip(s: &str) -> IpAddr {
+        IpAddr::from_str(s).expect("test address must parse")
+    }
+
+    /// What an authority says back: the query, flagged as an authoritative
+    /// response, and one record whose owner points back at the question.
+    fn answer(query: &[u8], flags: u16, kind: u16, class: u16, data: &[u8]) -> Vec<u8> {
+        let mut message = query.to_vec();
+        message[2..4].copy_from_slice(&(QR | flags).to_be_bytes());
+        message[6..8].copy_from_slice(&1u16.to_be_bytes());
+
+        message.extend([0xc0, 0x0c]);
+        message.extend(kind.to_be_bytes());
+        message.extend(class.to_be_bytes());
+        message.extend(60u32.to_be_bytes());
+        message.extend(u16::try_from(data.len()).expect("fits").to_be_bytes());
+        message.extend(data);
+        message
+    }
+
+    fn txt(text: &str) -> Vec<u8> {
+        let mut data = vec![u8::try_from(text.len()).expect("fits")];
+        data.extend(text.as_bytes());
+        data
+    }
+
+    #[test]
+    fn a_query_asks_the_one_question_without_recursion() {
+        let query = encode(&OPENDNS, Family::V6, ID).expect("the name must encode");
+
+        assert_eq!(query[..12], [0xbe, 0xef, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&query[12..30], b"\x04myip\x07opendns\x03com\x00");
+        assert_eq!(query[30..], [0, 28, 0, 1]);
+    }
+
+    #[test]
+    fn a_name_dns_cannot_carry_is_refused_rather_than_encoded() {
+        let label = "a".repeat(64);
+        let err = encode_recursive(&format!("{label}.example"), Family::V4)
+            .expect_err("a 64 byte label must be refused");
+        assert_eq!(err.as_error_type(), "unreachable");
+
+        let long = vec!["a".repeat(63); 4].join(".");
+        assert!(encode_recursive(&long, Family::V4).is_err());
+        assert!(encode_recursive("api..ipify.org", Family::V4).is_err());
+
+        let longest = format!("{}.{}", "a".repeat(63), "b".repeat(63));
+        assert!(encode_recursive(&format!("{longest}."), Family::V6).is_ok());
+    }
+
+    #[test]
+    fn an_authoritative_answer_decodes_to_the_address_it_carries() {
+        let query = encode(&OPENDNS, Family::V4, ID).expect("the name must encode");
+        let message = answer(&query, AA, TYPE_A, 1, &[203, 0, 113, 9]);
+
+        assert_eq!(
+            decode(&message, &OPENDNS, Family::V4, ID).expect("the A record must decode"),
+            ip("203.0.113.9"),
+        );
+
+        let query = encode(&WHOAMI, Family::V6, ID).expect("the name must encode");
+        let message = answer(&query, AA, TYPE_TXT, 3, &txt("2001:db8::9"));
+
+        assert_eq!(
+            decode(&message, &WHOAMI, Family::V6, ID).expect("the TXT record must decode"),
+            ip("2001:db8::9"),
+        );
+    }
+
+    #[test]
+    fn an_answer_without_authority_is_a_resolver_speaking_for_itself() {
+        let query = encode(&OPENDNS, Family::V4, ID).expect("the name must encode");
+        let message = answer(&query, 0, TYPE_A, 1, &[198, 51, 100, 1]);
+
+        let err = decode(&message, &OPENDNS, Family::V4, ID)
+            .expect_err("an answer without AA must be refused");
+        assert_eq!(err.as_error_type(), "not_authoritative");
+    }
+
+    #[test]
+    fn a_recursive_answer_gives_every_address_at_the_end_of_its_chain() {
+        let query = encode_recursive("api.ipify.org.", Family::V4).expect("the name must encode");
+        assert_eq!(query[..4], [0, 0, 1, 0]);
+
+        // A CNAME to an edge name, then two A records owned by the edge.
+        let mut message = answer(&query, RD, 5, 1, b"\x04edge\x03net\x00");
+        message[6..8].copy_from_slice(&3u16.to_be_bytes());
+        for octet in [1, 2] {
+            message.extend(b"\x04edge\x03net\x00");
+            message.extend(TYPE_A.to_be_bytes());
+            message.extend(1u16.to_be_bytes());
+            message.extend(60u32.to_be_bytes());
+            message.extend(4u16.to_be_bytes());
+            message.extend([192, 0, 2, octet]);
+        }
+
+        assert_eq!(
+            decode_recursive(&message, "api.ipify.org", Family::V4)
+                .expect("an answer without authority is what a resolver gives"),
+            [ip("192.0.2.1"), ip("192.0.2.2")],
+        );
+
+        let mut refused = query.clone();
+        refused[2..4].copy_from_slice(&(QR | 3).to_be_bytes());
+        let err = decode_recursive(&refused, "api.ipify.org", Family::V4)
+            .expect_err("NXDOMAIN holds no address");
+        assert_eq!(err.as_error_type(), "dns_status");
+    }
+
+    #[test]
+    fn a_refusal_is_a_dns_status() {
+        let query = encode(&OPENDNS, Family::V4, ID).expect("the name must encode");
+        let mut message = query.clone();
+        message[2..4].copy_from_slice(&(QR | 5).to_be_bytes());
+
+        match decode(&message, &OPENDNS, Family::V4, ID) {
+            Err(FetchError::DnsStatus { code, name }) => {
+                assert_eq!(code, 5);
+                assert_eq!(name, "REFUSED");
+            }
+            other => panic!("a refusal must be a status, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn an_answer_to_another_query_is_not_ours() {
+        let query = encode(&OPENDNS, Family::V4, ID).expect("the name must encode");
+        let message = answer(&query, AA, TYPE_A, 1, &[203, 0, 113, 9]);
+
+        assert!(!belongs_to(&message, ID ^ 1));
+        assert!(!belongs_to(&query, ID), "a query is no answer");
+
+        let other = encode(&WHOAMI, Family::V4, ID).expect("the name must encode");
+        let message = answer(&other, AA, TYPE_TXT, 3, &txt("203.0.113.9"));
+        let err = decode(&message, &OPENDNS, Family::V4, ID)
+            .expect_err("an answer to another question must not decode");
+        assert_eq!(err.as_error_type(), "decode");
+    }
+
+    #[test]
+    fn a_truncated_or_looping_answer_reports_what_it_saw() {
+        let query = encode(&OPENDNS, Family::V4, ID).expect("the name must encode");
+        let message = answer(&query, AA, TYPE_A, 1, &[203, 0, 113, 9]);
+
+        let err = decode(&message[..message.len() - 2], &OPENDNS, Family::V4, ID)
+            .expect_err("a truncated message must not decode");
+        assert_eq!(err.as_error_type(), "decode");
+        assert!(err.to_string().contains("beef8400"));
+
+        let mut looping = message.clone();
+        let owner = query.len();
+        looping[owner + 1] = u8::try_from(owner).expect("fits");
+        decode(&looping, &OPENDNS, Family::V4, ID).expect_err("a pointer loop must not decode");
+
+        let mut flagged = message;
+        flagged[2] |= 0x02;
+        decode(&flagged, &OPENDNS, Family::V4, ID).expect_err("TC must not decode");
+    }
+
+    // A stand-in on loopback that lets the first query go unanswered and
+    // sends a stray response before the real one, so both the retransmission
+    // and the ID check have to work.
+    #[tokio::test]
+    async fn a_local_stand_in_is_asked_over_udp() {
+        let stand_in = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .expect("loopback must be bindable");
+        let port = stand_in.local_addr().expect("it has an address").port();
+
+        let serving = tokio::spawn(async move {
+            let mut buf = [0; 512];
+
+            let _ = stand_in.recv_from(&mut buf).await.expect("a first query");
+            let (received, from) = stand_in.recv_from(&mut buf).await.expect("a retry");
+            let query = &buf[..received];
+
+            let mut stray = answer(query, AA, TYPE_TXT, 3, &txt("198.51.100.1"));
+            stray[1] ^= 0x01;
+
+            for response in [stray, answer(query, AA, TYPE_TXT, 3, &txt("203.0.113.9"))] {
+                stand_in
+                    .send_to(&response, from)
+                    .await
+                    .expect("the answer must go");
+            }
+        });
+
+        let lookup = Lookup { port, ..WHOAMI };
+        let answered = query(&lookup, Family::V4, &Uplink::Default)
+            .await
+            .expect("the stand-in must be asked");
+
+        assert_eq!(answered, ip("203.0.113.9"));
+        serving.await.expect("the stand-in must not panic");
+    }
+}
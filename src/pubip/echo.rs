@@ -0,0 +1,480 @@
+use {
+    crate::pubip::error::{FetchError, hex},
+    anyhow::{Context as _, Result, ensure},
+    reqwest::Url,
+    ring::{
+        hmac,
+        rand::{SecureRandom as _, SystemRandom},
+    },
+    serde::{Deserialize, Serialize},
+    std::{
+        fmt, fs, io,
+        net::IpAddr,
+        path::Path,
+        sync::Arc,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+    tokio::{
+        io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _},
+        net::TcpListener,
+        time::timeout,
+    },
+    tokio_rustls::TlsAcceptor,
+    tracing::debug,
+};
+
+// How far apart the two clocks may be before a signed answer is refused as
+// replayed. A nonce alone would do, were the asker to remember every one it
+// ever sent; the timestamp is what lets it forget them.
+const SKEW: Duration = Duration::from_mins(5);
+
+// A request is one line and a few headers. Anything longer is not an asker
+// this server was written for.
+const LONGEST_HEAD: usize = 8 * 1024;
+const LONGEST_NONCE: usize = 64;
+const HEAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Shorter than the output of SHA-256 is a key easier to guess than the MAC
+// it makes.
+const SHORTEST_SECRET: usize = 32;
+
+/// A key shared by `fckloud echo` and the providers that ask it. Printed as
+/// `Secret(..)` wherever it is printed at all.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Secret(Vec<u8>);
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+impl Secret {
+    /// The file's contents, the trailing newline an editor leaves aside.
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read(path)
+            .with_context(|| format!("cannot read secret file {}", path.display()))?;
+
+        let secret = text.trim_ascii_end().to_vec();
+        ensure!(
+            secret.len() >= SHORTEST_SECRET,
+            "secret file {} holds fewer than {SHORTEST_SECRET} bytes",
+            path.display(),
+        );
+
+        Ok(Self(secret))
+    }
+
+    fn key(&self) -> hmac::Key {
+        hmac::Key::new(hmac::HMAC_SHA256, &self.0)
+    }
+}
+
+/// A declared provider that is an `fckloud echo`, and the key its answers
+/// are signed with when they are.
+#[derive(PartialEq, Eq, Hash, Debug)]
+pub struct Echo {
+    pub secret: Option<Secret>,
+}
+
+/// What `fckloud echo` answers: the shape ipify and seeip answer, so that
+/// anything able to ask them can ask it, and the signature beside it when
+/// one was asked for.
+#[derive(Serialize, Deserialize, Debug)]
+struct Answer {
+    ip: IpAddr,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+}
+
+impl Answer {
+    /// Signed when the server holds a secret and the asker sent a nonce: a
+    /// signature over no nonce could be replayed to anybody.
+    fn new(ip: IpAddr, secret: Option<&Secret>, nonce: Option<String>, now: u64) -> Self {
+        match (secret, nonce) {
+            (Some(secret), Some(nonce)) => Self {
+                ip,
+                timestamp: Some(now),
+                signature: Some(hex(hmac::sign(
+                    &secret.key(),
+                    signed(ip, now, &nonce).as_bytes(),
+                )
+                .as_ref())),
+                nonce: Some(nonce),
+            },
+            _ => Self {
+                ip,
+                timestamp: None,
+                nonce: None,
+                signature: None,
+            },
+        }
+    }
+}
+
+/// What the signature covers: the address, when it was seen, and for whom.
+fn signed(ip: IpAddr, timestamp: u64, nonce: &str) -> String {
+    format!("{ip}\n{timestamp}\n{nonce}")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+impl Echo {
+    /// Sixteen random bytes for one request, when its answer is to be
+    /// signed.
+    pub fn nonce(&self) -> Option<String> {
+        self.secret.as_ref().map(|_| {
+            let mut nonce = [0; 16];
+            SystemRandom::new()
+                .fill(&mut nonce)
+                .expect("the system must have randomness to give");
+
+            hex(&nonce)
+        })
+    }
+
+    /// The URI with the nonce in its query, where the server looks for it.
+    pub fn url(uri: &str, nonce: Option<&str>) -> Result<Url, FetchError> {
+        let mut url = Url::parse(uri).map_err(|err| FetchError::Unreachable(err.into()))?;
+
+        if let Some(nonce) = nonce {
+            url.query_pairs_mut().append_pair("nonce", nonce);
+        }
+
+        Ok(url)
+    }
+
+    /// The address in the body, once the signature over it holds when a
+    /// signature is wanted.
+    pub fn decode(&self, body: &[u8], nonce: Option<&str>) -> Result<IpAddr, FetchError> {
+        self.decode_at(body, nonce, now())
+    }
+
+    fn decode_at(&self, body: &[u8], nonce: Option<&str>, now: u64) -> Result<IpAddr, FetchError> {
+        let answer: Answer = serde_json::from_slice(body).map_err(|source| FetchError::Decode {
+            body: String::from_utf8_lossy(body).into_owned(),
+            source: source.into(),
+        })?;
+
+        let (Some(secret), Some(nonce)) = (&self.secret, nonce) else {
+            return Ok(answer.ip);
+        };
+
+        let (Some(timestamp), Some(echoed), Some(signature)) =
+            (answer.timestamp, answer.nonce, answer.signature)
+        else {
+            return Err(FetchError::BadSignature("the answer is not signed"));
+        };
+
+        if echoed != nonce {
+            return Err(FetchError::BadSignature("the answer is to another request"));
+        }
+
+        if now.abs_diff(timestamp) > SKEW.as_secs() {
+            return Err(FetchError::BadSignature(
+                "the answer was signed too long ago",
+            ));
+        }
+
+        let signature =
+            unhex(&signature).ok_or(FetchError::BadSignature("the signature is not hex"))?;
+
+        hmac::verify(
+            &secret.key(),
+            signed(answer.ip, timestamp, nonce).as_bytes(),
+            &signature,
+        )
+        .map_err(|_| FetchError::BadSignature("the signature does not match"))?;
+
+        Ok(answer.ip)
+    }
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(hex.get(at..at + 2)?, 16).ok())
+        .collect()
+}
+
+/// Answers every connection on the listener with the address it came from,
+/// over TLS when given an acceptor, until the process is told to stop.
+///
+/// Deployed outside the NAT, this is a provider whose operator is the one
+/// running the cluster. Put nothing in front of it that terminates the
+/// connection: a proxy's address is all it would ever see.
+pub async fn serve(
+    listener: TcpListener,
+    tls: Option<TlsAcceptor>,
+    secret: Option<Secret>,
+) -> io::Result<()> {
+    let secret = Arc::new(secret);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let tls = tls.clone();
+        let secret = Arc::clone(&secret);
+
+        // A v4 asker of a socket bound to `[::]` shows up v4-mapped.
+        let ip = peer.ip().to_canonical();
+
+        tokio::spawn(async move {
+            let answered = match tls {
+                Some(tls) => match tls.accept(stream).await {
+                    Ok(stream) => answer(stream, ip, secret.as_ref().as_ref()).await,
+                    Err(err) => Err(err),
+                },
+                None => answer(stream, ip, secret.as_ref().as_ref()).await,
+            };
+
+            if let Err(err) = answered {
+                debug!(%ip, %err, "asker got no answer");
+            }
+        });
+    }
+}
+
+/// Reads one request head and answers it, then closes the connection.
+async fn answer<S>(mut stream: S, ip: IpAddr, secret: Option<&Secret>) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let head = timeout(HEAD_TIMEOUT, read_head(&mut stream))
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+
+    let (status, body) = match request_nonce(&head) {
+        Ok(nonce) => {
+            let signed = secret.is_some() && nonce.is_some();
+            debug!(%ip, signed, "asker answered");
+
+            let answer = Answer::new(ip, secret, nonce, now());
+            let body = serde_json::to_string(&answer).expect("an answer always serialises");
+            ("200 OK", body)
+        }
+        Err(status) => (status, String::new()),
+    };
+
+    let response = format!(
+        concat!(
+            "HTTP/1.1 {}\r\n",
+            "content-type: application/json\r\n",
+            "content-length: {}\r\n",
+            "cache-control: no-store\r\n",
+            "connection: close\r\n",
+            "\r\n",
+            "{}",
+        ),
+        status,
+        body.len(),
+        body,
+    );
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+async fn read_head<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<String> {
+    let mut head = Vec::with_capacity(1024);
+    let mut buf = [0; 1024];
+
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() > LONGEST_HEAD {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request head too long",
+            ));
+        }
+
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        head.extend_from_slice(&buf[..read]);
+    }
+
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+/// The nonce a request carries in its query, or the status refusing it.
+fn request_nonce(head: &str) -> Result<Option<String>, &'static str> {
+    let mut line = head.lines().next().unwrap_or_default().split(' ');
+
+    let (Some(method), Some(target)) = (line.next(), line.next()) else {
+        return Err("400 Bad Request");
+    };
+
+    if method != "GET" {
+        return Err("405 Method Not Allowed");
+    }
+
+    if !target.starts_with('/') {
+        return Err("400 Bad Request");
+    }
+
+    let url = Url::parse(&format!("http://echo{target}")).map_err(|_| "400 Bad Request")?;
+    let nonce = url
+        .query_pairs()
+        .find(|(key, _)| key == "nonce")
+        .map(|(_, nonce)| nonce.into_owned());
+
+    match nonce {
+        Some(nonce) if nonce.is_empty() || nonce.len() > LONGEST_NONCE => Err("400 Bad Request"),
+        nonce => Ok(nonce),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, tokio::net::TcpStream};
+
+    const NOW: u64 = 1_790_000_000;
+
+    fn secret(fill: u8) -> Secret {
+        Secret(vec![fill; SHORTEST_SECRET])
+    }
+
+    fn signed_body(secret: &Secret, ip: &str, nonce: &str, at: u64) -> Vec<u8> {
+        let ip = ip.parse().expect("test address must parse");
+        let answer = Answer::new(ip, Some(secret), Some(nonce.to_owned()), at);
+
+        serde_json::to_vec(&answer).expect("it serialises")
+    }
+
+    #[test]
+    fn a_signed_answer_decodes_to_its_address() {
+        let echo = Echo {
+            secret: Some(secret(7)),
+        };
+        let body = signed_body(&secret(7), "1.2.3.4", "abc", NOW);
+
+        assert_eq!(
+            echo.decode_at(&body, Some("abc"), NOW + 30)
+                .expect("a good signature must hold"),
+            IpAddr::from([1, 2, 3, 4]),
+        );
+    }
+
+    #[test]
+    fn an_answer_altered_on_the_way_is_refused() {
+        let echo = Echo {
+            secret: Some(secret(7)),
+        };
+        let body = signed_body(&secret(7), "1.2.3.4", "abc", NOW);
+        let altered = String::from_utf8(body)
+            .expect("it is JSON")
+            .replace("1.2.3.4", "5.6.7.8");
+
+        let err = echo
+            .decode_at(altered.as_bytes(), Some("abc"), NOW)
+            .expect_err("an altered address must not verify");
+        assert_eq!(err.as_error_type(), "bad_signature");
+
+        for (body, nonce, now) in [
+            (signed_body(&secret(8), "1.2.3.4", "abc", NOW), "abc", NOW),
+            (signed_body(&secret(7), "1.2.3.4", "abc", NOW), "abd", NOW),
+            (
+                signed_body(&secret(7), "1.2.3.4", "abc", NOW),
+                "abc",
+                NOW + 3600,
+            ),
+            (br#"{"ip":"1.2.3.4"}"#.to_vec(), "abc", NOW),
+        ] {
+            let err = echo
+                .decode_at(&body, Some(nonce), now)
+                .expect_err("a bad signature must be refused");
+            assert_eq!(err.as_error_type(), "bad_signature");
+        }
+    }
+
+    #[test]
+    fn without_a_secret_the_plain_shape_is_enough() {
+        let echo = Echo { secret: None };
+
+        assert!(echo.nonce().is_none());
+        assert_eq!(
+            echo.decode_at(br#"{"ip":"1.2.3.4"}"#, None, NOW)
+                .expect("an unsigned echo must decode"),
+            IpAddr::from([1, 2, 3, 4]),
+        );
+    }
+
+    #[test]
+    fn a_secret_is_never_printed() {
+        let echo = Echo {
+            secret: Some(secret(b'k')),
+        };
+
+        assert!(!format!("{echo:?}").contains("kkkk"));
+    }
+
+    #[test]
+    fn only_a_get_is_answered() {
+        assert_eq!(request_nonce("GET / HTTP/1.1\r\n\r\n"), Ok(None));
+        assert_eq!(
+            request_nonce("GET /?nonce=abc HTTP/1.1\r\n\r\n"),
+            Ok(Some("abc".to_owned()))
+        );
+        assert!(request_nonce("POST / HTTP/1.1\r\n\r\n").is_err());
+        assert!(request_nonce("GET http://x/ HTTP/1.1\r\n\r\n").is_err());
+        assert!(
+            request_nonce(&format!("GET /?nonce={} HTTP/1.1\r\n\r\n", "a".repeat(65))).is_err()
+        );
+    }
+
+    // The server and the provider side by side on loopback, the way a
+    // cluster and its VPS would talk.
+    #[tokio::test]
+    async fn the_server_signs_what_the_provider_verifies() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("loopback must be bindable");
+        let addr = listener.local_addr().expect("it has an address");
+        tokio::spawn(serve(listener, None, Some(secret(7))));
+
+        let echo = Echo {
+            secret: Some(secret(7)),
+        };
+        let nonce = echo.nonce().expect("a secret wants a nonce");
+        let url = Echo::url(&format!("http://{addr}/"), Some(&nonce)).expect("it parses");
+
+        let mut stream = TcpStream::connect(addr).await.expect("it listens");
+        let request = format!(
+            "GET {}?{} HTTP/1.1\r\nhost: echo\r\n\r\n",
+            url.path(),
+            url.query().unwrap_or_default()
+        );
+        stream
+            .write_all(request.as_bytes())
+            .await
+            .expect("the request must go");
+
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .await
+            .expect("an answer must come");
+
+        let (head, body) = response.split_once("\r\n\r\n").expect("a head and a body");
+        assert!(head.starts_with("HTTP/1.1 200 OK"));
+
+        assert_eq!(
+            echo.decode(body.as_bytes(), Some(&nonce))
+                .expect("the signature must hold"),
+            IpAddr::from([127, 0, 0, 1]),
+        );
+    }
+}
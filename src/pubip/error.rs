@@ -1,8 +1,22 @@
 use {
+    crate::pubip::{Family, tls},
+    humantime::Duration as DisplayedDuration,
     reqwest::StatusCode,
-    std::{error::Error, fmt, net::IpAddr},
+    std::{
+        error::Error,
+        fmt::{self, Write as _},
+        io,
+        net::IpAddr,
+        time::Duration,
+    },
+    tokio_rustls::rustls,
 };
 
+/// Whatever a transport had to say about why it got nowhere. Boxed, because
+/// HTTP, STUN and TLS each have an error type of their own and the taxonomy
+/// below only cares which kind of failure it was.
+pub type Cause = Box<dyn Error + Send + Sync>;
+
 /// Why a provider's answer could not be used this round.
 ///
 /// The variants are a closed taxonomy on purpose: [`Self::as_error_type`] is
@@ -11,25 +25,118 @@ use {
 #[derive(Debug)]
 pub enum FetchError {
     Timeout,
-    Unreachable(reqwest::Error),
-    HttpStatus(StatusCode),
+    Unreachable(Cause),
+    Tls(Cause),
+    PinMismatch(Cause),
+    HttpStatus {
+        status: StatusCode,
+        /// How long the provider asked to be left alone, when it did.
+        retry_after: Option<Duration>,
+    },
+    StunStatus {
+        code: u16,
+        reason: String,
+    },
+    DnsStatus {
+        code: u16,
+        name: &'static str,
+    },
+    NotAuthoritative,
+    GatewayStatus {
+        code: u16,
+        reason: String,
+    },
+    BadSignature(&'static str),
+    NotPublished(String),
     Decode {
         body: String,
-        source: serde_json::Error,
+        source: Cause,
     },
     NotPublic(IpAddr),
+    WrongFamily {
+        asked: Family,
+        answered: IpAddr,
+    },
 }
 
 impl FetchError {
+    /// Every value [`Self::as_error_type`] takes, for whatever is configured
+    /// by them.
+    pub const TYPES: [&str; 14] = [
+        "timeout",
+        "unreachable",
+        "tls",
+        "pin_mismatch",
+        "http_status",
+        "stun_status",
+        "dns_status",
+        "not_authoritative",
+        "gateway_status",
+        "bad_signature",
+        "not_published",
+        "decode",
+        "not_public",
+        "wrong_family",
+    ];
+
     /// The `error.type` attribute value. Static strings, never the detail:
     /// the status code and the offending body belong in the log, not here.
     pub const fn as_error_type(&self) -> &'static str {
         match self {
             Self::Timeout => "timeout",
             Self::Unreachable(_) => "unreachable",
-            Self::HttpStatus(_) => "http_status",
+            Self::Tls(_) => "tls",
+            Self::PinMismatch(_) => "pin_mismatch",
+            Self::HttpStatus { .. } => "http_status",
+            Self::StunStatus { .. } => "stun_status",
+            Self::DnsStatus { .. } => "dns_status",
+            Self::NotAuthoritative => "not_authoritative",
+            Self::GatewayStatus { .. } => "gateway_status",
+            Self::BadSignature(_) => "bad_signature",
+            Self::NotPublished(_) => "not_published",
             Self::Decode { .. } => "decode",
             Self::NotPublic(_) => "not_public",
+            Self::WrongFamily { .. } => "wrong_family",
+        }
+    }
+
+    /// Whether the endpoint, rather than the provider, may be what failed, so
+    /// that a mirror of it is worth asking in the same round: nothing came
+    /// back, or the server said it was at fault. An answer that came back
+    /// wrong would come back as wrong from a mirror serving the same body; a
+    /// certificate that does not hold is the endpoint's own.
+    pub fn fails_over(&self) -> bool {
+        match self {
+            Self::Timeout | Self::Unreachable(_) | Self::Tls(_) => true,
+            Self::HttpStatus {
+                status,
+                retry_after,
+            } => status.is_server_error() && retry_after.is_none(),
+            _ => false,
+        }
+    }
+
+    /// Whether the provider said it is being asked too often: a 429, or a
+    /// 503 that says when to come back. A 503 without a word is a server
+    /// having a bad day, not one pushing back.
+    pub fn throttled(&self) -> bool {
+        match self {
+            Self::HttpStatus {
+                status,
+                retry_after,
+            } => {
+                *status == StatusCode::TOO_MANY_REQUESTS
+                    || (*status == StatusCode::SERVICE_UNAVAILABLE && retry_after.is_some())
+            }
+            _ => false,
+        }
+    }
+
+    /// How long the provider asked to be left alone, its `Retry-After`.
+    pub const fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::HttpStatus { retry_after, .. } => *retry_after,
+            _ => None,
         }
     }
 
@@ -38,7 +145,7 @@ impl FetchError {
     /// from a 503 without either becoming an error kind of its own.
     pub fn status_code(&self) -> Option<u16> {
         match self {
-            Self::HttpStatus(status) => Some(status.as_u16()),
+            Self::HttpStatus { status, .. } => Some(status.as_u16()),
             _ => None,
         }
     }
@@ -49,13 +156,47 @@ impl fmt::Display for FetchError {
         match self {
             Self::Timeout => f.write_str("provider timed out"),
             Self::Unreachable(err) => write!(f, "provider is unreachable: {err}"),
-            Self::HttpStatus(status) => write!(f, "provider responded with {status}"),
+            Self::Tls(err) => write!(f, "provider's TLS cannot be trusted: {err}"),
+            Self::PinMismatch(err) => {
+                write!(f, "provider's certificate is not one pinned for it: {err}")
+            }
+            Self::HttpStatus {
+                status,
+                retry_after: None,
+            } => write!(f, "provider responded with {status}"),
+            Self::HttpStatus {
+                status,
+                retry_after: Some(wait),
+            } => write!(
+                f,
+                "provider responded with {status}, to be asked again in {}",
+                DisplayedDuration::from(*wait),
+            ),
+            Self::StunStatus { code, reason } => {
+                write!(f, "provider responded with STUN error {code} {reason}")
+            }
+            Self::DnsStatus { code, name } => {
+                write!(f, "provider responded with DNS rcode {code} {name}")
+            }
+            Self::NotAuthoritative => f.write_str(
+                "provider answered without authority, which a resolver in the path would",
+            ),
+            Self::GatewayStatus { code, reason } => {
+                write!(f, "router responded with error {code} {reason}")
+            }
+            Self::BadSignature(reason) => {
+                write!(f, "provider's answer fails its signature: {reason}")
+            }
+            Self::NotPublished(key) => write!(f, "the Node carries no {key}"),
             Self::Decode { body, source } => {
                 write!(f, "cannot decode the response: {source}, data: {body}")
             }
             Self::NotPublic(ip_addr) => {
                 write!(f, "provider reported a non-public address {ip_addr}")
             }
+            Self::WrongFamily { asked, answered } => {
+                write!(f, "provider was asked over {asked} and answered {answered}")
+            }
         }
     }
 }
@@ -63,8 +204,8 @@ impl fmt::Display for FetchError {
 impl Error for FetchError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            Self::Unreachable(err) => Some(err),
-            Self::Decode { source, .. } => Some(source),
+            Self::Unreachable(err) | Self::Tls(err) | Self::PinMismatch(err) => Some(err.as_ref()),
+            Self::Decode { source, .. } => Some(source.as_ref()),
             _ => None,
         }
     }
@@ -73,27 +214,167 @@ impl Error for FetchError {
 impl From<reqwest::Error> for FetchError {
     fn from(err: reqwest::Error) -> Self {
         if err.is_timeout() {
-            Self::Timeout
-        } else {
-            Self::Unreachable(err)
+            return Self::Timeout;
+        }
+
+        match handshake_error(&err).map(tls::is_pin_mismatch) {
+            Some(true) => Self::PinMismatch(err.into()),
+            Some(false) => Self::Tls(err.into()),
+            None => Self::Unreachable(err.into()),
+        }
+    }
+}
+
+impl From<io::Error> for FetchError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::TimedOut {
+            return Self::Timeout;
+        }
+
+        match handshake_error(&err).map(tls::is_pin_mismatch) {
+            Some(true) => Self::PinMismatch(err.into()),
+            Some(false) => Self::Tls(err.into()),
+            None => Self::Unreachable(err.into()),
         }
     }
 }
 
+/// The rustls error anywhere in the chain: a handshake that failed, which
+/// reqwest and `tokio_rustls` alike hand back as an I/O error wrapping it.
+fn handshake_error<'a>(err: &'a (dyn Error + 'static)) -> Option<&'a rustls::Error> {
+    if let Some(handshake) = err.downcast_ref::<rustls::Error>() {
+        return Some(handshake);
+    }
+
+    // An I/O error's source is its inner error's source, the inner error
+    // itself skipped, so that one is looked at by hand.
+    err.downcast_ref::<io::Error>()
+        .and_then(io::Error::get_ref)
+        .and_then(|inner| handshake_error(inner))
+        .or_else(|| err.source().and_then(handshake_error))
+}
+
+/// A binary message as it goes in the log: a body nobody can read is still
+/// one somebody can paste into a decoder.
+pub fn hex(message: &[u8]) -> String {
+    message.iter().fold(String::new(), |mut hex, octet| {
+        let _ = write!(hex, "{octet:02x}");
+        hex
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use {super::*, std::str::FromStr};
 
+    fn status(status: StatusCode, retry_after: Option<u64>) -> FetchError {
+        FetchError::HttpStatus {
+            status,
+            retry_after: retry_after.map(Duration::from_secs),
+        }
+    }
+
     #[test]
     fn every_variant_has_its_own_error_type() {
         let types = [
             FetchError::Timeout.as_error_type(),
-            FetchError::HttpStatus(StatusCode::TOO_MANY_REQUESTS).as_error_type(),
+            FetchError::Tls("invalid peer certificate: UnknownIssuer".into()).as_error_type(),
+            FetchError::PinMismatch("api.ipify.org presented no pinned key".into()).as_error_type(),
+            FetchError::HttpStatus {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                retry_after: None,
+            }
+            .as_error_type(),
+            FetchError::StunStatus {
+                code: 420,
+                reason: "Unknown Attribute".into(),
+            }
+            .as_error_type(),
+            FetchError::DnsStatus {
+                code: 5,
+                name: "REFUSED",
+            }
+            .as_error_type(),
+            FetchError::NotAuthoritative.as_error_type(),
+            FetchError::GatewayStatus {
+                code: 2,
+                reason: "Not Authorized/Refused".into(),
+            }
+            .as_error_type(),
+            FetchError::BadSignature("the signature does not match").as_error_type(),
+            FetchError::NotPublished("annotation:k3s.io/external-ip".into()).as_error_type(),
             FetchError::NotPublic(IpAddr::from_str("10.0.0.1").expect("test address must parse"))
                 .as_error_type(),
+            FetchError::WrongFamily {
+                asked: Family::V6,
+                answered: IpAddr::from_str("1.1.1.1").expect("test address must parse"),
+            }
+            .as_error_type(),
         ];
 
-        assert_eq!(types, ["timeout", "http_status", "not_public"]);
+        assert_eq!(
+            types,
+            [
+                "timeout",
+                "tls",
+                "pin_mismatch",
+                "http_status",
+                "stun_status",
+                "dns_status",
+                "not_authoritative",
+                "gateway_status",
+                "bad_signature",
+                "not_published",
+                "not_public",
+                "wrong_family"
+            ]
+        );
+
+        assert!(types.iter().all(|kind| FetchError::TYPES.contains(kind)));
+    }
+
+    #[test]
+    fn only_an_endpoint_that_gave_no_answer_fails_over_to_a_mirror() {
+        assert!(FetchError::Timeout.fails_over());
+        assert!(FetchError::Unreachable("connection refused".into()).fails_over());
+        assert!(status(StatusCode::BAD_GATEWAY, None).fails_over());
+
+        assert!(!status(StatusCode::TOO_MANY_REQUESTS, None).fails_over());
+        assert!(!FetchError::NotPublic(IpAddr::from([10, 0, 0, 1])).fails_over());
+        assert!(
+            !FetchError::Decode {
+                body: "<html>".into(),
+                source: "not an address".into(),
+            }
+            .fails_over()
+        );
+    }
+
+    #[test]
+    fn a_provider_pushing_back_is_throttled_and_not_failed_over() {
+        assert!(status(StatusCode::TOO_MANY_REQUESTS, None).throttled());
+        assert!(status(StatusCode::SERVICE_UNAVAILABLE, Some(30)).throttled());
+        assert!(!status(StatusCode::SERVICE_UNAVAILABLE, None).throttled());
+        assert!(!status(StatusCode::INTERNAL_SERVER_ERROR, Some(30)).throttled());
+
+        assert!(status(StatusCode::SERVICE_UNAVAILABLE, None).fails_over());
+        assert!(!status(StatusCode::SERVICE_UNAVAILABLE, Some(30)).fails_over());
+        assert_eq!(
+            status(StatusCode::SERVICE_UNAVAILABLE, Some(30)).retry_after(),
+            Some(Duration::from_secs(30)),
+        );
+    }
+
+    #[test]
+    fn a_certificate_that_does_not_hold_is_told_from_a_host_that_is_not_there() {
+        let handshake = io::Error::new(
+            io::ErrorKind::InvalidData,
+            rustls::Error::InvalidCertificate(rustls::CertificateError::UnknownIssuer),
+        );
+        assert_eq!(FetchError::from(handshake).as_error_type(), "tls");
+
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        assert_eq!(FetchError::from(refused).as_error_type(), "unreachable");
     }
 
     #[test]
@@ -0,0 +1,124 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    str::FromStr,
+};
+
+/// An address family, and the consensus that is reached within it.
+///
+/// Each family is asked and weighed on its own. A dual-stacked provider
+/// answers over whichever family the connection arrived on, so a round that
+/// lets the connection choose splits its trust between two addresses and
+/// confirms neither.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Family {
+    V4,
+    V6,
+}
+
+impl Family {
+    pub const ALL: [Self; 2] = [Self::V4, Self::V6];
+
+    pub const fn of(ip_addr: &IpAddr) -> Self {
+        match ip_addr {
+            IpAddr::V4(_) => Self::V4,
+            IpAddr::V6(_) => Self::V6,
+        }
+    }
+
+    /// The address a socket binds to when only the family matters. Binding to
+    /// it is what keeps a connection from falling back to the other one.
+    pub const fn unspecified(self) -> IpAddr {
+        match self {
+            Self::V4 => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            Self::V6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        }
+    }
+
+    /// The `network.type` attribute value, spelled the way the semantic
+    /// conventions spell it.
+    pub const fn as_network_type(self) -> &'static str {
+        match self {
+            Self::V4 => "ipv4",
+            Self::V6 => "ipv6",
+        }
+    }
+
+    /// Whether a socket bound to `source` has a route into the Internet over
+    /// this family.
+    ///
+    /// Connecting a UDP socket sends nothing; it only asks the kernel which way
+    /// a packet would leave. A node without IPv6 then costs a debug line per
+    /// round rather than every provider failing as unreachable, loudly, because
+    /// their silence left a whole family unconfirmed.
+    pub fn routable_from(self, source: IpAddr) -> bool {
+        // a.root-servers.net: any global address does, since nothing is sent.
+        let probe = match self {
+            Self::V4 => SocketAddr::from((Ipv4Addr::new(198, 41, 0, 4), 53)),
+            Self::V6 => {
+                SocketAddr::from((Ipv6Addr::new(0x2001, 0x503, 0xba3e, 0, 0, 0, 2, 0x30), 53))
+            }
+        };
+
+        Self::of(&source) == self
+            && UdpSocket::bind((source, 0))
+                .and_then(|socket| socket.connect(probe))
+                .is_ok()
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::V4 => "IPv4",
+            Self::V6 => "IPv6",
+        })
+    }
+}
+
+impl FromStr for Family {
+    type Err = String;
+
+    /// `ipv4`, `v4` and `4` alike, whatever their case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "ipv4" | "v4" | "4" => Ok(Self::V4),
+            "ipv6" | "v6" | "6" => Ok(Self::V6),
+            _ => Err(format!("`{s}` is not an address family, use ipv4 or ipv6")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_address_belongs_to_the_family_it_is_written_in() {
+        let v4 = IpAddr::from_str("1.1.1.1").expect("test address must parse");
+        let v6 = IpAddr::from_str("2606:4700::1111").expect("test address must parse");
+
+        assert_eq!(Family::of(&v4), Family::V4);
+        assert_eq!(Family::of(&v6), Family::V6);
+    }
+
+    #[test]
+    fn a_family_is_named_the_way_people_write_it() {
+        for spelling in ["ipv4", "IPv4", "v4", "4"] {
+            assert_eq!(Family::from_str(spelling), Ok(Family::V4), "{spelling}");
+        }
+
+        for spelling in ["ipv6", "IPV6", "v6", "6"] {
+            assert_eq!(Family::from_str(spelling), Ok(Family::V6), "{spelling}");
+        }
+
+        assert!(Family::from_str("ipv5").is_err());
+    }
+
+    #[test]
+    fn the_unspecified_address_of_a_family_is_in_that_family() {
+        for family in Family::ALL {
+            assert_eq!(Family::of(&family.unspecified()), family);
+        }
+    }
+}
@@ -0,0 +1,544 @@
+use {
+    crate::pubip::{
+        Family, Uplink, client,
+        error::{FetchError, hex},
+        socket::{self, INITIAL_RTO, LARGEST_DATAGRAM},
+        tls::Handshake,
+        uplink,
+    },
+    reqwest::{Url, header::CONTENT_TYPE},
+    std::{
+        net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
+        str::FromStr,
+        time::Duration,
+    },
+    tokio::time::{Instant, timeout, timeout_at},
+    tracing::{Span, debug},
+};
+
+// RFC 6886, section 3.2: version 0, opcode 0 asks for the external address,
+// and the answer's opcode is the request's plus 128.
+const NAT_PMP_PORT: u16 = 5351;
+const NAT_PMP_REQUEST: [u8; 2] = [0, 0];
+const NAT_PMP_ANSWER: u8 = 128;
+const NAT_PMP_ANSWER_LEN: usize = 12;
+
+// A router speaking NAT-PMP answers within a few round trips on the LAN.
+// One that does not is given up on early, while UPnP still has time left.
+const NAT_PMP_BUDGET: Duration = Duration::from_secs(2);
+
+// UPnP Device Architecture 1.1, section 1.3.2.
+const SSDP: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(239, 255, 255, 250), 1900));
+const SEARCH_TARGET: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+
+// The two services of an IGD that know the WAN address, whichever of them
+// the router's uplink is.
+const WAN_SERVICES: [&str; 2] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:",
+    "urn:schemas-upnp-org:service:WANPPPConnection:",
+];
+
+/// Where the router is asked: the default gateway of the uplink unless a
+/// router is given, at the ports the protocols fix unless others are.
+#[derive(PartialEq, Eq, Hash, Debug)]
+pub struct Gateway {
+    pub router: Option<Ipv4Addr>,
+    pub nat_pmp_port: u16,
+    pub ssdp: SocketAddr,
+}
+
+impl Gateway {
+    pub const DEFAULT: Self = Self {
+        router: None,
+        nat_pmp_port: NAT_PMP_PORT,
+        ssdp: SSDP,
+    };
+}
+
+/// Asks the router what its WAN address is: over NAT-PMP first, then over
+/// `UPnP` IGD when NAT-PMP gets nowhere. A PCP router that keeps NAT-PMP beside
+/// it, as RFC 6887 section 9 lets it, answers the first; one that does not
+/// says `Unsupported Version` and is asked the second way.
+///
+/// IPv4 alone: neither protocol knows any other WAN address, and an IPv6
+/// node is seldom behind a NAT for it to know about. Whatever the router
+/// says is held to [`address::is_public`](crate::pubip::address::is_public)
+/// like every other answer, so a router that is itself behind a carrier's
+/// NAT fails the round as `not_public` rather than confirming its CGNAT
+/// address.
+pub async fn query(gateway: &Gateway, uplink: &Uplink) -> Result<IpAddr, FetchError> {
+    let router = gateway
+        .router
+        .or_else(|| uplink::default_gateway(uplink))
+        .ok_or_else(|| {
+            FetchError::Unreachable(
+                format!("no IPv4 default route by {uplink} has a router").into(),
+            )
+        })?;
+
+    let ask = async {
+        let nat_pmp = SocketAddr::from((router, gateway.nat_pmp_port));
+
+        match timeout(NAT_PMP_BUDGET, nat_pmp_query(nat_pmp, uplink)).await {
+            Ok(Ok(ip_addr)) => {
+                Span::current().record("network.protocol.name", "nat-pmp");
+                return Ok(ip_addr);
+            }
+            Ok(Err(err)) => {
+                debug!(%router, %err, "router did not answer over NAT-PMP, asking over UPnP");
+            }
+            Err(_) => debug!(%router, "router is silent over NAT-PMP, asking over UPnP"),
+        }
+
+        Span::current().record("network.protocol.name", "upnp");
+        upnp_query(router, gateway.ssdp, uplink).await
+    };
+
+    timeout(client::REQUEST_TIMEOUT, ask)
+        .await
+        .map_err(|_| FetchError::Timeout)?
+}
+
+async fn nat_pmp_query(router: SocketAddr, uplink: &Uplink) -> Result<IpAddr, FetchError> {
+    let answer = socket::exchange_udp(router, uplink, &NAT_PMP_REQUEST, |message| {
+        message.len() >= 2 && message[0] == 0 && message[1] == NAT_PMP_ANSWER
+    })
+    .await?;
+
+    nat_pmp_decode(&answer)
+}
+
+/// `version opcode result-code(2) epoch(4) address(4)`, RFC 6886 section 3.2.
+fn nat_pmp_decode(answer: &[u8]) -> Result<IpAddr, FetchError> {
+    let too_short = || FetchError::Decode {
+        body: hex(answer),
+        source: "a NAT-PMP answer shorter than 12 bytes".into(),
+    };
+
+    let Some(&[hi, lo]) = answer.get(2..4) else {
+        return Err(too_short());
+    };
+
+    let code = u16::from_be_bytes([hi, lo]);
+    if code != 0 {
+        return Err(FetchError::GatewayStatus {
+            code,
+            reason: nat_pmp_result_name(code).into(),
+        });
+    }
+
+    let Some(&[a, b, c, d]) = answer.get(8..NAT_PMP_ANSWER_LEN) else {
+        return Err(too_short());
+    };
+
+    Ok(IpAddr::from([a, b, c, d]))
+}
+
+/// RFC 6886, section 3.5. A PCP router that does not speak NAT-PMP says 1.
+const fn nat_pmp_result_name(code: u16) -> &'static str {
+    match code {
+        1 => "Unsupported Version",
+        2 => "Not Authorized/Refused",
+        3 => "Network Failure",
+        4 => "Out of resources",
+        5 => "Unsupported opcode",
+        _ => "Unknown",
+    }
+}
+
+/// Finds the router's IGD over SSDP, reads its description and asks the WAN
+/// service it names for `GetExternalIPAddress`.
+///
+/// Only an answer from the router itself is listened to, and only a
+/// description on the router itself is read: anything else on the LAN can
+/// answer a multicast search, and nothing else on it is the router.
+async fn upnp_query(
+    router: Ipv4Addr,
+    ssdp: SocketAddr,
+    uplink: &Uplink,
+) -> Result<IpAddr, FetchError> {
+    let location = search(router, ssdp, uplink).await?;
+
+    // The router is on the LAN, where a proxy has no business.
+    let http = client::get(
+        uplink,
+        Family::V4,
+        client::Route::Direct,
+        Handshake::default(),
+    );
+    let description = read_text(http.get(location.clone()).send().await?).await?;
+
+    let (service, control) = wan_service(&description).ok_or_else(|| FetchError::Decode {
+        body: description.clone(),
+        source: "the router describes no WAN connection service".into(),
+    })?;
+    let control = location
+        .join(&control)
+        .map_err(|err| FetchError::Unreachable(err.into()))?;
+
+    if control.host_str() != Some(&router.to_string()) {
+        return Err(FetchError::Unreachable(
+            format!("the router's WAN service is not on the router, but at {control}").into(),
+        ));
+    }
+
+    let response = http
+        .post(control)
+        .header(CONTENT_TYPE, r#"text/xml; charset="utf-8""#)
+        .header("SOAPAction", format!(r#""{service}#GetExternalIPAddress""#))
+        .body(envelope(&service))
+        .send()
+        .await?;
+
+    let status = response.status();
+    let body = response.text().await?;
+
+    if let Some(code) = element(&body, "errorCode").and_then(|code| u16::from_str(code).ok()) {
+        return Err(FetchError::GatewayStatus {
+            code,
+            reason: element(&body, "errorDescription")
+                .unwrap_or("UPnPError")
+                .to_owned(),
+        });
+    }
+
+    if !status.is_success() {
+        return Err(FetchError::HttpStatus {
+            status,
+            retry_after: None,
+        });
+    }
+
+    element(&body, "NewExternalIPAddress")
+        .ok_or("no NewExternalIPAddress in the answer")
+        .and_then(|ip_addr| {
+            IpAddr::from_str(ip_addr).map_err(|_| "NewExternalIPAddress is not an address")
+        })
+        .map_err(|source| FetchError::Decode {
+            body,
+            source: source.into(),
+        })
+}
+
+/// Sends M-SEARCH until the router answers it with a `LOCATION` on itself.
+async fn search(router: Ipv4Addr, ssdp: SocketAddr, uplink: &Uplink) -> Result<Url, FetchError> {
+    let socket = socket::bind_udp(uplink, Family::V4).await?;
+    let request = format!(
+        concat!(
+            "M-SEARCH * HTTP/1.1\r\n",
+            "HOST: {}\r\n",
+            "MAN: \"ssdp:discover\"\r\n",
+            "MX: 1\r\n",
+            "ST: {}\r\n",
+            "\r\n",
+        ),
+        ssdp, SEARCH_TARGET,
+    );
+
+    let mut rto = INITIAL_RTO;
+    let mut buf = [0; LARGEST_DATAGRAM];
+
+    loop {
+        socket.send_to(request.as_bytes(), ssdp).await?;
+        let deadline = Instant::now() + rto;
+
+        while let Ok(received) = timeout_at(deadline, socket.recv_from(&mut buf)).await {
+            let (len, from) = received?;
+            if from.ip() != IpAddr::V4(router) {
+                continue;
+            }
+
+            let location = location(&String::from_utf8_lossy(&buf[..len]))
+                .and_then(|location| Url::parse(location).ok())
+                .filter(|url| {
+                    url.scheme() == "http" && url.host_str() == Some(&router.to_string())
+                });
+
+            if let Some(location) = location {
+                return Ok(location);
+            }
+        }
+
+        rto = rto.saturating_mul(2);
+    }
+}
+
+async fn read_text(response: reqwest::Response) -> Result<String, FetchError> {
+    let status = response.status();
+    if !status.is_success() {
+        return Err(FetchError::HttpStatus {
+            status,
+            retry_after: None,
+        });
+    }
+
+    Ok(response.text().await?)
+}
+
+/// The `LOCATION` header of an SSDP answer, whatever its case.
+fn location(answer: &str) -> Option<&str> {
+    answer.lines().skip(1).find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("location")
+            .then(|| value.trim())
+    })
+}
+
+/// The service type and control URL of the first WAN connection service the
+/// description lists.
+fn wan_service(description: &str) -> Option<(String, String)> {
+    description.split("<service>").skip(1).find_map(|service| {
+        let service = service.split("</service>").next()?;
+        let kind = element(service, "serviceType")?;
+        let control = element(service, "controlURL")?;
+
+        WAN_SERVICES
+            .iter()
+            .any(|prefix| kind.starts_with(prefix))
+            .then(|| (kind.to_owned(), control.to_owned()))
+    })
+}
+
+/// The text of the first element by that name, a namespace prefix or
+/// attributes on it aside. Enough XML for the few leaves an IGD is read for.
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = xml;
+
+    while let Some(open) = rest.find('<') {
+        rest = &rest[open + 1..];
+        let end = rest.find('>')?;
+        let tag = rest[..end].split_whitespace().next().unwrap_or_default();
+        let local = tag.rsplit(':').next().unwrap_or(tag);
+
+        if local == name && !tag.starts_with('/') {
+            let text = &rest[end + 1..];
+            return Some(text[..text.find("</")?].trim());
+        }
+    }
+
+    None
+}
+
+fn envelope(service: &str) -> String {
+    format!(
+        concat!(
+            r#"<?xml version="1.0"?>"#,
+            r#"<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" "#,
+            r#"s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">"#,
+            r#"<s:Body><u:GetExternalIPAddress xmlns:u="{}"/></s:Body>"#,
+            r#"</s:Envelope>"#,
+        ),
+        service,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::pubip::{Access, LocalProvider, Provider, Published, provider::Request},
+        tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::{TcpListener, UdpSocket},
+        },
+    };
+
+    const DESCRIPTION: &str = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <device>
+    <deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>
+    <serviceList>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>
+        <controlURL>/ctl/L3F</controlURL>
+      </service>
+    </serviceList>
+    <deviceList><device><deviceList><device><serviceList>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>
+        <controlURL>/ctl/IPConn</controlURL>
+      </service>
+    </serviceList></device></deviceList></device></deviceList>
+  </device>
+</root>"#;
+
+    fn soap(ip: &str) -> String {
+        format!(
+            concat!(
+                r#"<?xml version="1.0"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/">"#,
+                r#"<s:Body><u:GetExternalIPAddressResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1">"#,
+                "<NewExternalIPAddress>{}</NewExternalIPAddress>",
+                "</u:GetExternalIPAddressResponse></s:Body></s:Envelope>",
+            ),
+            ip,
+        )
+    }
+
+    /// A NAT-PMP router on loopback answering with the address, and its port.
+    async fn nat_pmp_stand_in(answer: [u8; 4]) -> u16 {
+        let socket = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .expect("loopback must be bindable");
+        let port = socket.local_addr().expect("it has an address").port();
+
+        tokio::spawn(async move {
+            let mut buf = [0; 64];
+            while let Ok((_, from)) = socket.recv_from(&mut buf).await {
+                let mut message = vec![0, NAT_PMP_ANSWER, 0, 0, 0, 0, 0, 7];
+                message.extend_from_slice(&answer);
+                let _ = socket.send_to(&message, from).await;
+            }
+        });
+
+        port
+    }
+
+    /// An IGD on loopback: SSDP on one port, its description and control URL
+    /// over HTTP on another. The SSDP port is returned.
+    async fn upnp_stand_in(ip: &'static str) -> SocketAddr {
+        let http = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("loopback must be bindable");
+        let http_addr = http.local_addr().expect("it has an address");
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = http.accept().await {
+                let mut request = [0; 4096];
+                let read = stream.read(&mut request).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&request[..read]);
+
+                let body = if request.starts_with("GET /rootDesc.xml") {
+                    DESCRIPTION.to_owned()
+                } else if request.starts_with("POST /ctl/IPConn")
+                    && request.contains("#GetExternalIPAddress")
+                {
+                    soap(ip)
+                } else {
+                    String::new()
+                };
+
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len(),
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let ssdp = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .expect("loopback must be bindable");
+        let ssdp_addr = ssdp.local_addr().expect("it has an address");
+
+        tokio::spawn(async move {
+            let mut buf = [0; 1024];
+            while let Ok((read, from)) = ssdp.recv_from(&mut buf).await {
+                if !String::from_utf8_lossy(&buf[..read]).starts_with("M-SEARCH") {
+                    continue;
+                }
+
+                let answer = format!(
+                    "HTTP/1.1 200 OK\r\nST: {SEARCH_TARGET}\r\nLocation: http://{http_addr}/rootDesc.xml\r\n\r\n",
+                );
+                let _ = ssdp.send_to(answer.as_bytes(), from).await;
+            }
+        });
+
+        ssdp_addr
+    }
+
+    // Nothing listens on it, so NAT-PMP is refused at once and UPnP is asked.
+    async fn closed_port() -> u16 {
+        let socket = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .expect("loopback must be bindable");
+        socket.local_addr().expect("it has an address").port()
+    }
+
+    #[tokio::test]
+    async fn a_nat_pmp_router_is_asked_first() {
+        let gateway = Gateway {
+            router: Some(Ipv4Addr::LOCALHOST),
+            nat_pmp_port: nat_pmp_stand_in([203, 0, 113, 7]).await,
+            ssdp: SocketAddr::from((Ipv4Addr::LOCALHOST, 9)),
+        };
+
+        assert_eq!(
+            query(&gateway, &Uplink::Default)
+                .await
+                .expect("the stand-in must answer"),
+            IpAddr::from([203, 0, 113, 7]),
+        );
+    }
+
+    #[tokio::test]
+    async fn a_router_without_nat_pmp_is_asked_over_upnp() {
+        let gateway = Gateway {
+            router: Some(Ipv4Addr::LOCALHOST),
+            nat_pmp_port: closed_port().await,
+            ssdp: upnp_stand_in("198.51.100.20").await,
+        };
+
+        assert_eq!(
+            query(&gateway, &Uplink::Default)
+                .await
+                .expect("the stand-in must answer"),
+            IpAddr::from([198, 51, 100, 20]),
+        );
+    }
+
+    // What the router's own uplink is given by a carrier sharing one address
+    // among its customers. The router is right about it, and it is still not
+    // the node's address.
+    #[tokio::test]
+    async fn a_router_behind_carrier_grade_nat_is_not_believed() {
+        let gateway: &'static Gateway = Box::leak(Box::new(Gateway {
+            router: Some(Ipv4Addr::LOCALHOST),
+            nat_pmp_port: nat_pmp_stand_in([100, 64, 12, 34]).await,
+            ssdp: SocketAddr::from((Ipv4Addr::LOCALHOST, 9)),
+        }));
+
+        let err = crate::pubip::fetch(
+            Provider::Local(LocalProvider::Gateway),
+            Request::Gateway(gateway),
+            LocalProvider::Gateway.request_uri(),
+            Access::default(),
+            Family::V4,
+            &Uplink::Default,
+            &Published::default(),
+        )
+        .await
+        .expect_err("a CGNAT address must not be an answer");
+
+        assert_eq!(err.as_error_type(), "not_public");
+    }
+
+    #[test]
+    fn a_nat_pmp_refusal_is_a_status_and_not_an_address() {
+        let err = nat_pmp_decode(&[0, NAT_PMP_ANSWER, 0, 2, 0, 0, 0, 7])
+            .expect_err("a refusal must not decode");
+
+        assert_eq!(err.as_error_type(), "gateway_status");
+        assert!(err.to_string().contains("Not Authorized"));
+    }
+
+    #[test]
+    fn an_igd_description_names_its_wan_service_however_deep() {
+        assert_eq!(
+            wan_service(DESCRIPTION),
+            Some((
+                "urn:schemas-upnp-org:service:WANIPConnection:1".to_owned(),
+                "/ctl/IPConn".to_owned()
+            )),
+        );
+        assert_eq!(
+            element(&soap("1.2.3.4"), "NewExternalIPAddress"),
+            Some("1.2.3.4")
+        );
+        assert_eq!(
+            location("HTTP/1.1 200 OK\r\nlocation: http://192.168.1.1:5000/d.xml\r\n\r\n"),
+            Some("http://192.168.1.1:5000/d.xml"),
+        );
+    }
+}
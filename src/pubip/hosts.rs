@@ -0,0 +1,198 @@
+use {
+    crate::pubip::{
+        Family, Uplink, client, dns,
+        error::{Cause, FetchError},
+        tls::Handshake,
+    },
+    reqwest::{
+        Url,
+        dns::{Addrs, Name, Resolve, Resolving},
+        header::{ACCEPT, CONTENT_TYPE},
+    },
+    std::{
+        collections::HashMap,
+        net::{IpAddr, SocketAddr},
+        sync::OnceLock,
+    },
+    tokio::net::lookup_host,
+};
+
+// RFC 8484, section 6.
+const DNS_MESSAGE: &str = "application/dns-message";
+
+// Set once at startup, before the first client is built, and never again.
+static OVERRIDES: OnceLock<Overrides> = OnceLock::new();
+static DOH: OnceLock<Url> = OnceLock::new();
+
+type Overrides = HashMap<String, Vec<IpAddr>>;
+
+/// Answers these hosts with these addresses, whatever any resolver says.
+/// Only the first call counts.
+pub fn set_overrides(overrides: Overrides) {
+    let _ = OVERRIDES.set(canonical_overrides(overrides));
+}
+
+fn canonical_overrides(overrides: Overrides) -> Overrides {
+    overrides
+        .into_iter()
+        .map(|(host, addrs)| (canonical(&host), addrs))
+        .collect()
+}
+
+/// Asks the resolver at this URL, over HTTPS, for every host not overridden
+/// rather than the system's. Only the first call counts.
+pub fn set_doh(url: Url) {
+    let _ = DOH.set(url);
+}
+
+/// Whether hosts are looked up any other way than the system's.
+pub fn configured() -> bool {
+    OVERRIDES
+        .get()
+        .is_some_and(|overrides| !overrides.is_empty())
+        || DOH.get().is_some()
+}
+
+/// The addresses of the host in the family: those `--resolve` gives it, or
+/// those the resolver over HTTPS answers with, or the system's.
+///
+/// The resolver's own host is the one name it is never asked, the system or
+/// an override of it having to tell where it is.
+pub async fn lookup(
+    host: &str,
+    family: Family,
+    uplink: &Uplink,
+) -> Result<Vec<IpAddr>, FetchError> {
+    lookup_with(OVERRIDES.get(), host, family, uplink).await
+}
+
+/// [`lookup`], with the overrides given rather than those set at startup.
+async fn lookup_with(
+    overrides: Option<&Overrides>,
+    host: &str,
+    family: Family,
+    uplink: &Uplink,
+) -> Result<Vec<IpAddr>, FetchError> {
+    let host = canonical(host);
+    let in_family = |addrs: Vec<IpAddr>| -> Vec<IpAddr> {
+        addrs
+            .into_iter()
+            .filter(|ip_addr| Family::of(ip_addr) == family)
+            .collect()
+    };
+
+    let addrs = if let Ok(ip_addr) = host.parse::<IpAddr>() {
+        in_family(vec![ip_addr])
+    } else if let Some(addrs) = overrides.and_then(|overrides| overrides.get(&host)) {
+        in_family(addrs.clone())
+    } else if let Some(url) = DOH.get().filter(|url| url.host_str() != Some(&host)) {
+        in_family(over_https(url, &host, family, uplink).await?)
+    } else {
+        let found = lookup_host((host.as_str(), 0)).await?;
+        in_family(found.map(|addr| addr.ip()).collect())
+    };
+
+    if addrs.is_empty() {
+        return Err(FetchError::Unreachable(
+            format!("{host} has no address over {family}").into(),
+        ));
+    }
+
+    Ok(addrs)
+}
+
+/// One recursive query, posted the way RFC 8484 has it, out by the uplink
+/// over the family the addresses are wanted in.
+async fn over_https(
+    url: &Url,
+    host: &str,
+    family: Family,
+    uplink: &Uplink,
+) -> Result<Vec<IpAddr>, FetchError> {
+    let unresolved = |err: &dyn std::fmt::Display| {
+        FetchError::Unreachable(format!("{host} cannot be resolved over DoH: {err}").into())
+    };
+
+    let query = dns::encode_recursive(host, family).map_err(|err| unresolved(&err))?;
+
+    let response = client::get(uplink, family, client::Route::Proxied, Handshake::default())
+        .post(url.clone())
+        .header(CONTENT_TYPE, DNS_MESSAGE)
+        .header(ACCEPT, DNS_MESSAGE)
+        .body(query)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|err| unresolved(&err))?;
+
+    let message = response.bytes().await.map_err(|err| unresolved(&err))?;
+    dns::decode_recursive(&message, host, family).map_err(|err| unresolved(&err))
+}
+
+/// Lower case and without the root's dot, the way names are compared.
+fn canonical(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// What reqwest resolves provider hosts with once `--resolve` or `--doh` is
+/// given: [`lookup`], over the family and uplink of the client it is in.
+pub struct Hosts {
+    pub family: Family,
+    pub uplink: Uplink,
+}
+
+impl Resolve for Hosts {
+    fn resolve(&self, name: Name) -> Resolving {
+        let (family, uplink) = (self.family, self.uplink.clone());
+
+        Box::pin(async move {
+            let addrs = lookup(name.as_str(), family, &uplink)
+                .await
+                .map_err(Cause::from)?;
+
+            // Port zero stands for the one the URL's scheme implies.
+            let addrs: Addrs =
+                Box::new(addrs.into_iter().map(|ip_addr| SocketAddr::new(ip_addr, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::str::FromStr};
+
+    fn ip(s: &str) -> IpAddr {
+        IpAddr::from_str(s).expect("test address must parse")
+    }
+
+    // The overrides are handed in rather than set: the ones set at startup
+    // are there for good, and every other test in the binary would see them.
+    #[tokio::test]
+    async fn an_overridden_host_is_answered_in_the_family_alone() {
+        let overrides = canonical_overrides(HashMap::from([
+            (
+                "API.ipify.test.".to_owned(),
+                vec![ip("192.0.2.7"), ip("2001:db8::7")],
+            ),
+            ("ipinfo.test".to_owned(), vec![ip("192.0.2.8")]),
+        ]));
+        let lookup = |host, family| lookup_with(Some(&overrides), host, family, &Uplink::Default);
+
+        let v4 = lookup("api.ipify.test", Family::V4)
+            .await
+            .expect("the override holds an IPv4 address");
+        assert_eq!(v4, [ip("192.0.2.7")]);
+
+        let v6 = lookup("Api.Ipify.Test", Family::V6)
+            .await
+            .expect("the override holds an IPv6 address");
+        assert_eq!(v6, [ip("2001:db8::7")]);
+
+        let err = lookup("ipinfo.test", Family::V6)
+            .await
+            .expect_err("the override holds no IPv6 address");
+        assert_eq!(err.as_error_type(), "unreachable");
+        assert!(!configured());
+    }
+}
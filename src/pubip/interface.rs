@@ -0,0 +1,137 @@
+use {
+    crate::pubip::{Family, Uplink, address, error::FetchError, socket},
+    if_addrs::get_if_addrs,
+    std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+// a.root-servers.net, for being as unlikely to move as an address gets.
+// Nothing is ever sent to it: connecting a UDP socket only asks the routing
+// table which of the node's addresses a packet to it would leave from.
+const PROBE_V4: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 41, 0, 4));
+const PROBE_V6: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2001, 0x503, 0xba3e, 0, 0, 0, 2, 0x30));
+
+/// The public address bound to the node itself over the family, on the
+/// uplink's interface or equal to its source address when it names either.
+///
+/// Several of them, as a node with IPv6 temporary addresses has, come down to
+/// the one the routing table would send a request to the Internet from,
+/// which is the one every other provider sees. None of them being public is
+/// a node behind a NAT, which this provider cannot see past and says so as
+/// `not_public`.
+pub async fn query(family: Family, uplink: &Uplink) -> Result<IpAddr, FetchError> {
+    let own: Vec<IpAddr> = get_if_addrs()?
+        .into_iter()
+        .filter(|iface| !iface.is_loopback() && iface.is_oper_up())
+        .filter(|iface| match uplink {
+            Uplink::Default => true,
+            Uplink::Interface(name) => iface.name == *name,
+            Uplink::Source(addr) => iface.ip() == *addr,
+        })
+        .map(|iface| iface.ip())
+        .filter(|ip_addr| Family::of(ip_addr) == family)
+        .collect();
+
+    pick(&own, source_address(family, uplink).await, family, uplink)
+}
+
+/// The address the routing table would send from out by the uplink, if it
+/// has a route at all.
+async fn source_address(family: Family, uplink: &Uplink) -> Option<IpAddr> {
+    let probe = match family {
+        Family::V4 => PROBE_V4,
+        Family::V6 => PROBE_V6,
+    };
+
+    let socket = socket::bind_udp(uplink, family).await.ok()?;
+    socket.connect(SocketAddr::new(probe, 53)).await.ok()?;
+    socket.local_addr().ok().map(|local| local.ip())
+}
+
+fn pick(
+    own: &[IpAddr],
+    source: Option<IpAddr>,
+    family: Family,
+    uplink: &Uplink,
+) -> Result<IpAddr, FetchError> {
+    let public: Vec<IpAddr> = own.iter().copied().filter(address::is_public).collect();
+
+    if let Some(source) = source.filter(|source| public.contains(source)) {
+        return Ok(source);
+    }
+
+    if let Some(first) = public.first() {
+        return Ok(*first);
+    }
+
+    match source
+        .filter(|source| own.contains(source))
+        .or_else(|| own.first().copied())
+    {
+        Some(private) => Err(FetchError::NotPublic(private)),
+        None => Err(FetchError::Unreachable(
+            format!("no address over {family} is bound by {uplink}").into(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::str::FromStr};
+
+    fn ips(addrs: &[&str]) -> Vec<IpAddr> {
+        addrs.iter().copied().map(ip).collect()
+    }
+
+    fn ip(addr: &str) -> IpAddr {
+        IpAddr::from_str(addr).expect("test address must parse")
+    }
+
+    #[test]
+    fn the_address_a_request_would_leave_from_is_the_one_reported() {
+        let own = ips(&["2a01:4f8::1", "fe80::1", "2a01:4f8::beef"]);
+
+        assert_eq!(
+            pick(
+                &own,
+                Some(ip("2a01:4f8::beef")),
+                Family::V6,
+                &Uplink::Default
+            )
+            .expect("a public address is bound"),
+            ip("2a01:4f8::beef"),
+        );
+        assert_eq!(
+            pick(&own, None, Family::V6, &Uplink::Default).expect("a public address is bound"),
+            ip("2a01:4f8::1"),
+        );
+    }
+
+    #[test]
+    fn a_public_address_beside_a_private_source_is_still_found() {
+        let own = ips(&["10.0.0.5", "192.0.2.99", "185.12.64.1"]);
+
+        assert_eq!(
+            pick(&own, Some(ip("10.0.0.5")), Family::V4, &Uplink::Default)
+                .expect("a public address is bound"),
+            ip("185.12.64.1"),
+        );
+    }
+
+    #[test]
+    fn a_node_behind_a_nat_has_nothing_public_to_report() {
+        let err = pick(
+            &ips(&["10.0.0.5", "100.64.1.1"]),
+            Some(ip("100.64.1.1")),
+            Family::V4,
+            &Uplink::Default,
+        )
+        .expect_err("no public address is bound");
+
+        assert_eq!(err.as_error_type(), "not_public");
+        assert!(err.to_string().contains("100.64.1.1"));
+
+        let err = pick(&[], None, Family::V6, &Uplink::Interface("eth1".into()))
+            .expect_err("nothing is bound at all");
+        assert_eq!(err.as_error_type(), "unreachable");
+    }
+}
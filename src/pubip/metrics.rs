@@ -1,6 +1,6 @@
 use {
     crate::{
-        pubip::{HttpProvider, error::FetchError},
+        pubip::{Family, Provider, Uplink, breaker, error::FetchError},
         telemetry::meter,
     },
     opentelemetry::{
@@ -72,6 +72,58 @@ static RATE_LIMITED: LazyLock<Counter<u64>> = LazyLock::new(|| {
         .build()
 });
 
+// Counted apart from the histogram, which records one duration per provider
+// and round however many attempts it took: a retry that saved the round is
+// otherwise invisible, and one that did not is only a longer request.
+static RETRIES: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    meter()
+        .u64_counter("fckloud.provider.retries")
+        .with_unit("{retry}")
+        .with_description("Providers asked again within a round, by the failure that prompted it")
+        .build()
+});
+
+// A gauge and not a counter: what the operator wants to know is how long a
+// provider is being left alone right now, stretched or not.
+static GAP: LazyLock<Gauge<f64>> = LazyLock::new(|| {
+    meter()
+        .f64_gauge("fckloud.provider.gap")
+        .with_unit("s")
+        .with_description("The gap a provider is held to, stretched while it pushes back")
+        .build()
+});
+
+// Neither a failure nor rate limited: the provider was asked, and the round
+// settled before it answered. Its request was cancelled, so like a rate
+// limited one it has no duration worth recording.
+static SKIPPED: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    meter()
+        .u64_counter("fckloud.provider.skipped")
+        .with_unit("{round}")
+        .with_description("Rounds that settled their verdict before a provider answered")
+        .build()
+});
+
+// Counted apart from the consensus gauge, which would otherwise show a round
+// that confirmed nothing, with no telling why.
+static PROXIED: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    meter()
+        .u64_counter("fckloud.consensus.proxied")
+        .with_unit("{address}")
+        .with_description("Addresses consensus would have confirmed but for being the proxy's")
+        .build()
+});
+
+// One series per state, the one a breaker is in at one and the rest at zero,
+// the way the consensus gauge above splits its addresses.
+static BREAKER: LazyLock<Gauge<u64>> = LazyLock::new(|| {
+    meter()
+        .u64_gauge("fckloud.provider.breaker")
+        .with_unit("{provider}")
+        .with_description("Whether a provider's breaker is in each state, one where it is")
+        .build()
+});
+
 static FAILURES: LazyLock<Mutex<Failures>> = LazyLock::new(|| Mutex::new(Failures::new()));
 
 struct Failures {
@@ -80,13 +132,13 @@ struct Failures {
 
 struct Window {
     started: Instant,
-    counts: HashMap<HttpProvider, u64>,
+    counts: HashMap<Provider, u64>,
 }
 
 /// Puts the enabled providers on the board at zero and registers the gauge
 /// before the first export, so that a provider which never fails reads as
 /// healthy rather than going missing.
-pub fn register(providers: &[HttpProvider]) {
+pub fn register(providers: &[Provider]) {
     failures().seed(providers);
     LazyLock::force(&FAILURE_COUNT);
 }
@@ -108,7 +160,7 @@ impl Failures {
         }
     }
 
-    fn seed(&mut self, providers: &[HttpProvider]) {
+    fn seed(&mut self, providers: &[Provider]) {
         for window in &mut self.windows {
             for provider in providers {
                 window.counts.entry(*provider).or_insert(0);
@@ -116,7 +168,7 @@ impl Failures {
         }
     }
 
-    fn count(&mut self, provider: HttpProvider) {
+    fn count(&mut self, provider: Provider) {
         let now = Instant::now();
 
         for (window, (_, span)) in self.windows.iter_mut().zip(WINDOWS) {
@@ -159,9 +211,26 @@ impl Window {
 ///
 /// The address it reported is not among the attributes, and must not become
 /// one: a label is a time series kept forever, and there is no bound on what
-/// a misbehaving provider can put in it.
-pub fn record_request(provider: HttpProvider, elapsed: Duration, failure: Option<&FetchError>) {
-    let mut attributes = vec![KeyValue::new("fckloud.provider", provider.host())];
+/// a misbehaving provider can put in it. The mirror asked last is, when the
+/// provider failed over to one: mirrors come from a table as fixed as the
+/// providers themselves.
+pub fn record_request(
+    provider: Provider,
+    mirror: Option<&str>,
+    family: Family,
+    uplink: &Uplink,
+    elapsed: Duration,
+    failure: Option<&FetchError>,
+) {
+    let mut attributes = vec![
+        KeyValue::new("fckloud.provider", provider.host()),
+        KeyValue::new("network.type", family.as_network_type()),
+        KeyValue::new("fckloud.uplink", uplink.to_string()),
+    ];
+
+    if let Some(mirror) = mirror {
+        attributes.push(KeyValue::new("fckloud.provider.mirror", mirror.to_owned()));
+    }
 
     if let Some(err) = failure {
         failures().count(provider);
@@ -180,15 +249,90 @@ pub fn record_request(provider: HttpProvider, elapsed: Duration, failure: Option
 
 /// Records that a round went without a provider because its gap had not
 /// elapsed.
-pub fn record_rate_limited(provider: HttpProvider) {
+pub fn record_rate_limited(provider: Provider) {
     RATE_LIMITED.add(1, &[KeyValue::new("fckloud.provider", provider.host())]);
 }
 
-/// Records how the round divided: how many addresses cleared the threshold and
-/// how many fell short. Both zero means nobody answered.
-pub fn record_consensus(confirmed: usize, unconfirmed: usize) {
+/// Records that a round stopped waiting for a provider, its verdict settled
+/// without it.
+pub fn record_skipped(provider: Provider, family: Family, uplink: &Uplink) {
+    SKIPPED.add(
+        1,
+        &[
+            KeyValue::new("fckloud.provider", provider.host()),
+            KeyValue::new("network.type", family.as_network_type()),
+            KeyValue::new("fckloud.uplink", uplink.to_string()),
+        ],
+    );
+}
+
+/// Records the state a provider's breaker just moved to on one uplink.
+pub fn record_breaker(provider: Provider, uplink: &Uplink, state: breaker::State) {
+    for each in breaker::State::ALL {
+        BREAKER.record(
+            u64::from(each == state),
+            &[
+                KeyValue::new("fckloud.provider", provider.host()),
+                KeyValue::new("fckloud.uplink", uplink.to_string()),
+                KeyValue::new("fckloud.breaker.state", each.as_str()),
+            ],
+        );
+    }
+}
+
+/// Records the gap a provider is held to since it pushed back, or since it
+/// answered and the stretch eased off.
+pub fn record_gap(provider: Provider, gap: Duration) {
+    GAP.record(
+        gap.as_secs_f64(),
+        &[KeyValue::new("fckloud.provider", provider.host())],
+    );
+}
+
+/// Records that a provider was asked again within the round after failing
+/// so.
+pub fn record_retry(provider: Provider, family: Family, uplink: &Uplink, failure: &FetchError) {
+    RETRIES.add(
+        1,
+        &[
+            KeyValue::new("fckloud.provider", provider.host()),
+            KeyValue::new("network.type", family.as_network_type()),
+            KeyValue::new("fckloud.uplink", uplink.to_string()),
+            KeyValue::new("error.type", failure.as_error_type()),
+        ],
+    );
+}
+
+/// Records that a round refused to confirm addresses the proxy reaches the
+/// Internet from.
+pub fn record_proxied(uplink: &Uplink, family: Family, refused: usize) {
+    PROXIED.add(
+        refused as u64,
+        &[
+            KeyValue::new("network.type", family.as_network_type()),
+            KeyValue::new("fckloud.uplink", uplink.to_string()),
+        ],
+    );
+}
+
+/// Records how the round divided within one family on one uplink: how many
+/// addresses cleared the threshold and how many fell short. Both zero means
+/// nobody answered.
+///
+/// The uplink is a label because the operator named it, so there are only as
+/// many of them as were configured.
+pub fn record_consensus(uplink: &Uplink, family: Family, confirmed: usize, unconfirmed: usize) {
     const STATE: &str = "fckloud.consensus.state";
 
-    CONSENSUS_ADDRESSES.record(confirmed as u64, &[KeyValue::new(STATE, "confirmed")]);
-    CONSENSUS_ADDRESSES.record(unconfirmed as u64, &[KeyValue::new(STATE, "unconfirmed")]);
+    let path = [
+        KeyValue::new("network.type", family.as_network_type()),
+        KeyValue::new("fckloud.uplink", uplink.to_string()),
+    ];
+
+    for (state, count) in [("confirmed", confirmed), ("unconfirmed", unconfirmed)] {
+        let mut attributes = vec![KeyValue::new(STATE, state)];
+        attributes.extend_from_slice(&path);
+
+        CONSENSUS_ADDRESSES.record(count as u64, &attributes);
+    }
 }
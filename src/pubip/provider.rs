@@ -1,22 +1,343 @@
 use {
-    crate::pubip::error::FetchError,
+    crate::pubip::{
+        Family, TrustFactorAuthority,
+        catalog::{Declared, Protocol},
+        credential::Placement,
+        dns::{Class, Lookup, Record},
+        echo::Echo,
+        error::{Cause, FetchError},
+        gateway::Gateway,
+        published::Key,
+        stun::Server,
+        tls::{self, Pin},
+    },
     reqwest::Method,
     serde::Deserialize,
     serde_json::from_slice as unjson,
-    std::{fmt, net::IpAddr, time::Duration},
-    strum::{EnumString, VariantArray, VariantNames},
+    serde_yaml::from_slice as unyaml,
+    std::{
+        borrow::Cow,
+        fmt,
+        net::{AddrParseError, IpAddr, Ipv4Addr},
+        time::Duration,
+    },
+    strum::{EnumString, IntoStaticStr, VariantArray, VariantNames},
 };
 
-#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, EnumString, VariantArray, VariantNames)]
+/// Anything that can be asked where this machine lives, and the identity its
+/// trust factor, rate limit and telemetry are kept under.
+///
+/// Copied around freely: a declared provider is a borrow of the table read at
+/// startup, which lives as long as the process does.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Provider {
+    Http(HttpProvider),
+    Stun(StunProvider),
+    Dns(DnsProvider),
+    Local(LocalProvider),
+    Metadata(MetadataProvider),
+    Node(NodeProvider),
+    Declared(&'static Declared),
+}
+
+/// What asking a provider takes.
+pub enum Request {
+    Http(Method),
+    Stun(&'static Server),
+    Dns(&'static Lookup),
+    Echo(&'static Echo),
+    Gateway(&'static Gateway),
+    Interface,
+    /// A GET over IPv4 whichever family is asked about: a metadata service
+    /// is reached at a link-local IPv4 address and states both.
+    Metadata,
+    /// A look at what the Node carries under the key, never a request.
+    Node(&'static Key),
+}
+
+impl From<HttpProvider> for Provider {
+    fn from(provider: HttpProvider) -> Self {
+        Self::Http(provider)
+    }
+}
+
+impl From<StunProvider> for Provider {
+    fn from(provider: StunProvider) -> Self {
+        Self::Stun(provider)
+    }
+}
+
+impl From<DnsProvider> for Provider {
+    fn from(provider: DnsProvider) -> Self {
+        Self::Dns(provider)
+    }
+}
+
+impl From<LocalProvider> for Provider {
+    fn from(provider: LocalProvider) -> Self {
+        Self::Local(provider)
+    }
+}
+
+impl From<MetadataProvider> for Provider {
+    fn from(provider: MetadataProvider) -> Self {
+        Self::Metadata(provider)
+    }
+}
+
+impl From<NodeProvider> for Provider {
+    fn from(provider: NodeProvider) -> Self {
+        Self::Node(provider)
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.host())
+    }
+}
+
+impl Provider {
+    /// The name `--providers` and `--trust-factor` know the provider by.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Http(provider) => provider.into(),
+            Self::Stun(provider) => provider.into(),
+            Self::Dns(provider) => provider.into(),
+            Self::Local(provider) => provider.into(),
+            Self::Metadata(provider) => provider.into(),
+            Self::Node(provider) => provider.into(),
+            Self::Declared(declared) => &declared.name,
+        }
+    }
+
+    /// See [`HttpProvider::host`].
+    pub fn host(self) -> &'static str {
+        match self {
+            Self::Http(provider) => provider.host(),
+            Self::Stun(provider) => provider.server().host.as_ref(),
+            Self::Dns(provider) => provider.lookup().server,
+            Self::Local(provider) => provider.host(),
+            Self::Metadata(provider) => provider.host(),
+            Self::Node(provider) => &provider.key().name,
+            Self::Declared(declared) => &declared.host,
+        }
+    }
+
+    pub fn request_uri(self) -> &'static str {
+        match self {
+            Self::Http(provider) => provider.request_uri(),
+            Self::Stun(provider) => provider.request_uri(),
+            Self::Dns(provider) => provider.request_uri(),
+            Self::Local(provider) => provider.request_uri(),
+            Self::Metadata(provider) => provider.request_uri(),
+            Self::Node(provider) => provider.request_uri(),
+            Self::Declared(declared) => &declared.uri,
+        }
+    }
+
+    /// How the provider is asked: an HTTP request, a STUN Binding request, a
+    /// DNS query, a request to an `fckloud echo`, the router's own word, a
+    /// look at the node's own interfaces, a metadata service's, or what
+    /// another tool wrote on the Node.
+    pub fn request(self) -> Request {
+        match self {
+            Self::Http(provider) => Request::Http(provider.request_method()),
+            Self::Stun(provider) => Request::Stun(provider.server()),
+            Self::Dns(provider) => Request::Dns(provider.lookup()),
+            Self::Local(LocalProvider::Gateway) => Request::Gateway(&Gateway::DEFAULT),
+            Self::Local(LocalProvider::Interface) => Request::Interface,
+            Self::Metadata(_) => Request::Metadata,
+            Self::Node(provider) => Request::Node(provider.key()),
+            Self::Declared(declared) => match &declared.protocol {
+                Protocol::Http { method, .. } => Request::Http(method.clone()),
+                Protocol::Stun(server) => Request::Stun(server),
+                Protocol::Echo(echo) => Request::Echo(echo),
+                Protocol::Node(key) => Request::Node(key),
+            },
+        }
+    }
+
+    pub fn enabled_by_default(self) -> bool {
+        match self {
+            Self::Http(provider) => provider.enabled_by_default(),
+            Self::Stun(_) | Self::Dns(_) | Self::Local(_) | Self::Metadata(_) | Self::Node(_) => {
+                false
+            }
+            Self::Declared(declared) => declared.enabled_by_default,
+        }
+    }
+
+    /// The trust factor the provider carries unless `--trust-factor` says
+    /// otherwise.
+    pub fn default_trust_factor(self) -> usize {
+        match self {
+            Self::Http(provider) => TrustFactorAuthority::default_trust_factor(provider),
+            Self::Stun(_) | Self::Dns(_) | Self::Local(_) | Self::Node(_) => {
+                TrustFactorAuthority::LOW
+            }
+            Self::Metadata(_) => TrustFactorAuthority::HIG,
+            Self::Declared(declared) => declared.trust_factor,
+        }
+    }
+
+    /// See [`HttpProvider::rate_limit`].
+    pub fn rate_limit(self) -> Option<Duration> {
+        match self {
+            Self::Http(provider) => provider.rate_limit(),
+            Self::Stun(_) | Self::Dns(_) | Self::Local(_) | Self::Metadata(_) | Self::Node(_) => {
+                None
+            }
+            Self::Declared(declared) => declared.rate_limit,
+        }
+    }
+
+    /// How many times the provider is asked again within a round when it gave
+    /// no answer, unless `--retries` says otherwise. Once for anything asked
+    /// over the Internet or of a metadata service. The router is already
+    /// asked over and over within its one request, and the node's own
+    /// interfaces and its Node answer the same however often they are read.
+    pub fn retries(self) -> u32 {
+        match self {
+            Self::Http(_) | Self::Stun(_) | Self::Dns(_) | Self::Metadata(_) => 1,
+            Self::Local(_) | Self::Node(_) => 0,
+            Self::Declared(declared) => match declared.protocol {
+                Protocol::Node(_) => 0,
+                Protocol::Http { .. } | Protocol::Stun(_) | Protocol::Echo(_) => 1,
+            },
+        }
+    }
+
+    /// Where the provider states what it allows, when anybody said.
+    pub fn terms(self) -> Option<&'static str> {
+        match self {
+            Self::Http(provider) => Some(provider.terms()),
+            Self::Stun(provider) => provider.terms(),
+            Self::Dns(provider) => Some(provider.terms()),
+            Self::Local(_) | Self::Node(_) => None,
+            Self::Metadata(provider) => Some(provider.terms()),
+            Self::Declared(declared) => declared.terms.as_deref(),
+        }
+    }
+
+    pub fn has_ipv6(self) -> bool {
+        match self {
+            Self::Http(provider) => provider.has_ipv6(),
+            Self::Stun(_) => true,
+            Self::Dns(provider) => provider.has_ipv6(),
+            Self::Local(provider) => provider.has_ipv6(),
+            Self::Metadata(provider) => provider.has_ipv6(),
+            Self::Node(provider) => provider.has_ipv6(),
+            Self::Declared(declared) => declared.ipv6,
+        }
+    }
+
+    /// Whether the provider can be asked over the given family. Every one of
+    /// them answers over IPv4.
+    pub fn serves(self, family: Family) -> bool {
+        match family {
+            Family::V4 => true,
+            Family::V6 => self.has_ipv6(),
+        }
+    }
+
+    pub fn summary(self) -> &'static str {
+        match self {
+            Self::Http(provider) => provider.summary(),
+            Self::Stun(provider) => provider.summary(),
+            Self::Dns(provider) => provider.summary(),
+            Self::Local(provider) => provider.summary(),
+            Self::Metadata(provider) => provider.summary(),
+            Self::Node(provider) => provider.summary(),
+            Self::Declared(declared) => &declared.summary,
+        }
+    }
+
+    /// See [`HttpProvider::mirrors`]. Only a provider asked over HTTP has
+    /// any.
+    pub fn mirrors(self, family: Family) -> Vec<&'static str> {
+        match self {
+            Self::Http(provider) => provider.mirrors(family).to_vec(),
+            Self::Declared(declared) => declared.mirrors.iter().map(String::as_str).collect(),
+            Self::Stun(_) | Self::Dns(_) | Self::Local(_) | Self::Metadata(_) | Self::Node(_) => {
+                Vec::new()
+            }
+        }
+    }
+
+    /// See [`HttpProvider::pins`]. Only a built-in provider asked over HTTPS
+    /// has any of its own.
+    pub fn pins(self) -> &'static [Pin] {
+        match self {
+            Self::Http(provider) => provider.pins(),
+            Self::Stun(_)
+            | Self::Dns(_)
+            | Self::Local(_)
+            | Self::Metadata(_)
+            | Self::Node(_)
+            | Self::Declared(_) => &[],
+        }
+    }
+
+    /// Where the provider wants the key it is asked with, if it takes one.
+    pub fn key_placement(self) -> Option<Placement> {
+        match self {
+            Self::Http(provider) => provider.key_placement(),
+            Self::Declared(Declared {
+                protocol: Protocol::Http { key_placement, .. },
+                ..
+            }) => key_placement.clone(),
+            Self::Stun(_)
+            | Self::Dns(_)
+            | Self::Local(_)
+            | Self::Metadata(_)
+            | Self::Node(_)
+            | Self::Declared(_) => None,
+        }
+    }
+
+    /// Whether the provider came from the providers file.
+    pub const fn is_declared(self) -> bool {
+        matches!(self, Self::Declared(_))
+    }
+
+    /// Only ever asked of a provider [`Self::request`] says is asked over
+    /// HTTP; any other answer is decoded where it is received.
+    pub fn response_decode(self, body: &[u8], family: Family) -> Result<IpAddr, FetchError> {
+        match self {
+            Self::Http(provider) => provider.response_decode(body, family),
+            Self::Metadata(provider) => provider.response_decode(body, family),
+            Self::Stun(provider) => unreachable!("{provider} answers over STUN, not HTTP"),
+            Self::Dns(provider) => unreachable!("{provider} answers over DNS, not HTTP"),
+            Self::Local(provider) => unreachable!("{provider} is asked on the node's own network"),
+            Self::Node(provider) => unreachable!("{provider} is read off the Node"),
+            Self::Declared(declared) => declared.response_decode(body),
+        }
+    }
+}
+
+#[derive(
+    Clone, Copy, Eq, PartialEq, Hash, Debug, EnumString, IntoStaticStr, VariantArray, VariantNames,
+)]
 #[strum(ascii_case_insensitive)]
 pub enum HttpProvider {
     HttpBin,
-    MyIpWtf,      // https://myip.wtf/automation
-    SeeIp,        // https://seeip.org
-    Ipify,        // https://www.ipify.org
-    MyIpCom,      // https://www.myip.com/api-docs
-    BigDataCloud, // https://www.bigdatacloud.com/free-api/public-ip-address-api
-    MyIpLa,       // https://www.myip.la
+    MyIpWtf,           // https://myip.wtf/automation
+    SeeIp,             // https://seeip.org
+    Ipify,             // https://www.ipify.org
+    MyIpCom,           // https://www.myip.com/api-docs
+    BigDataCloud,      // https://www.bigdatacloud.com/free-api/public-ip-address-api
+    MyIpLa,            // https://www.myip.la
+    IcanHazIp,         // https://icanhazip.com
+    CheckIpAws,        // https://checkip.amazonaws.com
+    IfconfigMe,        // https://ifconfig.me
+    IfconfigCo,        // https://ifconfig.co
+    IdentMe,           // https://api.ident.me
+    MyExternalIp,      // https://myexternalip.com
+    FreeIpApi,         // https://freeipapi.com
+    IpInfo,            // https://ipinfo.io/developers
+    IpGeolocation,     // https://ipgeolocation.io/documentation/ip-geolocation-api.html
+    BigDataCloudKeyed, // https://www.bigdatacloud.com/docs/api/client-ip-api
 }
 
 impl fmt::Display for HttpProvider {
@@ -37,6 +358,16 @@ impl HttpProvider {
             Self::MyIpCom => "api.myip.com",
             Self::BigDataCloud => "api.bigdatacloud.net",
             Self::MyIpLa => "api.myip.la",
+            Self::IcanHazIp => "icanhazip.com",
+            Self::CheckIpAws => "checkip.amazonaws.com",
+            Self::IfconfigMe => "ifconfig.me",
+            Self::IfconfigCo => "ifconfig.co",
+            Self::IdentMe => "api.ident.me",
+            Self::MyExternalIp => "myexternalip.com",
+            Self::FreeIpApi => "freeipapi.com",
+            Self::IpInfo => "ipinfo.io",
+            Self::IpGeolocation => "api.ipgeolocation.io",
+            Self::BigDataCloudKeyed => "api-bdc.net",
         }
     }
 
@@ -53,6 +384,19 @@ impl HttpProvider {
             Self::MyIpCom => "https://api.myip.com/",
             Self::BigDataCloud => "https://api.bigdatacloud.net/data/client-ip",
             Self::MyIpLa => "https://api.myip.la/en?json",
+            Self::IcanHazIp => "https://icanhazip.com/",
+            Self::CheckIpAws => "https://checkip.amazonaws.com/",
+            // `/all.json` carries the user agent and the headers besides, to
+            // be thrown away; `/ip` is the address alone.
+            Self::IfconfigMe => "https://ifconfig.me/ip",
+            Self::IfconfigCo => "https://ifconfig.co/ip",
+            Self::IdentMe => "https://api.ident.me/",
+            Self::MyExternalIp => "https://myexternalip.com/raw",
+            Self::FreeIpApi => "https://freeipapi.com/api/json",
+            Self::IpInfo => "https://ipinfo.io/json",
+            // `fields` trims the geolocation it would otherwise send along.
+            Self::IpGeolocation => "https://api.ipgeolocation.io/ipgeo?fields=ip",
+            Self::BigDataCloudKeyed => "https://api-bdc.net/data/client-ip",
         }
     }
 
@@ -64,18 +408,40 @@ impl HttpProvider {
             | Self::Ipify
             | Self::MyIpCom
             | Self::BigDataCloud
-            | Self::MyIpLa => Method::GET,
+            | Self::MyIpLa
+            | Self::IcanHazIp
+            | Self::CheckIpAws
+            | Self::IfconfigMe
+            | Self::IfconfigCo
+            | Self::IdentMe
+            | Self::MyExternalIp
+            | Self::FreeIpApi
+            | Self::IpInfo
+            | Self::IpGeolocation
+            | Self::BigDataCloudKeyed => Method::GET,
         }
     }
 
     /// Whether the provider takes part in consensus without being asked for.
     ///
-    /// httpbin.org is the only one that does not: it drops close to a fifth of
-    /// the requests this controller makes, and it is a request-inspection demo
-    /// that happens to echo an address rather than a public-IP service.
+    /// httpbin.org does not: it drops close to a fifth of the requests this
+    /// controller makes, and it is a request-inspection demo that happens to
+    /// echo an address rather than a public-IP service. Neither does any
+    /// provider added since the default set was last pinned, so that adding
+    /// one moves no deployment's threshold.
     pub const fn enabled_by_default(self) -> bool {
         match self {
-            Self::HttpBin => false,
+            Self::HttpBin
+            | Self::IcanHazIp
+            | Self::CheckIpAws
+            | Self::IfconfigMe
+            | Self::IfconfigCo
+            | Self::IdentMe
+            | Self::MyExternalIp
+            | Self::FreeIpApi
+            | Self::IpInfo
+            | Self::IpGeolocation
+            | Self::BigDataCloudKeyed => false,
             Self::MyIpWtf
             | Self::SeeIp
             | Self::Ipify
@@ -93,13 +459,105 @@ impl HttpProvider {
     /// 429, and asking for one a minute is still what it asks for.
     pub const fn rate_limit(self) -> Option<Duration> {
         match self {
-            Self::MyIpWtf => Some(Duration::from_mins(1)),
+            Self::MyIpWtf | Self::IfconfigCo => Some(Duration::from_mins(1)),
+            Self::MyExternalIp => Some(Duration::from_secs(2)),
+            Self::FreeIpApi => Some(Duration::from_secs(1)),
+            Self::HttpBin
+            | Self::SeeIp
+            | Self::Ipify
+            | Self::MyIpCom
+            | Self::BigDataCloud
+            | Self::MyIpLa
+            | Self::IcanHazIp
+            | Self::CheckIpAws
+            | Self::IfconfigMe
+            | Self::IdentMe
+            | Self::IpInfo
+            | Self::IpGeolocation
+            | Self::BigDataCloudKeyed => None,
+        }
+    }
+
+    /// Further endpoints serving the same body, asked in turn within the round
+    /// when the one before fails for want of an answer: see
+    /// [`crate::pubip::FetchError::fails_over`]. An address one of them
+    /// reports counts as the provider's own.
+    ///
+    /// `seeip.org`'s are per family, so each is only ever asked over the
+    /// family it pins.
+    pub const fn mirrors(self, family: Family) -> &'static [&'static str] {
+        match (self, family) {
+            (Self::SeeIp, Family::V4) => &["https://ipv4.seeip.org/jsonip"],
+            (Self::SeeIp, Family::V6) => &["https://ipv6.seeip.org/jsonip"],
+            (Self::BigDataCloud, _) => &["https://api-bdc.net/data/client-ip"],
+            (
+                Self::HttpBin
+                | Self::MyIpWtf
+                | Self::Ipify
+                | Self::MyIpCom
+                | Self::MyIpLa
+                | Self::IcanHazIp
+                | Self::CheckIpAws
+                | Self::IfconfigMe
+                | Self::IfconfigCo
+                | Self::IdentMe
+                | Self::MyExternalIp
+                | Self::FreeIpApi
+                | Self::IpInfo
+                | Self::IpGeolocation
+                | Self::BigDataCloudKeyed,
+                _,
+            ) => &[],
+        }
+    }
+
+    /// The keys one of the provider's certificates must be issued under, for
+    /// the providers trusted highly enough that one impersonated provider
+    /// would be most of the way to an address. Those of the roots their chains
+    /// lead to, see [`tls::ipify_pins`] and [`tls::ipinfo_pins`].
+    pub fn pins(self) -> &'static [Pin] {
+        match self {
+            Self::Ipify => tls::ipify_pins(),
+            Self::IpInfo => tls::ipinfo_pins(),
+            Self::HttpBin
+            | Self::MyIpWtf
+            | Self::SeeIp
+            | Self::MyIpCom
+            | Self::BigDataCloud
+            | Self::MyIpLa
+            | Self::IcanHazIp
+            | Self::CheckIpAws
+            | Self::IfconfigMe
+            | Self::IfconfigCo
+            | Self::IdentMe
+            | Self::MyExternalIp
+            | Self::FreeIpApi
+            | Self::IpGeolocation
+            | Self::BigDataCloudKeyed => &[],
+        }
+    }
+
+    /// Where the provider wants the key it is asked with, [`None`] for the
+    /// ones anybody may ask. A keyed provider is never asked without one.
+    pub const fn key_placement(self) -> Option<Placement> {
+        match self {
+            Self::IpInfo => Some(Placement::Bearer),
+            Self::IpGeolocation => Some(Placement::Query(Cow::Borrowed("apiKey"))),
+            Self::BigDataCloudKeyed => Some(Placement::Query(Cow::Borrowed("key"))),
             Self::HttpBin
+            | Self::MyIpWtf
             | Self::SeeIp
             | Self::Ipify
             | Self::MyIpCom
             | Self::BigDataCloud
-            | Self::MyIpLa => None,
+            | Self::MyIpLa
+            | Self::IcanHazIp
+            | Self::CheckIpAws
+            | Self::IfconfigMe
+            | Self::IfconfigCo
+            | Self::IdentMe
+            | Self::MyExternalIp
+            | Self::FreeIpApi => None,
         }
     }
 
@@ -112,8 +570,19 @@ impl HttpProvider {
             Self::SeeIp => "https://seeip.org",
             Self::Ipify => "https://www.ipify.org",
             Self::MyIpCom => "https://www.myip.com/api-docs/",
-            Self::BigDataCloud => "https://www.bigdatacloud.com/terms-and-conditions",
+            Self::BigDataCloud | Self::BigDataCloudKeyed => {
+                "https://www.bigdatacloud.com/terms-and-conditions"
+            }
             Self::MyIpLa => "https://www.myip.la",
+            Self::IcanHazIp => "https://major.io/p/a-new-future-for-icanhazip/",
+            Self::CheckIpAws => "https://aws.amazon.com/service-terms/",
+            Self::IfconfigMe => "https://ifconfig.me",
+            Self::IfconfigCo => "https://ifconfig.co",
+            Self::IdentMe => "https://api.ident.me",
+            Self::MyExternalIp => "https://myexternalip.com",
+            Self::FreeIpApi => "https://freeipapi.com",
+            Self::IpInfo => "https://ipinfo.io/terms-of-service",
+            Self::IpGeolocation => "https://ipgeolocation.io/tos.html",
         }
     }
 
@@ -121,13 +590,23 @@ impl HttpProvider {
     /// a node that has only IPv6.
     pub const fn has_ipv6(self) -> bool {
         match self {
-            Self::HttpBin => false,
+            Self::HttpBin
+            | Self::CheckIpAws
+            | Self::MyExternalIp
+            | Self::IpInfo
+            | Self::IpGeolocation => false,
             Self::MyIpWtf
             | Self::SeeIp
             | Self::Ipify
             | Self::MyIpCom
             | Self::BigDataCloud
-            | Self::MyIpLa => true,
+            | Self::MyIpLa
+            | Self::IcanHazIp
+            | Self::IfconfigMe
+            | Self::IfconfigCo
+            | Self::IdentMe
+            | Self::FreeIpApi
+            | Self::BigDataCloudKeyed => true,
         }
     }
 
@@ -160,31 +639,476 @@ impl HttpProvider {
             ),
             Self::BigDataCloud => concat!(
                 "No API key, no published limit, a company's infrastructure and the fastest ",
-                "answer of the seven. Ranked low only because its documentation names the ",
-                "wrong field, and has long enough for nobody to have noticed.",
+                "answer of the original seven. Ranked low only because its documentation ",
+                "names the wrong field, and has long enough for nobody to have noticed.",
             ),
             Self::MyIpLa => concat!(
                 "Documented as unlimited, no visitor logging. No named operator, no source, ",
                 "no terms page and no contact, so it carries the smallest weight there is.",
             ),
+            Self::IcanHazIp => concat!(
+                "The plain-text original, run by Cloudflare since its author handed it over. ",
+                "No stated limit and no terms beyond the handover post.",
+            ),
+            Self::CheckIpAws => concat!(
+                "The endpoint AWS's own tooling asks. Undocumented as a service, no stated ",
+                "limit, and no AAAA, so it answers over IPv4 only.",
+            ),
+            Self::IfconfigMe => concat!(
+                "A long-lived echo service with no named operator and no stated limit. ",
+                "Asked at `/ip`, the address alone.",
+            ),
+            Self::IfconfigCo => concat!(
+                "Runs the open source echoip and asks for one automated request per minute, ",
+                "the default tick again. Asked at `/ip`, the address alone.",
+            ),
+            Self::IdentMe => concat!(
+                "A plain-text echo with separate `v4.` and `v6.` hosts beside the dual-stack ",
+                "one asked here. Open source, no stated limit.",
+            ),
+            Self::MyExternalIp => concat!(
+                "Asks for no more than one request every two seconds. No AAAA on the host ",
+                "asked, so it answers over IPv4 only.",
+            ),
+            Self::FreeIpApi => concat!(
+                "A geolocation API that allows sixty requests a minute without a key, the ",
+                "address in `ipAddress` beside everything it guessed about it.",
+            ),
+            Self::IpInfo => concat!(
+                "A commercial IP data service with paid plans and an SLA, asked with a token ",
+                "as a bearer header. `ipinfo.io` has no AAAA; IPv6 lives on another host.",
+            ),
+            Self::IpGeolocation => concat!(
+                "A commercial geolocation API with paid plans and an SLA, asked with a key in ",
+                "the `apiKey` parameter and trimmed to the address by `fields=ip`. IPv4 only.",
+            ),
+            Self::BigDataCloudKeyed => concat!(
+                "BigDataCloud's keyed API, the same answer as the free endpoint behind a key ",
+                "in the `key` parameter, and the service agreement that comes with one.",
+            ),
         }
     }
 
-    pub fn response_decode(self, body: &[u8]) -> Result<IpAddr, FetchError> {
+    pub fn response_decode(self, body: &[u8], family: Family) -> Result<IpAddr, FetchError> {
         match self {
-            Self::HttpBin => decode::<HttpBinResponse>(body),
-            Self::MyIpWtf => decode::<MyIpWtfResponse>(body),
-            Self::BigDataCloud => decode::<BigDataCloudResponse>(body),
-            Self::SeeIp | Self::Ipify | Self::MyIpCom | Self::MyIpLa => {
-                decode::<IpFieldResponse>(body)
+            Self::HttpBin => decode::<HttpBinResponse>(body, family),
+            Self::MyIpWtf => decode::<MyIpWtfResponse>(body, family),
+            Self::BigDataCloud | Self::BigDataCloudKeyed => {
+                decode::<BigDataCloudResponse>(body, family)
             }
+            Self::FreeIpApi => decode::<FreeIpApiResponse>(body, family),
+            Self::SeeIp
+            | Self::Ipify
+            | Self::MyIpCom
+            | Self::MyIpLa
+            | Self::IpInfo
+            | Self::IpGeolocation => decode::<IpFieldResponse>(body, family),
+            Self::IcanHazIp
+            | Self::CheckIpAws
+            | Self::IfconfigMe
+            | Self::IfconfigCo
+            | Self::IdentMe
+            | Self::MyExternalIp => decode_text(body),
+        }
+    }
+}
+
+/// A public STUN server, asked with a Binding request rather than over HTTP.
+///
+/// Never on by default and at the lowest trust: these servers exist for WebRTC
+/// clients, nobody behind them promises anything to anyone else, and what they
+/// report is the address of the flow the request took, which a NAT may map
+/// differently from the connections the HTTP providers see. Worth having for
+/// failing differently, not for being believed alone.
+#[derive(
+    Clone, Copy, Eq, PartialEq, Hash, Debug, EnumString, IntoStaticStr, VariantArray, VariantNames,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum StunProvider {
+    GoogleStun,     // https://webrtc.org
+    CloudflareStun, // https://developers.cloudflare.com/realtime/turn/
+}
+
+impl fmt::Display for StunProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.server().host)
+    }
+}
+
+static GOOGLE_STUN: Server = Server::new("stun.l.google.com", 19302, false);
+static CLOUDFLARE_STUN: Server = Server::new("stun.cloudflare.com", Server::TLS_PORT, true);
+
+impl StunProvider {
+    pub fn server(self) -> &'static Server {
+        match self {
+            Self::GoogleStun => &GOOGLE_STUN,
+            Self::CloudflareStun => &CLOUDFLARE_STUN,
+        }
+    }
+
+    /// The server as a URI, the way a declared one is written down.
+    pub const fn request_uri(self) -> &'static str {
+        match self {
+            Self::GoogleStun => "stun:stun.l.google.com:19302",
+            // The only one of the two that speaks TLS, and so the one whose
+            // answer an on-path box cannot quietly rewrite.
+            Self::CloudflareStun => "stuns:stun.cloudflare.com:5349",
+        }
+    }
+
+    pub const fn terms(self) -> Option<&'static str> {
+        match self {
+            Self::GoogleStun => None,
+            Self::CloudflareStun => Some("https://developers.cloudflare.com/realtime/turn/"),
+        }
+    }
+
+    pub const fn summary(self) -> &'static str {
+        match self {
+            Self::GoogleStun => concat!(
+                "The STUN server every WebRTC example points at, over plain UDP. Published ",
+                "nowhere as a service and promised to nobody, so it carries the smallest ",
+                "weight there is.",
+            ),
+            Self::CloudflareStun => concat!(
+                "Cloudflare's free STUN server, asked over TLS on 5349. Documented beside ",
+                "their TURN service, with no key and no stated limit for STUN alone.",
+            ),
+        }
+    }
+}
+
+/// An authoritative nameserver asked for a name that resolves to whoever
+/// asks, directly and without recursion.
+///
+/// Off by default and at the lowest trust for the reasons STUN is: nobody
+/// offers these names as a service, and port 53 is the one most often
+/// intercepted on the way out. An interceptor that answers from a resolver
+/// is caught by the authority check; one that forges the AA bit is not, which
+/// is why `CloudflareDns`, asked over TLS, is the one to prefer.
+#[derive(
+    Clone, Copy, Eq, PartialEq, Hash, Debug, EnumString, IntoStaticStr, VariantArray, VariantNames,
+)]
+#[strum(ascii_case_insensitive)]
+#[allow(
+    clippy::enum_variant_names,
+    reason = "the names share one namespace with every other provider's"
+)]
+pub enum DnsProvider {
+    OpenDns,       // https://www.opendns.com
+    GoogleDns,     // https://developers.google.com/speed/public-dns
+    CloudflareDns, // https://developers.cloudflare.com/1.1.1.1/
+}
+
+impl fmt::Display for DnsProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.lookup().server)
+    }
+}
+
+static OPEN_DNS: Lookup = Lookup {
+    server: "resolver1.opendns.com",
+    port: Lookup::PORT,
+    tls: false,
+    name: "myip.opendns.com",
+    class: Class::In,
+    record: Record::Address,
+};
+
+static GOOGLE_DNS: Lookup = Lookup {
+    server: "ns1.google.com",
+    port: Lookup::PORT,
+    tls: false,
+    name: "o-o.myaddr.l.google.com",
+    class: Class::In,
+    record: Record::Txt,
+};
+
+static CLOUDFLARE_DNS: Lookup = Lookup {
+    server: "one.one.one.one",
+    port: Lookup::TLS_PORT,
+    tls: true,
+    name: "whoami.cloudflare",
+    class: Class::Ch,
+    record: Record::Txt,
+};
+
+impl DnsProvider {
+    pub fn lookup(self) -> &'static Lookup {
+        match self {
+            Self::OpenDns => &OPEN_DNS,
+            Self::GoogleDns => &GOOGLE_DNS,
+            Self::CloudflareDns => &CLOUDFLARE_DNS,
+        }
+    }
+
+    /// The query as an RFC 4501 URI. That RFC knows nothing of TLS, so DNS
+    /// over TLS shows only as the port it is reached on.
+    pub const fn request_uri(self) -> &'static str {
+        match self {
+            Self::OpenDns => "dns://resolver1.opendns.com/myip.opendns.com?type=A",
+            Self::GoogleDns => "dns://ns1.google.com/o-o.myaddr.l.google.com?type=TXT",
+            Self::CloudflareDns => "dns://one.one.one.one:853/whoami.cloudflare?class=CH;type=TXT",
+        }
+    }
+
+    pub const fn terms(self) -> &'static str {
+        match self {
+            Self::OpenDns => "https://www.opendns.com",
+            Self::GoogleDns => "https://developers.google.com/speed/public-dns/terms",
+            Self::CloudflareDns => "https://developers.cloudflare.com/1.1.1.1/privacy/",
+        }
+    }
+
+    /// Whether the server publishes an AAAA record. `myip.opendns.com` is
+    /// only ever asked of an IPv4 resolver here, so it answers only A.
+    pub const fn has_ipv6(self) -> bool {
+        match self {
+            Self::OpenDns => false,
+            Self::GoogleDns | Self::CloudflareDns => true,
+        }
+    }
+
+    pub const fn summary(self) -> &'static str {
+        match self {
+            Self::OpenDns => concat!(
+                "The oldest whoami name there is, an A record OpenDNS's resolvers answer for ",
+                "themselves. Plain DNS over IPv4 only.",
+            ),
+            Self::GoogleDns => concat!(
+                "A TXT record Google's own nameserver answers with the address that asked, ",
+                "meant for debugging resolvers. Plain DNS, over either family.",
+            ),
+            Self::CloudflareDns => concat!(
+                "A CHAOS TXT record 1.1.1.1 answers with the address that asked, over TLS on ",
+                "853, and so the one answer of the three nobody on the path can rewrite.",
+            ),
+        }
+    }
+}
+
+/// Something on the node's own network that knows the address, asked without
+/// a request ever leaving it.
+///
+/// Off by default and at the lowest trust. What the router says is only as
+/// good as the router, and a consumer router is not much of an authority; its
+/// answer is worth most beside the others, as the one voice that is not a
+/// stranger on the Internet. An address bound to the node is as good as the
+/// node's own configuration, which only the operator can vouch for, by
+/// raising its trust with `--trust-factor`.
+#[derive(
+    Clone, Copy, Eq, PartialEq, Hash, Debug, EnumString, IntoStaticStr, VariantArray, VariantNames,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum LocalProvider {
+    Gateway,   // RFC 6886, RFC 6887, UPnP IGD
+    Interface, // getifaddrs(3)
+}
+
+impl fmt::Display for LocalProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.host())
+    }
+}
+
+impl LocalProvider {
+    /// What stands for a host that is different on every node.
+    pub const fn host(self) -> &'static str {
+        match self {
+            Self::Gateway => "default-gateway",
+            Self::Interface => "node-interfaces",
+        }
+    }
+
+    pub const fn request_uri(self) -> &'static str {
+        match self {
+            Self::Gateway => "nat-pmp://default-gateway:5351",
+            Self::Interface => "getifaddrs://localhost",
+        }
+    }
+
+    /// NAT-PMP and `GetExternalIPAddress` both know an IPv4 address alone.
+    pub const fn has_ipv6(self) -> bool {
+        match self {
+            Self::Gateway => false,
+            Self::Interface => true,
+        }
+    }
+
+    pub const fn summary(self) -> &'static str {
+        match self {
+            Self::Gateway => concat!(
+                "The router the default route leads to, asked for its WAN address over NAT-PMP ",
+                "or PCP, then over UPnP IGD. Nothing leaves the LAN, and a router behind a ",
+                "carrier's NAT is caught by the address it gives.",
+            ),
+            Self::Interface => concat!(
+                "The public address bound to the node's own interface, the one a request to ",
+                "the Internet would leave from. Asks nobody; a node behind a NAT has no such ",
+                "address and says so.",
+            ),
+        }
+    }
+}
+
+/// An address another tool in the cluster already wrote on the Node, read
+/// from the Node fckloud reads anyway.
+///
+/// Off by default, since only a cluster running the tool has it, and at the
+/// lowest trust: the value is whatever the tool was told or worked out, and
+/// anybody allowed to annotate Nodes can write it. What it is worth is that
+/// it asks nobody on the Internet; an operator who set the value themselves
+/// can vouch for it with `--trust-factor`. Other keys are declared in the
+/// providers file as `annotation:KEY` or `label:KEY`.
+#[derive(
+    Clone, Copy, Eq, PartialEq, Hash, Debug, EnumString, IntoStaticStr, VariantArray, VariantNames,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum NodeProvider {
+    Flannel, // https://github.com/flannel-io/flannel/blob/master/Documentation/kubernetes.md
+    K3s,     // https://docs.k3s.io/cli/agent#networking
+}
+
+impl fmt::Display for NodeProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.key().name)
+    }
+}
+
+static FLANNEL: Key = Key::annotation("flannel.alpha.coreos.com/public-ip");
+static K3S: Key = Key::annotation("k3s.io/external-ip");
+
+impl NodeProvider {
+    /// The annotation the tool writes. Its name stands for the host.
+    pub fn key(self) -> &'static Key {
+        match self {
+            Self::Flannel => &FLANNEL,
+            Self::K3s => &K3S,
+        }
+    }
+
+    pub const fn request_uri(self) -> &'static str {
+        match self {
+            Self::Flannel => "annotation:flannel.alpha.coreos.com/public-ip",
+            Self::K3s => "annotation:k3s.io/external-ip",
+        }
+    }
+
+    /// Flannel writes an IPv6 address under a key of its own,
+    /// `flannel.alpha.coreos.com/public-ipv6`; k3s lists both under one.
+    pub const fn has_ipv6(self) -> bool {
+        match self {
+            Self::Flannel => false,
+            Self::K3s => true,
+        }
+    }
+
+    pub const fn summary(self) -> &'static str {
+        match self {
+            Self::Flannel => concat!(
+                "The `flannel.alpha.coreos.com/public-ip` annotation flannel writes on every ",
+                "Node it runs on, the address it reaches the other nodes at.",
+            ),
+            Self::K3s => concat!(
+                "The `k3s.io/external-ip` annotation k3s writes from `--node-external-ip`, ",
+                "in either family.",
+            ),
+        }
+    }
+}
+
+/// The metadata service of a cloud whose VMs run without its controller
+/// manager, asked at the link-local address it answers on.
+///
+/// Off by default, since it answers nowhere but on its own cloud, and at the
+/// highest trust once named: the address it states is the one the cloud
+/// routes to the VM, from the one party that knows for certain. Reached
+/// without leaving the host, so nothing on the Internet can change it.
+#[derive(
+    Clone, Copy, Eq, PartialEq, Hash, Debug, EnumString, IntoStaticStr, VariantArray, VariantNames,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum MetadataProvider {
+    Hetzner,      // https://docs.hetzner.cloud/#server-metadata
+    Scaleway,     // https://www.scaleway.com/en/developers/api/instance/
+    DigitalOcean, // https://docs.digitalocean.com/reference/api/metadata/
+}
+
+impl fmt::Display for MetadataProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.host())
+    }
+}
+
+impl MetadataProvider {
+    /// What stands for a host two of them share, `169.254.169.254`.
+    pub const fn host(self) -> &'static str {
+        match self {
+            Self::Hetzner => "hetzner-metadata",
+            Self::Scaleway => "scaleway-metadata",
+            Self::DigitalOcean => "digitalocean-metadata",
+        }
+    }
+
+    /// Plain HTTP to a link-local address, which is all any of them speaks.
+    /// `--provider-url` points one at a stand-in.
+    pub const fn request_uri(self) -> &'static str {
+        match self {
+            Self::Hetzner => "http://169.254.169.254/hetzner/v1/metadata",
+            Self::Scaleway => "http://169.254.42.42/conf?format=json",
+            Self::DigitalOcean => "http://169.254.169.254/metadata/v1.json",
+        }
+    }
+
+    pub const fn terms(self) -> &'static str {
+        match self {
+            Self::Hetzner => "https://docs.hetzner.cloud/#server-metadata",
+            Self::Scaleway => "https://www.scaleway.com/en/developers/api/instance/",
+            Self::DigitalOcean => "https://docs.digitalocean.com/reference/api/metadata/",
+        }
+    }
+
+    /// Hetzner states the IPv6 network of a server, never an address in it.
+    pub const fn has_ipv6(self) -> bool {
+        match self {
+            Self::Hetzner => false,
+            Self::Scaleway | Self::DigitalOcean => true,
+        }
+    }
+
+    pub const fn summary(self) -> &'static str {
+        match self {
+            Self::Hetzner => concat!(
+                "The Hetzner Cloud metadata service, `public-ipv4` out of its YAML. ",
+                "IPv4 only: what it says of IPv6 is the /64, not an address.",
+            ),
+            Self::Scaleway => concat!(
+                "The Scaleway instance metadata service, the public addresses out of its ",
+                "JSON in either family.",
+            ),
+            Self::DigitalOcean => concat!(
+                "The DigitalOcean droplet metadata service, the first public interface's ",
+                "addresses out of its JSON in either family.",
+            ),
+        }
+    }
+
+    pub fn response_decode(self, body: &[u8], family: Family) -> Result<IpAddr, FetchError> {
+        match self {
+            Self::Hetzner => decode_yaml::<HetznerResponse>(body, family),
+            Self::Scaleway => decode::<ScalewayResponse>(body, family),
+            Self::DigitalOcean => decode::<DigitalOceanResponse>(body, family),
         }
     }
 }
 
 /// What every provider's answer boils down to, whatever it calls the field.
+///
+/// Asked for the family the round is over: an echo states the one address it
+/// saw, whichever family that was, and a metadata service states both or only
+/// one of them.
 trait Response: for<'de> Deserialize<'de> {
-    fn into_ip_addr(self) -> IpAddr;
+    fn into_ip_addr(self, family: Family) -> Option<IpAddr>;
 }
 
 #[derive(Deserialize)]
@@ -213,37 +1137,153 @@ struct BigDataCloudResponse {
     ip_addr: IpAddr,
 }
 
+#[derive(Deserialize)]
+struct FreeIpApiResponse {
+    #[serde(rename = "ipAddress")]
+    ip_addr: IpAddr,
+}
+
+#[derive(Deserialize)]
+struct HetznerResponse {
+    #[serde(rename = "public-ipv4")]
+    public_ipv4: Option<Ipv4Addr>,
+}
+
+// `public_ips` is what an instance with more than one address has; the two
+// fields beside it are what older instances still have instead.
+#[derive(Deserialize)]
+struct ScalewayResponse {
+    #[serde(default)]
+    public_ips: Vec<ScalewayAddress>,
+    public_ip: Option<ScalewayAddress>,
+    ipv6: Option<ScalewayAddress>,
+}
+
+#[derive(Deserialize)]
+struct ScalewayAddress {
+    address: IpAddr,
+}
+
+#[derive(Deserialize)]
+struct DigitalOceanResponse {
+    interfaces: DigitalOceanInterfaces,
+}
+
+#[derive(Deserialize)]
+struct DigitalOceanInterfaces {
+    #[serde(default)]
+    public: Vec<DigitalOceanInterface>,
+}
+
+#[derive(Deserialize)]
+struct DigitalOceanInterface {
+    ipv4: Option<DigitalOceanAddress>,
+    ipv6: Option<DigitalOceanAddress>,
+}
+
+#[derive(Deserialize)]
+struct DigitalOceanAddress {
+    ip_address: IpAddr,
+}
+
 impl Response for HttpBinResponse {
-    fn into_ip_addr(self) -> IpAddr {
-        self.origin
+    fn into_ip_addr(self, _: Family) -> Option<IpAddr> {
+        Some(self.origin)
     }
 }
 
 impl Response for MyIpWtfResponse {
-    fn into_ip_addr(self) -> IpAddr {
-        self.ip_addr
+    fn into_ip_addr(self, _: Family) -> Option<IpAddr> {
+        Some(self.ip_addr)
     }
 }
 
 impl Response for IpFieldResponse {
-    fn into_ip_addr(self) -> IpAddr {
-        self.ip
+    fn into_ip_addr(self, _: Family) -> Option<IpAddr> {
+        Some(self.ip)
     }
 }
 
 impl Response for BigDataCloudResponse {
-    fn into_ip_addr(self) -> IpAddr {
-        self.ip_addr
+    fn into_ip_addr(self, _: Family) -> Option<IpAddr> {
+        Some(self.ip_addr)
+    }
+}
+
+impl Response for FreeIpApiResponse {
+    fn into_ip_addr(self, _: Family) -> Option<IpAddr> {
+        Some(self.ip_addr)
+    }
+}
+
+impl Response for HetznerResponse {
+    fn into_ip_addr(self, family: Family) -> Option<IpAddr> {
+        match family {
+            Family::V4 => self.public_ipv4.map(IpAddr::V4),
+            Family::V6 => None,
+        }
+    }
+}
+
+impl Response for ScalewayResponse {
+    fn into_ip_addr(self, family: Family) -> Option<IpAddr> {
+        self.public_ips
+            .into_iter()
+            .chain(self.public_ip)
+            .chain(self.ipv6)
+            .map(|public| public.address)
+            .find(|ip_addr| Family::of(ip_addr) == family)
+    }
+}
+
+impl Response for DigitalOceanResponse {
+    fn into_ip_addr(self, family: Family) -> Option<IpAddr> {
+        let public = self.interfaces.public.into_iter().next()?;
+
+        match family {
+            Family::V4 => public.ipv4,
+            Family::V6 => public.ipv6,
+        }
+        .map(|address| address.ip_address)
     }
 }
 
-fn decode<T: Response>(body: &[u8]) -> Result<IpAddr, FetchError> {
-    let decoded: T = unjson(body).map_err(|source| FetchError::Decode {
+fn decode<T: Response>(body: &[u8], family: Family) -> Result<IpAddr, FetchError> {
+    answer(body, family, unjson::<T>(body).map_err(Into::into))
+}
+
+/// Hetzner's metadata is YAML, and nobody else's is.
+fn decode_yaml<T: Response>(body: &[u8], family: Family) -> Result<IpAddr, FetchError> {
+    answer(body, family, unyaml::<T>(body).map_err(Into::into))
+}
+
+fn answer<T: Response>(
+    body: &[u8],
+    family: Family,
+    decoded: Result<T, Cause>,
+) -> Result<IpAddr, FetchError> {
+    let failed = |source: Cause| FetchError::Decode {
         body: String::from_utf8_lossy(body).into_owned(),
         source,
-    })?;
+    };
+
+    decoded
+        .map_err(failed)?
+        .into_ip_addr(family)
+        .ok_or_else(|| failed(format!("the answer states no {family} address").into()))
+}
+
+/// A body that is the address and nothing else, the trailing newline most of
+/// them send included.
+pub fn decode_text(body: &[u8]) -> Result<IpAddr, FetchError> {
+    let text = String::from_utf8_lossy(body);
 
-    Ok(decoded.into_ip_addr())
+    text.trim()
+        .parse()
+        .map_err(|source: AddrParseError| FetchError::Decode {
+            body: text.into_owned(),
+            source: source.into(),
+        })
 }
 
 #[cfg(test)]
@@ -252,7 +1292,7 @@ mod tests {
 
     // One captured body per provider, trimmed of everything but the fields
     // that matter, so that a provider changing its shape fails here first.
-    const SHAPES: [(HttpProvider, &str); 7] = [
+    const SHAPES: [(HttpProvider, &str); 17] = [
         (HttpProvider::HttpBin, r#"{"origin":"1.2.3.4"}"#),
         (
             HttpProvider::MyIpWtf,
@@ -272,13 +1312,32 @@ mod tests {
             HttpProvider::MyIpLa,
             r#"{"ip":"1.2.3.4","location":{"country_code":"RS"}}"#,
         ),
+        (HttpProvider::IcanHazIp, "1.2.3.4\n"),
+        (HttpProvider::CheckIpAws, "1.2.3.4\n"),
+        (HttpProvider::IfconfigMe, "1.2.3.4"),
+        (HttpProvider::IfconfigCo, "1.2.3.4\n"),
+        (HttpProvider::IdentMe, "1.2.3.4"),
+        (HttpProvider::MyExternalIp, "1.2.3.4"),
+        (
+            HttpProvider::FreeIpApi,
+            r#"{"ipVersion":4,"ipAddress":"1.2.3.4","countryCode":"RS"}"#,
+        ),
+        (
+            HttpProvider::IpInfo,
+            r#"{"ip":"1.2.3.4","city":"Belgrade","country":"RS"}"#,
+        ),
+        (HttpProvider::IpGeolocation, r#"{"ip":"1.2.3.4"}"#),
+        (
+            HttpProvider::BigDataCloudKeyed,
+            r#"{"ipString":"1.2.3.4","ipType":"IPv4"}"#,
+        ),
     ];
 
     #[test]
     fn each_provider_decodes_its_own_shape() {
         for (provider, body) in SHAPES {
             let decoded = provider
-                .response_decode(body.as_bytes())
+                .response_decode(body.as_bytes(), Family::V4)
                 .unwrap_or_else(|err| panic!("{provider} shape must decode: {err}"));
 
             assert_eq!(decoded.to_string(), "1.2.3.4", "{provider} decoded wrongly");
@@ -319,6 +1378,146 @@ mod tests {
         }
     }
 
+    #[test]
+    fn the_uri_of_every_stun_provider_is_the_server_it_asks() {
+        use std::str::FromStr;
+
+        for provider in <StunProvider as VariantArray>::VARIANTS {
+            let parsed = Server::from_str(provider.request_uri())
+                .unwrap_or_else(|err| panic!("{provider} has a URI that does not parse: {err}"));
+
+            assert_eq!(&parsed, provider.server(), "{provider} asks elsewhere");
+            assert!(!provider.summary().is_empty(), "{provider} has no summary");
+        }
+    }
+
+    #[test]
+    fn a_stun_provider_is_never_asked_unless_named_and_never_decides_alone() {
+        for provider in <StunProvider as VariantArray>::VARIANTS {
+            let provider = Provider::from(*provider);
+
+            assert!(
+                !provider.enabled_by_default(),
+                "{provider} is on by default"
+            );
+            assert_eq!(provider.default_trust_factor(), TrustFactorAuthority::LOW);
+        }
+    }
+
+    // Trimmed the same way, each stating both families where it can.
+    const METADATA_SHAPES: [(MetadataProvider, &str); 3] = [
+        (
+            MetadataProvider::Hetzner,
+            "hostname: node-1\ninstance-id: 42\npublic-ipv4: 1.2.3.4\nlocal-ipv4: ''\n",
+        ),
+        (
+            MetadataProvider::Scaleway,
+            r#"{"hostname":"node-1","public_ip":{"address":"1.2.3.4"},"ipv6":null,
+               "public_ips":[{"address":"1.2.3.4","family":"inet"},
+                             {"address":"2a01:4f8::1","family":"inet6"}]}"#,
+        ),
+        (
+            MetadataProvider::DigitalOcean,
+            r#"{"droplet_id":42,"interfaces":{"public":[{
+                "ipv4":{"ip_address":"1.2.3.4","netmask":"255.255.240.0"},
+                "ipv6":{"ip_address":"2a01:4f8::1","cidr":64}}]}}"#,
+        ),
+    ];
+
+    #[test]
+    fn each_metadata_provider_decodes_its_own_shape_in_either_family() {
+        for (provider, body) in METADATA_SHAPES {
+            let v4 = provider
+                .response_decode(body.as_bytes(), Family::V4)
+                .unwrap_or_else(|err| panic!("{provider} shape must decode: {err}"));
+            assert_eq!(v4.to_string(), "1.2.3.4", "{provider} decoded wrongly");
+
+            let v6 = provider.response_decode(body.as_bytes(), Family::V6);
+            if provider.has_ipv6() {
+                let v6 = v6.unwrap_or_else(|err| panic!("{provider} shape must decode: {err}"));
+                assert_eq!(v6.to_string(), "2a01:4f8::1", "{provider} decoded wrongly");
+            } else {
+                let err = v6.expect_err("no address of the family is stated");
+                assert_eq!(err.as_error_type(), "decode");
+            }
+        }
+    }
+
+    #[test]
+    fn an_older_scaleway_instance_is_read_from_its_single_address_fields() {
+        let body = br#"{"public_ip":{"address":"1.2.3.4"},"ipv6":{"address":"2a01:4f8::1"}}"#;
+
+        let v6 = MetadataProvider::Scaleway
+            .response_decode(body, Family::V6)
+            .expect("the ipv6 field must be read");
+        assert_eq!(v6.to_string(), "2a01:4f8::1");
+    }
+
+    #[test]
+    fn a_metadata_provider_is_never_asked_unless_named_and_trusted_once_it_is() {
+        for provider in <MetadataProvider as VariantArray>::VARIANTS {
+            let provider = Provider::from(*provider);
+
+            assert!(
+                !provider.enabled_by_default(),
+                "{provider} is on by default"
+            );
+            assert_eq!(provider.default_trust_factor(), TrustFactorAuthority::HIG);
+            assert!(provider.terms().is_some(), "{provider} has no terms");
+            assert!(matches!(provider.request(), Request::Metadata));
+        }
+    }
+
+    #[test]
+    fn a_node_provider_is_never_asked_unless_named_and_reads_the_key_it_names() {
+        for provider in <NodeProvider as VariantArray>::VARIANTS {
+            assert_eq!(
+                provider.request_uri(),
+                provider.key().to_string(),
+                "{provider} names another key than it reads"
+            );
+
+            let provider = Provider::from(*provider);
+            assert!(
+                !provider.enabled_by_default(),
+                "{provider} is on by default"
+            );
+            assert_eq!(provider.default_trust_factor(), TrustFactorAuthority::LOW);
+        }
+    }
+
+    #[test]
+    fn the_uri_of_every_dns_provider_names_the_server_and_the_question() {
+        for provider in <DnsProvider as VariantArray>::VARIANTS {
+            let lookup = provider.lookup();
+            let uri = provider.request_uri();
+
+            assert!(
+                uri.starts_with(&format!("dns://{}", lookup.server)),
+                "{uri} asks elsewhere"
+            );
+            assert!(
+                uri.contains(&format!("/{}?", lookup.name)),
+                "{uri} asks another name"
+            );
+            assert_eq!(uri.contains(":853/"), lookup.tls, "{uri} hides its port");
+            assert!(!provider.summary().is_empty(), "{provider} has no summary");
+        }
+    }
+
+    #[test]
+    fn a_dns_provider_is_never_asked_unless_named_and_never_decides_alone() {
+        for provider in <DnsProvider as VariantArray>::VARIANTS {
+            let provider = Provider::from(*provider);
+
+            assert!(
+                !provider.enabled_by_default(),
+                "{provider} is on by default"
+            );
+            assert_eq!(provider.default_trust_factor(), TrustFactorAuthority::LOW);
+        }
+    }
+
     #[test]
     fn a_provider_name_is_matched_whatever_its_case() {
         use std::str::FromStr;
@@ -347,24 +1546,36 @@ mod tests {
     #[test]
     fn the_bigdatacloud_documented_field_name_is_not_the_one_it_sends() {
         HttpProvider::BigDataCloud
-            .response_decode(br#"{"ip":"1.2.3.4"}"#)
+            .response_decode(br#"{"ip":"1.2.3.4"}"#, Family::V4)
             .expect_err("`ip` is what the docs show, not what the endpoint sends");
     }
 
     #[test]
     fn a_body_that_is_not_json_reports_what_it_saw() {
         let err = HttpProvider::HttpBin
-            .response_decode(b"<html>502 Bad Gateway</html>")
+            .response_decode(b"<html>502 Bad Gateway</html>", Family::V4)
             .expect_err("HTML must not decode as an address");
 
         assert_eq!(err.as_error_type(), "decode");
         assert!(err.to_string().contains("502 Bad Gateway"));
     }
 
+    #[test]
+    fn a_text_body_is_the_address_and_nothing_else() {
+        let decoded = decode_text(b"  2606:4700::1111\r\n").expect("a bare address must decode");
+        assert_eq!(decoded.to_string(), "2606:4700::1111");
+
+        let err = HttpProvider::IcanHazIp
+            .response_decode(b"<html>429 Too Many Requests</html>", Family::V4)
+            .expect_err("HTML must not decode as an address");
+        assert_eq!(err.as_error_type(), "decode");
+        assert!(err.to_string().contains("429 Too Many Requests"));
+    }
+
     #[test]
     fn invalid_utf8_does_not_panic() {
         let err = HttpProvider::MyIpWtf
-            .response_decode(&[0xff, 0xfe, 0x00, 0x80])
+            .response_decode(&[0xff, 0xfe, 0x00, 0x80], Family::V4)
             .expect_err("garbage must not decode as an address");
 
         assert!(!err.to_string().is_empty());
@@ -0,0 +1,230 @@
+use {
+    crate::pubip::{Family, error::FetchError},
+    std::{borrow::Cow, collections::BTreeMap, fmt, net::IpAddr, str::FromStr},
+};
+
+/// Where on the Node another tool wrote an address down: an annotation or a
+/// label, under its full key.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Key {
+    pub field: Field,
+    pub name: Cow<'static, str>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Field {
+    Annotation,
+    Label,
+}
+
+impl Key {
+    pub const fn annotation(name: &'static str) -> Self {
+        Self {
+            field: Field::Annotation,
+            name: Cow::Borrowed(name),
+        }
+    }
+}
+
+impl Field {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Annotation => "annotation",
+            Self::Label => "label",
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.field, self.name)
+    }
+}
+
+/// `annotation:KEY` or `label:KEY`, the key being whatever Kubernetes takes:
+/// an optional DNS prefix and a slash, then a name.
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((scheme, name)) = s.split_once(':') else {
+            return Err(format!("`{s}` is not annotation:KEY or label:KEY"));
+        };
+
+        let field = match scheme.to_ascii_lowercase().as_str() {
+            "annotation" => Field::Annotation,
+            "label" => Field::Label,
+            _ => return Err(format!("`{scheme}` is neither annotation nor label")),
+        };
+
+        let (prefix, short) = name.rsplit_once('/').unwrap_or(("", name));
+        let valid = |part: &str, extra: &[char]| {
+            part.chars()
+                .all(|c| c.is_ascii_alphanumeric() || extra.contains(&c))
+        };
+
+        if short.is_empty() || short.len() > 63 || !valid(short, &['-', '_', '.']) {
+            return Err(format!("`{name}` is not a key Kubernetes takes"));
+        }
+        if name.contains('/') && (prefix.is_empty() || !valid(prefix, &['-', '.'])) {
+            return Err(format!("the prefix of `{name}` is not a DNS subdomain"));
+        }
+
+        Ok(Self {
+            field,
+            name: Cow::Owned(name.to_owned()),
+        })
+    }
+}
+
+/// The annotations and labels of the Node, as [`crate::node::Manager`] last
+/// read them.
+///
+/// Read afresh at the start of every tick, so a value another tool changes
+/// is seen by the next round. Empty in `fckloud test`, which has no Node to
+/// read.
+#[derive(Clone, Default, Debug)]
+pub struct Published {
+    annotations: BTreeMap<String, String>,
+    labels: BTreeMap<String, String>,
+}
+
+impl Published {
+    pub const fn new(
+        annotations: BTreeMap<String, String>,
+        labels: BTreeMap<String, String>,
+    ) -> Self {
+        Self {
+            annotations,
+            labels,
+        }
+    }
+
+    fn get(&self, key: &Key) -> Option<&str> {
+        let values = match key.field {
+            Field::Annotation => &self.annotations,
+            Field::Label => &self.labels,
+        };
+
+        values.get(key.name.as_ref()).map(String::as_str)
+    }
+}
+
+/// The address the Node carries under the key in the family.
+///
+/// A value may list several, comma-separated, as k3s does on a dual-stack
+/// node; the first of the family is the one. A key that is missing is
+/// `not_published`, which is what a node the tool has not got to yet looks
+/// like, and one holding something other than addresses is `decode`.
+pub fn query(key: &Key, published: &Published, family: Family) -> Result<IpAddr, FetchError> {
+    let Some(value) = published.get(key) else {
+        return Err(FetchError::NotPublished(key.to_string()));
+    };
+
+    let decoded: Result<Vec<IpAddr>, _> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(IpAddr::from_str)
+        .collect();
+
+    let decode_error = |source| FetchError::Decode {
+        body: value.to_owned(),
+        source,
+    };
+
+    decoded
+        .map_err(|err| decode_error(err.into()))?
+        .into_iter()
+        .find(|ip_addr| Family::of(ip_addr) == family)
+        .ok_or_else(|| decode_error(format!("{key} states no {family} address").into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn published(annotations: &[(&str, &str)], labels: &[(&str, &str)]) -> Published {
+        let owned = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(key, value)| ((*key).to_owned(), (*value).to_owned()))
+                .collect()
+        };
+
+        Published::new(owned(annotations), owned(labels))
+    }
+
+    fn key(s: &str) -> Key {
+        Key::from_str(s).unwrap_or_else(|err| panic!("`{s}`: {err}"))
+    }
+
+    #[test]
+    fn a_dual_stack_value_answers_each_family_with_its_own_address() {
+        let node = published(&[("k3s.io/external-ip", "1.2.3.4, 2a01:4f8::1")], &[]);
+        let external = key("annotation:k3s.io/external-ip");
+
+        let v4 = query(&external, &node, Family::V4).expect("an IPv4 address is listed");
+        let v6 = query(&external, &node, Family::V6).expect("an IPv6 address is listed");
+
+        assert_eq!(v4.to_string(), "1.2.3.4");
+        assert_eq!(v6.to_string(), "2a01:4f8::1");
+    }
+
+    #[test]
+    fn an_annotation_and_a_label_of_one_name_are_told_apart() {
+        let node = published(
+            &[("example.com/ip", "1.2.3.4")],
+            &[("example.com/ip", "5.6.7.8")],
+        );
+
+        let label = query(&key("label:example.com/ip"), &node, Family::V4)
+            .expect("the label holds an address");
+        assert_eq!(label.to_string(), "5.6.7.8");
+    }
+
+    #[test]
+    fn a_missing_or_unreadable_value_is_not_an_answer() {
+        let node = published(&[("k3s.io/external-ip", "not-an-address")], &[]);
+
+        let err = query(&key("annotation:k3s.io/external-ip"), &node, Family::V4)
+            .expect_err("the value is not an address");
+        assert_eq!(err.as_error_type(), "decode");
+
+        let err =
+            query(&key("label:k3s.io/external-ip"), &node, Family::V4).expect_err("no such label");
+        assert_eq!(err.as_error_type(), "not_published");
+        assert!(err.to_string().contains("label:k3s.io/external-ip"));
+
+        let node = published(&[("k3s.io/external-ip", "1.2.3.4")], &[]);
+        let err = query(&key("annotation:k3s.io/external-ip"), &node, Family::V6)
+            .expect_err("no IPv6 address is listed");
+        assert_eq!(err.as_error_type(), "decode");
+    }
+
+    #[test]
+    fn a_key_is_what_kubernetes_takes() {
+        assert_eq!(
+            key("Annotation:flannel.alpha.coreos.com/public-ip"),
+            Key::annotation("flannel.alpha.coreos.com/public-ip"),
+        );
+        assert_eq!(key("label:public-ip").to_string(), "label:public-ip");
+
+        for wrong in [
+            "k3s.io/external-ip",
+            "taint:k3s.io/external-ip",
+            "annotation:",
+            "annotation:k3s.io/",
+            "annotation:/external-ip",
+            "label:has space",
+        ] {
+            assert!(Key::from_str(wrong).is_err(), "`{wrong}` is taken");
+        }
+    }
+}
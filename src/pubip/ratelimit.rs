@@ -1,17 +1,59 @@
 use {
-    crate::pubip::HttpProvider,
+    crate::pubip::{Family, Provider},
     std::{
         collections::HashMap,
-        time::{Duration, Instant},
+        net::IpAddr,
+        time::{Duration, Instant, SystemTime},
     },
 };
 
+/// How old the last answer of a provider still serving out its gap may be
+/// and still be weighed in a round that does not ask it. Longer than any gap
+/// a provider publishes, so that one answering every time it is asked is
+/// never missing from a round, and short enough that an address that moved
+/// is not vouched for by a provider that saw it before it did for long.
+pub const CARRY_FORWARD: Duration = Duration::from_mins(5);
+
+/// The gap a provider that pushed back without naming a wait is first held
+/// to, and the shortest one a stretched gap decays to before it is dropped.
+pub const MIN_STRETCH: Duration = Duration::from_mins(1);
+
+/// The longest a provider is left alone on its own say-so. A `Retry-After`
+/// is the provider's to set, and one a week out is a provider gone for good
+/// as far as this round's trust is concerned, which is for the operator to
+/// decide rather than a header.
+pub const MAX_STRETCH: Duration = Duration::from_hours(6);
+
+/// When a provider was last asked, and the gap it stretched its own limit to
+/// by pushing back since.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Asked {
+    pub at: Instant,
+    pub stretched: Option<Duration>,
+}
+
+impl Asked {
+    pub const fn at(at: Instant) -> Self {
+        Self {
+            at,
+            stretched: None,
+        }
+    }
+}
+
+/// What a provider last answered over one family, and when.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Answered {
+    pub ip_addr: IpAddr,
+    pub at: Instant,
+}
+
 /// What a round may ask for, once the providers still serving out their gap
 /// have been set aside.
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct Split {
-    pub allowed: Vec<HttpProvider>,
-    pub holding: Vec<(HttpProvider, Duration)>,
+    pub allowed: Vec<Provider>,
+    pub holding: Vec<(Provider, Duration)>,
 }
 
 /// Decides which providers may be asked, and how long the rest must wait.
@@ -19,9 +61,9 @@ pub struct Split {
 /// Pure on purpose, like the consensus beside it: a gap that has or has not
 /// elapsed is arithmetic, and a test should not need a clock to exercise it.
 pub fn split(
-    providers: &[HttpProvider],
-    gaps: &HashMap<HttpProvider, Duration>,
-    asked: &HashMap<HttpProvider, Instant>,
+    providers: &[Provider],
+    gaps: &HashMap<Provider, Duration>,
+    asked: &HashMap<Provider, Asked>,
     now: Instant,
     honour: Honour,
 ) -> Split {
@@ -45,11 +87,12 @@ pub enum Honour {
 }
 
 /// How long the provider still owes before it may be asked again, [`None`]
-/// when it may be asked now.
+/// when it may be asked now. A gap the provider stretched by pushing back
+/// counts for as long as it is the longer one.
 fn remaining(
-    provider: HttpProvider,
-    gaps: &HashMap<HttpProvider, Duration>,
-    asked: &HashMap<HttpProvider, Instant>,
+    provider: Provider,
+    gaps: &HashMap<Provider, Duration>,
+    asked: &HashMap<Provider, Asked>,
     now: Instant,
     honour: Honour,
 ) -> Option<Duration> {
@@ -57,32 +100,92 @@ fn remaining(
         return None;
     }
 
-    let gap = gap_of(provider, gaps)?;
     let last = asked.get(&provider)?;
+    let gap = gap_of(provider, gaps).max(last.stretched)?;
 
     // `saturating_sub` and not a comparison: a clock that went backwards
     // should let a provider through, not hold it for the age of the universe.
-    let elapsed = now.saturating_duration_since(*last);
+    let elapsed = now.saturating_duration_since(last.at);
     gap.checked_sub(elapsed).filter(|left| !left.is_zero())
 }
 
+/// The answers a round over the family carries forward: what every provider
+/// still serving out its gap last answered, when that is no older than
+/// `max_age`. One that never answered, or not lately, carries nothing.
+pub fn carried(
+    holding: &[(Provider, Duration)],
+    answered: &HashMap<(Provider, Family), Answered>,
+    family: Family,
+    now: Instant,
+    max_age: Duration,
+) -> Vec<(Provider, IpAddr)> {
+    holding
+        .iter()
+        .filter_map(|(provider, _)| {
+            let last = answered.get(&(*provider, family))?;
+            let fresh = now.saturating_duration_since(last.at) <= max_age;
+
+            (fresh && !max_age.is_zero()).then_some((*provider, last.ip_addr))
+        })
+        .collect()
+}
+
 /// The gap the provider asks for, or the one the operator put in its place.
-pub fn gap_of(provider: HttpProvider, gaps: &HashMap<HttpProvider, Duration>) -> Option<Duration> {
+pub fn gap_of(provider: Provider, gaps: &HashMap<Provider, Duration>) -> Option<Duration> {
     gaps.get(&provider)
         .copied()
         .or_else(|| provider.rate_limit())
         .filter(|gap| !gap.is_zero())
 }
 
+/// The gap a provider that pushed back is held to from now on: the wait it
+/// named if it named one, or else twice the gap it was held to, and never
+/// less than the gap it asks for anyway.
+pub fn stretch(
+    held: Option<Duration>,
+    gap: Option<Duration>,
+    retry_after: Option<Duration>,
+) -> Duration {
+    let stretched = match retry_after {
+        Some(wait) => wait,
+        None => held.map_or(MIN_STRETCH, |held| held.saturating_mul(2)),
+    };
+
+    stretched.max(gap.unwrap_or_default()).min(MAX_STRETCH)
+}
+
+/// The gap a provider that answered is held to after a stretch: half the
+/// stretched one, until that falls to the gap it asks for anyway, or below
+/// [`MIN_STRETCH`], and the stretch is over.
+pub fn decay(stretched: Duration, gap: Option<Duration>) -> Option<Duration> {
+    let halved = stretched / 2;
+    (halved > gap.unwrap_or_default() && halved >= MIN_STRETCH).then_some(halved)
+}
+
+/// The wait a `Retry-After` names, RFC 9110 section 10.2.3: a number of
+/// seconds, or the date to come back at. A date already past names none.
+pub fn retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    httpdate::parse_http_date(value)
+        .ok()?
+        .duration_since(now)
+        .ok()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use {super::*, crate::pubip::HttpProvider};
 
-    const LIMITED: HttpProvider = HttpProvider::MyIpWtf;
-    const FREE: HttpProvider = HttpProvider::Ipify;
+    const LIMITED: Provider = Provider::Http(HttpProvider::MyIpWtf);
+    const FREE: Provider = Provider::Http(HttpProvider::Ipify);
 
-    fn at(now: Instant, ago: Duration) -> Instant {
-        now.checked_sub(ago).expect("test instant must exist")
+    fn at(now: Instant, ago: Duration) -> Asked {
+        Asked::at(now.checked_sub(ago).expect("test instant must exist"))
     }
 
     #[test]
@@ -103,7 +206,7 @@ mod tests {
     #[test]
     fn a_provider_without_a_limit_is_always_allowed() {
         let now = Instant::now();
-        let asked = HashMap::from([(FREE, now)]);
+        let asked = HashMap::from([(FREE, Asked::at(now))]);
         let out = split(&[FREE], &HashMap::new(), &asked, now, Honour::Limits);
 
         assert_eq!(out.allowed, vec![FREE]);
@@ -144,7 +247,7 @@ mod tests {
     fn an_override_can_add_a_gap_where_the_provider_asked_for_none() {
         let now = Instant::now();
         let gaps = HashMap::from([(FREE, Duration::from_mins(5))]);
-        let asked = HashMap::from([(FREE, now)]);
+        let asked = HashMap::from([(FREE, Asked::at(now))]);
 
         assert!(
             split(&[FREE], &gaps, &asked, now, Honour::Limits)
@@ -157,7 +260,7 @@ mod tests {
     fn a_zero_override_lifts_the_gap_entirely() {
         let now = Instant::now();
         let gaps = HashMap::from([(LIMITED, Duration::ZERO)]);
-        let asked = HashMap::from([(LIMITED, now)]);
+        let asked = HashMap::from([(LIMITED, Asked::at(now))]);
 
         assert_eq!(
             split(&[LIMITED], &gaps, &asked, now, Honour::Limits).allowed,
@@ -165,11 +268,96 @@ mod tests {
         );
     }
 
+    #[test]
+    fn a_stretched_gap_holds_a_provider_beyond_its_own() {
+        let now = Instant::now();
+        let asked = HashMap::from([(
+            FREE,
+            Asked {
+                stretched: Some(Duration::from_mins(2)),
+                ..at(now, Duration::from_secs(90))
+            },
+        )]);
+
+        let out = split(&[FREE], &HashMap::new(), &asked, now, Honour::Limits);
+        assert_eq!(out.holding, vec![(FREE, Duration::from_secs(30))]);
+    }
+
+    #[test]
+    fn a_pushback_stretches_the_gap_and_answers_decay_it_back() {
+        let minute = Some(Duration::from_mins(1));
+
+        // Named, the wait is the provider's, though never below its own gap.
+        assert_eq!(
+            stretch(None, None, Some(Duration::from_secs(90))),
+            Duration::from_secs(90)
+        );
+        assert_eq!(
+            stretch(None, minute, Some(Duration::from_secs(5))),
+            Duration::from_mins(1)
+        );
+        assert_eq!(
+            stretch(None, None, Some(Duration::from_hours(100))),
+            MAX_STRETCH
+        );
+
+        // Unnamed, it doubles whatever the provider was held to.
+        assert_eq!(stretch(None, None, None), MIN_STRETCH);
+        assert_eq!(stretch(minute, minute, None), Duration::from_mins(2));
+        assert_eq!(
+            stretch(Some(Duration::from_mins(2)), minute, None),
+            Duration::from_mins(4)
+        );
+
+        assert_eq!(
+            decay(Duration::from_mins(8), minute),
+            Some(Duration::from_mins(4))
+        );
+        assert_eq!(decay(Duration::from_mins(2), minute), None);
+        assert_eq!(decay(Duration::from_secs(90), None), None);
+    }
+
+    #[test]
+    fn a_retry_after_is_seconds_or_a_date() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2026 07:28:00 GMT")
+            .expect("test date must parse");
+
+        assert_eq!(retry_after("120", now), Some(Duration::from_mins(2)));
+        assert_eq!(
+            retry_after("Wed, 21 Oct 2026 07:30:00 GMT", now),
+            Some(Duration::from_mins(2))
+        );
+        assert_eq!(retry_after("Wed, 21 Oct 2026 07:00:00 GMT", now), None);
+        assert_eq!(retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn a_held_provider_carries_its_last_answer_until_it_is_too_old() {
+        let now = Instant::now();
+        let ip_addr = IpAddr::from([192, 0, 2, 1]);
+        let holding = [(LIMITED, Duration::from_secs(30))];
+        let answered = HashMap::from([(
+            (LIMITED, Family::V4),
+            Answered {
+                ip_addr,
+                at: now
+                    .checked_sub(Duration::from_secs(90))
+                    .expect("test instant must exist"),
+            },
+        )]);
+        let carried = |family, max_age| carried(&holding, &answered, family, now, max_age);
+
+        assert_eq!(carried(Family::V4, CARRY_FORWARD), [(LIMITED, ip_addr)]);
+        assert!(carried(Family::V6, CARRY_FORWARD).is_empty());
+        assert!(carried(Family::V4, Duration::from_mins(1)).is_empty());
+        assert!(carried(Family::V4, Duration::ZERO).is_empty());
+    }
+
     #[test]
     fn honouring_nothing_asks_every_provider_every_round() {
         let now = Instant::now();
         let gaps = HashMap::from([(FREE, Duration::from_mins(5))]);
-        let asked = HashMap::from([(LIMITED, now), (FREE, now)]);
+        let asked = HashMap::from([(LIMITED, Asked::at(now)), (FREE, Asked::at(now))]);
 
         let out = split(&[LIMITED, FREE], &gaps, &asked, now, Honour::Nothing);
 
@@ -0,0 +1,129 @@
+use {crate::pubip::error::FetchError, std::time::Duration};
+
+/// How long a provider has to answer within a round, retries included: a
+/// request's own timeout and a third again, which leaves a retry after a
+/// timed-out attempt a few seconds and still ends well inside the shortest
+/// interval a round can be run at.
+pub const BUDGET: Duration = Duration::from_secs(20);
+
+/// The wait before the first retry, doubled before each one after it.
+pub const BACKOFF: Duration = Duration::from_millis(250);
+
+/// What a retry is worth making after: a provider that gave no answer may
+/// well give one a moment later. One that answered wrong would answer just
+/// as wrong again.
+pub const RETRY_ON: [&str; 2] = ["timeout", "unreachable"];
+
+/// How a provider that failed is asked again within the round.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Policy {
+    /// Asked again at most this many times after the first; zero asks once.
+    pub retries: u32,
+    pub backoff: Duration,
+    /// The [`FetchError::as_error_type`] of every failure worth a retry.
+    pub on: Vec<&'static str>,
+}
+
+impl Default for Policy {
+    /// Asks once.
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            backoff: BACKOFF,
+            on: RETRY_ON.to_vec(),
+        }
+    }
+}
+
+/// The error type named, as [`FetchError::as_error_type`] has it.
+pub fn parse_kind(s: &str) -> Result<&'static str, String> {
+    FetchError::TYPES
+        .into_iter()
+        .find(|kind| kind.eq_ignore_ascii_case(s.trim()))
+        .ok_or_else(|| {
+            format!(
+                "`{s}` is not an error type: {}",
+                FetchError::TYPES.join(", ")
+            )
+        })
+}
+
+impl Policy {
+    /// Whether a provider that has been retried `retried` times already is
+    /// worth another attempt after failing so. An HTTP status is only ever
+    /// worth one when the server owned up to the fault: a 4xx comes back
+    /// the same, and a provider that is throttled asked for fewer requests,
+    /// not more.
+    pub fn retries_after(&self, err: &FetchError, retried: u32) -> bool {
+        retried < self.retries
+            && self.on.contains(&err.as_error_type())
+            && err.status_code().is_none_or(|status| status >= 500)
+            && !err.throttled()
+    }
+
+    /// The wait before retry number `retry`, counting from one.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(1 << retry.saturating_sub(1).min(16))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, reqwest::StatusCode};
+
+    fn policy(retries: u32, on: &[&'static str]) -> Policy {
+        Policy {
+            retries,
+            backoff: Duration::from_millis(100),
+            on: on.to_vec(),
+        }
+    }
+
+    #[test]
+    fn a_failure_is_retried_when_it_is_one_named_and_retries_are_left() {
+        let twice = policy(2, &RETRY_ON);
+        let refused = FetchError::Unreachable("connection refused".into());
+
+        assert!(twice.retries_after(&refused, 0));
+        assert!(twice.retries_after(&FetchError::Timeout, 1));
+        assert!(!twice.retries_after(&refused, 2));
+        assert!(!policy(0, &RETRY_ON).retries_after(&refused, 0));
+
+        let wrong = FetchError::Decode {
+            body: "<html>".into(),
+            source: "not an address".into(),
+        };
+        assert!(!twice.retries_after(&wrong, 0));
+    }
+
+    #[test]
+    fn only_a_server_fault_is_worth_asking_again_over_http() {
+        let on_status = policy(1, &["http_status"]);
+
+        let status = |status, retry_after| FetchError::HttpStatus {
+            status,
+            retry_after,
+        };
+
+        assert!(on_status.retries_after(&status(StatusCode::BAD_GATEWAY, None), 0));
+        assert!(!on_status.retries_after(&status(StatusCode::TOO_MANY_REQUESTS, None), 0));
+        assert!(!on_status.retries_after(&status(StatusCode::NOT_FOUND, None), 0));
+        assert!(!on_status.retries_after(
+            &status(
+                StatusCode::SERVICE_UNAVAILABLE,
+                Some(Duration::from_secs(30))
+            ),
+            0
+        ));
+    }
+
+    #[test]
+    fn the_backoff_doubles_with_every_retry() {
+        let backoff = policy(3, &RETRY_ON);
+
+        assert_eq!(backoff.backoff(1), Duration::from_millis(100));
+        assert_eq!(backoff.backoff(2), Duration::from_millis(200));
+        assert_eq!(backoff.backoff(3), Duration::from_millis(400));
+    }
+}
@@ -1,7 +1,6 @@
 use {
-    crate::pubip::{HttpProvider, TrustFactorAuthority},
+    crate::pubip::{Catalog, HttpProvider, Provider, TrustFactorAuthority, catalog::Protocol},
     std::{cmp::Ordering, fmt, str::FromStr},
-    strum::VariantArray,
 };
 
 /// A `MAJOR.MINOR.PATCH` triple, ordered the way releases are.
@@ -54,8 +53,17 @@ pub enum Set {
     /// A released version, reproducing what that version asked by default.
     /// The only kind that pins.
     Version(Version),
-    /// Every provider there is, the ones off by default included.
+    /// Every provider out on the Internet, the ones off by default included.
+    /// Not those answering from the node's own network, its cloud or its Node
+    /// object: a metadata service alone carries enough trust to confirm an
+    /// address, and what the host says of itself is no outside opinion.
     All,
+    /// The router, asked over NAT-PMP or `UPnP`, and the node's own interfaces.
+    Local,
+    /// The cloud's metadata services.
+    Metadata,
+    /// What the Node object carries, built in or declared.
+    Node,
     /// What this build asks when nothing is said.
     Default,
     /// Every provider on by default carrying at least this trust factor.
@@ -90,24 +98,29 @@ impl Set {
 
     /// The providers the set stands for.
     ///
-    /// Only [`Set::All`] and a provider named outright reach one that is off by
-    /// default. Every other set leaves it where the defaults put it, which is
-    /// what stops a broad name from quietly re-enabling something withdrawn for
-    /// misbehaving.
-    pub fn members(self, tfa: &TrustFactorAuthority) -> Vec<HttpProvider> {
-        let all = <HttpProvider as VariantArray>::VARIANTS;
+    /// Only [`Set::All`], a set naming a kind of provider and a provider named
+    /// outright reach one that is off by default. Every other set leaves it
+    /// where the defaults put it, which is what stops a broad name from
+    /// quietly re-enabling something withdrawn for misbehaving.
+    ///
+    /// A declared provider joins every set but a version: what a release asked
+    /// cannot depend on a file it never saw. A provider that takes a key
+    /// joins none until it has been given one.
+    pub fn members(self, catalog: Catalog, tfa: &TrustFactorAuthority) -> Vec<Provider> {
+        let all = catalog.all().filter(|p| catalog.can_ask(*p));
 
         match self {
-            Self::Version(version) => resolve(version).to_vec(),
-            Self::All => all.to_vec(),
-            Self::Default => all
+            Self::Version(version) => resolve(version)
                 .iter()
                 .copied()
-                .filter(|p| p.enabled_by_default())
+                .map(Provider::Http)
                 .collect(),
+            Self::All => all.filter(|p| outside(*p)).collect(),
+            Self::Local => all.filter(|p| matches!(p, Provider::Local(_))).collect(),
+            Self::Metadata => all.filter(|p| matches!(p, Provider::Metadata(_))).collect(),
+            Self::Node => all.filter(|p| on_the_node(*p)).collect(),
+            Self::Default => all.filter(|p| p.enabled_by_default()).collect(),
             Self::Trust(least) => all
-                .iter()
-                .copied()
                 .filter(|p| p.enabled_by_default() && tfa.trust_factor(*p) >= least)
                 .collect(),
         }
@@ -115,20 +128,38 @@ impl Set {
 
     /// The providers this set left behind only because they are off by default.
     /// Worth saying out loud: a broad name not covering everything surprises.
-    pub fn skipped(self, tfa: &TrustFactorAuthority) -> Vec<HttpProvider> {
-        if matches!(self, Self::All | Self::Version(_)) {
+    pub fn skipped(self, catalog: Catalog, tfa: &TrustFactorAuthority) -> Vec<Provider> {
+        if !matches!(self, Self::Default | Self::Trust(_)) {
             return Vec::new();
         }
 
-        let taken = self.members(tfa);
-        <HttpProvider as VariantArray>::VARIANTS
-            .iter()
-            .copied()
+        let taken = self.members(catalog, tfa);
+        catalog
+            .all()
             .filter(|p| !p.enabled_by_default() && !taken.contains(p))
             .collect()
     }
 }
 
+/// Whether the provider is somebody out on the Internet telling us what it
+/// sees, rather than the node, its network or its cloud.
+fn outside(provider: Provider) -> bool {
+    match provider {
+        Provider::Http(_) | Provider::Stun(_) | Provider::Dns(_) => true,
+        Provider::Declared(_) => !on_the_node(provider),
+        Provider::Local(_) | Provider::Metadata(_) | Provider::Node(_) => false,
+    }
+}
+
+/// Whether the provider is read off the Node object.
+fn on_the_node(provider: Provider) -> bool {
+    match provider {
+        Provider::Node(_) => true,
+        Provider::Declared(declared) => matches!(declared.protocol, Protocol::Node(_)),
+        _ => false,
+    }
+}
+
 /// The newest changepoint at or below the given version.
 fn resolve(version: Version) -> &'static [HttpProvider] {
     CHANGEPOINTS
@@ -183,17 +214,20 @@ impl FromStr for Version {
 }
 
 /// One name given to `--providers`.
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Token {
-    Provider(HttpProvider),
+    /// A provider's name, looked up once the providers file has been read:
+    /// until then, nobody can tell a typo from a declared provider.
+    Provider(String),
     Set(Set),
 }
 
-/// Turns one `--providers` name into a provider or a set of them.
+/// Turns one `--providers` name into a set of providers, or the name of one.
 pub fn parse_token(name: &str) -> Result<Token, Rejected> {
-    match HttpProvider::from_str(name) {
-        Ok(provider) => Ok(Token::Provider(provider)),
-        Err(_) => parse(name).map(Token::Set),
+    match parse(name) {
+        Ok(set) => Ok(Token::Set(set)),
+        Err(Rejected::Unknown(name)) => Ok(Token::Provider(name)),
+        Err(err) => Err(err),
     }
 }
 
@@ -207,9 +241,13 @@ pub fn parse(name: &str) -> Result<Set, Rejected> {
     }
 
     // `all` and `trust1` name every trust factor there is and are still not the
-    // same set: only `all` reaches a provider that is off by default.
+    // same set: only `all`, like the sets naming a kind, reaches a provider
+    // that is off by default.
     let set = match name.to_ascii_lowercase().as_str() {
         "all" => Set::All,
+        "local" => Set::Local,
+        "metadata" => Set::Metadata,
+        "node" => Set::Node,
         "default" => Set::Default,
         "trust1" | "low" => Set::Trust(TrustFactorAuthority::LOW),
         "trust2" | "med" | "medium" => Set::Trust(TrustFactorAuthority::MED),
@@ -222,16 +260,19 @@ pub fn parse(name: &str) -> Result<Set, Rejected> {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use {
+        super::*,
+        crate::pubip::{LocalProvider, MetadataProvider, NodeProvider, StunProvider},
+    };
 
     fn version(s: &str) -> Version {
         Version::from_str(s).unwrap_or_else(|()| panic!("`{s}` must parse as a version"))
     }
 
-    fn members(name: &str) -> Vec<HttpProvider> {
+    fn members(name: &str) -> Vec<Provider> {
         parse(name)
             .unwrap_or_else(|err| panic!("`{name}` must resolve: {err}"))
-            .members(&TrustFactorAuthority::default())
+            .members(Catalog::default(), &TrustFactorAuthority::default())
     }
 
     #[test]
@@ -265,31 +306,53 @@ mod tests {
 
     #[test]
     fn only_all_reaches_a_provider_that_is_off_by_default() {
-        assert!(members("all").contains(&HttpProvider::HttpBin));
+        assert!(members("all").contains(&HttpProvider::HttpBin.into()));
 
         for name in ["trust1", "low", "default", "trust2", "hig"] {
             assert!(
-                !members(name).contains(&HttpProvider::HttpBin),
+                !members(name).contains(&HttpProvider::HttpBin.into()),
                 "`{name}` must not enable a provider that is off by default",
             );
         }
     }
 
+    #[test]
+    fn all_is_every_outside_provider_and_the_rest_are_named_by_kind() {
+        let all = members("all");
+        assert!(all.contains(&StunProvider::GoogleStun.into()));
+        assert!(!all.contains(&LocalProvider::Interface.into()));
+        assert!(!all.contains(&MetadataProvider::Hetzner.into()));
+        assert!(!all.contains(&NodeProvider::K3s.into()));
+
+        assert_eq!(
+            members("local"),
+            [
+                LocalProvider::Gateway.into(),
+                LocalProvider::Interface.into()
+            ]
+        );
+        assert!(members("metadata").contains(&MetadataProvider::Hetzner.into()));
+        assert!(members("node").contains(&NodeProvider::K3s.into()));
+        assert!(!members("node").contains(&HttpProvider::Ipify.into()));
+    }
+
     #[test]
     fn a_trust_set_takes_that_factor_and_above() {
-        assert_eq!(members("hig"), vec![HttpProvider::Ipify]);
+        assert_eq!(members("hig"), vec![HttpProvider::Ipify.into()]);
 
         let med = members("med");
-        assert!(med.contains(&HttpProvider::Ipify));
-        assert!(med.contains(&HttpProvider::SeeIp));
-        assert!(!med.contains(&HttpProvider::MyIpLa));
+        assert!(med.contains(&HttpProvider::Ipify.into()));
+        assert!(med.contains(&HttpProvider::SeeIp.into()));
+        assert!(!med.contains(&HttpProvider::MyIpLa.into()));
     }
 
     #[test]
     fn only_a_version_pins() {
         assert!(parse("v1.5").expect("a release must parse").pins());
 
-        for name in ["all", "default", "trust1", "med", "hig"] {
+        for name in [
+            "all", "local", "metadata", "node", "default", "trust1", "med", "hig",
+        ] {
             assert!(
                 !parse(name).expect("a set must parse").pins(),
                 "{name} pins"
@@ -0,0 +1,151 @@
+use {
+    crate::pubip::{Family, Uplink, client, error::FetchError, hosts, tls},
+    std::{
+        io,
+        net::SocketAddr,
+        sync::{Arc, LazyLock},
+        time::Duration,
+    },
+    tokio::{
+        net::{TcpSocket, TcpStream, UdpSocket},
+        time::{Instant, timeout, timeout_at},
+    },
+    tokio_rustls::{
+        TlsConnector,
+        client::TlsStream,
+        rustls::{ClientConfig, pki_types::ServerName},
+    },
+};
+
+// The providers that are not asked over HTTP build their own sockets, bound
+// to the uplink the way `client` binds reqwest's, and time out within the
+// same budget.
+
+// The first retransmission timeout of RFC 8489, section 6.2.1, doubled every
+// time it runs out; DNS has no number of its own and is well served by it.
+pub const INITIAL_RTO: Duration = Duration::from_millis(500);
+
+// Whatever an Ethernet path carries. Neither STUN nor DNS without EDNS ever
+// sends more, and a longer datagram would be cut short without a word.
+pub const LARGEST_DATAGRAM: usize = 1500;
+
+// Built once, the way reqwest's are, see `tls`.
+static TLS: LazyLock<Arc<ClientConfig>> =
+    LazyLock::new(|| Arc::new(tls::config(tls::Handshake::default())));
+
+/// The first address of the host in the family, looked up the way the
+/// HTTP providers' hosts are.
+pub async fn resolve(
+    host: &str,
+    port: u16,
+    family: Family,
+    uplink: &Uplink,
+) -> Result<SocketAddr, FetchError> {
+    let addrs = hosts::lookup(host, family, uplink).await?;
+    Ok(SocketAddr::new(addrs[0], port))
+}
+
+/// Sends the request until an answer to it arrives, at a timeout that
+/// doubles each time, for as long as the round lets it.
+///
+/// The socket is connected, so the kernel drops whatever does not come from
+/// the peer; what does come from it and is not an answer to this request is
+/// dropped here.
+pub async fn exchange_udp(
+    peer: SocketAddr,
+    uplink: &Uplink,
+    request: &[u8],
+    answers: impl Fn(&[u8]) -> bool,
+) -> Result<Vec<u8>, FetchError> {
+    let socket = bind_udp(uplink, Family::of(&peer.ip())).await?;
+    socket.connect(peer).await?;
+
+    let mut rto = INITIAL_RTO;
+    let mut buf = [0; LARGEST_DATAGRAM];
+
+    loop {
+        socket.send(request).await?;
+        let deadline = Instant::now() + rto;
+
+        while let Ok(received) = timeout_at(deadline, socket.recv(&mut buf)).await {
+            let message = &buf[..received?];
+            if answers(message) {
+                return Ok(message.to_vec());
+            }
+        }
+
+        rto = rto.saturating_mul(2);
+    }
+}
+
+/// A UDP socket leaving by the uplink over the family, not yet connected.
+pub async fn bind_udp(uplink: &Uplink, family: Family) -> Result<UdpSocket, FetchError> {
+    let socket = UdpSocket::bind((uplink.local_address(family), 0)).await?;
+    bind_udp_interface(&socket, uplink)?;
+    Ok(socket)
+}
+
+/// A TLS connection to the peer, its certificate checked against the host.
+pub async fn connect_tls(
+    host: &str,
+    peer: SocketAddr,
+    uplink: &Uplink,
+) -> Result<TlsStream<TcpStream>, FetchError> {
+    let socket = match Family::of(&peer.ip()) {
+        Family::V4 => TcpSocket::new_v4()?,
+        Family::V6 => TcpSocket::new_v6()?,
+    };
+
+    bind_tcp_interface(&socket, uplink)?;
+    socket.bind(SocketAddr::new(
+        uplink.local_address(Family::of(&peer.ip())),
+        0,
+    ))?;
+
+    let stream = timeout(client::CONNECT_TIMEOUT, socket.connect(peer))
+        .await
+        .map_err(|_| FetchError::Timeout)??;
+
+    let name =
+        ServerName::try_from(host.to_owned()).map_err(|err| FetchError::Unreachable(err.into()))?;
+
+    Ok(TlsConnector::from(Arc::clone(&TLS))
+        .connect(name, stream)
+        .await?)
+}
+
+#[cfg(target_os = "linux")]
+fn bind_udp_interface(socket: &UdpSocket, uplink: &Uplink) -> io::Result<()> {
+    match uplink {
+        Uplink::Interface(name) => socket.bind_device(Some(name.as_bytes())),
+        Uplink::Default | Uplink::Source(_) => Ok(()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn bind_tcp_interface(socket: &TcpSocket, uplink: &Uplink) -> io::Result<()> {
+    match uplink {
+        Uplink::Interface(name) => socket.bind_device(Some(name.as_bytes())),
+        Uplink::Default | Uplink::Source(_) => Ok(()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_udp_interface(_: &UdpSocket, uplink: &Uplink) -> io::Result<()> {
+    match uplink {
+        Uplink::Interface(name) => {
+            unreachable!("interface {name} must have been refused at startup on this platform")
+        }
+        Uplink::Default | Uplink::Source(_) => Ok(()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_tcp_interface(_: &TcpSocket, uplink: &Uplink) -> io::Result<()> {
+    match uplink {
+        Uplink::Interface(name) => {
+            unreachable!("interface {name} must have been refused at startup on this platform")
+        }
+        Uplink::Default | Uplink::Source(_) => Ok(()),
+    }
+}
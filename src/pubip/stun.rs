@@ -0,0 +1,530 @@
+use {
+    crate::pubip::{
+        Family, Uplink, client,
+        error::{Cause, FetchError, hex},
+        socket,
+    },
+    ring::rand::{SecureRandom as _, SystemRandom},
+    std::{
+        borrow::Cow,
+        fmt,
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+        str::FromStr,
+    },
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        time::timeout,
+    },
+};
+
+// RFC 8489, section 5 and section 18.
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const HEADER_LEN: usize = 20;
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const BINDING_ERROR: u16 = 0x0111;
+const ERROR_CODE: u16 = 0x0009;
+const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+type TransactionId = [u8; 12];
+
+/// A STUN server as RFC 7064 writes one: `stun:host[:port]`, or `stuns:` for
+/// STUN over TLS.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Server {
+    pub host: Cow<'static, str>,
+    pub port: u16,
+    pub tls: bool,
+}
+
+impl Server {
+    pub const PORT: u16 = 3478;
+    pub const TLS_PORT: u16 = 5349;
+
+    pub const fn new(host: &'static str, port: u16, tls: bool) -> Self {
+        Self {
+            host: Cow::Borrowed(host),
+            port,
+            tls,
+        }
+    }
+
+    /// The `network.transport` attribute value: STUN over TLS rides on TCP,
+    /// plain STUN on UDP.
+    pub const fn transport(&self) -> &'static str {
+        if self.tls { "tcp" } else { "udp" }
+    }
+}
+
+impl fmt::Display for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = if self.tls { "stuns" } else { "stun" };
+
+        if self.host.contains(':') {
+            write!(f, "{scheme}:[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{scheme}:{}:{}", self.host, self.port)
+        }
+    }
+}
+
+impl FromStr for Server {
+    type Err = String;
+
+    /// `stun:host`, `stuns:host:5349` and `stun:[2001:db8::1]:3478` alike; the
+    /// port is the scheme's own when left out.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (scheme, rest) = s
+            .split_once(':')
+            .ok_or_else(|| format!("`{s}` is not a STUN URI, which starts with stun: or stuns:"))?;
+
+        let tls = match scheme.to_ascii_lowercase().as_str() {
+            "stun" => false,
+            "stuns" => true,
+            _ => {
+                return Err(format!(
+                    "`{scheme}` is not a STUN scheme, use stun or stuns"
+                ));
+            }
+        };
+
+        if rest.contains(|c: char| matches!(c, '/' | '?' | '#' | '@') || c.is_whitespace()) {
+            return Err(format!("`{s}` names more than a host and a port"));
+        }
+
+        let (host, port) = if let Some(bracketed) = rest.strip_prefix('[') {
+            let (host, after) = bracketed
+                .split_once(']')
+                .ok_or_else(|| format!("`{s}` opens a bracket it never closes"))?;
+
+            match after {
+                "" => (host, None),
+                _ => match after.strip_prefix(':') {
+                    Some(port) => (host, Some(port)),
+                    None => return Err(format!("`{s}` has something after its host")),
+                },
+            }
+        } else {
+            match rest.split_once(':') {
+                Some((_, port)) if port.contains(':') => {
+                    return Err(format!("`{s}` has an IPv6 address outside brackets"));
+                }
+                Some((host, port)) => (host, Some(port)),
+                None => (rest, None),
+            }
+        };
+
+        if host.is_empty() {
+            return Err(format!("`{s}` names no host"));
+        }
+
+        let port = match port {
+            None => {
+                if tls {
+                    Self::TLS_PORT
+                } else {
+                    Self::PORT
+                }
+            }
+            Some(port) => port
+                .parse()
+                .ok()
+                .filter(|port| *port != 0)
+                .ok_or_else(|| format!("`{port}` is not a port"))?,
+        };
+
+        Ok(Self {
+            host: Cow::Owned(host.to_owned()),
+            port,
+            tls,
+        })
+    }
+}
+
+/// Asks the server which address our requests arrive from, over the family
+/// and out by the uplink.
+///
+/// The answer is the address of the flow the request travelled in: a UDP one
+/// for `stun:`, a TCP one for `stuns:`. A NAT is free to map either
+/// differently from the connections the HTTP providers see.
+pub async fn query(server: &Server, family: Family, uplink: &Uplink) -> Result<IpAddr, FetchError> {
+    let id = transaction_id();
+    let request = binding_request(&id);
+
+    let exchange = async {
+        let peer = socket::resolve(&server.host, server.port, family, uplink).await?;
+
+        if server.tls {
+            over_tls(server, peer, uplink, &request).await
+        } else {
+            socket::exchange_udp(peer, uplink, &request, |message| belongs_to(message, &id)).await
+        }
+    };
+
+    let message = timeout(client::REQUEST_TIMEOUT, exchange)
+        .await
+        .map_err(|_| FetchError::Timeout)??;
+
+    decode(&message, &id)
+}
+
+/// One request and one answer over a TLS connection. TCP frames nothing, so
+/// the header says how much of the stream the answer takes.
+async fn over_tls(
+    server: &Server,
+    peer: SocketAddr,
+    uplink: &Uplink,
+    request: &[u8],
+) -> Result<Vec<u8>, FetchError> {
+    let mut stream = socket::connect_tls(&server.host, peer, uplink).await?;
+    stream.write_all(request).await?;
+
+    let mut message = vec![0; HEADER_LEN];
+    stream.read_exact(&mut message).await?;
+
+    let length = usize::from(u16::from_be_bytes([message[2], message[3]]));
+    message.resize(HEADER_LEN + length, 0);
+    stream.read_exact(&mut message[HEADER_LEN..]).await?;
+
+    Ok(message)
+}
+
+/// Twelve bytes nobody else on the path could have guessed ahead of time,
+/// drawn from the system's own randomness: an ID an off-path attacker could
+/// predict would let them answer for the server.
+fn transaction_id() -> TransactionId {
+    let mut id = [0; 12];
+    SystemRandom::new()
+        .fill(&mut id)
+        .expect("the system must have randomness to give");
+
+    id
+}
+
+/// A Binding request with no attributes: nothing else is needed to learn
+/// the mapped address, and no server asks credentials for it.
+fn binding_request(id: &TransactionId) -> [u8; HEADER_LEN] {
+    let mut message = [0; HEADER_LEN];
+
+    message[0..2].copy_from_slice(&BINDING_REQUEST.to_be_bytes());
+    message[4..8].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    message[8..20].copy_from_slice(id);
+
+    message
+}
+
+/// Whether the message is a STUN one answering our transaction.
+fn belongs_to(message: &[u8], id: &TransactionId) -> bool {
+    message.len() >= HEADER_LEN
+        && message[4..8] == MAGIC_COOKIE.to_be_bytes()
+        && message[8..20] == *id
+}
+
+/// The address a Binding response maps us to.
+///
+/// Only XOR-MAPPED-ADDRESS is read. The plain MAPPED-ADDRESS of RFC 3489 is
+/// what a NAT's application-level gateway rewrites on the way back, and the
+/// address it rewrites it to is the private one this is meant to see past.
+fn decode(message: &[u8], id: &TransactionId) -> Result<IpAddr, FetchError> {
+    let malformed = |reason: &'static str| FetchError::Decode {
+        body: hex(message),
+        source: Cause::from(reason),
+    };
+
+    if !belongs_to(message, id) {
+        return Err(malformed("not a STUN answer to this request"));
+    }
+
+    let kind = u16::from_be_bytes([message[0], message[1]]);
+    let length = usize::from(u16::from_be_bytes([message[2], message[3]]));
+
+    if length != message.len() - HEADER_LEN || !length.is_multiple_of(4) {
+        return Err(malformed("the length in the header is not the message's"));
+    }
+
+    let mut attributes = attributes(&message[HEADER_LEN..]);
+
+    match kind {
+        BINDING_SUCCESS => {
+            let value = attributes
+                .find_map(|(kind, value)| (kind == XOR_MAPPED_ADDRESS).then_some(value))
+                .ok_or_else(|| malformed("no XOR-MAPPED-ADDRESS in the response"))?;
+
+            unxor(value, id).ok_or_else(|| malformed("XOR-MAPPED-ADDRESS does not parse"))
+        }
+        BINDING_ERROR => {
+            let value = attributes
+                .find_map(|(kind, value)| (kind == ERROR_CODE).then_some(value))
+                .filter(|value| value.len() >= 4)
+                .ok_or_else(|| malformed("an error response without ERROR-CODE"))?;
+
+            Err(FetchError::StunStatus {
+                code: u16::from(value[2] & 0x07) * 100 + u16::from(value[3]),
+                reason: String::from_utf8_lossy(&value[4..]).into_owned(),
+            })
+        }
+        _ => Err(malformed("not a Binding response")),
+    }
+}
+
+/// Every attribute in order, until one runs past the end of the message.
+/// Each value is padded to four bytes, and the padding is no part of it.
+fn attributes(mut rest: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        let kind = u16::from_be_bytes([*rest.first()?, *rest.get(1)?]);
+        let length = usize::from(u16::from_be_bytes([*rest.get(2)?, *rest.get(3)?]));
+
+        let value = rest.get(4..4 + length)?;
+        rest = rest
+            .get(4 + length.next_multiple_of(4)..)
+            .unwrap_or_default();
+
+        Some((kind, value))
+    })
+}
+
+/// The address XOR-MAPPED-ADDRESS carries, undone of the magic cookie and,
+/// for IPv6, of the transaction ID after it.
+fn unxor(value: &[u8], id: &TransactionId) -> Option<IpAddr> {
+    const IPV4: u8 = 0x01;
+    const IPV6: u8 = 0x02;
+
+    let mut key = [0; 16];
+    key[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    key[4..].copy_from_slice(id);
+
+    let xored =
+        |octets: &[u8]| -> Vec<u8> { octets.iter().zip(key).map(|(octet, k)| octet ^ k).collect() };
+
+    match (value.get(1)?, value.len()) {
+        (&IPV4, 8) => {
+            let octets: [u8; 4] = xored(&value[4..8]).try_into().ok()?;
+            Some(IpAddr::V4(Ipv4Addr::from(octets)))
+        }
+        (&IPV6, 20) => {
+            let octets: [u8; 16] = xored(&value[4..20]).try_into().ok()?;
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, tokio::net::UdpSocket};
+
+    // RFC 5769, sections 2.2 and 2.3: the same transaction answered over each
+    // family, a SOFTWARE attribute before the address and integrity after it.
+    const ID: TransactionId = [
+        0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae,
+    ];
+
+    const RESPONSE_V4: [u8; 80] = [
+        0x01, 0x01, 0x00, 0x3c, 0x21, 0x12, 0xa4, 0x42, 0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6,
+        0x86, 0xfa, 0x87, 0xdf, 0xae, 0x80, 0x22, 0x00, 0x0b, 0x74, 0x65, 0x73, 0x74, 0x20, 0x76,
+        0x65, 0x63, 0x74, 0x6f, 0x72, 0x20, 0x00, 0x20, 0x00, 0x08, 0x00, 0x01, 0xa1, 0x47, 0xe1,
+        0x12, 0xa6, 0x43, 0x00, 0x08, 0x00, 0x14, 0x2b, 0x91, 0xf5, 0x99, 0xfd, 0x9e, 0x90, 0xc3,
+        0x8c, 0x74, 0x89, 0xf9, 0x2a, 0xf9, 0xba, 0x53, 0xf0, 0x6b, 0xe7, 0xd7, 0x80, 0x28, 0x00,
+        0x04, 0xc0, 0x7d, 0x4c, 0x96,
+    ];
+
+    const RESPONSE_V6: [u8; 92] = [
+        0x01, 0x01, 0x00, 0x48, 0x21, 0x12, 0xa4, 0x42, 0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6,
+        0x86, 0xfa, 0x87, 0xdf, 0xae, 0x80, 0x22, 0x00, 0x0b, 0x74, 0x65, 0x73, 0x74, 0x20, 0x76,
+        0x65, 0x63, 0x74, 0x6f, 0x72, 0x20, 0x00, 0x20, 0x00, 0x14, 0x00, 0x02, 0xa1, 0x47, 0x01,
+        0x13, 0xa9, 0xfa, 0xa5, 0xd3, 0xf1, 0x79, 0xbc, 0x25, 0xf4, 0xb5, 0xbe, 0xd2, 0xb9, 0xd9,
+        0x00, 0x08, 0x00, 0x14, 0xa3, 0x82, 0x95, 0x4e, 0x4b, 0xe6, 0x7b, 0xf1, 0x17, 0x84, 0xc9,
+        0x7c, 0x82, 0x92, 0xc2, 0x75, 0xbf, 0xe3, 0xed, 0x41, 0x80, 0x28, 0x00, 0x04, 0xc8, 0xfb,
+        0x0b, 0x4c,
+    ];
+
+    fn ip(s: &str) -> IpAddr {
+        IpAddr::from_str(s).expect("test address must parse")
+    }
+
+    fn server(s: &str) -> Server {
+        Server::from_str(s).unwrap_or_else(|err| panic!("`{s}` must parse: {err}"))
+    }
+
+    /// What a server says back: the address, folded the way the RFC says.
+    fn success(id: &TransactionId, mapped: IpAddr) -> Vec<u8> {
+        let mut key = [0; 16];
+        key[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        key[4..].copy_from_slice(id);
+
+        let (family, octets) = match mapped {
+            IpAddr::V4(v4) => (0x01, v4.octets().to_vec()),
+            IpAddr::V6(v6) => (0x02, v6.octets().to_vec()),
+        };
+
+        let mut value = vec![0, family, 0x21, 0x12];
+        value.extend(octets.iter().zip(key).map(|(octet, k)| octet ^ k));
+
+        let mut message = binding_request(id).to_vec();
+        message[0..2].copy_from_slice(&BINDING_SUCCESS.to_be_bytes());
+        message[2..4]
+            .copy_from_slice(&(u16::try_from(value.len() + 4).expect("fits")).to_be_bytes());
+        message.extend(XOR_MAPPED_ADDRESS.to_be_bytes());
+        message.extend((u16::try_from(value.len()).expect("fits")).to_be_bytes());
+        message.extend(value);
+        message
+    }
+
+    #[test]
+    fn the_rfc_5769_responses_decode_to_the_addresses_they_carry() {
+        assert_eq!(
+            decode(&RESPONSE_V4, &ID).expect("the IPv4 vector must decode"),
+            ip("192.0.2.1")
+        );
+        assert_eq!(
+            decode(&RESPONSE_V6, &ID).expect("the IPv6 vector must decode"),
+            ip("2001:db8:1234:5678:11:2233:4455:6677"),
+        );
+    }
+
+    #[test]
+    fn an_answer_to_another_transaction_is_not_ours() {
+        let mut other = ID;
+        other[0] ^= 0xff;
+
+        assert!(!belongs_to(&RESPONSE_V4, &other));
+
+        let err = decode(&RESPONSE_V4, &other).expect_err("another transaction must not decode");
+        assert_eq!(err.as_error_type(), "decode");
+    }
+
+    #[test]
+    fn a_truncated_response_reports_what_it_saw() {
+        let err = decode(&RESPONSE_V4[..60], &ID).expect_err("a truncated message must not decode");
+
+        assert_eq!(err.as_error_type(), "decode");
+        assert!(err.to_string().contains("0101003c2112a442"));
+    }
+
+    #[test]
+    fn an_error_response_is_a_stun_status() {
+        let reason = b"Try Alternate";
+
+        let mut message = binding_request(&ID).to_vec();
+        message[0..2].copy_from_slice(&BINDING_ERROR.to_be_bytes());
+        message[2..4].copy_from_slice(&24u16.to_be_bytes());
+        message.extend([0x00, 0x09, 0x00, 0x11, 0x00, 0x00, 0x03, 0x00]);
+        message.extend(reason);
+        message.extend([0; 3]);
+
+        match decode(&message, &ID) {
+            Err(FetchError::StunStatus { code, reason }) => {
+                assert_eq!(code, 300);
+                assert_eq!(reason, "Try Alternate");
+            }
+            other => panic!("an error response must be a status, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn a_request_is_a_bare_binding_request() {
+        let request = binding_request(&ID);
+
+        assert_eq!(
+            request[..8],
+            [0x00, 0x01, 0x00, 0x00, 0x21, 0x12, 0xa4, 0x42]
+        );
+        assert_eq!(request[8..], ID);
+        assert_ne!(transaction_id(), transaction_id());
+    }
+
+    #[test]
+    fn a_stun_uri_is_a_host_and_a_port() {
+        assert_eq!(
+            server("stun:stun.l.google.com:19302"),
+            Server::new("stun.l.google.com", 19302, false),
+        );
+        assert_eq!(
+            server("STUNS:stun.example.net"),
+            Server::new("stun.example.net", Server::TLS_PORT, true),
+        );
+        assert_eq!(
+            server("stun:[2001:db8::1]"),
+            Server::new("2001:db8::1", Server::PORT, false),
+        );
+        assert_eq!(
+            server("stun:[2001:db8::1]:3479").to_string(),
+            "stun:[2001:db8::1]:3479"
+        );
+
+        for bad in [
+            "https://stun.example.net/",
+            "stun:",
+            "stun:2001:db8::1",
+            "stun:[2001:db8::1",
+            "stun:host:0",
+            "stun:host:port",
+            "stun:user@host",
+            "stun:host/path",
+        ] {
+            assert!(Server::from_str(bad).is_err(), "`{bad}` must be refused");
+        }
+    }
+
+    // A stand-in on loopback that lets the first request go unanswered and
+    // sends a stray datagram before the real answer, so both the
+    // retransmission and the transaction check have to work.
+    #[tokio::test]
+    async fn a_local_stand_in_is_asked_over_udp() {
+        let stand_in = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .expect("loopback must be bindable");
+        let port = stand_in.local_addr().expect("it has an address").port();
+
+        let serving = tokio::spawn(async move {
+            let mut buf = [0; 576];
+
+            let _ = stand_in.recv_from(&mut buf).await.expect("a first request");
+            let (received, from) = stand_in.recv_from(&mut buf).await.expect("a retry");
+
+            let id: TransactionId = buf[8..20].try_into().expect("a header");
+            let mut stray = id;
+            stray[11] ^= 0x01;
+
+            for answer in [
+                success(&stray, ip("198.51.100.1")),
+                success(&id, ip("203.0.113.9")),
+            ] {
+                stand_in
+                    .send_to(&answer, from)
+                    .await
+                    .expect("the answer must go");
+            }
+
+            received
+        });
+
+        let answered = query(
+            &Server::new("127.0.0.1", port, false),
+            Family::V4,
+            &Uplink::Default,
+        )
+        .await
+        .expect("the stand-in must be asked");
+
+        assert_eq!(answered, ip("203.0.113.9"));
+        assert_eq!(
+            serving.await.expect("the stand-in must not panic"),
+            HEADER_LEN
+        );
+    }
+
+    #[tokio::test]
+    async fn a_server_without_the_family_is_unreachable_over_it() {
+        let err = query(
+            &Server::new("127.0.0.1", Server::PORT, false),
+            Family::V6,
+            &Uplink::Default,
+        )
+        .await
+        .expect_err("an IPv4 literal has no IPv6 address");
+
+        assert_eq!(err.as_error_type(), "unreachable");
+    }
+}
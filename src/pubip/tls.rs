@@ -0,0 +1,549 @@
+use {
+    anyhow::{Context as _, Result, ensure},
+    base64::{Engine as _, engine::general_purpose::STANDARD as BASE64},
+    ring::digest::{SHA256, digest},
+    std::{
+        error::Error,
+        fmt,
+        hash::{Hash, Hasher},
+        iter,
+        path::{Path, PathBuf},
+        str::FromStr,
+        sync::{Arc, LazyLock, OnceLock},
+    },
+    tokio_rustls::rustls::{
+        self, CertificateError, ClientConfig, DigitallySignedStruct, OtherError, RootCertStore,
+        SignatureScheme,
+        client::{
+            WebPkiServerVerifier,
+            danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        },
+        crypto::{self, CryptoProvider},
+        pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime, pem::PemObject as _},
+    },
+};
+
+// Every TLS connection a provider is asked over is made here, reqwest's and
+// the sockets' alike: ring is the only crypto provider in the tree, and the
+// roots are the ones reqwest's rustls trusts, those of `--provider-ca` among
+// them.
+
+static PROVIDER: LazyLock<Arc<CryptoProvider>> =
+    LazyLock::new(|| Arc::new(crypto::ring::default_provider()));
+
+// Set once at startup, before the first connection is made, and never again.
+static ROOTS: OnceLock<Vec<CertificateDer<'static>>> = OnceLock::new();
+
+// Built on the first connection, by which time `ROOTS` is what it will stay.
+static STORE: LazyLock<Arc<RootCertStore>> = LazyLock::new(|| {
+    let mut store = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    store.add_parsable_certificates(roots().iter().cloned());
+
+    Arc::new(store)
+});
+
+/// The roots `Ipify`'s chains lead to, each named above its pins. Its hosts sit
+/// behind Cloudflare, whose edge certificates come from Let's Encrypt, Google
+/// Trust Services and SSL.com in turn, under an RSA or an ECDSA root of each:
+/// whichever issues the next renewal, the others are its backup.
+const IPIFY_ROOTS: &[&str] = &[
+    // ISRG Root X1 and X2, Let's Encrypt's.
+    "sha256/C5+lpZ7tcVwmwQIMcRtPbsQtWLABXhQzejna0wHFr8M=",
+    "sha256/diGVwiVYbubAI3RW4hB9xU8e/CH2GnkuvVFZE8zmgzI=",
+    // GTS Root R1 and R4.
+    "sha256/hxqRlPTu1bMS/0DITB1SSu0vd4u/8l8TjPgfaAp63Gc=",
+    "sha256/mEflZT5enoR1FuXLgYYGqnVEoZvmf9c2bVBpiOjYQ0c=",
+    // SSL.com TLS RSA and ECC Root CA 2022.
+    "sha256/K89VOmb1cJAN3TK6bf4ezAbJGC1mLcG2Dh97dnwr3VQ=",
+    "sha256/G/ANXI8TwJTdF+AFBM8IiIUPEv0Gf6H5LA/b9guG4yE=",
+];
+
+/// The roots `IpInfo`'s chain leads to. Its certificate is one Google manages
+/// for its load balancer, issued by Google Trust Services, with Let's Encrypt
+/// as the CA Google falls back on.
+const IPINFO_ROOTS: &[&str] = &[
+    // GTS Root R1 and R4.
+    "sha256/hxqRlPTu1bMS/0DITB1SSu0vd4u/8l8TjPgfaAp63Gc=",
+    "sha256/mEflZT5enoR1FuXLgYYGqnVEoZvmf9c2bVBpiOjYQ0c=",
+    // ISRG Root X1 and X2, Let's Encrypt's.
+    "sha256/C5+lpZ7tcVwmwQIMcRtPbsQtWLABXhQzejna0wHFr8M=",
+    "sha256/diGVwiVYbubAI3RW4hB9xU8e/CH2GnkuvVFZE8zmgzI=",
+];
+
+fn parse_built_in(pins: &[&str]) -> Vec<Pin> {
+    pins.iter()
+        .map(|pin| Pin::from_str(pin).expect("a built-in pin must parse"))
+        .collect()
+}
+
+static IPIFY_PINS: LazyLock<Vec<Pin>> = LazyLock::new(|| parse_built_in(IPIFY_ROOTS));
+static IPINFO_PINS: LazyLock<Vec<Pin>> = LazyLock::new(|| parse_built_in(IPINFO_ROOTS));
+
+/// Reads the CA bundle every provider's certificate may chain to, beside the
+/// built-in roots, and trusts it from then on. Only the first call counts.
+pub fn load_roots(path: &Path) -> Result<usize> {
+    let roots = CertificateDer::pem_file_iter(path)
+        .with_context(|| format!("cannot read CA bundle {}", path.display()))?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("CA bundle {} is not PEM", path.display()))?;
+    ensure!(
+        !roots.is_empty(),
+        "CA bundle {} holds no certificate",
+        path.display()
+    );
+
+    // Checked here, where a certificate that is no root can be named, rather
+    // than when the first client is built with it.
+    let mut store = RootCertStore::empty();
+    for root in &roots {
+        store
+            .add(root.clone())
+            .with_context(|| format!("CA bundle {} holds a bad root", path.display()))?;
+    }
+
+    let count = roots.len();
+    let _ = ROOTS.set(roots);
+    Ok(count)
+}
+
+/// The roots `--provider-ca` added, none unless it was given.
+pub fn roots() -> &'static [CertificateDer<'static>] {
+    ROOTS.get().map_or(&[], Vec::as_slice)
+}
+
+/// `Ipify`'s built-in pins, see [`IPIFY_ROOTS`].
+pub fn ipify_pins() -> &'static [Pin] {
+    &IPIFY_PINS
+}
+
+/// `IpInfo`'s built-in pins, see [`IPINFO_ROOTS`].
+pub fn ipinfo_pins() -> &'static [Pin] {
+    &IPINFO_PINS
+}
+
+/// What a handshake with a provider presents and insists on beyond what every
+/// handshake does: a client certificate, and the keys one of the provider's
+/// certificates must have. Neither unless the provider was given one.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug)]
+pub struct Handshake {
+    pub identity: Option<&'static ClientIdentity>,
+    pub pins: &'static [Pin],
+}
+
+/// The TLS settings of a connection to a provider.
+pub fn config(handshake: Handshake) -> ClientConfig {
+    let builder = ClientConfig::builder_with_provider(Arc::clone(&PROVIDER))
+        .with_safe_default_protocol_versions()
+        .expect("ring must support the default TLS versions");
+
+    let builder = if handshake.pins.is_empty() {
+        builder.with_root_certificates(Arc::clone(&STORE))
+    } else {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(Pinned::new(
+                handshake.pins,
+                Arc::clone(&STORE),
+            )))
+    };
+
+    match handshake.identity {
+        Some(identity) => builder
+            .with_client_auth_cert(identity.chain.clone(), identity.key.clone_key())
+            .expect("the key was matched to its certificate when read"),
+        None => builder.with_no_client_auth(),
+    }
+}
+
+/// The SHA-256 of a certificate's `SubjectPublicKeyInfo`, written
+/// `sha256/BASE64` as curl's `--pinnedpubkey` and HPKP write it.
+///
+/// A key outlives the certificates issued for it, so a pin on it survives a
+/// renewal that keeps the key, and a pin on a CA's survives every renewal
+/// that CA makes.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Pin([u8; 32]);
+
+impl Pin {
+    /// The pin of a key given as the contents of its `SubjectPublicKeyInfo`,
+    /// the outer SEQUENCE left out, which is how a trust anchor keeps it.
+    fn of_contents(spki: &[u8]) -> Self {
+        // DER's length: short form below 128, long form above.
+        let length = spki.len().to_be_bytes();
+        let significant = length.iter().skip_while(|octet| **octet == 0).count();
+
+        let mut der = vec![0x30];
+        if spki.len() < 0x80 {
+            der.extend(&length[length.len() - 1..]);
+        } else {
+            #[allow(
+                clippy::cast_possible_truncation,
+                reason = "a length never takes more than eight octets"
+            )]
+            der.push(0x80 | significant as u8);
+            der.extend(&length[length.len() - significant..]);
+        }
+        der.extend(spki);
+
+        Self(
+            digest(&SHA256, &der)
+                .as_ref()
+                .try_into()
+                .expect("SHA-256 is 32 octets"),
+        )
+    }
+
+    /// The pin of the key a certificate was issued for, if it parses.
+    fn of_cert(cert: &CertificateDer<'_>) -> Option<Self> {
+        // A root store is the one certificate parser rustls lends out, and
+        // adding a certificate to one takes its subject and key, nothing else.
+        let mut store = RootCertStore::empty();
+        store.add(cert.clone().into_owned()).ok()?;
+
+        store
+            .roots
+            .first()
+            .map(|anchor| Self::of_contents(&anchor.subject_public_key_info))
+    }
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sha256/{}", BASE64.encode(self.0))
+    }
+}
+
+impl fmt::Debug for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Pin({self})")
+    }
+}
+
+impl FromStr for Pin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(hash) = s.strip_prefix("sha256/") else {
+            return Err(format!("`{s}` is not sha256/BASE64"));
+        };
+
+        BASE64
+            .decode(hash)
+            .ok()
+            .and_then(|hash| hash.try_into().ok())
+            .map(Self)
+            .ok_or_else(|| format!("`{hash}` is not the base64 of a SHA-256"))
+    }
+}
+
+/// Why a provider's certificate, valid as it is, was still refused: none of
+/// the keys on the way from it to its root is one pinned for the provider.
+#[derive(Debug)]
+pub struct PinMismatch {
+    host: String,
+}
+
+impl fmt::Display for PinMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} presented a certificate chain with no key pinned for it",
+            self.host,
+        )
+    }
+}
+
+impl Error for PinMismatch {}
+
+/// Checks a provider's certificate the way every one is checked, and then
+/// that one of the keys it was issued under is pinned: its own, an
+/// intermediate's, or that of the root it chains to.
+#[derive(Debug)]
+struct Pinned {
+    pins: &'static [Pin],
+    everyone: Arc<WebPkiServerVerifier>,
+    /// The roots that are pinned, if any is among those trusted at all. A
+    /// root is seldom sent, so whether it is the one a chain leads to is
+    /// only known by leading the chain to it.
+    roots: Option<Arc<WebPkiServerVerifier>>,
+}
+
+impl Pinned {
+    fn new(pins: &'static [Pin], store: Arc<RootCertStore>) -> Self {
+        let verifier = |store: Arc<RootCertStore>| {
+            WebPkiServerVerifier::builder_with_provider(store, Arc::clone(&PROVIDER))
+                .build()
+                .expect("a verifier over roots that are there must build")
+        };
+
+        let pinned: Vec<_> = store
+            .roots
+            .iter()
+            .filter(|anchor| pins.contains(&Pin::of_contents(&anchor.subject_public_key_info)))
+            .cloned()
+            .collect();
+
+        Self {
+            pins,
+            everyone: verifier(store),
+            roots: (!pinned.is_empty())
+                .then(|| verifier(Arc::new(RootCertStore { roots: pinned }))),
+        }
+    }
+}
+
+impl ServerCertVerifier for Pinned {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.everyone.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+
+        let presented = iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(Pin::of_cert)
+            .any(|pin| self.pins.contains(&pin));
+
+        let rooted = || {
+            self.roots.as_ref().is_some_and(|pinned| {
+                pinned
+                    .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+                    .is_ok()
+            })
+        };
+
+        if presented || rooted() {
+            return Ok(verified);
+        }
+
+        Err(rustls::Error::InvalidCertificate(CertificateError::Other(
+            OtherError(Arc::new(PinMismatch {
+                host: server_name.to_str().into_owned(),
+            })),
+        )))
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.everyone.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.everyone.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.everyone.supported_verify_schemes()
+    }
+}
+
+/// Whether the handshake failed on a pin rather than on anything every
+/// handshake checks.
+pub fn is_pin_mismatch(err: &rustls::Error) -> bool {
+    matches!(
+        err,
+        rustls::Error::InvalidCertificate(CertificateError::Other(OtherError(other)))
+            if other.is::<PinMismatch>()
+    )
+}
+
+/// The certificate a provider is asked with, for one that wants to know who
+/// is asking, and the key that goes with it.
+///
+/// Told apart by the certificate's file: two providers given the same one
+/// share their connections.
+pub struct ClientIdentity {
+    chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+    cert: PathBuf,
+}
+
+impl fmt::Debug for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ClientIdentity({})", self.cert.display())
+    }
+}
+
+impl PartialEq for ClientIdentity {
+    fn eq(&self, other: &Self) -> bool {
+        self.cert == other.cert
+    }
+}
+
+impl Eq for ClientIdentity {}
+
+impl Hash for ClientIdentity {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.cert.hash(state);
+    }
+}
+
+impl ClientIdentity {
+    /// Reads both PEM files: the certificate, any intermediates after it, and
+    /// its private key.
+    pub fn read(cert: &Path, key: &Path) -> Result<Self> {
+        let chain = CertificateDer::pem_file_iter(cert)
+            .with_context(|| format!("cannot read client certificate {}", cert.display()))?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("client certificate {} is not PEM", cert.display()))?;
+        ensure!(
+            !chain.is_empty(),
+            "client certificate {} holds no certificate",
+            cert.display()
+        );
+
+        let key_der = PrivateKeyDer::from_pem_file(key)
+            .with_context(|| format!("cannot read client key {}", key.display()))?;
+
+        // Checked here rather than at the first handshake, which would fail
+        // with nothing to say which file was wrong.
+        ClientConfig::builder_with_provider(Arc::clone(&PROVIDER))
+            .with_safe_default_protocol_versions()
+            .expect("ring must support the default TLS versions")
+            .with_root_certificates(RootCertStore::empty())
+            .with_client_auth_cert(chain.clone(), key_der.clone_key())
+            .with_context(|| format!("{} is not the key of {}", key.display(), cert.display()))?;
+
+        Ok(Self {
+            chain,
+            key: key_der,
+            cert: cert.to_owned(),
+        })
+    }
+
+    /// The certificate's file, which is all that is ever shown of it.
+    pub fn cert(&self) -> &Path {
+        &self.cert
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::pubip::error::FetchError,
+        rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, DnType, IsCa, KeyPair},
+        std::io,
+    };
+
+    fn root(name: &str) -> CertifiedIssuer<'static, KeyPair> {
+        let mut params = CertificateParams::new([]).expect("no names to check");
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let key = KeyPair::generate().expect("a key must generate");
+
+        CertifiedIssuer::self_signed(params, key).expect("a root must sign itself")
+    }
+
+    #[test]
+    fn every_built_in_pin_is_that_of_a_root_the_client_trusts() {
+        let trusted: Vec<Pin> = webpki_roots::TLS_SERVER_ROOTS
+            .iter()
+            .map(|anchor| Pin::of_contents(&anchor.subject_public_key_info))
+            .collect();
+
+        for (pins, roots) in [(ipify_pins(), IPIFY_ROOTS), (ipinfo_pins(), IPINFO_ROOTS)] {
+            // More than one, so that a CA moving a provider to another root
+            // leaves a backup that still holds.
+            assert!(pins.len() > 1);
+            assert_eq!(pins.len(), roots.len());
+            for pin in pins {
+                assert!(trusted.contains(pin), "{pin} is no root webpki-roots has");
+            }
+        }
+    }
+
+    #[test]
+    fn a_pin_is_the_base64_of_a_sha256() {
+        let isrg = "sha256/C5+lpZ7tcVwmwQIMcRtPbsQtWLABXhQzejna0wHFr8M=";
+        assert_eq!(Pin::from_str(isrg).expect("it parses").to_string(), isrg);
+
+        for wrong in [
+            "C5+lpZ7tcVwmwQIMcRtPbsQtWLABXhQzejna0wHFr8M=",
+            "sha1/C5+lpZ7tcVwmwQIMcRtPbsQtWLABXhQzejna0wHFr8M=",
+            "sha256/C5+lpZ7tcVwmwQIMcRtPbsQtWLAB",
+            "sha256/not base64",
+        ] {
+            assert!(Pin::from_str(wrong).is_err(), "`{wrong}` is taken");
+        }
+    }
+
+    #[test]
+    fn a_pin_that_does_not_hold_is_told_from_any_other_bad_certificate() {
+        let handshake = |err: CertificateError| {
+            FetchError::from(io::Error::new(
+                io::ErrorKind::InvalidData,
+                rustls::Error::InvalidCertificate(err),
+            ))
+        };
+
+        let pinned = handshake(CertificateError::Other(OtherError(Arc::new(PinMismatch {
+            host: "api.ipify.org".to_owned(),
+        }))));
+        assert_eq!(pinned.as_error_type(), "pin_mismatch");
+        assert!(pinned.to_string().contains("api.ipify.org"), "{pinned}");
+        assert!(!pinned.fails_over());
+
+        let expired = handshake(CertificateError::Expired);
+        assert_eq!(expired.as_error_type(), "tls");
+    }
+
+    #[test]
+    fn a_chain_from_another_trusted_root_is_refused_by_the_pins() {
+        let host = "ip.example.net";
+        let ours = root("Ours");
+        let theirs = root("Theirs");
+
+        let leaf_key = KeyPair::generate().expect("a key must generate");
+        let leaf = CertificateParams::new([host.to_owned()])
+            .expect("the name must be taken")
+            .signed_by(&leaf_key, &theirs)
+            .expect("the root must sign the leaf");
+
+        let mut store = RootCertStore::empty();
+        for trusted in [&ours, &theirs] {
+            store
+                .add(trusted.der().clone())
+                .expect("the root must be taken");
+        }
+        let store = Arc::new(store);
+
+        let verify = |pinned: &CertificateDer<'_>| {
+            let pin = Pin::of_cert(pinned).expect("the pinned certificate must parse");
+            Pinned::new(Box::leak(Box::new([pin])), Arc::clone(&store)).verify_server_cert(
+                leaf.der(),
+                &[],
+                &ServerName::try_from(host).expect("the name must parse"),
+                &[],
+                UnixTime::now(),
+            )
+        };
+
+        let err = verify(ours.der()).expect_err("the leaf does not chain to the pinned root");
+        assert!(is_pin_mismatch(&err), "{err}");
+
+        assert!(verify(theirs.der()).is_ok(), "the leaf chains to this root");
+        assert!(verify(leaf.der()).is_ok(), "the leaf's own key is pinned");
+    }
+}
@@ -1,13 +1,13 @@
 use {
-    crate::pubip::{HttpProvider, TrustShare},
+    crate::pubip::{HttpProvider, Provider, TrustShare},
     std::collections::HashMap,
 };
 
-/// The mutable source of trust factors for every known [`HttpProvider`], and
+/// The mutable source of trust factors for every known [`Provider`], and
 /// the arithmetic that turns them into the threshold an address must reach.
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct TrustFactorAuthority {
-    custom: HashMap<HttpProvider, usize>,
+    custom: HashMap<Provider, usize>,
     share: TrustShare,
 }
 
@@ -21,20 +21,21 @@ impl TrustFactorAuthority {
         (Self::LOW..=Self::HIG).contains(&trust_factor)
     }
 
-    /// Returns trust factor for the given [`HttpProvider`] that is
+    /// Returns trust factor for the given [`Provider`] that is
     /// either defined by the user via [`Self::set_trust_factor`] or default one.
-    pub fn trust_factor(&self, provider: HttpProvider) -> usize {
+    pub fn trust_factor(&self, provider: impl Into<Provider>) -> usize {
+        let provider = provider.into();
         self.custom
             .get(&provider)
             .copied()
-            .unwrap_or_else(|| Self::default_trust_factor(provider))
+            .unwrap_or_else(|| provider.default_trust_factor())
     }
 
-    /// Overwrites default trust factor for the given [`HttpProvider`].
+    /// Overwrites default trust factor for the given [`Provider`].
     /// New trust factor must be in valid range, panic otherwise.
-    pub fn set_trust_factor(&mut self, provider: HttpProvider, new_trust_factor: usize) {
+    pub fn set_trust_factor(&mut self, provider: impl Into<Provider>, new_trust_factor: usize) {
         assert!(Self::is_valid(new_trust_factor));
-        self.custom.insert(provider, new_trust_factor);
+        self.custom.insert(provider.into(), new_trust_factor);
     }
 
     /// The share of the total trust an address must gather. Two thirds unless
@@ -55,7 +56,7 @@ impl TrustFactorAuthority {
     /// The share of the total trust, rounded up while only two providers are
     /// enabled and rounded down once there are three or more, so the threshold
     /// stays reachable without demanding unanimity.
-    pub fn calc_confirmation_number(&self, providers: &[HttpProvider]) -> usize {
+    pub fn calc_confirmation_number(&self, providers: &[Provider]) -> usize {
         let total: usize = providers
             .iter()
             .map(|provider| self.trust_factor(*provider))
@@ -69,12 +70,26 @@ impl TrustFactorAuthority {
         }
     }
 
-    // Returns default trust factor for the given [HttpProvider].
-    fn default_trust_factor(provider: HttpProvider) -> usize {
+    // Returns default trust factor for the given [HttpProvider]. A declared
+    // provider states its own.
+    pub(super) const fn default_trust_factor(provider: HttpProvider) -> usize {
         match provider {
-            HttpProvider::HttpBin | HttpProvider::BigDataCloud | HttpProvider::MyIpLa => Self::LOW,
-            HttpProvider::MyIpWtf | HttpProvider::SeeIp | HttpProvider::MyIpCom => Self::MED,
-            HttpProvider::Ipify => Self::HIG,
+            HttpProvider::HttpBin
+            | HttpProvider::BigDataCloud
+            | HttpProvider::MyIpLa
+            | HttpProvider::CheckIpAws
+            | HttpProvider::IfconfigMe
+            | HttpProvider::IdentMe
+            | HttpProvider::MyExternalIp => Self::LOW,
+            HttpProvider::MyIpWtf
+            | HttpProvider::SeeIp
+            | HttpProvider::MyIpCom
+            | HttpProvider::IcanHazIp
+            | HttpProvider::IfconfigCo
+            | HttpProvider::FreeIpApi
+            | HttpProvider::IpGeolocation
+            | HttpProvider::BigDataCloudKeyed => Self::MED,
+            HttpProvider::Ipify | HttpProvider::IpInfo => Self::HIG,
         }
     }
 }
@@ -83,11 +98,14 @@ impl TrustFactorAuthority {
 mod tests {
     use {super::*, strum::VariantArray};
 
-    fn enabled_by_default() -> Vec<HttpProvider> {
-        HttpProvider::VARIANTS
-            .iter()
+    fn http(providers: &[HttpProvider]) -> Vec<Provider> {
+        providers.iter().copied().map(Provider::Http).collect()
+    }
+
+    fn enabled_by_default() -> Vec<Provider> {
+        http(HttpProvider::VARIANTS)
+            .into_iter()
             .filter(|provider| provider.enabled_by_default())
-            .copied()
             .collect()
     }
 
@@ -95,11 +113,20 @@ mod tests {
     fn confirmation_number_matches_two_thirds_of_total_trust() {
         let tfa = TrustFactorAuthority::default();
 
-        assert_eq!(tfa.calc_confirmation_number(&[HttpProvider::HttpBin]), 1);
-        assert_eq!(tfa.calc_confirmation_number(&[HttpProvider::MyIpWtf]), 2);
-
-        // Every provider there is, total 12: floor(24/3) = 8.
-        assert_eq!(tfa.calc_confirmation_number(HttpProvider::VARIANTS), 8);
+        assert_eq!(
+            tfa.calc_confirmation_number(&http(&[HttpProvider::HttpBin])),
+            1
+        );
+        assert_eq!(
+            tfa.calc_confirmation_number(&http(&[HttpProvider::MyIpWtf])),
+            2
+        );
+
+        // Every provider there is, total 29: floor(58/3) = 19.
+        assert_eq!(
+            tfa.calc_confirmation_number(&http(HttpProvider::VARIANTS)),
+            19
+        );
     }
 
     #[test]
@@ -108,15 +135,15 @@ mod tests {
         tfa.set_trust_factor(HttpProvider::HttpBin, TrustFactorAuthority::MED);
 
         // Two providers, total 4: ceil(8/3) = 3, so neither confirms alone.
-        let two = [HttpProvider::HttpBin, HttpProvider::MyIpWtf];
+        let two = http(&[HttpProvider::HttpBin, HttpProvider::MyIpWtf]);
         assert_eq!(tfa.calc_confirmation_number(&two), 3);
 
         // Three providers, total 6: floor(12/3) = 4, so two of three suffice.
-        let three = [
+        let three = http(&[
             HttpProvider::HttpBin,
             HttpProvider::MyIpWtf,
             HttpProvider::MyIpWtf,
-        ];
+        ]);
         assert_eq!(tfa.calc_confirmation_number(&three), 4);
     }
 
@@ -0,0 +1,282 @@
+use {
+    crate::pubip::Family,
+    std::{
+        fmt, fs,
+        net::{IpAddr, Ipv4Addr},
+        str::FromStr,
+    },
+};
+
+/// Which way out of the node a round's requests leave by.
+///
+/// Each uplink is resolved by a [`Resolver`](crate::pubip::Resolver) of its
+/// own, so a node with two uplinks confirms the public address behind each of
+/// them rather than whichever one the default route happens to prefer.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Uplink {
+    /// Wherever the routing table sends a connection. The only uplink a node
+    /// had before there could be more than one.
+    #[default]
+    Default,
+    /// Bound to the named interface, over whichever family it carries.
+    Interface(String),
+    /// Bound to a source address, and so only ever over its family.
+    Source(IpAddr),
+}
+
+impl Uplink {
+    /// Whether binding a connection to an interface by name is possible here.
+    /// It takes `SO_BINDTODEVICE`, and `auto` reads the Linux routing table.
+    pub const BINDS_INTERFACES: bool = cfg!(target_os = "linux");
+
+    /// Whether the uplink can carry the family at all.
+    pub fn carries(&self, family: Family) -> bool {
+        match self {
+            Self::Default | Self::Interface(_) => true,
+            Self::Source(addr) => Family::of(addr) == family,
+        }
+    }
+
+    /// The address a socket leaving by the uplink binds to over the family.
+    /// An interface is bound by name on top of it, which only the socket's own
+    /// API can do.
+    pub const fn local_address(&self, family: Family) -> IpAddr {
+        match self {
+            Self::Default | Self::Interface(_) => family.unspecified(),
+            Self::Source(addr) => *addr,
+        }
+    }
+
+    /// Whether the uplink leads into the Internet over the family right now.
+    /// An interface does once a default route leaves by it, and stops doing so
+    /// the moment that route is withdrawn.
+    pub fn routable(&self, family: Family) -> bool {
+        match self {
+            Self::Default => family.routable_from(family.unspecified()),
+            Self::Interface(name) => default_routes(family).contains(name),
+            Self::Source(addr) => family.routable_from(*addr),
+        }
+    }
+}
+
+impl fmt::Display for Uplink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => f.write_str("default"),
+            Self::Interface(name) => f.write_str(name),
+            Self::Source(addr) => write!(f, "{addr}"),
+        }
+    }
+}
+
+impl FromStr for Uplink {
+    type Err = String;
+
+    /// An address is a source address, anything else names an interface.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // IFNAMSIZ is 16 with the terminating zero.
+        const LONGEST_NAME: usize = 15;
+
+        let s = s.trim();
+
+        if let Ok(addr) = IpAddr::from_str(s) {
+            return Ok(Self::Source(addr));
+        }
+
+        if s.is_empty()
+            || s.len() > LONGEST_NAME
+            || s.contains(|c: char| c == '/' || c.is_whitespace())
+        {
+            return Err(format!("`{s}` is neither an address nor an interface name"));
+        }
+
+        Ok(Self::Interface(s.to_owned()))
+    }
+}
+
+/// Every interface a default route leaves by, in either family: the uplinks
+/// `auto` stands for.
+///
+/// Read once, at startup. An interface whose default route comes and goes is
+/// still tracked by [`Uplink::routable`]; one that did not exist yet is not.
+pub fn discover() -> Vec<Uplink> {
+    let mut uplinks = Vec::new();
+
+    for name in Family::ALL.into_iter().flat_map(default_routes) {
+        let uplink = Uplink::Interface(name);
+        if !uplinks.contains(&uplink) {
+            uplinks.push(uplink);
+        }
+    }
+
+    uplinks
+}
+
+/// The router an IPv4 default route by the uplink leads to: the one by the
+/// interface for an interface, the first there is otherwise. None for a
+/// default route straight out of a point-to-point link, which has no router
+/// on the other end to ask.
+pub fn default_gateway(uplink: &Uplink) -> Option<Ipv4Addr> {
+    let table = fs::read_to_string("/proc/net/route").ok()?;
+
+    default_routes_v4(&table)
+        .into_iter()
+        .filter(|(iface, _)| match uplink {
+            Uplink::Interface(name) => iface == name,
+            Uplink::Default | Uplink::Source(_) => true,
+        })
+        .map(|(_, gateway)| gateway)
+        .find(|gateway| !gateway.is_unspecified())
+}
+
+/// Whether an interface by that name exists on this node.
+pub fn interface_exists(name: &str) -> bool {
+    fs::exists(format!("/sys/class/net/{name}")).unwrap_or(false)
+}
+
+/// The interfaces a default route in the family leaves by. Empty wherever the
+/// routing table is not where Linux keeps it.
+fn default_routes(family: Family) -> Vec<String> {
+    let (path, parse): (_, fn(&str) -> Vec<String>) = match family {
+        Family::V4 => ("/proc/net/route", parse_route_v4),
+        Family::V6 => ("/proc/net/ipv6_route", parse_route_v6),
+    };
+
+    fs::read_to_string(path)
+        .map(|table| parse(&table))
+        .unwrap_or_default()
+}
+
+// <linux/route.h>: the route is up, and it is not one that only exists to
+// refuse - the kernel keeps an unreachable IPv6 default on `lo` for that.
+const RTF_UP: u32 = 0x0001;
+const RTF_REJECT: u32 = 0x0200;
+
+fn usable(flags: &str) -> bool {
+    u32::from_str_radix(flags, 16).is_ok_and(|flags| flags & RTF_UP != 0 && flags & RTF_REJECT == 0)
+}
+
+fn parse_route_v4(table: &str) -> Vec<String> {
+    default_routes_v4(table)
+        .into_iter()
+        .map(|(iface, _)| iface)
+        .fold(Vec::new(), dedup)
+}
+
+/// `Iface Destination Gateway Flags RefCnt Use Metric Mask ...`, a header
+/// first and every number in hex, an address in the order it is in memory.
+fn default_routes_v4(table: &str) -> Vec<(String, Ipv4Addr)> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [iface, destination, gateway, flags, _, _, _, mask, ..] = fields[..] else {
+                return None;
+            };
+
+            let default = destination == "00000000" && mask == "00000000";
+            let gateway = u32::from_str_radix(gateway, 16).ok()?.to_le_bytes();
+            (default && usable(flags)).then(|| (iface.to_owned(), Ipv4Addr::from(gateway)))
+        })
+        .collect()
+}
+
+/// `Destination PrefixLen Source PrefixLen NextHop Metric RefCnt Use Flags
+/// Iface`, with no header and every number in hex.
+fn parse_route_v6(table: &str) -> Vec<String> {
+    table
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [destination, prefix, _, _, _, _, _, _, flags, iface, ..] = fields[..] else {
+                return None;
+            };
+
+            let default = prefix == "00" && destination.bytes().all(|b| b == b'0');
+            (default && usable(flags) && iface != "lo").then(|| iface.to_owned())
+        })
+        .fold(Vec::new(), dedup)
+}
+
+fn dedup(mut names: Vec<String>, name: String) -> Vec<String> {
+    if !names.contains(&name) {
+        names.push(name);
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTE_V4: &str = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+eth0\t00000000\t0101A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0
+eth1\t00000000\t01010A0A\t0003\t0\t0\t200\t00000000\t0\t0\t0
+eth0\t0001A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0
+cni0\t0000F40A\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0
+";
+
+    const ROUTE_V6: &str = "\
+fd000000000000000000000000000000 40 00000000000000000000000000000000 00 00000000000000000000000000000000 00000100 00000001 00000000 00000001     eth0
+00000000000000000000000000000000 00 00000000000000000000000000000000 00 fe800000000000000000000000000001 00000400 00000002 00000000 00000003     eth0
+00000000000000000000000000000000 00 00000000000000000000000000000000 00 00000000000000000000000000000000 ffffffff 00000001 00000000 00200200       lo
+";
+
+    #[test]
+    fn every_interface_a_default_route_leaves_by_is_an_uplink() {
+        assert_eq!(parse_route_v4(ROUTE_V4), ["eth0", "eth1"]);
+    }
+
+    #[test]
+    fn a_default_route_names_the_router_it_leads_to() {
+        assert_eq!(
+            default_routes_v4(ROUTE_V4),
+            [
+                ("eth0".to_owned(), Ipv4Addr::new(192, 168, 1, 1)),
+                ("eth1".to_owned(), Ipv4Addr::new(10, 10, 1, 1)),
+            ],
+        );
+    }
+
+    #[test]
+    fn the_unreachable_default_the_kernel_keeps_on_lo_is_not() {
+        assert_eq!(parse_route_v6(ROUTE_V6), ["eth0"]);
+    }
+
+    #[test]
+    fn a_route_that_is_down_leads_nowhere() {
+        let down = "Iface\tDestination\tGateway\tFlags\tRefCnt\tUse\tMetric\tMask\n\
+                    eth0\t00000000\t0101A8C0\t0002\t0\t0\t0\t00000000\n";
+
+        assert!(parse_route_v4(down).is_empty());
+    }
+
+    #[test]
+    fn an_address_is_a_source_and_anything_else_an_interface() {
+        assert_eq!(
+            Uplink::from_str("203.0.113.7"),
+            Ok(Uplink::Source(
+                IpAddr::from_str("203.0.113.7").expect("test address must parse")
+            )),
+        );
+        assert_eq!(
+            Uplink::from_str("eth1"),
+            Ok(Uplink::Interface("eth1".into()))
+        );
+
+        assert!(Uplink::from_str("").is_err());
+        assert!(Uplink::from_str("a-name-far-too-long").is_err());
+        assert!(Uplink::from_str("eth0/1").is_err());
+    }
+
+    #[test]
+    fn a_source_address_carries_only_its_own_family() {
+        let source = Uplink::from_str("2001:db8::7").expect("test uplink must parse");
+
+        assert!(source.carries(Family::V6));
+        assert!(!source.carries(Family::V4));
+        assert!(Uplink::Interface("eth0".into()).carries(Family::V4));
+    }
+}